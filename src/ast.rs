@@ -1,77 +1,114 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CompUnit {
     pub items: Vec<CompUnitItem>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum CompUnitItem {
     FuncDef(FuncDef),
+    FuncDecl(FuncDecl),     // 函数原型(没有函数体)
     GlobalDecl(GlobalDecl), // 全局声明
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FuncDef {
+    pub storage: StorageClass,
     pub func_type: FuncType,
     pub id: String,
     pub params: Option<FuncFParams>,
     pub block: Block,
+    pub pos: usize, // 函数名在源码中的位置
+}
+
+/// 函数原型: int f(int a[]);
+#[derive(Debug, Clone)]
+pub struct FuncDecl {
+    pub storage: StorageClass,
+    pub func_type: FuncType,
+    pub id: String,
+    pub params: Option<FuncFParams>,
+    pub pos: usize, // 函数名在源码中的位置
 }
 
-#[derive(Debug)]
+/// 全局声明的存储类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageClass {
+    Default, // 无说明符: 外部链接的定义
+    Static,  // static: 仅在本文件可见
+    Extern,  // extern: 引用其他文件中的定义
+}
+
+#[derive(Debug, Clone)]
 pub enum FuncType {
     Int,
+    Float,
     Void,
 }
 
-#[derive(Debug)]
+impl From<BType> for FuncType {
+    fn from(b_type: BType) -> Self {
+        match b_type {
+            BType::Int => FuncType::Int,
+            BType::Float => FuncType::Float,
+        }
+    }
+}
+
+/// 基本类型(变量/常量/形参的元素类型)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BType {
+    Int,
+    Float,
+}
+
+#[derive(Debug, Clone)]
 pub struct FuncFParams {
     pub params: Vec<FuncFParam>, // 形参列表
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FuncFParam {
-    pub b_type: String,
+    pub b_type: BType,
+    pub pointer: usize, // 声明符中'*'的个数, 0表示不是指针
     pub ident: String,
     pub dimensions: Vec<Option<ConstExp>>, // 数组参数的维度信息，第一维为None表示不定长
+    pub pos: usize, // 形参名在源码中的位置
 }
 
 #[derive(Debug, Clone)]
-pub struct FuncRParams {
-    pub params: Vec<Exp>,   // 实参列表
-}
-
-#[derive(Debug)]
 pub struct Block {
     pub block_item_list: Vec<BlockItem>,
+    pub item_positions: Vec<usize>, // 每一项在源码中的起始位置
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum BlockItem {
     Decl(Decl),
     Stmt(Stmt),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Stmt {
-    Return(Option<Exp>),
-    Exp(Option<Exp>),
+    Return(Option<Expr>),
+    Exp(Option<Expr>),
     Block(Block),
-    Assign(LVal, Exp), // 赋值语句: LVal = Exp
-    If(Exp, Box<Stmt>, Option<Box<Stmt>>), // if语句：条件，then分支，可选else分支
-    While(Exp, Box<Stmt>), 
+    Assign(LVal, Expr), // 赋值语句: LVal = Exp
+    DerefAssign(Expr, Expr), // 通过指针赋值: *Exp = Exp, 第一个表达式为被解引用的指针
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>), // if语句：条件，then分支，可选else分支
+    While(Expr, Box<Stmt>), 
     Break,
     Continue,
 }
 
 // 全局声明（只能在编译单元级别出现）
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum GlobalDecl {
     Const(ConstDecl),     // 全局常量
     Var(GlobalVarDecl),   // 全局变量
 }
 
 // 局部声明（只能在块内出现）
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Decl {
     Const(ConstDecl),
     Var(VarDecl),
@@ -80,174 +117,296 @@ pub enum Decl {
 #[derive(Debug, Clone)]
 pub struct LVal {
     pub ident: String,
-    pub indices: Vec<Exp>, // 数组索引表达式列表，空表示普通变量
+    pub indices: Vec<Expr>, // 数组索引表达式列表，空表示普通变量
+    pub pos: usize, // 标识符在源码中的位置
 }
 
 // region 常量声明
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConstDecl {
-    pub b_type: String,
+    pub storage: StorageClass, // 局部常量恒为 Default
+    pub b_type: BType,
     pub const_def_list: Vec<ConstDef>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConstDef {
     pub ident: String,
     pub dimensions: Vec<ConstExp>, // 数组维度，空表示普通常量
     pub const_init_val: ConstInitVal,
+    pub pos: usize, // 常量名在源码中的位置
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ConstInitVal {
     Exp(ConstExp),           // 单个常量表达式
     List(Vec<ConstInitVal>), // 数组初始化列表
 }
 
-#[derive(Debug)]
-pub struct ConstExp {
-    pub lor_exp: LOrExp,
-}
-
 // endregion 常量声明
 
 // region 变量声明
 
 // 局部变量声明
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VarDecl {
-    pub b_type: String,
+    pub b_type: BType,
     pub var_def_list: Vec<VarDef>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VarDef {
+    pub pointer: usize, // 声明符中'*'的个数, 0表示不是指针
     pub ident: String,
     pub dimensions: Vec<ConstExp>, // 数组维度，空表示普通变量
     pub init_val: Option<InitVal>, // 局部变量可以没有初始化值
+    pub pos: usize, // 变量名在源码中的位置
 }
 
 // 全局变量声明
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GlobalVarDecl {
-    pub b_type: String,
+    pub storage: StorageClass,
+    pub b_type: BType,
     pub var_def_list: Vec<GlobalVarDef>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GlobalVarDef {
+    pub pointer: usize, // 声明符中'*'的个数, 0表示不是指针
     pub ident: String,
     pub dimensions: Vec<ConstExp>, // 数组维度，空表示普通变量
     pub init_val: Option<InitVal>, // 全局变量如果没有显式初始值，IR生成时会使用zeroinit
+    pub pos: usize, // 变量名在源码中的位置
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum InitVal {
-    Exp(Exp),           // 单个表达式
+    Exp(Expr),          // 单个表达式
     List(Vec<InitVal>), // 数组初始化列表
 }
 
 // endregion 变量声明
 
 // region 表达式
+/// 表达式统一为运算符节点, 优先级与结合性只体现在语法规则(sysy.lalrpop)中:
 /// ```text
-/// Exp (最低优先级)
-/// └── LOrExp      (逻辑或 ||)
-///     └── LAndExp  (逻辑与 &&)
-///         └── EqExp    (相等比较 == !=)
-///             └── RelExp   (关系比较 < > <= >=)
-///                 └── AddExp   (加减 + -)
-///                     └── MulExp   (乘除模 * / %)
-///                         └── UnaryExp (一元运算 + - !)
-///                             └── PrimaryExp (最高优先级)
+/// ||  (最低优先级)
+/// &&
+/// == !=
+/// < > <= >=
+/// + -
+/// * / %
+/// 一元 + - ! * &
+/// 字面量 变量 函数调用 括号 (最高优先级)
 /// ```
+/// 括号不单独成节点, 只体现在树的结构与 span 中
 #[derive(Debug, Clone)]
-pub enum Exp {
-    LOr(Box<LOrExp>),
-}
-
-#[derive(Debug, Clone)]
-pub enum RelExp {
-    Add(Box<AddExp>),
-    Rel(Box<RelExp>, RelOp, Box<AddExp>),
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone)]
-pub enum EqExp {
-    Rel(Box<RelExp>), 
-    Eq(Box<EqExp>, EqOp, Box<RelExp>),
+pub enum ExprKind {
+    Number(i32),
+    Float(f32),   // 浮点字面量
+    Str(Vec<u8>), // 字符串字面量(转义后的字节, 不含结尾的'\0'), 只能作为 putf 的实参
+    LVal(LVal),   // 变量/常量/数组元素
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>), // 函数调用(函数名, 实参列表)
 }
 
-#[derive(Debug, Clone)]
-pub enum LAndExp {
-    Eq(Box<EqExp>),
-    LAnd(Box<LAndExp>, Box<EqExp>), // 不需要op，因为只有&&
+/// 源码区间: (预处理后的)源码中的字节偏移, 左闭右开
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
-#[derive(Debug, Clone)]
-pub enum LOrExp {
-    LAnd(Box<LAndExp>),
-    LOr(Box<LOrExp>, Box<LAndExp>), // 不需要op，因为||
-}
+impl Expr {
+    pub fn new(kind: ExprKind, start: usize, end: usize) -> Self {
+        Self { kind, span: Span { start, end } }
+    }
 
-#[derive(Debug, Clone)]
-pub enum AddExp {
-    Mul(Box<MulExp>),
-    AddMul(Box<AddExp>, PlusSubOp, Box<MulExp>),
-}
+    /// 二元表达式, 区间从左操作数开始到右操作数结束
+    pub fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Self {
+        let (start, end) = (lhs.span.start, rhs.span.end);
+        Self::new(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), start, end)
+    }
 
-#[derive(Debug, Clone)]
-pub enum MulExp {
-    Unary(Box<UnaryExp>),
-    MulDiv(Box<MulExp>, MulDivOp, Box<UnaryExp>),
+    /// 一元表达式, start 为运算符的位置
+    pub fn unary(op: UnaryOp, operand: Expr, start: usize) -> Self {
+        let end = operand.span.end;
+        Self::new(ExprKind::Unary(op, Box::new(operand)), start, end)
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum UnaryExp {
-    Primary(PrimaryExp),
-    Unary(UnaryOp, Box<UnaryExp>),
-    FuncCall(String, Option<FuncRParams>), // 函数调用(函数名, 可选参数列表)
-}
+/// 常量表达式: 语法上与普通表达式相同, 在IR生成时求值
+pub type ConstExp = Expr;
 
-#[derive(Debug, Clone)]
-pub enum PrimaryExp {
-    Number(i32),
-    Paren(Box<Exp>),
-    LVal(LVal), // LVal ::= IDENT,表示对一个标识符(常量名)的引用
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnaryOp {
     Plus,   // +
     Minus,  // -
     Not,    // !
+    Deref,  // *
+    AddrOf, // &
 }
 
-#[derive(Debug, Clone)]
-pub enum PlusSubOp {
-    Plus,  // +
-    Minus, // -
-}
-
-#[derive(Debug, Clone)]
-pub enum MulDivOp {
-    Mul, // *
-    Div, // /
-    Mod, // %
-}
-
-#[derive(Debug, Clone)]
-pub enum RelOp {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Or,  // ||
+    And, // &&
+    Eq,  // ==
+    Ne,  // !=
     Lt,  // <
     Gt,  // >
     Le,  // <=
     Ge,  // >=
+    Add, // +
+    Sub, // -
+    Mul, // *
+    Div, // /
+    Mod, // %
 }
 
-#[derive(Debug, Clone)]
-pub enum EqOp {
-    Eq,  // ==
-    Ne,  // !=
+impl BinaryOp {
+    /// 优先级, 数值越大结合越紧; 所有二元运算符都是左结合的
+    pub fn precedence(self) -> u8 {
+        match self {
+            Self::Or => 1,
+            Self::And => 2,
+            Self::Eq | Self::Ne => 3,
+            Self::Lt | Self::Gt | Self::Le | Self::Ge => 4,
+            Self::Add | Self::Sub => 5,
+            Self::Mul | Self::Div | Self::Mod => 6,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Or => "||",
+            Self::And => "&&",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Gt => ">",
+            Self::Le => "<=",
+            Self::Ge => ">=",
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+        }
+    }
 }
 
 // endregion 表达式
+
+// region 字面量
+
+/// 解析浮点字面量(十进制与十六进制两种形式)
+/// ```text
+/// 1.5  .5  1.  1e3  1.5E-2
+/// 0x1.8p1  0X.8P-1  0x1p10
+/// ```
+/// 无法解析或超出 float 范围时返回错误信息
+pub fn parse_float_literal(text: &str) -> Result<f32, &'static str> {
+    let value = if text.starts_with("0x") || text.starts_with("0X") {
+        parse_hex_float(&text[2..])?
+    } else {
+        text.parse::<f32>().map_err(|_| "invalid floating constant")?
+    };
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err("floating constant out of range")
+    }
+}
+
+// 十六进制浮点数: 尾数为十六进制, 指数(p)以2为底
+fn parse_hex_float(text: &str) -> Result<f32, &'static str> {
+    const INVALID: &str = "invalid floating constant";
+    let (mantissa, exponent) = text.split_once(['p', 'P']).ok_or(INVALID)?;
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+    let mut value = 0f64;
+    for digit in int_part.chars() {
+        value = value * 16.0 + digit.to_digit(16).ok_or(INVALID)? as f64;
+    }
+    let mut scale = 1.0 / 16.0;
+    for digit in frac_part.chars() {
+        value += digit.to_digit(16).ok_or(INVALID)? as f64 * scale;
+        scale /= 16.0;
+    }
+
+    // 指数超出 i32 时尾数非零的值必然溢出或下溢
+    let exponent: i32 = match exponent.parse() {
+        Ok(exponent) => exponent,
+        Err(_) if value == 0.0 => 0,
+        Err(_) if exponent.starts_with('-') => return Ok(0.0),
+        Err(_) => return Err("floating constant out of range"),
+    };
+    Ok((value * 2f64.powi(exponent)) as f32)
+}
+
+/// 解析字符串字面量(含两侧引号), 处理转义序列
+/// ```text
+/// "a\tb\n"  "\x41\101"  "\"quoted\""
+/// ```
+pub fn parse_string_literal(text: &str) -> Vec<u8> {
+    let bytes = &text.as_bytes()[1..text.len() - 1];
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'\\' {
+            result.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        // 转义序列
+        i += 1;
+        let escape = bytes[i];
+        i += 1;
+        let byte = match escape {
+            b'n' => b'\n',
+            b't' => b'\t',
+            b'r' => b'\r',
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'v' => 0x0b,
+            // \xHH: 十六进制
+            b'x' => {
+                let mut value = 0u32;
+                while i < bytes.len() && bytes[i].is_ascii_hexdigit() {
+                    value = value * 16 + (bytes[i] as char).to_digit(16).unwrap();
+                    i += 1;
+                }
+                value as u8
+            }
+            // \ooo: 至多三位八进制
+            b'0'..=b'7' => {
+                let mut value = (escape - b'0') as u32;
+                let mut digits = 1;
+                while digits < 3 && i < bytes.len() && (b'0'..=b'7').contains(&bytes[i]) {
+                    value = value * 8 + (bytes[i] - b'0') as u32;
+                    i += 1;
+                    digits += 1;
+                }
+                value as u8
+            }
+            // \\ \" \' \? 以及其他字符原样保留
+            other => other,
+        };
+        result.push(byte);
+    }
+    result
+}
+
+// endregion 字面量
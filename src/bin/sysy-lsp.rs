@@ -0,0 +1,11 @@
+//! SysY 语言服务器, 由编辑器启动, 通过标准输入输出通信
+use koopa::ir::Type;
+use pku_compiler::lab9::lsp::Server;
+use std::io::{stdin, stdout};
+
+fn main() -> std::io::Result<()> {
+    Type::set_ptr_size(4);
+    let shutdown = Server::new(stdout().lock()).run(stdin().lock())?;
+    // 协议规定: 收到 exit 前没有 shutdown 时以1退出
+    std::process::exit(if shutdown { 0 } else { 1 });
+}
@@ -0,0 +1,276 @@
+//! 将完整 SysY 的 AST(crate::ast) 降级为 lab8 的 AST, 以便用 lab8 的 irgen 编译同一份源码
+//!
+//! lab8 只支持 int 标量、函数、if/while 与 getint/getch/putint/putch,
+//! 超出这个子集的程序(数组、浮点、指针、字符串、static/extern、函数原型等)返回 Err
+use crate::ast;
+use crate::lab8::ast::*;
+use crate::lab8::irgen::IRGen;
+use koopa::ir::Program;
+use std::collections::HashSet;
+
+/// lab8 中可以用 int 实参调用的库函数
+const LIBRARY_FUNCTIONS: [&str; 4] = ["getint", "getch", "putint", "putch"];
+
+/// 用 lab8 的 irgen 编译完整 AST, 程序超出 lab8 子集时返回 Err
+pub fn generate_koopa_ir(comp_unit: &ast::CompUnit) -> Result<Program, String> {
+    IRGen::new().generate_koopa_ir(convert_comp_unit(comp_unit)?)
+}
+
+fn convert_comp_unit(comp_unit: &ast::CompUnit) -> Result<CompUnit, String> {
+    let mut functions: HashSet<&str> = LIBRARY_FUNCTIONS.into_iter().collect();
+    for item in &comp_unit.items {
+        if let ast::CompUnitItem::FuncDef(func_def) = item {
+            functions.insert(&func_def.id);
+        }
+    }
+    let converter = Converter { functions };
+
+    let mut items = Vec::new();
+    for item in &comp_unit.items {
+        items.push(match item {
+            ast::CompUnitItem::FuncDef(func_def) => CompUnitItem::FuncDef(converter.func_def(func_def)?),
+            ast::CompUnitItem::FuncDecl(func_decl) => {
+                return Err(format!("function prototype '{}' is not supported", func_decl.id));
+            }
+            ast::CompUnitItem::GlobalDecl(ast::GlobalDecl::Const(decl)) => {
+                CompUnitItem::GlobalDecl(GlobalDecl::Const(converter.const_decl(decl)?))
+            }
+            ast::CompUnitItem::GlobalDecl(ast::GlobalDecl::Var(decl)) => {
+                if decl.storage != ast::StorageClass::Default {
+                    return Err("static/extern variables are not supported".to_string());
+                }
+                check_b_type(decl.b_type)?;
+                let mut var_def_list = Vec::new();
+                for def in &decl.var_def_list {
+                    check_scalar(&def.ident, def.pointer, def.dimensions.len())?;
+                    var_def_list.push(GlobalVarDef {
+                        ident: def.ident.clone(),
+                        init_val: def.init_val.as_ref().map(|init| converter.init_val(init)).transpose()?,
+                    });
+                }
+                CompUnitItem::GlobalDecl(GlobalDecl::Var(GlobalVarDecl { b_type: "int".to_string(), var_def_list }))
+            }
+        });
+    }
+    Ok(CompUnit { items })
+}
+
+fn check_b_type(b_type: ast::BType) -> Result<(), String> {
+    match b_type {
+        ast::BType::Int => Ok(()),
+        ast::BType::Float => Err("float is not supported".to_string()),
+    }
+}
+
+fn check_scalar(ident: &str, pointer: usize, dimensions: usize) -> Result<(), String> {
+    if pointer > 0 || dimensions > 0 {
+        return Err(format!("'{}' is not an int scalar", ident));
+    }
+    Ok(())
+}
+
+struct Converter<'a> {
+    functions: HashSet<&'a str>, // 可以调用的函数
+}
+
+impl Converter<'_> {
+    fn func_def(&self, func_def: &ast::FuncDef) -> Result<FuncDef, String> {
+        if func_def.storage != ast::StorageClass::Default {
+            return Err(format!("static function '{}' is not supported", func_def.id));
+        }
+        let func_type = match func_def.func_type {
+            ast::FuncType::Int => FuncType::Int,
+            ast::FuncType::Void => FuncType::Void,
+            ast::FuncType::Float => return Err("float is not supported".to_string()),
+        };
+        let params = match &func_def.params {
+            Some(params) => {
+                let mut converted = Vec::new();
+                for param in &params.params {
+                    check_b_type(param.b_type)?;
+                    check_scalar(&param.ident, param.pointer, param.dimensions.len())?;
+                    converted.push(FuncFParam { b_type: "int".to_string(), ident: param.ident.clone() });
+                }
+                Some(FuncFParams { params: converted })
+            }
+            None => None,
+        };
+        Ok(FuncDef {
+            func_type,
+            id: func_def.id.clone(),
+            params,
+            block: self.block(&func_def.block)?,
+        })
+    }
+
+    fn block(&self, block: &ast::Block) -> Result<Block, String> {
+        let mut block_item_list = Vec::new();
+        for item in &block.block_item_list {
+            block_item_list.push(match item {
+                ast::BlockItem::Decl(ast::Decl::Const(decl)) => BlockItem::Decl(Decl::Const(self.const_decl(decl)?)),
+                ast::BlockItem::Decl(ast::Decl::Var(decl)) => {
+                    check_b_type(decl.b_type)?;
+                    let mut var_def_list = Vec::new();
+                    for def in &decl.var_def_list {
+                        check_scalar(&def.ident, def.pointer, def.dimensions.len())?;
+                        var_def_list.push(VarDef {
+                            ident: def.ident.clone(),
+                            init_val: def.init_val.as_ref().map(|init| self.init_val(init)).transpose()?,
+                        });
+                    }
+                    BlockItem::Decl(Decl::Var(VarDecl { b_type: "int".to_string(), var_def_list }))
+                }
+                ast::BlockItem::Stmt(stmt) => BlockItem::Stmt(self.stmt(stmt)?),
+            });
+        }
+        Ok(Block { block_item_list })
+    }
+
+    fn stmt(&self, stmt: &ast::Stmt) -> Result<Stmt, String> {
+        Ok(match stmt {
+            ast::Stmt::Return(exp) => Stmt::Return(exp.as_ref().map(|exp| self.exp(exp)).transpose()?),
+            ast::Stmt::Exp(exp) => Stmt::Exp(exp.as_ref().map(|exp| self.exp(exp)).transpose()?),
+            ast::Stmt::Block(block) => Stmt::Block(self.block(block)?),
+            ast::Stmt::Assign(lval, exp) => Stmt::Assign(self.lval(lval)?, self.exp(exp)?),
+            ast::Stmt::DerefAssign(..) => return Err("pointers are not supported".to_string()),
+            ast::Stmt::If(cond, then_stmt, else_stmt) => Stmt::If(
+                self.exp(cond)?,
+                Box::new(self.stmt(then_stmt)?),
+                else_stmt.as_ref().map(|stmt| self.stmt(stmt).map(Box::new)).transpose()?,
+            ),
+            ast::Stmt::While(cond, body) => Stmt::While(self.exp(cond)?, Box::new(self.stmt(body)?)),
+            ast::Stmt::Break => Stmt::Break,
+            ast::Stmt::Continue => Stmt::Continue,
+        })
+    }
+
+    fn const_decl(&self, decl: &ast::ConstDecl) -> Result<ConstDecl, String> {
+        if decl.storage != ast::StorageClass::Default {
+            return Err("static/extern constants are not supported".to_string());
+        }
+        check_b_type(decl.b_type)?;
+        let mut const_def_list = Vec::new();
+        for def in &decl.const_def_list {
+            check_scalar(&def.ident, 0, def.dimensions.len())?;
+            let ast::ConstInitVal::Exp(exp) = &def.const_init_val else {
+                return Err(format!("'{}' is not an int scalar", def.ident));
+            };
+            const_def_list.push(ConstDef {
+                ident: def.ident.clone(),
+                const_init_val: ConstInitVal { const_exp: ConstExp { lor_exp: self.lor_exp(exp)? } },
+            });
+        }
+        Ok(ConstDecl { b_type: "int".to_string(), const_def_list })
+    }
+
+    fn init_val(&self, init_val: &ast::InitVal) -> Result<InitVal, String> {
+        match init_val {
+            ast::InitVal::Exp(exp) => Ok(InitVal { exp: self.exp(exp)? }),
+            ast::InitVal::List(_) => Err("initializer lists are not supported".to_string()),
+        }
+    }
+
+    fn lval(&self, lval: &ast::LVal) -> Result<LVal, String> {
+        check_scalar(&lval.ident, 0, lval.indices.len())?;
+        Ok(LVal { ident: lval.ident.clone() })
+    }
+
+    // region 表达式
+    // 按优先级逐层重建分层的表达式树, 某一层放不下的子表达式最终在 PrimaryExp 中加上括号
+
+    fn exp(&self, exp: &ast::Expr) -> Result<Exp, String> {
+        Ok(Exp::LOr(Box::new(self.lor_exp(exp)?)))
+    }
+
+    fn lor_exp(&self, exp: &ast::Expr) -> Result<LOrExp, String> {
+        Ok(match &exp.kind {
+            ast::ExprKind::Binary(ast::BinaryOp::Or, lhs, rhs) => {
+                LOrExp::LOr(Box::new(self.lor_exp(lhs)?), Box::new(self.land_exp(rhs)?))
+            }
+            _ => LOrExp::LAnd(Box::new(self.land_exp(exp)?)),
+        })
+    }
+
+    fn land_exp(&self, exp: &ast::Expr) -> Result<LAndExp, String> {
+        Ok(match &exp.kind {
+            ast::ExprKind::Binary(ast::BinaryOp::And, lhs, rhs) => {
+                LAndExp::LAnd(Box::new(self.land_exp(lhs)?), Box::new(self.eq_exp(rhs)?))
+            }
+            _ => LAndExp::Eq(Box::new(self.eq_exp(exp)?)),
+        })
+    }
+
+    fn eq_exp(&self, exp: &ast::Expr) -> Result<EqExp, String> {
+        let op = match &exp.kind {
+            ast::ExprKind::Binary(ast::BinaryOp::Eq, ..) => EqOp::Eq,
+            ast::ExprKind::Binary(ast::BinaryOp::Ne, ..) => EqOp::Ne,
+            _ => return Ok(EqExp::Rel(Box::new(self.rel_exp(exp)?))),
+        };
+        let ast::ExprKind::Binary(_, lhs, rhs) = &exp.kind else { unreachable!() };
+        Ok(EqExp::Eq(Box::new(self.eq_exp(lhs)?), op, Box::new(self.rel_exp(rhs)?)))
+    }
+
+    fn rel_exp(&self, exp: &ast::Expr) -> Result<RelExp, String> {
+        let op = match &exp.kind {
+            ast::ExprKind::Binary(ast::BinaryOp::Lt, ..) => RelOp::Lt,
+            ast::ExprKind::Binary(ast::BinaryOp::Gt, ..) => RelOp::Gt,
+            ast::ExprKind::Binary(ast::BinaryOp::Le, ..) => RelOp::Le,
+            ast::ExprKind::Binary(ast::BinaryOp::Ge, ..) => RelOp::Ge,
+            _ => return Ok(RelExp::Add(Box::new(self.add_exp(exp)?))),
+        };
+        let ast::ExprKind::Binary(_, lhs, rhs) = &exp.kind else { unreachable!() };
+        Ok(RelExp::Rel(Box::new(self.rel_exp(lhs)?), op, Box::new(self.add_exp(rhs)?)))
+    }
+
+    fn add_exp(&self, exp: &ast::Expr) -> Result<AddExp, String> {
+        let op = match &exp.kind {
+            ast::ExprKind::Binary(ast::BinaryOp::Add, ..) => PlusSubOp::Plus,
+            ast::ExprKind::Binary(ast::BinaryOp::Sub, ..) => PlusSubOp::Minus,
+            _ => return Ok(AddExp::Mul(Box::new(self.mul_exp(exp)?))),
+        };
+        let ast::ExprKind::Binary(_, lhs, rhs) = &exp.kind else { unreachable!() };
+        Ok(AddExp::AddMul(Box::new(self.add_exp(lhs)?), op, Box::new(self.mul_exp(rhs)?)))
+    }
+
+    fn mul_exp(&self, exp: &ast::Expr) -> Result<MulExp, String> {
+        let op = match &exp.kind {
+            ast::ExprKind::Binary(ast::BinaryOp::Mul, ..) => MulDivOp::Mul,
+            ast::ExprKind::Binary(ast::BinaryOp::Div, ..) => MulDivOp::Div,
+            ast::ExprKind::Binary(ast::BinaryOp::Mod, ..) => MulDivOp::Mod,
+            _ => return Ok(MulExp::Unary(Box::new(self.unary_exp(exp)?))),
+        };
+        let ast::ExprKind::Binary(_, lhs, rhs) = &exp.kind else { unreachable!() };
+        Ok(MulExp::MulDiv(Box::new(self.mul_exp(lhs)?), op, Box::new(self.unary_exp(rhs)?)))
+    }
+
+    fn unary_exp(&self, exp: &ast::Expr) -> Result<UnaryExp, String> {
+        Ok(match &exp.kind {
+            ast::ExprKind::Number(n) => UnaryExp::Primary(PrimaryExp::Number(*n)),
+            ast::ExprKind::LVal(lval) => UnaryExp::Primary(PrimaryExp::LVal(self.lval(lval)?)),
+            ast::ExprKind::Unary(op, operand) => {
+                let op = match op {
+                    ast::UnaryOp::Plus => UnaryOp::Plus,
+                    ast::UnaryOp::Minus => UnaryOp::Minus,
+                    ast::UnaryOp::Not => UnaryOp::Not,
+                    ast::UnaryOp::Deref | ast::UnaryOp::AddrOf => return Err("pointers are not supported".to_string()),
+                };
+                UnaryExp::Unary(op, Box::new(self.unary_exp(operand)?))
+            }
+            ast::ExprKind::Call(name, args) => {
+                if !self.functions.contains(name.as_str()) {
+                    return Err(format!("call to '{}' is not supported", name));
+                }
+                let params = match args.is_empty() {
+                    true => None,
+                    false => Some(FuncRParams { params: args.iter().map(|arg| self.exp(arg)).collect::<Result<_, _>>()? }),
+                };
+                UnaryExp::FuncCall(name.clone(), params)
+            }
+            ast::ExprKind::Binary(..) => UnaryExp::Primary(PrimaryExp::Paren(Box::new(self.exp(exp)?))),
+            ast::ExprKind::Float(_) => return Err("float is not supported".to_string()),
+            ast::ExprKind::Str(_) => return Err("strings are not supported".to_string()),
+        })
+    }
+
+    // endregion 表达式
+}
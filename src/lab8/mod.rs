@@ -2,4 +2,5 @@
 /// 防止单文件代码过多
 pub mod irgen;
 pub mod codegen;
+pub mod convert;
 mod ast;
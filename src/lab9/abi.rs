@@ -0,0 +1,354 @@
+//! irgen 与 codegen 之间关于 Koopa IR 无法直接表达的特性的约定
+//!
+//! Koopa IR 只有 i32 一种标量类型, 也没有变参函数, 因此:
+//! - 浮点值在 IR 中以 i32 位模式(IEEE754 单精度)表示
+//! - 浮点运算/比较/类型转换用对内建函数(`@__sysy_fadd` 等)的调用表示, 由 codegen 内联为 RV32F 指令
+//! - 函数的浮点形参/返回值信息无法写进 Koopa 函数类型, 由 [`FloatAbi`] 这张附加表传递给 codegen
+//!   输出IR文本时这张表以空函数声明 `@__sysy_fsig_<函数名>_<签名串>` 的形式写入, 读入文本时据此恢复(见 [`FloatSig::marker_name`])
+//! - 字符串字面量是名为 `@__sysy_str_N` 的全局 `[i32, len]` 数组(每个元素一个字节), codegen 将其输出为 `.asciz`
+//! - 局部常量数组的初始值放在名为 `@__sysy_const_N` 的全局数组中, 由局部数组从中复制; codegen 将其输出到 `.rodata`
+//! - static 函数/全局变量改名为 `@__static_<单元编号>_<名字>`, codegen 不为其生成 `.global`
+//! - 变参调用按实参类型改名为 `@__sysy_va_<函数名>_<类型串>` 的定长函数(见 [`VariadicCall`])
+//! - 指针与整数之间的转换(指针比较/相减, 空指针)用对 `@__sysy_ptrtoint_<类型>` / `@__sysy_inttoptr_<类型>` 的调用表示,
+//!   codegen 将其实现为寄存器移动(见 [`pointer_cast_name`])
+use koopa::ir::{Type, TypeKind};
+use std::collections::HashMap;
+
+/// 浮点内建函数, 在IR中表现为 `call @__sysy_xxx(...)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FloatIntrinsic {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    IntToFloat, // int -> float
+    FloatToInt, // float -> int (向零取整)
+}
+
+impl FloatIntrinsic {
+    pub const ALL: [FloatIntrinsic; 13] = [
+        Self::Add, Self::Sub, Self::Mul, Self::Div, Self::Neg,
+        Self::Eq, Self::Ne, Self::Lt, Self::Le, Self::Gt, Self::Ge,
+        Self::IntToFloat, Self::FloatToInt,
+    ];
+
+    /// 内建函数名(不含@)
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Add => "__sysy_fadd",
+            Self::Sub => "__sysy_fsub",
+            Self::Mul => "__sysy_fmul",
+            Self::Div => "__sysy_fdiv",
+            Self::Neg => "__sysy_fneg",
+            Self::Eq => "__sysy_feq",
+            Self::Ne => "__sysy_fne",
+            Self::Lt => "__sysy_flt",
+            Self::Le => "__sysy_fle",
+            Self::Gt => "__sysy_fgt",
+            Self::Ge => "__sysy_fge",
+            Self::IntToFloat => "__sysy_itof",
+            Self::FloatToInt => "__sysy_ftoi",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        Self::ALL.into_iter().find(|intrinsic| intrinsic.name() == name)
+    }
+
+    /// 参数个数
+    pub fn arity(self) -> usize {
+        match self {
+            Self::Neg | Self::IntToFloat | Self::FloatToInt => 1,
+            _ => 2,
+        }
+    }
+
+    /// 结果是否为浮点数(比较与 ftoi 的结果是 int)
+    pub fn returns_float(self) -> bool {
+        matches!(self, Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Neg | Self::IntToFloat)
+    }
+}
+
+/// 单个函数的浮点签名: 哪些形参/返回值是 float 标量
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FloatSig {
+    pub params: Vec<bool>,
+    pub ret: bool,
+}
+
+/// 参数在调用约定(ilp32f)中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgLocation {
+    IntReg(usize),   // a0-a7
+    FloatReg(usize), // fa0-fa7
+    Stack(usize),    // 第n个栈上参数, 位于调用者 sp + 4n
+    IntRegPair(usize), // 变参中的double: 低32位在a{n}, 高32位在a{n+1}
+    StackPair(usize),  // 变参中的double: 占用第n, n+1个栈上参数位置
+}
+
+impl FloatSig {
+    /// 按 ilp32f 规则为前 `count` 个参数分配位置:
+    /// 整数参数用 a0-a7, 浮点参数用 fa0-fa7, fa 用尽后浮点参数改用剩余 a 寄存器, 都用尽后放栈上
+    pub fn arg_locations(&self, count: usize) -> Vec<ArgLocation> {
+        let mut next_int = 0;
+        let mut next_float = 0;
+        let mut next_stack = 0;
+        let mut locations = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let is_float = self.params.get(i).copied().unwrap_or(false);
+            let location = if is_float && next_float < 8 {
+                next_float += 1;
+                ArgLocation::FloatReg(next_float - 1)
+            } else if next_int < 8 {
+                next_int += 1;
+                ArgLocation::IntReg(next_int - 1)
+            } else {
+                next_stack += 1;
+                ArgLocation::Stack(next_stack - 1)
+            };
+            locations.push(location);
+        }
+
+        locations
+    }
+}
+
+/// 程序中所有含浮点形参/返回值的函数签名(函数名不含@)
+/// 没有记录的函数视为纯整数签名
+#[derive(Debug, Clone, Default)]
+pub struct FloatAbi {
+    sigs: HashMap<String, FloatSig>,
+}
+
+impl FloatAbi {
+    /// 只包含 SysY 运行时库中浮点函数的签名
+    pub fn sysy_library() -> Self {
+        let mut abi = Self::default();
+        abi.insert("getfloat", FloatSig { params: vec![], ret: true });
+        abi.insert("putfloat", FloatSig { params: vec![true], ret: false });
+        abi
+    }
+
+    pub fn insert(&mut self, name: &str, sig: FloatSig) {
+        if sig.ret || sig.params.iter().any(|&p| p) {
+            self.sigs.insert(name.to_string(), sig);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> FloatSig {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        self.sigs.get(name).cloned().unwrap_or_default()
+    }
+
+    /// 运行时库之外的浮点签名在IR文本中的标记函数名(不含@), 按函数名排序
+    pub fn marker_names(&self) -> Vec<String> {
+        let library = Self::sysy_library();
+        let mut names: Vec<String> = self.sigs.iter()
+            .filter(|&(name, sig)| library.get(name) != *sig)
+            .map(|(name, sig)| sig.marker_name(name))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// 若函数名是浮点签名的标记, 记录其中的签名并返回 true
+    pub fn insert_marker(&mut self, name: &str) -> bool {
+        match FloatSig::from_marker_name(name) {
+            Some((function, sig)) => {
+                self.insert(function, sig);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl FloatSig {
+    /// 标记函数名 `__sysy_fsig_<函数名>_<签名串>`, 签名串依次为返回值与各形参, f 为 float, i 为其他
+    pub fn marker_name(&self, function: &str) -> String {
+        let kinds: String = std::iter::once(self.ret).chain(self.params.iter().copied())
+            .map(|is_float| if is_float { 'f' } else { 'i' })
+            .collect();
+        format!("{}{}_{}", FLOAT_SIG_PREFIX, function, kinds)
+    }
+
+    pub fn from_marker_name(name: &str) -> Option<(&str, Self)> {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        let (function, kinds) = name.strip_prefix(FLOAT_SIG_PREFIX)?.rsplit_once('_')?;
+        let mut kinds = kinds.chars().map(|kind| match kind {
+            'f' => Some(true),
+            'i' => Some(false),
+            _ => None,
+        });
+        let ret = kinds.next()??;
+        let params = kinds.collect::<Option<Vec<_>>>()?;
+        Some((function, Self { params, ret }))
+    }
+}
+
+/// 浮点签名标记的名字前缀(不含@)
+pub const FLOAT_SIG_PREFIX: &str = "__sysy_fsig_";
+
+/// 字符串字面量全局数组的名字前缀(不含@)
+pub const STRING_PREFIX: &str = "__sysy_str_";
+
+/// 全局变量是否为字符串字面量
+pub fn is_string_literal(name: &str) -> bool {
+    name.strip_prefix('@').unwrap_or(name).starts_with(STRING_PREFIX)
+}
+
+/// 局部常量数组的只读镜像的名字前缀(不含@)
+pub const CONST_IMAGE_PREFIX: &str = "__sysy_const_";
+
+/// 全局变量是否为局部常量数组的只读镜像
+pub fn is_const_image(name: &str) -> bool {
+    name.strip_prefix('@').unwrap_or(name).starts_with(CONST_IMAGE_PREFIX)
+}
+
+/// static 符号的名字前缀(不含@)
+pub const STATIC_PREFIX: &str = "__static_";
+
+/// 第 `unit` 个翻译单元中的static符号在IR中的名字(不含@)
+pub fn static_symbol_name(unit: usize, name: &str) -> String {
+    format!("{}{}_{}", STATIC_PREFIX, unit, name)
+}
+
+/// 符号是否仅在本文件内可见(static符号、字符串字面量与常量镜像), 这类符号不生成 `.global`
+pub fn is_file_local(name: &str) -> bool {
+    let name = name.strip_prefix('@').unwrap_or(name);
+    name.starts_with(STATIC_PREFIX) || is_string_literal(name) || is_const_image(name)
+}
+
+const PTR_TO_INT_PREFIX: &str = "__sysy_ptrtoint_";
+const INT_TO_PTR_PREFIX: &str = "__sysy_inttoptr_";
+
+/// 指针类型 `ty` 与 i32 互相转换的内建函数名(不含@)
+///
+/// Koopa 函数的形参类型是固定的, 因此每种指针类型各有一个转换函数, 类型编码进名字:
+/// `i32` 为 `i`, `*T` 为 `p<T>`, `[T, n]` 为 `a<n><T>`, 例如 `*[i32, 3]` 的 ptrtoint 为 `@__sysy_ptrtoint_pa3i`
+pub fn pointer_cast_name(to_int: bool, ty: &Type) -> String {
+    fn encode(ty: &Type, code: &mut String) {
+        match ty.kind() {
+            TypeKind::Int32 => code.push('i'),
+            TypeKind::Pointer(base) => {
+                code.push('p');
+                encode(base, code);
+            }
+            TypeKind::Array(base, len) => {
+                code.push_str(&format!("a{}", len));
+                encode(base, code);
+            }
+            _ => panic!("Cannot cast type '{}' to or from a pointer", ty),
+        }
+    }
+
+    let mut name = String::from(if to_int { PTR_TO_INT_PREFIX } else { INT_TO_PTR_PREFIX });
+    encode(ty, &mut name);
+    name
+}
+
+/// 函数是否为指针与整数之间的转换内建函数
+pub fn is_pointer_cast(name: &str) -> bool {
+    let name = name.strip_prefix('@').unwrap_or(name);
+    name.starts_with(PTR_TO_INT_PREFIX) || name.starts_with(INT_TO_PTR_PREFIX)
+}
+
+/// 变参库函数及其固定参数个数
+const VARIADIC_FUNCTIONS: [(&str, usize); 1] = [("putf", 1)];
+
+/// 可变参数的类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarArg {
+    Int,
+    Float,   // 按C规则提升为double传递
+    Pointer, // 字符串等
+}
+
+impl VarArg {
+    fn code(self) -> char {
+        match self {
+            Self::Int => 'i',
+            Self::Float => 'f',
+            Self::Pointer => 'p',
+        }
+    }
+
+    fn from_code(code: char) -> Option<Self> {
+        [Self::Int, Self::Float, Self::Pointer].into_iter().find(|kind| kind.code() == code)
+    }
+}
+
+/// 一次变参函数调用的实参形态
+///
+/// 同一个变参函数在不同调用处的实参类型可能不同, 因此每种形态在IR中声明为一个定长函数,
+/// 例如 `putf("%d %f", i, f)` 调用 `@__sysy_va_putf_if(*i32, i32, i32)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariadicCall {
+    pub callee: &'static str,
+    pub fixed: usize,         // 固定参数个数
+    pub var_args: Vec<VarArg>, // 可变参数的类别
+}
+
+impl VariadicCall {
+    /// 若 `callee` 是变参库函数, 返回对应调用形态
+    pub fn new(callee: &str, var_args: Vec<VarArg>) -> Option<Self> {
+        VARIADIC_FUNCTIONS.iter()
+            .find(|(name, _)| *name == callee)
+            .map(|&(callee, fixed)| Self { callee, fixed, var_args })
+    }
+
+    /// IR中使用的函数名(不含@)
+    pub fn mangled_name(&self) -> String {
+        let kinds: String = self.var_args.iter().map(|kind| kind.code()).collect();
+        format!("__sysy_va_{}_{}", self.callee, kinds)
+    }
+
+    pub fn from_mangled_name(name: &str) -> Option<Self> {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        let (callee, kinds) = name.strip_prefix("__sysy_va_")?.rsplit_once('_')?;
+        let var_args = kinds.chars().map(VarArg::from_code).collect::<Option<Vec<_>>>()?;
+        Self::new(callee, var_args)
+    }
+
+    /// 按调用约定为所有实参分配位置: 固定参数与int/指针可变参数同普通整数参数,
+    /// double可变参数占用一对偶数起始的a寄存器, 寄存器不足时放在8字节对齐的栈位置
+    pub fn arg_locations(&self) -> Vec<ArgLocation> {
+        let mut next_int = 0;
+        let mut next_stack = 0;
+        let mut locations = Vec::with_capacity(self.fixed + self.var_args.len());
+
+        let kinds = std::iter::repeat_n(VarArg::Pointer, self.fixed).chain(self.var_args.iter().copied());
+        for kind in kinds {
+            let location = if kind == VarArg::Float {
+                next_int += next_int % 2;
+                if next_int + 2 <= 8 {
+                    next_int += 2;
+                    ArgLocation::IntRegPair(next_int - 2)
+                } else {
+                    next_int = 8;
+                    next_stack += next_stack % 2;
+                    next_stack += 2;
+                    ArgLocation::StackPair(next_stack - 2)
+                }
+            } else if next_int < 8 {
+                next_int += 1;
+                ArgLocation::IntReg(next_int - 1)
+            } else {
+                next_stack += 1;
+                ArgLocation::Stack(next_stack - 1)
+            };
+            locations.push(location);
+        }
+
+        locations
+    }
+}
@@ -0,0 +1,247 @@
+//! 控制流图: 从 Koopa 函数的布局中提取基本块之间的跳转关系, 计算支配树, 并输出为 Graphviz DOT
+//!
+//! 节点标签直接取自 `-koopa` 输出的文本, 因此块名(如 `%loop_header_3`)与临时值编号和 Koopa IR 完全一致
+use koopa::ir::{BasicBlock, FunctionData, Program, ValueKind};
+use std::collections::HashMap;
+
+/// 一个函数的控制流图, 基本块按布局顺序编号, 0号为入口块
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub succs: Vec<Vec<usize>>,
+    pub preds: Vec<Vec<usize>>,
+    pub idom: Vec<Option<usize>>, // 直接支配者, 入口块与不可达块为None
+}
+
+impl Cfg {
+    pub fn new(func_data: &FunctionData) -> Self {
+        let blocks: Vec<BasicBlock> = func_data.layout().bbs().keys().copied().collect();
+        let index: HashMap<BasicBlock, usize> = blocks.iter().enumerate().map(|(i, &bb)| (bb, i)).collect();
+
+        let mut succs = vec![Vec::new(); blocks.len()];
+        let mut preds = vec![Vec::new(); blocks.len()];
+        for (i, (_, node)) in func_data.layout().bbs().iter().enumerate() {
+            let Some(&last) = node.insts().back_key() else { continue };
+            let targets = match func_data.dfg().value(last).kind() {
+                ValueKind::Jump(jump) => vec![jump.target()],
+                ValueKind::Branch(branch) => vec![branch.true_bb(), branch.false_bb()],
+                _ => vec![],
+            };
+            for target in targets {
+                succs[i].push(index[&target]);
+                preds[index[&target]].push(i);
+            }
+        }
+
+        let mut cfg = Self { blocks, succs, preds, idom: Vec::new() };
+        cfg.idom = cfg.compute_idom();
+        cfg
+    }
+
+    /// 从入口出发的逆后序, 不含不可达块
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        if self.blocks.is_empty() {
+            return order;
+        }
+        let mut visited = vec![false; self.blocks.len()];
+        // 栈中保存(块, 下一个要访问的后继下标)
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((bb, next)) = stack.pop() {
+            if let Some(&succ) = self.succs[bb].get(next) {
+                stack.push((bb, next + 1));
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                order.push(bb);
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// Cooper-Harvey-Kennedy 迭代算法
+    fn compute_idom(&self) -> Vec<Option<usize>> {
+        let order = self.reverse_postorder();
+        let mut rpo_index = vec![usize::MAX; self.blocks.len()];
+        for (i, &bb) in order.iter().enumerate() {
+            rpo_index[bb] = i;
+        }
+
+        let mut idom: Vec<Option<usize>> = vec![None; self.blocks.len()];
+        if order.is_empty() {
+            return idom;
+        }
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in order.iter().skip(1) {
+                let mut new_idom = None;
+                for &pred in &self.preds[bb] {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(current) => {
+                            // 沿支配树向上找两者的公共祖先
+                            let (mut a, mut b) = (pred, current);
+                            while a != b {
+                                while rpo_index[a] > rpo_index[b] {
+                                    a = idom[a].unwrap();
+                                }
+                                while rpo_index[b] > rpo_index[a] {
+                                    b = idom[b].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if idom[bb] != new_idom {
+                    idom[bb] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+        idom
+    }
+
+    /// 自然循环: (循环头, 各块是否属于该循环), 同一循环头的多条回边合并为一个循环
+    pub fn natural_loops(&self) -> Vec<(usize, Vec<bool>)> {
+        let n = self.blocks.len();
+        let mut loops: Vec<(usize, Vec<bool>)> = Vec::new();
+        for tail in 0..n {
+            for &header in &self.succs[tail] {
+                if !self.dominates(header, tail) {
+                    continue;
+                }
+                let index = match loops.iter().position(|(h, _)| *h == header) {
+                    Some(index) => index,
+                    None => {
+                        let mut body = vec![false; n];
+                        body[header] = true;
+                        loops.push((header, body));
+                        loops.len() - 1
+                    }
+                };
+                // 从回边的源沿前驱反向搜索, 到循环头为止(不可达的前驱不算循环的一部分)
+                let body = &mut loops[index].1;
+                let mut stack = vec![tail];
+                while let Some(bb) = stack.pop() {
+                    if !body[bb] && self.dominates(header, bb) {
+                        body[bb] = true;
+                        stack.extend(self.preds[bb].iter().copied());
+                    }
+                }
+            }
+        }
+        loops
+    }
+
+    /// a 是否支配 b (不可达块不被任何块支配)
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        if b != 0 && self.idom[b].is_none() {
+            return false;
+        }
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom[b] {
+                Some(parent) => b = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// 将程序中每个有函数体的函数输出为一个DOT图
+/// 回边(目标支配源的边)以红色粗线标出, dominators为true时用虚线叠加支配树的边
+pub fn generate_dot(program: &Program, dominators: bool) -> String {
+    let mut generator = koopa::back::KoopaGenerator::new(Vec::new());
+    generator.generate_on(program).expect("writing to memory should not fail");
+    let koopa_text = String::from_utf8(generator.writer()).unwrap();
+    let mut func_texts = split_functions(&koopa_text);
+
+    let mut dot = String::new();
+    for &func in program.func_layout() {
+        let func_data = program.func(func);
+        if func_data.layout().entry_bb().is_none() {
+            continue; // 函数声明
+        }
+        let blocks_text = func_texts.remove(func_data.name()).unwrap_or_default();
+        let cfg = Cfg::new(func_data);
+
+        dot.push_str(&format!("digraph \"{}\" {{\n", escape(func_data.name())));
+        dot.push_str("  node [shape=box, fontname=\"monospace\"];\n");
+        for i in 0..cfg.blocks.len() {
+            let label: String = blocks_text.get(i)
+                .map(|lines| lines.iter().map(|line| format!("{}\\l", escape(line))).collect())
+                .unwrap_or_default();
+            dot.push_str(&format!("  bb{} [label=\"{}\"];\n", i, label));
+        }
+        for (from, succs) in cfg.succs.iter().enumerate() {
+            for (k, &to) in succs.iter().enumerate() {
+                let mut attrs = Vec::new();
+                if succs.len() == 2 {
+                    attrs.push(if k == 0 { "label=\"T\"" } else { "label=\"F\"" });
+                }
+                if cfg.dominates(to, from) {
+                    attrs.push("color=red, penwidth=2");
+                }
+                dot.push_str(&format!("  bb{} -> bb{}{};\n", from, to, edge_attrs(&attrs)));
+            }
+        }
+        if dominators {
+            for (bb, idom) in cfg.idom.iter().enumerate() {
+                if let Some(idom) = idom {
+                    dot.push_str(&format!("  bb{} -> bb{} [style=dashed, color=gray, constraint=false];\n", idom, bb));
+                }
+            }
+        }
+        dot.push_str("}\n");
+    }
+    dot
+}
+
+/// 将Koopa IR文本按函数拆开: 函数名 -> 各基本块的文本行(首行为块名)
+fn split_functions(koopa_text: &str) -> HashMap<String, Vec<Vec<String>>> {
+    let mut funcs = HashMap::new();
+    let mut current: Option<(String, Vec<Vec<String>>)> = None;
+    for line in koopa_text.lines() {
+        if let Some(head) = line.strip_prefix("fun ") {
+            let name = head.split('(').next().unwrap_or_default().to_string();
+            current = Some((name, Vec::new()));
+        } else if line == "}" {
+            if let Some((name, blocks)) = current.take() {
+                funcs.insert(name, blocks);
+            }
+        } else if let Some((_, blocks)) = current.as_mut() {
+            if line.starts_with('%') {
+                blocks.push(vec![line.to_string()]);
+            } else if let Some(block) = blocks.last_mut() {
+                if !line.is_empty() {
+                    block.push(line.to_string());
+                }
+            }
+        }
+    }
+    funcs
+}
+
+fn edge_attrs(attrs: &[&str]) -> String {
+    if attrs.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", attrs.join(", "))
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -1,7 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use koopa::ir::{BinaryOp, FunctionData, Program, Value, ValueKind, BasicBlock, Type, TypeKind};
 use koopa::ir::dfg::DataFlowGraph;
 use koopa::ir::entities::ValueData;
+use crate::lab9::abi::{is_const_image, is_file_local, is_pointer_cast, is_string_literal, ArgLocation, FloatAbi, FloatIntrinsic, FloatSig, VariadicCall};
+use crate::lab9::irgen::IrModule;
+use crate::lab9::preprocess::LineMap;
+use crate::lab9::codegen::dwarf::DebugInfo;
+use crate::lab9::elf::assemble_object;
+use crate::lab9::profile::Profile;
+use crate::lab9::tailcall::tail_calls;
+
+mod dwarf;
+mod layout;
+
+/// 基本块标签的前缀, 后接基本块的编号
+pub const BLOCK_LABEL_PREFIX: &str = "LBB";
 
 // 计算类型的大小（字节数）
 fn calculate_type_size(ty: &Type) -> usize {
@@ -15,19 +28,38 @@ fn calculate_type_size(ty: &Type) -> usize {
     }
 }
 
-pub fn generate_riscv_assembly(program: Program) -> String {
+pub fn generate_riscv_assembly(module: IrModule) -> String {
+    generate(module, &[])
+}
+
+/// 生成带调试信息的汇编(-g), line_maps 为各翻译单元的行号映射
+/// IR没有源码映射(见 IRGen::with_debug_info)时与 generate_riscv_assembly 相同
+pub fn generate_riscv_assembly_with_debug_info(module: IrModule, line_maps: &[LineMap]) -> String {
+    generate(module, line_maps)
+}
+
+/// 生成RV32可重定位目标文件(.o), 汇编不依赖外部汇编器(见 lab9::elf)
+pub fn generate_riscv_object(module: IrModule) -> Result<Vec<u8>, String> {
+    assemble_object(&generate(module, &[]))
+}
+
+fn generate(module: IrModule, line_maps: &[LineMap]) -> String {
+    let IrModule { program, float_abi, extern_globals, source_map, profile, .. } = module;
+    let mut debug_info = source_map.as_ref()
+        .filter(|_| !line_maps.is_empty())
+        .map(|source_map| DebugInfo::new(source_map, line_maps));
     let mut asm = String::new();
     
     // 1. 生成数据段（全局变量）
-    let data_section = generate_data_section(&program);
+    let data_section = generate_data_section(&program, &extern_globals);
     if !data_section.is_empty() {
         asm.push_str(&data_section);
         asm.push_str("\n");
     }
     
     // 2. 生成代码段
-    asm.push_str(".text\n");
-    
+    let mut text = String::new();
+    let mut uses_float_to_double = false;
     for &func_handle in program.func_layout() {
         let func_data = program.func(func_handle);
         
@@ -38,26 +70,107 @@ pub fn generate_riscv_assembly(program: Program) -> String {
         
         let func_name = func_data.name().strip_prefix('@').unwrap_or(func_data.name());
 
-        asm.push_str(&format!(".global {}\n", func_name));
-        asm.push_str(&format!("{}:\n", func_name));
+        // static函数只在本文件可见
+        if !is_file_local(func_name) {
+            text.push_str(&format!(".global {}\n", func_name));
+        }
+        text.push_str(&format!("{}:\n", func_name));
+        if let Some(debug_info) = &mut debug_info {
+            text.push_str(&debug_info.begin_function(func_handle, func_name));
+        }
 
         // 生成函数体汇编
-        let mut generator = AsmGenerator::new(&program);
-        asm.push_str(&generator.gen_function(func_data));
+        let mut generator = AsmGenerator::new(&program, &float_abi, float_abi.get(func_data.name()), profile.as_ref());
+        text.push_str(&generator.gen_function(func_data, debug_info.as_mut()));
+        uses_float_to_double |= generator.uses_float_to_double;
+        if let Some(debug_info) = &mut debug_info {
+            text.push_str(&debug_info.end_function(func_data, &generator.value_stack_map));
+        }
+    }
+
+    // 文件表要出现在所有 .loc 之前, 生成完各函数后才知道用到了哪些文件
+    asm.push_str(".text\n");
+    if let Some(debug_info) = &debug_info {
+        asm.push_str(&debug_info.text_begin());
+    }
+    asm.push_str(&text);
+    if uses_float_to_double {
+        asm.push_str(FLOAT_TO_DOUBLE);
+    }
+    if let Some(debug_info) = &debug_info {
+        asm.push_str(&debug_info.finish());
     }
-    
     asm
 }
 
+/// 变参中的float提升为double: t0为float的位模式, 返回时t0为double的低32位, t1为高32位
+/// 目标只有RV32IMF, 因此只用整数指令拼出double的位模式, 除t0-t3与ra外不改动其他寄存器
+/// 指数域: 规格化数加上两种格式偏置之差(1023 - 127 = 896), 非规格化数先左移尾数使之规格化, 0保持为0, 无穷大与NaN为2047
+const FLOAT_TO_DOUBLE: &str = concat!(
+    "__sysy_f2d:\n",
+    "  srli  t2, t0, 23\n",
+    "  andi  t2, t2, 255\n",
+    "  slli  t1, t0, 9\n",
+    "  srli  t1, t1, 9\n",
+    "  beqz  t2, .L__sysy_f2d_small\n",
+    "  li    t3, 255\n",
+    "  beq   t2, t3, .L__sysy_f2d_special\n",
+    "  addi  t2, t2, 896\n",
+    ".L__sysy_f2d_pack:\n",
+    "  srli  t3, t0, 31\n",
+    "  slli  t3, t3, 31\n",
+    "  slli  t2, t2, 20\n",
+    "  or    t3, t3, t2\n",
+    "  srli  t2, t1, 3\n",
+    "  or    t3, t3, t2\n",
+    "  slli  t0, t1, 29\n",
+    "  mv    t1, t3\n",
+    "  ret\n",
+    ".L__sysy_f2d_special:\n",
+    "  li    t2, 2047\n",
+    "  j     .L__sysy_f2d_pack\n",
+    ".L__sysy_f2d_small:\n",
+    "  beqz  t1, .L__sysy_f2d_pack\n",
+    "  li    t2, 897\n",
+    ".L__sysy_f2d_normalize:\n",
+    "  slli  t1, t1, 1\n",
+    "  addi  t2, t2, -1\n",
+    "  srli  t3, t1, 23\n",
+    "  beqz  t3, .L__sysy_f2d_normalize\n",
+    "  slli  t1, t1, 9\n",
+    "  srli  t1, t1, 9\n",
+    "  j     .L__sysy_f2d_pack\n",
+);
+
 // 生成数据段
-fn generate_data_section(program: &Program) -> String {
+// 定义在其他目标文件中的extern变量只按名字引用, 不生成数据
+fn generate_data_section(program: &Program, extern_globals: &HashSet<String>) -> String {
     let mut data_asm = String::new();
+    let mut rodata_asm = String::new();
+    let mut const_asm = String::new();
     let mut has_globals = false;
     
     // 遍历所有全局值
     for &value_handle in program.inst_layout() {
         let value_data = program.borrow_value(value_handle);
         if let ValueKind::GlobalAlloc(global_alloc) = value_data.kind() {
+            // 字符串字面量放入只读数据段
+            let name = value_data.name().as_ref().unwrap();
+            if is_string_literal(name) {
+                rodata_asm.push_str(&format!("{}:\n", name.strip_prefix('@').unwrap()));
+                rodata_asm.push_str(&format!("  .asciz \"{}\"\n", escape_string_literal(program, global_alloc.init())));
+                continue;
+            }
+            // 局部常量数组的镜像同样只读
+            if is_const_image(name) {
+                const_asm.push_str(&format!("{}:\n", name.strip_prefix('@').unwrap()));
+                generate_init_data(program, global_alloc.init(), &mut const_asm);
+                continue;
+            }
+            if extern_globals.contains(name.strip_prefix('@').unwrap()) {
+                continue;
+            }
+
             if !has_globals {
                 data_asm.push_str(".data\n");
                 has_globals = true;
@@ -70,83 +183,132 @@ fn generate_data_section(program: &Program) -> String {
                 .strip_prefix('@')
                 .unwrap();
             
-            // 声明全局符号
-            data_asm.push_str(&format!(".global {}\n", var_name));
+            // 声明全局符号(static变量只在本文件可见)
+            if !is_file_local(var_name) {
+                data_asm.push_str(&format!(".global {}\n", var_name));
+            }
             data_asm.push_str(&format!("{}:\n", var_name));
             
-            // 生成初始化数据
-            let init_value = global_alloc.init();
-            let init_data = program.borrow_value(init_value);
-            
-            match init_data.kind() {
-                ValueKind::Integer(int_val) => {
-                    // 使用具体的整数值初始化
-                    data_asm.push_str(&format!("  .word {}\n", int_val.value()));
-                }
-                ValueKind::ZeroInit(_) => {
-                    // 零初始化，根据类型计算大小
-                    let size = calculate_type_size(&init_data.ty());
-                    data_asm.push_str(&format!("  .zero {}\n", size));
-                }
-                ValueKind::Aggregate(_) => {
-                    // 聚合类型初始化（数组初始化）
-                    fn generate_aggregate_data(program: &Program, aggregate_value: Value, data_asm: &mut String) {
-                        let aggregate_data = program.borrow_value(aggregate_value);
-                        if let ValueKind::Aggregate(aggregate) = aggregate_data.kind() {
-                            for &elem in aggregate.elems() {
-                                let elem_data = program.borrow_value(elem);
-                                match elem_data.kind() {
-                                    ValueKind::Integer(int_val) => {
-                                        data_asm.push_str(&format!("  .word {}\n", int_val.value()));
-                                    }
-                                    ValueKind::ZeroInit(_) => {
-                                        data_asm.push_str("  .word 0\n");
-                                    }
-                                    ValueKind::Aggregate(_) => {
-                                        // 递归处理嵌套聚合类型
-                                        generate_aggregate_data(program, elem, data_asm);
-                                    }
-                                    _ => {
-                                        data_asm.push_str("  .word 0\n");
-                                    }
-                                }
+            generate_init_data(program, global_alloc.init(), &mut data_asm);
+        }
+    }
+    
+    // 常量镜像按字对齐, 放在按字节排列的字符串之前
+    if !const_asm.is_empty() || !rodata_asm.is_empty() {
+        data_asm.push_str(".section .rodata\n");
+        data_asm.push_str(&const_asm);
+        data_asm.push_str(&rodata_asm);
+    }
+    data_asm
+}
+
+// 全局变量初始值对应的数据
+fn generate_init_data(program: &Program, init_value: Value, asm: &mut String) {
+    let init_data = program.borrow_value(init_value);
+    
+    match init_data.kind() {
+        ValueKind::Integer(int_val) => {
+            // 使用具体的整数值初始化
+            asm.push_str(&format!("  .word {}\n", int_val.value()));
+        }
+        ValueKind::ZeroInit(_) => {
+            // 零初始化，根据类型计算大小
+            let size = calculate_type_size(&init_data.ty());
+            asm.push_str(&format!("  .zero {}\n", size));
+        }
+        ValueKind::Aggregate(_) => {
+            // 聚合类型初始化（数组初始化）
+            fn generate_aggregate_data(program: &Program, aggregate_value: Value, asm: &mut String) {
+                let aggregate_data = program.borrow_value(aggregate_value);
+                if let ValueKind::Aggregate(aggregate) = aggregate_data.kind() {
+                    for &elem in aggregate.elems() {
+                        let elem_data = program.borrow_value(elem);
+                        match elem_data.kind() {
+                            ValueKind::Integer(int_val) => {
+                                asm.push_str(&format!("  .word {}\n", int_val.value()));
+                            }
+                            ValueKind::ZeroInit(_) => {
+                                asm.push_str("  .word 0\n");
+                            }
+                            ValueKind::Aggregate(_) => {
+                                // 递归处理嵌套聚合类型
+                                generate_aggregate_data(program, elem, asm);
+                            }
+                            _ => {
+                                asm.push_str("  .word 0\n");
                             }
                         }
                     }
-                    generate_aggregate_data(program, init_value, &mut data_asm);
-                }
-                _ => {
-                    // 默认零初始化，根据全局变量类型计算大小
-                    let size = calculate_type_size(&init_data.ty());
-                    data_asm.push_str(&format!("  .zero {}\n", size));
                 }
             }
+            generate_aggregate_data(program, init_value, asm);
+        }
+        _ => {
+            // 默认零初始化，根据全局变量类型计算大小
+            let size = calculate_type_size(&init_data.ty());
+            asm.push_str(&format!("  .zero {}\n", size));
         }
     }
-    
-    data_asm
+}
+
+// 将字符串字面量的初始化数组(每个元素一个字节, 以0结尾)转义为 .asciz 的内容
+fn escape_string_literal(program: &Program, init: Value) -> String {
+    let mut escaped = String::new();
+    if let ValueKind::Aggregate(aggregate) = program.borrow_value(init).kind() {
+        let elems = aggregate.elems();
+        // 去掉结尾的'\0', .asciz 会自动添加
+        for &elem in &elems[..elems.len() - 1] {
+            let byte = match program.borrow_value(elem).kind() {
+                ValueKind::Integer(int_val) => int_val.value() as u8,
+                _ => 0,
+            };
+            match byte {
+                b'"' => escaped.push_str("\\\""),
+                b'\\' => escaped.push_str("\\\\"),
+                0x20..=0x7e => escaped.push(byte as char),
+                _ => escaped.push_str(&format!("\\{:03o}", byte)),
+            }
+        }
+    }
+    escaped
 }
 
 struct AsmGenerator<'a> {
     program: &'a Program,                   // 添加对Program的引用
+    float_abi: &'a FloatAbi,                // 各函数的浮点签名(用于确定参数/返回值寄存器)
+    sig: FloatSig,                          // 当前函数的浮点签名
     stack_size: i32,                        // 当前栈帧大小
+    call_args_offset: i32,                  // 栈上实参在压栈前暂存区域的栈偏移
     value_stack_map: HashMap<Value, i32>,   // 中间值 -> 栈偏移映射
     bb_param_stack_map: HashMap<(BasicBlock, usize), i32>, // 基本块参数栈映射
     is_leaf_function: bool,                 // 是否为叶子函数
+    profile: Option<&'a Profile>,           // 基本块执行次数(-profile-use), 用于块布局
+    next_bb: Option<BasicBlock>,            // 布局中紧随当前块之后的块, 跳到它时可以顺序执行
+    fused_conds: HashSet<Value>,            // 与紧随的br合并为比较跳转指令的比较运算
+    tail_calls: HashSet<Value>,             // 以 tail 跳转实现的尾调用及其后的ret
+    uses_float_to_double: bool,             // 是否调用了 __sysy_f2d
 }
 
 impl<'a> AsmGenerator<'a> {
-    pub fn new(program: &'a Program) -> Self {
+    pub fn new(program: &'a Program, float_abi: &'a FloatAbi, sig: FloatSig, profile: Option<&'a Profile>) -> Self {
         Self {
             program,
+            float_abi,
+            sig,
             stack_size: 0, 
+            call_args_offset: 0,
             value_stack_map: HashMap::new(),
             bb_param_stack_map: HashMap::new(),
             is_leaf_function: true,
+            profile,
+            next_bb: None,
+            fused_conds: HashSet::new(),
+            tail_calls: HashSet::new(),
+            uses_float_to_double: false,
         }
     }
     
-    pub fn gen_function(&mut self, func_data: &FunctionData) -> String {
+    pub fn gen_function(&mut self, func_data: &FunctionData, mut debug_info: Option<&mut DebugInfo>) -> String {
         let mut asm = String::new();
         
         // 1. 检测是否为叶子函数
@@ -179,15 +341,18 @@ impl<'a> AsmGenerator<'a> {
             }
         }
 
-        // 4. 生成基本块和指令
-        let mut is_first_bb = true;
-        for (&bb_handle, bb_node) in func_data.layout().bbs() {
-            // 第一个基本块不需要额外标签，因为函数名已经是标签
-            if !is_first_bb {
+        // 4. 按布局顺序生成基本块和指令
+        self.find_fused_conds(func_data);
+        self.find_tail_calls(func_data);
+        let order = layout::block_order(func_data, self.profile);
+        for (i, &bb_handle) in order.iter().enumerate() {
+            // 第一个基本块(入口)不需要额外标签，因为函数名已经是标签
+            if i > 0 {
                 let bb_name = self.get_bb_label(bb_handle);
                 asm.push_str(&format!("{}:\n", bb_name));
             }
-            is_first_bb = false;
+            self.next_bb = order.get(i + 1).copied();
+            let bb_node = func_data.layout().bbs().node(&bb_handle).unwrap();
             
             // 处理基本块参数
             let bb_data = func_data.dfg().bb(bb_handle);
@@ -200,6 +365,9 @@ impl<'a> AsmGenerator<'a> {
             // 生成基本块内的指令
             for &inst_handle in bb_node.insts().keys() {
                 let value_data = func_data.dfg().value(inst_handle);
+                if let Some(debug_info) = debug_info.as_deref_mut() {
+                    asm.push_str(&debug_info.instruction(inst_handle));
+                }
                 asm.push_str(&self.gen_instruction(inst_handle, value_data, func_data.dfg()));
             }
         }
@@ -207,19 +375,56 @@ impl<'a> AsmGenerator<'a> {
         asm
     }
     
-    // 检测是否为叶子函数（不调用其他函数）
+    // 检测是否为叶子函数（不调用其他函数，浮点内建函数与指针转换会被内联，不算调用）
     fn detect_leaf_function(&mut self, func_data: &FunctionData) {
         for (&_, bb_node) in func_data.layout().bbs() {
             for &inst_handle in bb_node.insts().keys() {
                 let value_data = func_data.dfg().value(inst_handle);
-                if matches!(value_data.kind(), ValueKind::Call(_)) {
-                    self.is_leaf_function = false;
-                    return;
+                if let ValueKind::Call(call) = value_data.kind() {
+                    let callee_name = self.program.func(call.callee()).name();
+                    if FloatIntrinsic::from_name(callee_name).is_none() && !is_pointer_cast(callee_name) {
+                        self.is_leaf_function = false;
+                        return;
+                    }
                 }
             }
         }
     }
 
+    // 找出可以与br合并的比较: 紧挨在br之前且只被它使用
+    fn find_fused_conds(&mut self, func_data: &FunctionData) {
+        self.fused_conds.clear();
+        for (_, bb_node) in func_data.layout().bbs() {
+            let insts: Vec<Value> = bb_node.insts().keys().copied().collect();
+            let [.., prev, last] = insts[..] else { continue };
+            let ValueKind::Branch(branch) = func_data.dfg().value(last).kind() else { continue };
+            let cond = func_data.dfg().value(prev);
+            if branch.cond() == prev && cond.used_by().len() == 1 && matches!(cond.kind(), ValueKind::Binary(binary) if compare_branch(binary.op()).is_some()) {
+                self.fused_conds.insert(prev);
+            }
+        }
+    }
+
+    // 找出可以拆除栈帧后用 tail 跳转的尾调用: 实参都在寄存器中, 返回值所在的寄存器与当前函数相同
+    fn find_tail_calls(&mut self, func_data: &FunctionData) {
+        self.tail_calls.clear();
+        for (call, ret) in tail_calls(func_data) {
+            let ValueKind::Call(call_data) = func_data.dfg().value(call).kind() else { continue };
+            let callee_name = self.program.func(call_data.callee()).name();
+            let func_name = callee_name.strip_prefix('@').unwrap_or(callee_name);
+            if FloatIntrinsic::from_name(func_name).is_some() || is_pointer_cast(func_name) || VariadicCall::from_mangled_name(func_name).is_some() {
+                continue;
+            }
+            let in_registers = self.call_arg_locations(func_name, call_data.args().len()).iter()
+                .all(|location| matches!(location, ArgLocation::IntReg(_) | ArgLocation::FloatReg(_)));
+            let has_result = !func_data.dfg().value(call).ty().is_unit();
+            if in_registers && (!has_result || self.float_abi.get(func_name).ret == self.sig.ret) {
+                self.tail_calls.insert(call);
+                self.tail_calls.insert(ret);
+            }
+        }
+    }
+
     fn calculate_stack_size(&mut self, func_data: &FunctionData) {
         self.stack_size = 0;
         self.value_stack_map.clear();
@@ -297,6 +502,23 @@ impl<'a> AsmGenerator<'a> {
             }
         }
 
+        // 栈上实参先暂存在本栈帧内, 大小取所有调用中的最大值(8字节对齐以便存放double)
+        let mut call_args_words = 0;
+        for (&_, bb_node) in func_data.layout().bbs() {
+            for &inst_handle in bb_node.insts().keys() {
+                if let ValueKind::Call(call) = func_data.dfg().value(inst_handle).kind() {
+                    let callee_name = self.program.func(call.callee()).name();
+                    let locations = self.call_arg_locations(callee_name.strip_prefix('@').unwrap_or(callee_name), call.args().len());
+                    call_args_words = call_args_words.max(stack_arg_words(&locations));
+                }
+            }
+        }
+        if call_args_words > 0 {
+            self.stack_size = (self.stack_size + 7) & !7;
+            self.stack_size += call_args_words as i32 * 4;
+        }
+        self.call_args_offset = self.stack_size - call_args_words as i32 * 4;
+
         // 如果不是叶子函数，需要额外空间保存ra寄存器
         if !self.is_leaf_function {
             self.stack_size += 4;
@@ -308,6 +530,14 @@ impl<'a> AsmGenerator<'a> {
         }
     }
     
+    /// 按 ilp32f 调用约定确定每个实参的位置, 变参调用按其实参形态确定
+    fn call_arg_locations(&self, func_name: &str, arg_count: usize) -> Vec<ArgLocation> {
+        match VariadicCall::from_mangled_name(func_name) {
+            Some(variadic) => variadic.arg_locations(),
+            None => self.float_abi.get(func_name).arg_locations(arg_count),
+        }
+    }
+
     fn gen_instruction(&mut self, inst_handle: Value, value_data: &ValueData, dfg: &DataFlowGraph) -> String {
         match value_data.kind() { 
             ValueKind::Integer(_) => { 
@@ -325,6 +555,10 @@ impl<'a> AsmGenerator<'a> {
                 // 参数值已经通过跳转时的寄存器传递或栈传递
                 String::new()
             }
+            ValueKind::Binary(_) if self.fused_conds.contains(&inst_handle) => {
+                // 在br处生成比较跳转指令
+                String::new()
+            }
             ValueKind::Binary(binary) => {
                 let mut asm = String::new();
 
@@ -392,25 +626,79 @@ impl<'a> AsmGenerator<'a> {
                 // 通过program获取被调用函数的名称
                 let callee_data = self.program.func(callee);
                 let func_name = callee_data.name().strip_prefix('@').unwrap_or(callee_data.name());
+
+                // 浮点内建函数直接内联为 RV32F 指令
+                if let Some(intrinsic) = FloatIntrinsic::from_name(func_name) {
+                    return self.gen_float_intrinsic(inst_handle, intrinsic, args, dfg);
+                }
+
+                // 指针与整数的位模式相同, 转换只需把实参搬到结果的栈位置
+                if is_pointer_cast(func_name) {
+                    let offset = *self.value_stack_map.get(&inst_handle)
+                        .unwrap_or_else(|| panic!("Pointer cast result not found in stack map: {:?}", inst_handle));
+                    let mut asm = self.load_value_to_reg(args[0], "t0", dfg);
+                    asm.push_str(&self.store_reg_to_stack("t0", offset));
+                    return asm;
+                }
                 
-                // 准备参数
-                // 前8个参数通过a0-a7寄存器传递
-                for (i, &arg) in args.iter().enumerate().take(8) {
-                    asm.push_str(&self.load_value_to_reg(arg, &format!("a{}", i), dfg));
+                // 按 ilp32f 调用约定确定每个参数的位置, 变参调用按其实参形态确定
+                let variadic = VariadicCall::from_mangled_name(func_name);
+                let callee_sig = self.float_abi.get(func_name);
+                let locations = self.call_arg_locations(func_name, args.len());
+                let func_name = variadic.as_ref().map_or(func_name, |variadic| variadic.callee);
+                
+                // 栈上参数: (栈位置, 参数值, 是否为double)
+                let stack_args: Vec<(usize, Value, bool)> = args.iter().zip(&locations)
+                    .filter_map(|(&arg, location)| match location {
+                        ArgLocation::Stack(n) => Some((*n, arg, false)),
+                        ArgLocation::StackPair(n) => Some((*n, arg, true)),
+                        _ => None,
+                    })
+                    .collect();
+                let stack_words = stack_arg_words(&locations);
+                
+                // 寄存器参数：整数参数通过a0-a7传递，浮点参数通过fa0-fa7传递
+                for (&arg, location) in args.iter().zip(&locations) {
+                    match location {
+                        ArgLocation::IntReg(i) => asm.push_str(&self.load_value_to_reg(arg, &format!("a{}", i), dfg)),
+                        ArgLocation::FloatReg(i) => asm.push_str(&self.load_float_to_reg(arg, &format!("fa{}", i), dfg)),
+                        ArgLocation::IntRegPair(i) => {
+                            // float提升为double, 低32位在t0, 高32位在t1
+                            asm.push_str(&self.float_to_double(arg, dfg));
+                            asm.push_str(&format!("  mv    a{}, t0\n  mv    a{}, t1\n", i, i + 1));
+                        }
+                        ArgLocation::Stack(_) | ArgLocation::StackPair(_) => {}
+                    }
                 }
                 
-                // 第9个及以后的参数通过栈传递（从右到左压栈）
-                if args.len() > 8 {
-                    let stack_args = &args[8..];
-                    let stack_space = ((stack_args.len() * 4 + 15) / 16) * 16; // 16字节对齐
+                // 尾调用: 拆除栈帧后跳转, 被调函数直接返回到当前函数的调用者
+                if self.tail_calls.contains(&inst_handle) {
+                    asm.push_str(&self.gen_epilogue());
+                    asm.push_str(&format!("  tail  {}\n", func_name));
+                    return asm;
+                }
+
+                // 其余参数通过栈传递（从右到左压栈）
+                if stack_words > 0 {
+                    let stack_space = (stack_words * 4).div_ceil(16) * 16; // 16字节对齐
                     
                     // 先将所有栈参数值存储到临时栈位置，避免寄存器冲突
-                    for (i, &arg) in stack_args.iter().enumerate() {
+                    for &(n, arg, is_double) in &stack_args {
+                        let temp_offset = self.call_args_offset + n as i32 * 4;
+                        if is_double {
+                            asm.push_str(&self.float_to_double(arg, dfg));
+                            if temp_offset + 4 <= 2047 {
+                                asm.push_str(&format!("  sw    t0, {}(sp)\n  sw    t1, {}(sp)\n", temp_offset, temp_offset + 4));
+                            } else {
+                                asm.push_str(&format!("  li    t2, {}\n  add   t2, sp, t2\n  sw    t0, 0(t2)\n  sw    t1, 4(t2)\n", temp_offset));
+                            }
+                            continue;
+                        }
+
                         // 加载参数值到t0寄存器
                         asm.push_str(&self.load_value_to_reg(arg, "t0", dfg));
                         
                         // 存储到临时栈位置
-                        let temp_offset = self.stack_size + i as i32 * 4;
                         if temp_offset <= 2047 {
                             asm.push_str(&format!("  sw    t0, {}(sp)\n", temp_offset));
                         } else {
@@ -425,9 +713,9 @@ impl<'a> AsmGenerator<'a> {
                         asm.push_str(&format!("  li    t0, -{}\n  add   sp, sp, t0\n", stack_space));
                     }
                     
-                    // 按顺序从临时栈位置加载并压栈（第9个参数在偏移0，第10个参数在偏移4，等等）
-                    for i in 0..stack_args.len() {
-                        let temp_offset = self.stack_size + i as i32 * 4 + stack_space as i32;
+                    // 按顺序从临时栈位置加载并压栈（第一个栈参数在偏移0，第二个在偏移4，等等）
+                    for i in 0..stack_words {
+                        let temp_offset = self.call_args_offset + i as i32 * 4 + stack_space as i32;
                         let stack_offset = i * 4;
                         
                         // 从临时栈位置加载
@@ -450,9 +738,8 @@ impl<'a> AsmGenerator<'a> {
                 asm.push_str(&format!("  call  {}\n", func_name));
                 
                 // 恢复栈指针（如果有栈参数）
-                if args.len() > 8 {
-                    let stack_args = &args[8..];
-                    let stack_space = ((stack_args.len() * 4 + 15) / 16) * 16;
+                if stack_words > 0 {
+                    let stack_space = (stack_words * 4).div_ceil(16) * 16;
                     if stack_space <= 2047 {
                         asm.push_str(&format!("  addi  sp, sp, {}\n", stack_space));
                     } else {
@@ -460,48 +747,33 @@ impl<'a> AsmGenerator<'a> {
                     }
                 }
                 
-                // 如果函数有返回值，将a0的值保存到栈
+                // 如果函数有返回值，将a0(浮点返回值为fa0)的值保存到栈
                 if !matches!(value_data.ty().kind(), koopa::ir::TypeKind::Unit) {
                     if let Some(&offset) = self.value_stack_map.get(&inst_handle) {
-                        if offset >= -2048 && offset <= 2047 {
-                            asm.push_str(&format!("  sw    a0, {}(sp)\n", offset));
-                        } else {
-                            asm.push_str(&format!("  li    t6, {}\n  add   t6, sp, t6\n  sw    a0, 0(t6)\n", offset));
-                        }
+                        let ret_reg = if callee_sig.ret { "fa0" } else { "a0" };
+                        asm.push_str(&self.store_reg_to_stack(ret_reg, offset));
                     }
                 }
                 
                 asm
             }
+            ValueKind::Return(_) if self.tail_calls.contains(&inst_handle) => {
+                // 尾调用已经跳转到被调函数
+                String::new()
+            }
             ValueKind::Return(ret) => {
                 let mut asm = String::new();
 
-                // 如果有返回值，将其加载到a0寄存器
+                // 如果有返回值，将其加载到a0寄存器(浮点返回值加载到fa0)
                 if let Some(return_value) = ret.value() {
-                    asm.push_str(&self.load_value_to_reg(return_value, "a0", dfg));
-                }
-
-                // 恢复ra寄存器（如果不是叶子函数）
-                if !self.is_leaf_function && self.stack_size > 0 {
-                    let ra_offset = self.stack_size - 4;
-                    if ra_offset <= 2047 {
-                        asm.push_str(&format!("  lw    ra, {}(sp)\n", ra_offset));
+                    if self.sig.ret {
+                        asm.push_str(&self.load_float_to_reg(return_value, "fa0", dfg));
                     } else {
-                        asm.push_str(&format!("  li    t0, {}\n", ra_offset));
-                        asm.push_str("  add   t0, sp, t0\n");
-                        asm.push_str("  lw    ra, 0(t0)\n");
+                        asm.push_str(&self.load_value_to_reg(return_value, "a0", dfg));
                     }
                 }
 
-                // 恢复栈指针
-                if self.stack_size > 0 {
-                    if self.stack_size <= 2047 {
-                        asm.push_str(&format!("  addi  sp, sp, {}\n", self.stack_size));
-                    } else {
-                        asm.push_str(&format!("  li    t0, {}\n", self.stack_size));
-                        asm.push_str("  add   sp, sp, t0\n");
-                    }
-                }
+                asm.push_str(&self.gen_epilogue());
 
                 // 返回
                 asm.push_str("  ret\n");
@@ -510,17 +782,38 @@ impl<'a> AsmGenerator<'a> {
             }
             ValueKind::Branch(branch) => {
                 let mut asm = String::new();
-                
-                // 加载条件值到寄存器
-                asm.push_str(&self.load_value_to_reg(branch.cond(), "t0", dfg));
-                
-                // 生成条件分支指令
-                let true_label = self.get_bb_label(branch.true_bb());
-                let false_label = self.get_bb_label(branch.false_bb());
-                
-                asm.push_str(&format!("  bnez  t0, {}\n", true_label));
-                asm.push_str(&format!("  j     {}\n", false_label));
-                
+                let (true_bb, false_bb) = (branch.true_bb(), branch.false_bb());
+                if true_bb == false_bb {
+                    if self.next_bb != Some(true_bb) {
+                        asm.push_str(&format!("  j     {}\n", self.get_bb_label(true_bb)));
+                    }
+                    return asm;
+                }
+
+                // 比较与br合并时直接比较两个操作数, 否则判断条件值是否非零
+                let (op, operands) = match dfg.value(branch.cond()).kind() {
+                    ValueKind::Binary(binary) if self.fused_conds.contains(&branch.cond()) => {
+                        let (op, swap) = compare_branch(binary.op()).unwrap();
+                        let (lhs, rhs) = if swap { (binary.rhs(), binary.lhs()) } else { (binary.lhs(), binary.rhs()) };
+                        let lhs = self.load_operand(lhs, "t0", dfg, &mut asm);
+                        let rhs = self.load_operand(rhs, "t1", dfg, &mut asm);
+                        (op, format!("{}, {}", lhs, rhs))
+                    }
+                    _ => {
+                        asm.push_str(&self.load_value_to_reg(branch.cond(), "t0", dfg));
+                        ("bnez", "t0".to_string())
+                    }
+                };
+
+                // 后继之一紧随其后时省去j, 紧随的是真目标则反转条件
+                if self.next_bb == Some(true_bb) {
+                    asm.push_str(&format!("  {:<5} {}, {}\n", invert_branch(op), operands, self.get_bb_label(false_bb)));
+                } else {
+                    asm.push_str(&format!("  {:<5} {}, {}\n", op, operands, self.get_bb_label(true_bb)));
+                    if self.next_bb != Some(false_bb) {
+                        asm.push_str(&format!("  j     {}\n", self.get_bb_label(false_bb)));
+                    }
+                }
                 asm
             }
             ValueKind::Jump(jump) => {
@@ -538,8 +831,10 @@ impl<'a> AsmGenerator<'a> {
                     }
                 }
                 
-                let target_label = self.get_bb_label(jump.target());
-                asm.push_str(&format!("  j     {}\n", target_label));
+                if self.next_bb != Some(jump.target()) {
+                    let target_label = self.get_bb_label(jump.target());
+                    asm.push_str(&format!("  j     {}\n", target_label));
+                }
                 asm
             }
             ValueKind::Alloc(_) => {
@@ -735,6 +1030,34 @@ impl<'a> AsmGenerator<'a> {
         }
     }
 
+    // 函数尾声: 恢复ra与栈指针
+    fn gen_epilogue(&self) -> String {
+        let mut asm = String::new();
+
+        // 恢复ra寄存器（如果不是叶子函数）
+        if !self.is_leaf_function && self.stack_size > 0 {
+            let ra_offset = self.stack_size - 4;
+            if ra_offset <= 2047 {
+                asm.push_str(&format!("  lw    ra, {}(sp)\n", ra_offset));
+            } else {
+                asm.push_str(&format!("  li    t0, {}\n", ra_offset));
+                asm.push_str("  add   t0, sp, t0\n");
+                asm.push_str("  lw    ra, 0(t0)\n");
+            }
+        }
+
+        // 恢复栈指针
+        if self.stack_size > 0 {
+            if self.stack_size <= 2047 {
+                asm.push_str(&format!("  addi  sp, sp, {}\n", self.stack_size));
+            } else {
+                asm.push_str(&format!("  li    t0, {}\n", self.stack_size));
+                asm.push_str("  add   sp, sp, t0\n");
+            }
+        }
+        asm
+    }
+
     // 将值加载到指定寄存器的辅助方法
     fn load_value_to_reg(&self, value: Value, target_reg: &str, dfg: &DataFlowGraph) -> String {
         // 首先检查是否为全局变量（不在函数 dfg 中）
@@ -768,18 +1091,23 @@ impl<'a> AsmGenerator<'a> {
             },
             ValueKind::FuncArgRef(arg_ref) => {
                 let arg_index = arg_ref.index();
-                if arg_index < 8 {
-                    // 前8个参数通过a0-a7寄存器传递
-                    format!("  mv    {}, a{}\n", target_reg, arg_index)
-                } else {
-                    // 第9个及以后的参数从栈中获取
-                    // 参数在调用者栈帧中，需要计算正确的偏移
-                    let offset = self.stack_size + (arg_index as i32 - 8) * 4;
-                    if offset >= -2048 && offset <= 2047 {
-                        format!("  lw    {}, {}(sp)\n", target_reg, offset)
-                    } else {
-                        format!("  li    t6, {}\n  add   t6, sp, t6\n  lw    {}, 0(t6)\n", offset, target_reg)
+                let param_count = self.sig.params.len().max(arg_index + 1);
+                match self.sig.arg_locations(param_count)[arg_index] {
+                    // 整数参数通过a0-a7寄存器传递
+                    ArgLocation::IntReg(i) => format!("  mv    {}, a{}\n", target_reg, i),
+                    // 浮点参数通过fa0-fa7寄存器传递，取出其位模式
+                    ArgLocation::FloatReg(i) => format!("  fmv.x.w {}, fa{}\n", target_reg, i),
+                    ArgLocation::Stack(n) => {
+                        // 其余参数从栈中获取
+                        // 参数在调用者栈帧中，需要计算正确的偏移
+                        let offset = self.stack_size + n as i32 * 4;
+                        if (-2048..=2047).contains(&offset) {
+                            format!("  lw    {}, {}(sp)\n", target_reg, offset)
+                        } else {
+                            format!("  li    t6, {}\n  add   t6, sp, t6\n  lw    {}, 0(t6)\n", offset, target_reg)
+                        }
                     }
+                    ArgLocation::IntRegPair(_) | ArgLocation::StackPair(_) => unreachable!("only variadic arguments are passed as double"),
                 }
             },
             ValueKind::Alloc(_) => {
@@ -809,6 +1137,108 @@ impl<'a> AsmGenerator<'a> {
         }
     }
 
+    // 内联浮点内建函数：操作数加载到ft0/ft1，结果写回栈
+    fn gen_float_intrinsic(&mut self, inst_handle: Value, intrinsic: FloatIntrinsic, args: &[Value], dfg: &DataFlowGraph) -> String {
+        let mut asm = String::new();
+        let offset = *self.value_stack_map.get(&inst_handle)
+            .unwrap_or_else(|| panic!("Float intrinsic result not found in stack map: {:?}", inst_handle));
+
+        if intrinsic == FloatIntrinsic::IntToFloat {
+            asm.push_str(&self.load_value_to_reg(args[0], "t0", dfg));
+            asm.push_str("  fcvt.s.w ft0, t0\n");
+            asm.push_str(&self.store_reg_to_stack("ft0", offset));
+            return asm;
+        }
+
+        asm.push_str(&self.load_float_to_reg(args[0], "ft0", dfg));
+        if intrinsic.arity() == 2 {
+            asm.push_str(&self.load_float_to_reg(args[1], "ft1", dfg));
+        }
+
+        match intrinsic {
+            FloatIntrinsic::Add => asm.push_str("  fadd.s ft0, ft0, ft1\n"),
+            FloatIntrinsic::Sub => asm.push_str("  fsub.s ft0, ft0, ft1\n"),
+            FloatIntrinsic::Mul => asm.push_str("  fmul.s ft0, ft0, ft1\n"),
+            FloatIntrinsic::Div => asm.push_str("  fdiv.s ft0, ft0, ft1\n"),
+            FloatIntrinsic::Neg => asm.push_str("  fneg.s ft0, ft0\n"),
+            FloatIntrinsic::Eq => asm.push_str("  feq.s t0, ft0, ft1\n"),
+            FloatIntrinsic::Ne => {
+                asm.push_str("  feq.s t0, ft0, ft1\n");
+                asm.push_str("  seqz  t0, t0\n");
+            }
+            FloatIntrinsic::Lt => asm.push_str("  flt.s t0, ft0, ft1\n"),
+            FloatIntrinsic::Le => asm.push_str("  fle.s t0, ft0, ft1\n"),
+            FloatIntrinsic::Gt => asm.push_str("  flt.s t0, ft1, ft0\n"),
+            FloatIntrinsic::Ge => asm.push_str("  fle.s t0, ft1, ft0\n"),
+            // C语义：向零取整
+            FloatIntrinsic::FloatToInt => asm.push_str("  fcvt.w.s t0, ft0, rtz\n"),
+            FloatIntrinsic::IntToFloat => unreachable!(),
+        }
+
+        let result_reg = if intrinsic.returns_float() { "ft0" } else { "t0" };
+        asm.push_str(&self.store_reg_to_stack(result_reg, offset));
+        asm
+    }
+
+    // 将浮点值(位模式)加载到指定浮点寄存器
+    /// 把float值提升为double的位模式: 低32位在t0, 高32位在t1 (见 FLOAT_TO_DOUBLE)
+    fn float_to_double(&mut self, value: Value, dfg: &DataFlowGraph) -> String {
+        self.uses_float_to_double = true;
+        let mut asm = self.load_float_to_reg(value, "ft0", dfg);
+        asm.push_str("  fmv.x.w t0, ft0\n  call  __sysy_f2d\n");
+        asm
+    }
+
+    fn load_float_to_reg(&self, value: Value, target_reg: &str, dfg: &DataFlowGraph) -> String {
+        if dfg.values().contains_key(&value) {
+            let value_data = dfg.value(value);
+            match value_data.kind() {
+                ValueKind::FuncArgRef(arg_ref) => {
+                    // 本身就在浮点参数寄存器中的参数直接移动
+                    let param_count = self.sig.params.len().max(arg_ref.index() + 1);
+                    if let ArgLocation::FloatReg(i) = self.sig.arg_locations(param_count)[arg_ref.index()] {
+                        return format!("  fmv.s {}, fa{}\n", target_reg, i);
+                    }
+                }
+                ValueKind::Integer(_) | ValueKind::Alloc(_) => {}
+                _ => {
+                    // 栈上的值直接用flw加载
+                    if let Some(&offset) = self.value_stack_map.get(&value) {
+                        return if (-2048..=2047).contains(&offset) {
+                            format!("  flw   {}, {}(sp)\n", target_reg, offset)
+                        } else {
+                            format!("  li    t6, {}\n  add   t6, sp, t6\n  flw   {}, 0(t6)\n", offset, target_reg)
+                        };
+                    }
+                }
+            }
+        }
+
+        // 其余情况(常量等)先加载位模式到整数寄存器再移动
+        let mut asm = self.load_value_to_reg(value, "t6", dfg);
+        asm.push_str(&format!("  fmv.w.x {}, t6\n", target_reg));
+        asm
+    }
+
+    // 将寄存器的值存储到栈偏移处，浮点寄存器使用fsw
+    fn store_reg_to_stack(&self, reg: &str, offset: i32) -> String {
+        let op = if reg.starts_with('f') { "fsw  " } else { "sw   " };
+        if (-2048..=2047).contains(&offset) {
+            format!("  {} {}, {}(sp)\n", op, reg, offset)
+        } else {
+            format!("  li    t6, {}\n  add   t6, sp, t6\n  {} {}, 0(t6)\n", offset, op, reg)
+        }
+    }
+
+    // 比较跳转的操作数: 常数0直接用x0, 其余加载到reg
+    fn load_operand(&self, value: Value, reg: &'static str, dfg: &DataFlowGraph, asm: &mut String) -> &'static str {
+        if dfg.values().contains_key(&value) && matches!(dfg.value(value).kind(), ValueKind::Integer(i) if i.value() == 0) {
+            return "x0";
+        }
+        asm.push_str(&self.load_value_to_reg(value, reg, dfg));
+        reg
+    }
+
     // 生成基本块标签的辅助方法
     fn get_bb_label(&self, bb: BasicBlock) -> String {
         // 将 BasicBlock 转换为字符串，然后清理特殊字符
@@ -820,6 +1250,44 @@ impl<'a> AsmGenerator<'a> {
             .replace("BasicBlock", "")
             .replace("(", "")
             .replace(")", "");
-        format!("LBB{}", cleaned)
+        format!("{}{}", BLOCK_LABEL_PREFIX, cleaned)
+    }
+}
+
+/// 比较运算对应的比较跳转指令, 以及是否需要交换两个操作数
+fn compare_branch(op: BinaryOp) -> Option<(&'static str, bool)> {
+    match op {
+        BinaryOp::Eq => Some(("beq", false)),
+        BinaryOp::NotEq => Some(("bne", false)),
+        BinaryOp::Lt => Some(("blt", false)),
+        BinaryOp::Ge => Some(("bge", false)),
+        BinaryOp::Gt => Some(("blt", true)),
+        BinaryOp::Le => Some(("bge", true)),
+        _ => None,
+    }
+}
+
+/// 条件相反的跳转指令(操作数不变)
+fn invert_branch(op: &str) -> &'static str {
+    match op {
+        "beq" => "bne",
+        "bne" => "beq",
+        "blt" => "bge",
+        "bge" => "blt",
+        "bnez" => "beqz",
+        "beqz" => "bnez",
+        _ => unreachable!("not a branch: {}", op),
     }
-}
\ No newline at end of file
+}
+
+/// 调用时栈上实参占用的字数
+fn stack_arg_words(locations: &[ArgLocation]) -> usize {
+    locations.iter()
+        .filter_map(|location| match location {
+            ArgLocation::Stack(n) => Some(n + 1),
+            ArgLocation::StackPair(n) => Some(n + 2),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
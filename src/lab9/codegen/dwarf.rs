@@ -0,0 +1,324 @@
+//! 调试信息的汇编输出(-g)
+//! - 行号: 每个函数开头与源码位置变化处输出 `.loc`, 汇编器据此生成 .debug_line
+//! - DWARF(.debug_info/.debug_abbrev): 只描述编译单元、函数与栈上的局部变量/形参,
+//!   变量位置为相对sp的偏移(函数序言执行完之后才准确), 嵌套作用域不单独描述
+use std::collections::HashMap;
+use std::fmt::Write;
+use koopa::ir::{Function, FunctionData, Type, TypeKind, Value};
+use crate::lab9::irgen::debug::SourceMap;
+use crate::lab9::preprocess::LineMap;
+
+// DWARF 常量
+const DW_TAG_ARRAY_TYPE: u8 = 0x01;
+const DW_TAG_FORMAL_PARAMETER: u8 = 0x05;
+const DW_TAG_POINTER_TYPE: u8 = 0x0f;
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_SUBRANGE_TYPE: u8 = 0x21;
+const DW_TAG_BASE_TYPE: u8 = 0x24;
+const DW_TAG_SUBPROGRAM: u8 = 0x2e;
+const DW_TAG_VARIABLE: u8 = 0x34;
+
+const DW_AT_LOCATION: u8 = 0x02;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_BYTE_SIZE: u8 = 0x0b;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_LANGUAGE: u8 = 0x13;
+const DW_AT_COMP_DIR: u8 = 0x1b;
+const DW_AT_PRODUCER: u8 = 0x25;
+const DW_AT_COUNT: u8 = 0x37;
+const DW_AT_DECL_FILE: u8 = 0x3a;
+const DW_AT_DECL_LINE: u8 = 0x3b;
+const DW_AT_ENCODING: u8 = 0x3e;
+const DW_AT_FRAME_BASE: u8 = 0x40;
+const DW_AT_TYPE: u8 = 0x49;
+
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA2: u8 = 0x05;
+const DW_FORM_DATA4: u8 = 0x06;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_DATA1: u8 = 0x0b;
+const DW_FORM_REF4: u8 = 0x13;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+const DW_FORM_EXPRLOC: u8 = 0x18;
+
+const DW_ATE_FLOAT: u8 = 0x04;
+const DW_ATE_SIGNED: u8 = 0x05;
+const DW_LANG_C99: u16 = 0x0c;
+const DW_OP_REG2: u8 = 0x52; // sp
+const DW_OP_FBREG: u8 = 0x91;
+
+/// 缩写: (编号, 标签, 是否有子节点, 属性及其形式)
+type Abbreviation = (u8, u8, bool, &'static [(u8, u8)]);
+
+const ABBREVIATIONS: [Abbreviation; 8] = [
+    (1, DW_TAG_COMPILE_UNIT, true, &[
+        (DW_AT_PRODUCER, DW_FORM_STRING), (DW_AT_LANGUAGE, DW_FORM_DATA2), (DW_AT_NAME, DW_FORM_STRING),
+        (DW_AT_COMP_DIR, DW_FORM_STRING), (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+        (DW_AT_LOW_PC, DW_FORM_ADDR), (DW_AT_HIGH_PC, DW_FORM_DATA4),
+    ]),
+    (2, DW_TAG_SUBPROGRAM, true, &[
+        (DW_AT_NAME, DW_FORM_STRING), (DW_AT_DECL_FILE, DW_FORM_DATA1), (DW_AT_DECL_LINE, DW_FORM_DATA4),
+        (DW_AT_LOW_PC, DW_FORM_ADDR), (DW_AT_HIGH_PC, DW_FORM_DATA4), (DW_AT_FRAME_BASE, DW_FORM_EXPRLOC),
+    ]),
+    (3, DW_TAG_VARIABLE, false, VARIABLE_ATTRIBUTES),
+    (4, DW_TAG_FORMAL_PARAMETER, false, VARIABLE_ATTRIBUTES),
+    (5, DW_TAG_BASE_TYPE, false, &[(DW_AT_NAME, DW_FORM_STRING), (DW_AT_ENCODING, DW_FORM_DATA1), (DW_AT_BYTE_SIZE, DW_FORM_DATA1)]),
+    (6, DW_TAG_POINTER_TYPE, false, &[(DW_AT_BYTE_SIZE, DW_FORM_DATA1), (DW_AT_TYPE, DW_FORM_REF4)]),
+    (7, DW_TAG_ARRAY_TYPE, true, &[(DW_AT_TYPE, DW_FORM_REF4)]),
+    (8, DW_TAG_SUBRANGE_TYPE, false, &[(DW_AT_COUNT, DW_FORM_DATA4)]),
+];
+
+const VARIABLE_ATTRIBUTES: &[(u8, u8)] = &[
+    (DW_AT_NAME, DW_FORM_STRING), (DW_AT_DECL_FILE, DW_FORM_DATA1), (DW_AT_DECL_LINE, DW_FORM_DATA4),
+    (DW_AT_TYPE, DW_FORM_REF4), (DW_AT_LOCATION, DW_FORM_EXPRLOC),
+];
+
+/// 源码位置, file 为 `.file` 的编号(从1开始)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SourceLine {
+    file: usize,
+    line: usize,
+    column: usize,
+}
+
+/// 变量的类型, 数组的维度从外到内
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DebugType {
+    Base { is_float: bool },
+    Pointer(usize),
+    Array(usize, Vec<usize>),
+}
+
+struct Variable {
+    name: String,
+    line: SourceLine,
+    is_param: bool,
+    offset: i32,
+    ty: usize,
+}
+
+struct Subprogram {
+    name: String,
+    line: SourceLine,
+    label: String,
+    variables: Vec<Variable>,
+}
+
+/// 整个汇编文件的调试信息, 由代码生成器在生成各函数时逐步填充
+pub struct DebugInfo<'a> {
+    source_map: &'a SourceMap,
+    line_maps: &'a [LineMap],
+    files: Vec<String>,
+    subprograms: Vec<Subprogram>,
+    types: Vec<DebugType>,
+    current: Option<usize>,        // 正在生成的函数在 subprograms 中的编号
+    last_line: Option<SourceLine>, // 上一条 .loc, 位置不变时不重复输出
+}
+
+impl<'a> DebugInfo<'a> {
+    pub fn new(source_map: &'a SourceMap, line_maps: &'a [LineMap]) -> Self {
+        Self {
+            source_map,
+            line_maps,
+            files: Vec::new(),
+            subprograms: Vec::new(),
+            types: Vec::new(),
+            current: None,
+            last_line: None,
+        }
+    }
+
+    fn source_line(&mut self, (unit, pos): (usize, usize)) -> SourceLine {
+        let location = self.line_maps[unit].locate(pos);
+        let file = match self.files.iter().position(|file| *file == location.file) {
+            Some(index) => index + 1,
+            None => {
+                self.files.push(location.file);
+                self.files.len()
+            }
+        };
+        SourceLine { file, line: location.line, column: location.column }
+    }
+
+    /// 函数标签之后的 .loc, 指向函数名; 没有源码位置的函数(如编译器生成的)不描述
+    pub fn begin_function(&mut self, function: Function, label: &str) -> String {
+        self.last_line = None;
+        let Some(symbol) = self.source_map.functions.get(&function) else {
+            return String::new();
+        };
+        let line = self.source_line((symbol.unit, symbol.pos));
+        self.current = Some(self.subprograms.len());
+        self.subprograms.push(Subprogram { name: symbol.name.clone(), line, label: label.to_string(), variables: Vec::new() });
+        self.loc_directive(line)
+    }
+
+    /// 指令之前的 .loc, 位置与上一条相同时为空
+    pub fn instruction(&mut self, inst: Value) -> String {
+        match self.source_map.instructions.get(&inst) {
+            Some(&position) => {
+                let line = self.source_line(position);
+                if self.last_line == Some(line) {
+                    return String::new();
+                }
+                self.loc_directive(line)
+            }
+            None => String::new(),
+        }
+    }
+
+    fn loc_directive(&mut self, line: SourceLine) -> String {
+        self.last_line = Some(line);
+        format!("  .loc {} {} {}\n", line.file, line.line, line.column)
+    }
+
+    /// 函数结束: 输出结束标签(用于计算函数的地址范围), 并按栈帧布局记录变量位置
+    pub fn end_function(&mut self, func_data: &FunctionData, value_stack_map: &HashMap<Value, i32>) -> String {
+        let Some(index) = self.current.take() else {
+            return String::new();
+        };
+
+        // 按定义位置处理, 保证文件编号与类型编号的顺序确定
+        let mut allocs: Vec<_> = self.source_map.variables.iter()
+            .filter(|(alloc, _)| value_stack_map.contains_key(alloc))
+            .collect();
+        allocs.sort_by_key(|(_, variable)| (variable.symbol.unit, variable.symbol.pos));
+        let mut variables = Vec::new();
+        for (&alloc, variable) in allocs {
+            let offset = value_stack_map[&alloc];
+            let Some(base) = func_data.dfg().values().get(&alloc).and_then(|data| match data.ty().kind() {
+                TypeKind::Pointer(base) => Some(base.clone()),
+                _ => None,
+            }) else { continue };
+            let line = self.source_line((variable.symbol.unit, variable.symbol.pos));
+            let ty = self.debug_type(&base, variable.is_float);
+            variables.push(Variable { name: variable.symbol.name.clone(), line, is_param: variable.is_param, offset, ty });
+        }
+        self.subprograms[index].variables = variables;
+        format!(".Lfunc_end{}:\n", index)
+    }
+
+    /// Koopa类型对应的调试类型(去重后的编号), float 在IR中以i32表示
+    fn debug_type(&mut self, ty: &Type, is_float: bool) -> usize {
+        let debug_type = match ty.kind() {
+            TypeKind::Pointer(base) => DebugType::Pointer(self.debug_type(base, is_float)),
+            TypeKind::Array(..) => {
+                let mut dims = Vec::new();
+                let mut elem = ty;
+                while let TypeKind::Array(base, len) = elem.kind() {
+                    dims.push(*len);
+                    elem = base;
+                }
+                DebugType::Array(self.debug_type(elem, is_float), dims)
+            }
+            _ => DebugType::Base { is_float },
+        };
+        match self.types.iter().position(|existing| *existing == debug_type) {
+            Some(index) => index,
+            None => {
+                self.types.push(debug_type);
+                self.types.len() - 1
+            }
+        }
+    }
+
+    /// 文件表与代码段起始标签, 放在所有 .loc 之前(即生成完各函数后插入到 .text 开头)
+    pub fn text_begin(&self) -> String {
+        let mut asm = String::new();
+        for (index, file) in self.files.iter().enumerate() {
+            writeln!(asm, ".file {} \"{}\"", index + 1, escape(file)).unwrap();
+        }
+        asm.push_str(".Ltext_begin:\n");
+        asm
+    }
+
+    /// 代码段结束标签以及DWARF各节
+    pub fn finish(&self) -> String {
+        let mut asm = String::from(".Ltext_end:\n");
+        asm.push_str(&self.abbrev_section());
+        asm.push_str(&self.info_section());
+        // 汇编器把由 .loc 生成的行号表放在 .debug_line 中, 这里只定义其起始标签
+        asm.push_str(".section .debug_line,\"\",@progbits\n.Lline_table_start:\n");
+        asm
+    }
+
+    fn abbrev_section(&self) -> String {
+        let mut asm = String::from(".section .debug_abbrev,\"\",@progbits\n.Labbrev_begin:\n");
+        for (code, tag, children, attributes) in ABBREVIATIONS {
+            writeln!(asm, "  .uleb128 {}\n  .uleb128 {}\n  .byte {}", code, tag, children as u8).unwrap();
+            for &(attribute, form) in attributes {
+                writeln!(asm, "  .uleb128 {}\n  .uleb128 {}", attribute, form).unwrap();
+            }
+            asm.push_str("  .byte 0\n  .byte 0\n");
+        }
+        asm.push_str("  .byte 0\n");
+        asm
+    }
+
+    fn info_section(&self) -> String {
+        let mut asm = String::from(".section .debug_info,\"\",@progbits\n");
+        // 编译单元头: 长度、版本(DWARF 4)、缩写表偏移、地址大小
+        asm.push_str(".Lcu_begin:\n  .word .Lcu_end-.Lcu_version\n.Lcu_version:\n  .half 4\n  .word .Labbrev_begin\n  .byte 4\n");
+
+        let comp_dir = std::env::current_dir().map(|dir| dir.display().to_string()).unwrap_or_default();
+        asm.push_str("  .uleb128 1\n");
+        writeln!(asm, "  .asciz \"pku-compiler\"\n  .half {}", DW_LANG_C99).unwrap();
+        writeln!(asm, "  .asciz \"{}\"", escape(self.files.first().map_or("", String::as_str))).unwrap();
+        writeln!(asm, "  .asciz \"{}\"", escape(&comp_dir)).unwrap();
+        asm.push_str("  .word .Lline_table_start\n  .word .Ltext_begin\n  .word .Ltext_end-.Ltext_begin\n");
+
+        for (index, subprogram) in self.subprograms.iter().enumerate() {
+            asm.push_str("  .uleb128 2\n");
+            writeln!(asm, "  .asciz \"{}\"", escape(&subprogram.name)).unwrap();
+            writeln!(asm, "  .byte {}\n  .word {}", subprogram.line.file, subprogram.line.line).unwrap();
+            writeln!(asm, "  .word {}\n  .word .Lfunc_end{}-{}", subprogram.label, index, subprogram.label).unwrap();
+            writeln!(asm, "  .byte 1\n  .byte {}", DW_OP_REG2).unwrap();
+            for variable in &subprogram.variables {
+                writeln!(asm, "  .uleb128 {}", if variable.is_param { 4 } else { 3 }).unwrap();
+                writeln!(asm, "  .asciz \"{}\"", escape(&variable.name)).unwrap();
+                writeln!(asm, "  .byte {}\n  .word {}", variable.line.file, variable.line.line).unwrap();
+                writeln!(asm, "  .word .Ldebug_type{}-.Lcu_begin", variable.ty).unwrap();
+                // DW_OP_fbreg <偏移>
+                writeln!(asm, "  .byte {}\n  .byte {}\n  .sleb128 {}", 1 + sleb128_len(variable.offset), DW_OP_FBREG, variable.offset).unwrap();
+            }
+            asm.push_str("  .byte 0\n");
+        }
+
+        for (index, debug_type) in self.types.iter().enumerate() {
+            writeln!(asm, ".Ldebug_type{}:", index).unwrap();
+            match debug_type {
+                DebugType::Base { is_float } => {
+                    let (name, encoding) = if *is_float { ("float", DW_ATE_FLOAT) } else { ("int", DW_ATE_SIGNED) };
+                    writeln!(asm, "  .uleb128 5\n  .asciz \"{}\"\n  .byte {}\n  .byte 4", name, encoding).unwrap();
+                }
+                DebugType::Pointer(base) => {
+                    writeln!(asm, "  .uleb128 6\n  .byte 4\n  .word .Ldebug_type{}-.Lcu_begin", base).unwrap();
+                }
+                DebugType::Array(elem, dims) => {
+                    writeln!(asm, "  .uleb128 7\n  .word .Ldebug_type{}-.Lcu_begin", elem).unwrap();
+                    for dim in dims {
+                        writeln!(asm, "  .uleb128 8\n  .word {}", dim).unwrap();
+                    }
+                    asm.push_str("  .byte 0\n");
+                }
+            }
+        }
+        asm.push_str("  .byte 0\n.Lcu_end:\n");
+        asm
+    }
+}
+
+/// 有符号LEB128编码的字节数
+fn sleb128_len(mut value: i32) -> usize {
+    let mut len = 1;
+    while !(-64..64).contains(&value) {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,65 @@
+//! 基本块布局: 决定函数中各基本块在汇编中的排列顺序, 使尽量多的跳转变为顺序执行(fallthrough)
+//!
+//! 从入口开始贪心地拼接链: 当前块之后优先放一个"就绪"的后继(除回边外的前驱都已排好), 有剖析数据时取执行次数多的,
+//! 否则取分支的真目标. 仍有块未排好的循环不会被提前跳出, 因此循环体保持连续.
+//! 冷块(入口不可达的块, 如 ensure_terminator 补上返回的块, 以及剖析中从未执行的块)排在最后
+use koopa::ir::{BasicBlock, FunctionData};
+use crate::lab9::cfg::Cfg;
+use crate::lab9::profile::Profile;
+
+/// 函数中基本块的排列顺序, 入口块总在最前
+pub fn block_order(func_data: &FunctionData, profile: Option<&Profile>) -> Vec<BasicBlock> {
+    let cfg = Cfg::new(func_data);
+    let n = cfg.blocks.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let count = |bb: usize| profile.and_then(|profile| profile.block_count(cfg.blocks[bb]));
+    let cold: Vec<bool> = (0..n).map(|bb| bb != 0 && (cfg.idom[bb].is_none() || count(bb) == Some(0))).collect();
+    let loops: Vec<Vec<bool>> = cfg.natural_loops().into_iter().map(|(_, body)| body).collect();
+
+    let mut placed = vec![false; n];
+    let mut order = Vec::with_capacity(n);
+    let mut current = Some(0);
+    while order.len() < n {
+        let bb = match current {
+            Some(bb) => bb,
+            None => next_chain_start(&cfg, &loops, &placed, &cold),
+        };
+        placed[bb] = true;
+        order.push(bb);
+
+        // 链的下一个块: 就绪且不跳出未排完的循环, 冷块只接在冷块之后
+        let mut succs = cfg.succs[bb].clone();
+        succs.sort_by_key(|&succ| std::cmp::Reverse(count(succ)));
+        current = succs.into_iter().find(|&succ| {
+            !placed[succ] && (cold[bb] || !cold[succ]) && is_ready(&cfg, &placed, succ) && stays_in_open_loops(&loops, &placed, succ)
+        });
+    }
+    order.into_iter().map(|bb| cfg.blocks[bb]).collect()
+}
+
+/// 新链的起点: 按布局顺序取第一个满足条件的块, 条件由严到宽逐级放松, 冷块最后考虑
+fn next_chain_start(cfg: &Cfg, loops: &[Vec<bool>], placed: &[bool], cold: &[bool]) -> usize {
+    let unplaced = |bb: &usize| !placed[*bb];
+    let n = cfg.blocks.len();
+    (0..n).filter(unplaced).find(|&bb| !cold[bb] && is_ready(cfg, placed, bb) && stays_in_open_loops(loops, placed, bb))
+        .or_else(|| (0..n).filter(unplaced).find(|&bb| !cold[bb] && is_ready(cfg, placed, bb)))
+        .or_else(|| (0..n).filter(unplaced).find(|&bb| !cold[bb]))
+        .or_else(|| (0..n).find(unplaced))
+        .unwrap()
+}
+
+/// 除回边外的前驱都已排好
+fn is_ready(cfg: &Cfg, placed: &[bool], bb: usize) -> bool {
+    cfg.preds[bb].iter().all(|&pred| placed[pred] || cfg.dominates(bb, pred))
+}
+
+/// bb 属于每个已开始但未排完的循环
+fn stays_in_open_loops(loops: &[Vec<bool>], placed: &[bool], bb: usize) -> bool {
+    loops.iter().all(|body| {
+        let open = body.iter().zip(placed).any(|(&inside, &placed)| inside && placed)
+            && body.iter().zip(placed).any(|(&inside, &placed)| inside && !placed);
+        !open || body[bb]
+    })
+}
@@ -0,0 +1,529 @@
+//! 目标文件输出: 把 codegen 生成的汇编直接编码为 RV32 可重定位 ELF(.o), 不依赖外部汇编器
+//!
+//! 只支持 codegen 会生成的指令与伪操作. 同一文件内的跳转在汇编时解析, 超出范围的条件跳转改写为反向条件跳转加 j;
+//! call 与 la 以及 `.word <符号>` 生成重定位, 由链接器解析到运行时库等外部符号.
+//! 调试信息(.loc 与 .debug_* 段)被忽略
+use crate::lab9::codegen::BLOCK_LABEL_PREFIX;
+use crate::lab9::elf::encode::{addi, b_type, branch_in_range, call_pair, encode, invert_branch, j_type, jump_in_range, u_type, Item, AUIPC};
+use crate::lab9::rvsim::{label_end, parse_imm, parse_string_literal, split_operands, strip_comment};
+use std::collections::{HashMap, HashSet};
+
+pub mod encode;
+
+const R_RISCV_32: u32 = 1;
+const R_RISCV_CALL_PLT: u32 = 19;
+const R_RISCV_PCREL_HI20: u32 = 23;
+const R_RISCV_PCREL_LO12_I: u32 = 24;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHF_WRITE: u32 = 0x1;
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+const SHF_INFO_LINK: u32 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+const EM_RISCV: u16 = 243;
+/// 浮点参数按 ilp32f 调用约定传递
+const EF_RISCV_FLOAT_ABI_SINGLE: u32 = 0x2;
+
+const TEXT: usize = 0;
+/// 段名与属性, 按此顺序输出
+const SECTIONS: [(&str, u32); 3] = [
+    (".text", SHF_ALLOC | SHF_EXECINSTR),
+    (".data", SHF_ALLOC | SHF_WRITE),
+    (".rodata", SHF_ALLOC),
+];
+
+/// 汇编 codegen 输出的汇编文本, 生成可重定位目标文件
+pub fn assemble_object(asm: &str) -> Result<Vec<u8>, String> {
+    let source = parse(asm)?;
+    let Layout { text, relocations: text_relocations, pcrel_labels, offsets } = layout_text(&source)?;
+
+    let mut sections: Vec<Section> = SECTIONS.iter().map(|&(name, flags)| Section::new(name, flags)).collect();
+    sections[TEXT].data = text;
+    sections[TEXT].relocations = text_relocations;
+    for (index, section) in source.data.into_iter().enumerate() {
+        sections[index + 1].data = section.bytes;
+        sections[index + 1].relocations = section.relocations;
+    }
+
+    // 符号: 定义的标签(基本块与 .L 开头的除外)、la 的辅助标签与引用到的未定义符号
+    let mut labels: Vec<(String, usize, usize)> = source.labels.iter()
+        .filter(|(name, _)| !is_internal_label(name))
+        .map(|(name, &(section, position))| (name.clone(), section, if section == TEXT { offsets[position] } else { position }))
+        .collect();
+    labels.sort_by(|a, b| (a.1, a.2, &a.0).cmp(&(b.1, b.2, &b.0)));
+    let mut symbols = Vec::new();
+    for (name, section, offset) in &labels {
+        // 大小为到同一段中下一个符号(或段尾)的距离
+        let end = labels.iter()
+            .filter(|(_, other, other_offset)| other == section && other_offset > offset)
+            .map(|&(_, _, other_offset)| other_offset)
+            .min()
+            .unwrap_or(sections[*section].data.len());
+        symbols.push(Symbol {
+            name: name.clone(),
+            global: source.globals.contains(name),
+            kind: if *section == TEXT { STT_FUNC } else { STT_OBJECT },
+            section: Some(*section),
+            value: *offset as u32,
+            size: (end - offset) as u32,
+        });
+    }
+    for (name, offset) in pcrel_labels {
+        symbols.push(Symbol { name, global: false, kind: STT_NOTYPE, section: Some(TEXT), value: offset, size: 0 });
+    }
+    let mut undefined: Vec<&String> = sections.iter()
+        .flat_map(|section| section.relocations.iter().map(|relocation| &relocation.symbol))
+        .filter(|&name| !source.labels.contains_key(name) && !name.starts_with(".Lpcrel_hi"))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    undefined.sort();
+    for name in undefined {
+        symbols.push(Symbol { name: name.clone(), global: true, kind: STT_NOTYPE, section: None, value: 0, size: 0 });
+    }
+    // 局部符号必须在全局符号之前
+    symbols.sort_by_key(|symbol| symbol.global);
+
+    write_object(&sections, &symbols)
+}
+
+/// 基本块标签与 .L 开头的标签只在汇编时使用, 不进入符号表
+fn is_internal_label(name: &str) -> bool {
+    name.starts_with(".L") || name.strip_prefix(BLOCK_LABEL_PREFIX).is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+// region 解析
+
+/// 数据段的内容
+#[derive(Default)]
+struct DataSection {
+    bytes: Vec<u8>,
+    relocations: Vec<Relocation>,
+}
+
+struct Source {
+    items: Vec<Item>,
+    data: [DataSection; 2], // .data 与 .rodata
+    /// 标签 -> (段, 位置), 代码段中的位置为第几个 Item, 数据段中为字节偏移
+    labels: HashMap<String, (usize, usize)>,
+    globals: HashSet<String>,
+}
+
+fn parse(asm: &str) -> Result<Source, String> {
+    let mut source = Source {
+        items: Vec::new(),
+        data: Default::default(),
+        labels: HashMap::new(),
+        globals: HashSet::new(),
+    };
+    let mut section = Some(TEXT); // None: 被忽略的调试信息段
+
+    for (line_no, raw_line) in asm.lines().enumerate() {
+        let error = |msg: &str| format!("line {}: {}: '{}'", line_no + 1, msg, raw_line.trim());
+        let mut line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (mnemonic, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        // 切换段
+        match mnemonic {
+            ".text" => { section = Some(TEXT); continue; }
+            ".data" => { section = Some(1); continue; }
+            ".rodata" => { section = Some(2); continue; }
+            ".section" => {
+                let name = rest.split(',').next().unwrap_or_default().trim();
+                section = SECTIONS.iter().position(|&(known, _)| name == known || name.starts_with(&format!("{}.", known)));
+                if section.is_none() && !name.starts_with(".debug") {
+                    return Err(error("unsupported section"));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let Some(section) = section else {
+            continue;
+        };
+
+        while let Some(end) = label_end(line) {
+            let label = line[..end].trim().to_string();
+            let position = match section {
+                TEXT => source.items.len(),
+                _ => source.data[section - 1].bytes.len(),
+            };
+            if source.labels.insert(label.clone(), (section, position)).is_some() {
+                return Err(error(&format!("redefinition of '{}'", label)));
+            }
+            line = line[end + 1..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (mnemonic, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let operands = split_operands(rest.trim());
+
+        if mnemonic.starts_with('.') {
+            match mnemonic {
+                ".global" | ".globl" => source.globals.extend(operands),
+                ".word" | ".half" | ".byte" | ".zero" | ".space" | ".asciz" | ".string" | ".ascii" | ".align" | ".p2align" => {
+                    if section == TEXT {
+                        return Err(error("data directive in the text section"));
+                    }
+                    data_directive(&mut source.data[section - 1], mnemonic, rest.trim(), &operands).map_err(|msg| error(&msg))?;
+                }
+                // .loc .file .type .size 等与代码无关
+                _ => {}
+            }
+            continue;
+        }
+        if section != TEXT {
+            return Err(error("instruction outside of the text section"));
+        }
+        source.items.extend(encode(mnemonic, &operands).map_err(|msg| error(&msg))?);
+    }
+    Ok(source)
+}
+
+fn data_directive(section: &mut DataSection, mnemonic: &str, rest: &str, operands: &[String]) -> Result<(), String> {
+    match mnemonic {
+        ".word" | ".half" | ".byte" => {
+            let width = match mnemonic { ".word" => 4, ".half" => 2, _ => 1 };
+            for operand in operands {
+                match parse_imm(operand) {
+                    Some(value) => section.bytes.extend_from_slice(&value.to_le_bytes()[..width]),
+                    None if width == 4 => {
+                        section.relocations.push(Relocation::new(section.bytes.len(), R_RISCV_32, operand, 0));
+                        section.bytes.extend_from_slice(&[0; 4]);
+                    }
+                    None => return Err(format!("invalid value '{}'", operand)),
+                }
+            }
+        }
+        ".zero" | ".space" => {
+            let size = operands.first().and_then(|size| parse_imm(size)).filter(|&size| size >= 0).ok_or("invalid size")?;
+            section.bytes.resize(section.bytes.len() + size as usize, 0);
+        }
+        ".asciz" | ".string" | ".ascii" => {
+            section.bytes.extend(parse_string_literal(rest).ok_or("invalid string literal")?);
+            if mnemonic != ".ascii" {
+                section.bytes.push(0);
+            }
+        }
+        _ => {
+            let align = operands.first().and_then(|n| parse_imm(n)).filter(|n| (0..16).contains(n)).ok_or("invalid alignment")?;
+            let align = 1 << align;
+            section.bytes.resize(section.bytes.len().div_ceil(align) * align, 0);
+        }
+    }
+    Ok(())
+}
+
+// region 布局
+
+/// 编码后的代码段
+struct Layout {
+    text: Vec<u8>,
+    relocations: Vec<Relocation>,
+    pcrel_labels: Vec<(String, u32)>, // la 中 %pcrel_lo 引用的辅助标签及其位置
+    offsets: Vec<usize>,              // 每个 Item 的字节偏移, 最后一项为代码段的大小
+}
+
+/// 确定代码段的布局(放长超出范围的条件跳转)并编码
+fn layout_text(source: &Source) -> Result<Layout, String> {
+    let target_item = |target: &String| match source.labels.get(target) {
+        Some(&(TEXT, item)) => Ok(item),
+        Some(_) => Err(format!("jump to '{}' outside of the text section", target)),
+        None => Err(format!("undefined label '{}'", target)),
+    };
+
+    // 从全部短跳转开始, 把超出范围的条件跳转逐个改为长跳转, 直到不再变化(跳转只会变长, 因此一定收敛)
+    let mut long = vec![false; source.items.len()];
+    let offsets = loop {
+        let mut offsets = Vec::with_capacity(source.items.len() + 1);
+        let mut offset = 0;
+        for (item, &long) in source.items.iter().zip(&long) {
+            offsets.push(offset);
+            offset += if long { 8 } else { item.size() };
+        }
+        offsets.push(offset);
+
+        let mut changed = false;
+        for (index, item) in source.items.iter().enumerate() {
+            if let Item::Branch { target, .. } = item {
+                let distance = offsets[target_item(target)?] as i64 - offsets[index] as i64;
+                if !long[index] && !branch_in_range(distance) {
+                    long[index] = true;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break offsets;
+        }
+    };
+
+    let mut text = Vec::with_capacity(offsets[source.items.len()]);
+    let mut relocations = Vec::new();
+    let mut pcrel_labels = Vec::new();
+    for (index, item) in source.items.iter().enumerate() {
+        let offset = offsets[index];
+        let mut emit = |inst: u32| text.extend_from_slice(&inst.to_le_bytes());
+        match item {
+            Item::Word(inst) => emit(*inst),
+            Item::Branch { funct3, rs1, rs2, target } => {
+                let distance = offsets[target_item(target)?] as i64 - offset as i64;
+                if long[index] {
+                    // 条件不成立时跳过紧随其后的 j
+                    let distance = distance - 4;
+                    if !jump_in_range(distance) {
+                        return Err(format!("branch to '{}' is out of range", target));
+                    }
+                    emit(b_type(8, *rs2, *rs1, invert_branch(*funct3)));
+                    emit(j_type(distance as i32, 0));
+                } else {
+                    emit(b_type(distance as i32, *rs2, *rs1, *funct3));
+                }
+            }
+            Item::Jump { rd, target } => {
+                let distance = offsets[target_item(target)?] as i64 - offset as i64;
+                if !jump_in_range(distance) {
+                    return Err(format!("jump to '{}' is out of range", target));
+                }
+                emit(j_type(distance as i32, *rd));
+            }
+            Item::Call { link, symbol } => {
+                relocations.push(Relocation::new(offset, R_RISCV_CALL_PLT, symbol, 0));
+                let (auipc, jalr) = call_pair(*link);
+                emit(auipc);
+                emit(jalr);
+            }
+            Item::LoadAddress { rd, symbol } => {
+                // %pcrel_lo 引用的是 auipc 所在位置的标签
+                let label = format!(".Lpcrel_hi{}", pcrel_labels.len());
+                relocations.push(Relocation::new(offset, R_RISCV_PCREL_HI20, symbol, 0));
+                relocations.push(Relocation::new(offset + 4, R_RISCV_PCREL_LO12_I, &label, 0));
+                pcrel_labels.push((label, offset as u32));
+                emit(u_type(0, *rd, AUIPC));
+                emit(addi(*rd, *rd, 0));
+            }
+        }
+    }
+    Ok(Layout { text, relocations, pcrel_labels, offsets })
+}
+
+// region ELF
+
+struct Relocation {
+    offset: u32,
+    kind: u32,
+    symbol: String,
+    addend: i32,
+}
+
+impl Relocation {
+    fn new(offset: usize, kind: u32, symbol: &str, addend: i32) -> Self {
+        Relocation { offset: offset as u32, kind, symbol: symbol.to_string(), addend }
+    }
+}
+
+struct Section {
+    name: &'static str,
+    flags: u32,
+    data: Vec<u8>,
+    relocations: Vec<Relocation>,
+}
+
+impl Section {
+    fn new(name: &'static str, flags: u32) -> Self {
+        Section { name, flags, data: Vec::new(), relocations: Vec::new() }
+    }
+}
+
+struct Symbol {
+    name: String,
+    global: bool,
+    kind: u8,
+    section: Option<usize>, // 在 SECTIONS 中的下标, None 为未定义
+    value: u32,
+    size: u32,
+}
+
+/// 字符串表, 下标0为空串
+struct StringTable(Vec<u8>);
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable(vec![0])
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        let index = self.0.len() as u32;
+        self.0.extend_from_slice(name.as_bytes());
+        self.0.push(0);
+        index
+    }
+}
+
+/// 节头
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entry_size: u32,
+}
+
+fn write_object(sections: &[Section], symbols: &[Symbol]) -> Result<Vec<u8>, String> {
+    const HEADER_SIZE: usize = 52;
+    const SECTION_HEADER_SIZE: usize = 40;
+
+    // 节的顺序: 空节, 代码与数据段(代码段总是输出, 空的数据段省略), 各自的重定位节, .symtab .strtab .shstrtab
+    let present: Vec<usize> = (0..sections.len()).filter(|&index| index == TEXT || !sections[index].data.is_empty()).collect();
+    let section_index = |section: usize| present.iter().position(|&index| index == section).map(|i| i as u32 + 1);
+    let relocated: Vec<usize> = present.iter().copied().filter(|&index| !sections[index].relocations.is_empty()).collect();
+    let symtab_index = (1 + present.len() + relocated.len()) as u32;
+
+    let mut shstrtab = StringTable::new();
+    let mut headers = Vec::new();
+    let mut body: Vec<u8> = Vec::new();
+    // 各节的内容按4字节对齐依次放在ELF头之后
+    let place = |body: &mut Vec<u8>, bytes: &[u8]| {
+        body.resize(body.len().next_multiple_of(4), 0);
+        let offset = (HEADER_SIZE + body.len()) as u32;
+        body.extend_from_slice(bytes);
+        offset
+    };
+
+    for &index in &present {
+        let section = &sections[index];
+        headers.push(SectionHeader {
+            name: shstrtab.add(section.name),
+            kind: SHT_PROGBITS,
+            flags: section.flags,
+            offset: place(&mut body, &section.data),
+            size: section.data.len() as u32,
+            link: 0,
+            info: 0,
+            align: 4,
+            entry_size: 0,
+        });
+    }
+
+    // 符号表, 下标0为空符号
+    let mut strtab = StringTable::new();
+    let mut symtab = vec![0u8; 16];
+    let mut symbol_index = HashMap::new();
+    for (index, symbol) in symbols.iter().enumerate() {
+        symbol_index.insert(symbol.name.as_str(), index as u32 + 1);
+        let shndx = match symbol.section {
+            Some(section) => section_index(section).ok_or_else(|| format!("symbol '{}' is in an empty section", symbol.name))?,
+            None => 0,
+        };
+        let bind = if symbol.global { STB_GLOBAL } else { STB_LOCAL };
+        symtab.extend_from_slice(&strtab.add(&symbol.name).to_le_bytes());
+        symtab.extend_from_slice(&symbol.value.to_le_bytes());
+        symtab.extend_from_slice(&symbol.size.to_le_bytes());
+        symtab.push(bind << 4 | symbol.kind);
+        symtab.push(0);
+        symtab.extend_from_slice(&(shndx as u16).to_le_bytes());
+    }
+    let first_global = symbols.iter().position(|symbol| symbol.global).unwrap_or(symbols.len()) as u32 + 1;
+
+    for &index in &relocated {
+        let section = &sections[index];
+        let mut rela = Vec::new();
+        for relocation in &section.relocations {
+            let symbol = symbol_index[relocation.symbol.as_str()];
+            rela.extend_from_slice(&relocation.offset.to_le_bytes());
+            rela.extend_from_slice(&(symbol << 8 | relocation.kind).to_le_bytes());
+            rela.extend_from_slice(&relocation.addend.to_le_bytes());
+        }
+        headers.push(SectionHeader {
+            name: shstrtab.add(&format!(".rela{}", section.name)),
+            kind: SHT_RELA,
+            flags: SHF_INFO_LINK,
+            offset: place(&mut body, &rela),
+            size: rela.len() as u32,
+            link: symtab_index,
+            info: section_index(index).unwrap(),
+            align: 4,
+            entry_size: 12,
+        });
+    }
+
+    headers.push(SectionHeader {
+        name: shstrtab.add(".symtab"),
+        kind: SHT_SYMTAB,
+        flags: 0,
+        offset: place(&mut body, &symtab),
+        size: symtab.len() as u32,
+        link: symtab_index + 1,
+        info: first_global,
+        align: 4,
+        entry_size: 16,
+    });
+    headers.push(SectionHeader {
+        name: shstrtab.add(".strtab"),
+        kind: SHT_STRTAB,
+        flags: 0,
+        offset: place(&mut body, &strtab.0),
+        size: strtab.0.len() as u32,
+        link: 0,
+        info: 0,
+        align: 1,
+        entry_size: 0,
+    });
+    let name = shstrtab.add(".shstrtab");
+    headers.push(SectionHeader {
+        name,
+        kind: SHT_STRTAB,
+        flags: 0,
+        offset: place(&mut body, &shstrtab.0),
+        size: shstrtab.0.len() as u32,
+        link: 0,
+        info: 0,
+        align: 1,
+        entry_size: 0,
+    });
+    let section_headers_offset = place(&mut body, &[]);
+
+    let mut elf = Vec::new();
+    elf.extend_from_slice(b"\x7fELF");
+    elf.extend_from_slice(&[1, 1, 1, 0]); // 32位, 小端, 版本1, System V
+    elf.extend_from_slice(&[0; 8]);
+    elf.extend_from_slice(&1u16.to_le_bytes()); // ET_REL
+    elf.extend_from_slice(&EM_RISCV.to_le_bytes());
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&0u32.to_le_bytes()); // 入口
+    elf.extend_from_slice(&0u32.to_le_bytes()); // 程序头
+    elf.extend_from_slice(&section_headers_offset.to_le_bytes());
+    elf.extend_from_slice(&EF_RISCV_FLOAT_ABI_SINGLE.to_le_bytes());
+    elf.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&[0; 4]); // 程序头的大小与个数
+    elf.extend_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&(headers.len() as u16 + 1).to_le_bytes());
+    elf.extend_from_slice(&(headers.len() as u16).to_le_bytes()); // .shstrtab 是最后一节
+    elf.extend_from_slice(&body);
+
+    elf.extend_from_slice(&[0; SECTION_HEADER_SIZE]);
+    for header in headers {
+        for field in [header.name, header.kind, header.flags, 0, header.offset, header.size, header.link, header.info, header.align, header.entry_size] {
+            elf.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    Ok(elf)
+}
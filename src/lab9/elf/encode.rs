@@ -0,0 +1,344 @@
+//! 指令编码: codegen 会生成的 RV32IMF 指令与常见伪指令(另支持 fld/fsd/fcvt.d.s 等少量 D 扩展指令)
+use crate::lab9::rvsim::parse_imm;
+
+/// 一条汇编指令展开后的结果, 引用标签或符号的部分在布局确定后编码
+#[derive(Debug, Clone)]
+pub enum Item {
+    Word(u32),
+    /// 条件跳转, 目标超出范围时展开为反向条件跳转加 j
+    Branch { funct3: u32, rs1: u32, rs2: u32, target: String },
+    /// jal rd, 目标
+    Jump { rd: u32, target: String },
+    /// call/tail: auipc + jalr, 由链接器通过 R_RISCV_CALL_PLT 重定位
+    Call { link: bool, symbol: String },
+    /// la: auipc + addi, 由链接器通过 R_RISCV_PCREL_HI20/LO12_I 重定位
+    LoadAddress { rd: u32, symbol: String },
+}
+
+impl Item {
+    /// 字节数, 条件跳转按短格式计算
+    pub fn size(&self) -> usize {
+        match self {
+            Item::Word(_) | Item::Branch { .. } | Item::Jump { .. } => 4,
+            Item::Call { .. } | Item::LoadAddress { .. } => 8,
+        }
+    }
+}
+
+const OP: u32 = 0x33;
+const OP_IMM: u32 = 0x13;
+const LOAD: u32 = 0x03;
+const STORE: u32 = 0x23;
+const LOAD_FP: u32 = 0x07;
+const STORE_FP: u32 = 0x27;
+const OP_FP: u32 = 0x53;
+const BRANCH: u32 = 0x63;
+const JAL: u32 = 0x6f;
+const JALR: u32 = 0x67;
+const LUI: u32 = 0x37;
+pub const AUIPC: u32 = 0x17;
+
+const RA: u32 = 1;
+const T1: u32 = 6;
+/// 未指定舍入模式时使用动态舍入(与 GNU as 一致)
+const RM_DYN: u32 = 7;
+
+pub fn r_type(funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn i_type(imm: i32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+
+fn s_type(imm: i32, rs2: u32, rs1: u32, funct3: u32, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7f) << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | (imm & 0x1f) << 7 | opcode
+}
+
+pub fn b_type(offset: i32, rs2: u32, rs1: u32, funct3: u32) -> u32 {
+    let imm = offset as u32;
+    (imm >> 12 & 1) << 31 | (imm >> 5 & 0x3f) << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12
+        | (imm >> 1 & 0xf) << 8 | (imm >> 11 & 1) << 7 | BRANCH
+}
+
+pub fn j_type(offset: i32, rd: u32) -> u32 {
+    let imm = offset as u32;
+    (imm >> 20 & 1) << 31 | (imm >> 1 & 0x3ff) << 21 | (imm >> 11 & 1) << 20 | (imm >> 12 & 0xff) << 12 | rd << 7 | JAL
+}
+
+pub fn u_type(imm: u32, rd: u32, opcode: u32) -> u32 {
+    imm << 12 | rd << 7 | opcode
+}
+
+pub fn jalr(rd: u32, rs1: u32, offset: i32) -> u32 {
+    i_type(offset, rs1, 0, rd, JALR)
+}
+
+pub fn addi(rd: u32, rs1: u32, imm: i32) -> u32 {
+    i_type(imm, rs1, 0, rd, OP_IMM)
+}
+
+/// 跳转目标的偏移是否在条件跳转(±4KiB)的范围内
+pub fn branch_in_range(offset: i64) -> bool {
+    (-4096..4096).contains(&offset)
+}
+
+/// 跳转目标的偏移是否在 jal(±1MiB) 的范围内
+pub fn jump_in_range(offset: i64) -> bool {
+    (-(1 << 20)..1 << 20).contains(&offset)
+}
+
+/// 反转条件跳转的条件(beq<->bne, blt<->bge, bltu<->bgeu)
+pub fn invert_branch(funct3: u32) -> u32 {
+    funct3 ^ 1
+}
+
+fn fits_i12(imm: i64) -> bool {
+    (-2048..2048).contains(&imm)
+}
+
+fn int_reg(name: &str) -> Result<u32, String> {
+    const ABI_NAMES: [&str; 32] = [
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+        "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+    ];
+    if name == "fp" {
+        return Ok(8);
+    }
+    ABI_NAMES.iter().position(|&reg| reg == name)
+        .or_else(|| name.strip_prefix('x').and_then(|n| n.parse().ok()).filter(|&n: &usize| n < 32))
+        .map(|n| n as u32)
+        .ok_or_else(|| format!("invalid register '{}'", name))
+}
+
+fn float_reg(name: &str) -> Result<u32, String> {
+    let numbered = |prefix: &str, base: u32, count: u32| {
+        name.strip_prefix(prefix).and_then(|n| n.parse::<u32>().ok()).filter(|&n| n < count).map(|n| base + n)
+    };
+    // ft8-ft11 在 ft0-ft7 之后单独编号, fs2-fs11 同理
+    let reg = match name {
+        _ if name.starts_with("ft") => numbered("ft", 0, 8).or_else(|| numbered("ft", 20, 12).filter(|&n| n >= 28)),
+        _ if name.starts_with("fs") => numbered("fs", 8, 2).or_else(|| numbered("fs", 16, 12).filter(|&n| n >= 18)),
+        _ if name.starts_with("fa") => numbered("fa", 10, 8),
+        _ => numbered("f", 0, 32),
+    };
+    reg.ok_or_else(|| format!("invalid register '{}'", name))
+}
+
+fn imm12(text: &str) -> Result<i32, String> {
+    parse_imm(text).filter(|&imm| fits_i12(imm)).map(|imm| imm as i32)
+        .ok_or_else(|| format!("immediate '{}' is out of range", text))
+}
+
+/// 解析 `offset(base)` 形式的访存操作数
+fn memory_operand(text: &str) -> Result<(i32, u32), String> {
+    let (offset, base) = text.strip_suffix(')').and_then(|text| text.split_once('('))
+        .ok_or_else(|| format!("invalid memory operand '{}'", text))?;
+    let offset = if offset.is_empty() { 0 } else { imm12(offset)? };
+    Ok((offset, int_reg(base.trim())?))
+}
+
+fn rounding_mode(text: &str) -> Result<u32, String> {
+    ["rne", "rtz", "rdn", "rup", "rmm"].iter().position(|&mode| mode == text).map(|mode| mode as u32)
+        .or((text == "dyn").then_some(RM_DYN))
+        .ok_or_else(|| format!("invalid rounding mode '{}'", text))
+}
+
+/// li 的展开: 12位以内用 addi, 否则用 lui(+addi)
+fn load_immediate(rd: u32, imm: i64) -> Result<Vec<Item>, String> {
+    if !(i32::MIN as i64..=u32::MAX as i64).contains(&imm) {
+        return Err(format!("immediate {} is out of range", imm));
+    }
+    let imm = imm as i32;
+    if fits_i12(imm as i64) {
+        return Ok(vec![Item::Word(addi(rd, 0, imm))]);
+    }
+    let hi = (imm as u32).wrapping_add(0x800) >> 12;
+    let lo = imm.wrapping_sub((hi << 12) as i32);
+    let mut items = vec![Item::Word(u_type(hi, rd, LUI))];
+    if lo != 0 {
+        items.push(Item::Word(addi(rd, rd, lo)));
+    }
+    Ok(items)
+}
+
+/// 编码一条指令
+pub fn encode(mnemonic: &str, operands: &[String]) -> Result<Vec<Item>, String> {
+    let count = |n: usize| match operands.len() == n {
+        true => Ok(()),
+        false => Err(format!("'{}' expects {} operands", mnemonic, n)),
+    };
+    let x = |i: usize| int_reg(&operands[i]);
+    let f = |i: usize| float_reg(&operands[i]);
+    let word = |inst: u32| Ok(vec![Item::Word(inst)]);
+
+    let alu = match mnemonic {
+        "add" => Some((0x00, 0)), "sub" => Some((0x20, 0)), "sll" => Some((0x00, 1)), "slt" => Some((0x00, 2)),
+        "sltu" => Some((0x00, 3)), "xor" => Some((0x00, 4)), "srl" => Some((0x00, 5)), "sra" => Some((0x20, 5)),
+        "or" => Some((0x00, 6)), "and" => Some((0x00, 7)),
+        "mul" => Some((0x01, 0)), "mulh" => Some((0x01, 1)), "mulhsu" => Some((0x01, 2)), "mulhu" => Some((0x01, 3)),
+        "div" => Some((0x01, 4)), "divu" => Some((0x01, 5)), "rem" => Some((0x01, 6)), "remu" => Some((0x01, 7)),
+        _ => None,
+    };
+    if let Some((funct7, funct3)) = alu {
+        count(3)?;
+        return word(r_type(funct7, x(2)?, x(1)?, funct3, x(0)?, OP));
+    }
+    let alu_imm = match mnemonic {
+        "addi" => Some(0), "slti" => Some(2), "sltiu" => Some(3), "xori" => Some(4), "ori" => Some(6), "andi" => Some(7),
+        _ => None,
+    };
+    if let Some(funct3) = alu_imm {
+        count(3)?;
+        return word(i_type(imm12(&operands[2])?, x(1)?, funct3, x(0)?, OP_IMM));
+    }
+    let shift = match mnemonic {
+        "slli" => Some((0x000, 1)), "srli" => Some((0x000, 5)), "srai" => Some((0x400, 5)),
+        _ => None,
+    };
+    if let Some((high, funct3)) = shift {
+        count(3)?;
+        let shamt = parse_imm(&operands[2]).filter(|shamt| (0..32).contains(shamt))
+            .ok_or_else(|| format!("invalid shift amount '{}'", operands[2]))?;
+        return word(i_type(high | shamt as i32, x(1)?, funct3, x(0)?, OP_IMM));
+    }
+    let load = match mnemonic {
+        "lb" => Some((LOAD, 0)), "lh" => Some((LOAD, 1)), "lw" => Some((LOAD, 2)), "lbu" => Some((LOAD, 4)), "lhu" => Some((LOAD, 5)),
+        "flw" => Some((LOAD_FP, 2)), "fld" => Some((LOAD_FP, 3)),
+        _ => None,
+    };
+    if let Some((opcode, funct3)) = load {
+        count(2)?;
+        let rd = if opcode == LOAD { x(0)? } else { f(0)? };
+        let (offset, base) = memory_operand(&operands[1])?;
+        return word(i_type(offset, base, funct3, rd, opcode));
+    }
+    let store = match mnemonic {
+        "sb" => Some((STORE, 0)), "sh" => Some((STORE, 1)), "sw" => Some((STORE, 2)),
+        "fsw" => Some((STORE_FP, 2)), "fsd" => Some((STORE_FP, 3)),
+        _ => None,
+    };
+    if let Some((opcode, funct3)) = store {
+        count(2)?;
+        let rs = if opcode == STORE { x(0)? } else { f(0)? };
+        let (offset, base) = memory_operand(&operands[1])?;
+        return word(s_type(offset, rs, base, funct3, opcode));
+    }
+    let branch = match mnemonic {
+        "beq" => Some((0, false)), "bne" => Some((1, false)), "blt" => Some((4, false)), "bge" => Some((5, false)),
+        "bltu" => Some((6, false)), "bgeu" => Some((7, false)),
+        "bgt" => Some((4, true)), "ble" => Some((5, true)), "bgtu" => Some((6, true)), "bleu" => Some((7, true)),
+        _ => None,
+    };
+    if let Some((funct3, swap)) = branch {
+        count(3)?;
+        let (rs1, rs2) = if swap { (x(1)?, x(0)?) } else { (x(0)?, x(1)?) };
+        return Ok(vec![Item::Branch { funct3, rs1, rs2, target: operands[2].clone() }]);
+    }
+    // 与零比较的条件跳转: (funct3, 寄存器是否作为 rs2)
+    let branch_zero = match mnemonic {
+        "beqz" => Some((0, false)), "bnez" => Some((1, false)), "bltz" => Some((4, false)), "bgez" => Some((5, false)),
+        "bgtz" => Some((4, true)), "blez" => Some((5, true)),
+        _ => None,
+    };
+    if let Some((funct3, swap)) = branch_zero {
+        count(2)?;
+        let (rs1, rs2) = if swap { (0, x(0)?) } else { (x(0)?, 0) };
+        return Ok(vec![Item::Branch { funct3, rs1, rs2, target: operands[1].clone() }]);
+    }
+    let float_arith = match mnemonic {
+        "fadd.s" => Some(0x00), "fsub.s" => Some(0x04), "fmul.s" => Some(0x08), "fdiv.s" => Some(0x0c),
+        _ => None,
+    };
+    if let Some(funct7) = float_arith {
+        let rm = match operands.len() {
+            4 => rounding_mode(&operands[3])?,
+            _ => { count(3)?; RM_DYN }
+        };
+        return word(r_type(funct7, f(2)?, f(1)?, rm, f(0)?, OP_FP));
+    }
+    // 结果写入 rd 的浮点运算/比较: (funct7, funct3, rd 是否为整数寄存器)
+    let float_op = match mnemonic {
+        "fsgnj.s" => Some((0x10, 0, false)), "fsgnjn.s" => Some((0x10, 1, false)), "fsgnjx.s" => Some((0x10, 2, false)),
+        "fmin.s" => Some((0x14, 0, false)), "fmax.s" => Some((0x14, 1, false)),
+        "feq.s" => Some((0x50, 2, true)), "flt.s" => Some((0x50, 1, true)), "fle.s" => Some((0x50, 0, true)),
+        _ => None,
+    };
+    if let Some((funct7, funct3, int_rd)) = float_op {
+        count(3)?;
+        let rd = if int_rd { x(0)? } else { f(0)? };
+        return word(r_type(funct7, f(2)?, f(1)?, funct3, rd, OP_FP));
+    }
+    // 单操作数的浮点指令: (funct7, rs2, 目标/源是否为整数寄存器, 是否带舍入模式)
+    let float_unary = match mnemonic {
+        "fsqrt.s" => Some((0x2c, 0, (false, false), true)),
+        "fcvt.w.s" => Some((0x60, 0, (true, false), true)), "fcvt.wu.s" => Some((0x60, 1, (true, false), true)),
+        "fcvt.s.w" => Some((0x68, 0, (false, true), true)), "fcvt.s.wu" => Some((0x68, 1, (false, true), true)),
+        "fmv.x.w" => Some((0x70, 0, (true, false), false)), "fmv.w.x" => Some((0x78, 0, (false, true), false)),
+        "fcvt.d.s" => Some((0x21, 0, (false, false), false)), "fcvt.s.d" => Some((0x20, 1, (false, false), true)),
+        _ => None,
+    };
+    if let Some((funct7, rs2, (int_rd, int_rs), rounding)) = float_unary {
+        let rm = match operands.len() {
+            3 if rounding => rounding_mode(&operands[2])?,
+            _ => { count(2)?; if rounding { RM_DYN } else { 0 } }
+        };
+        let rd = if int_rd { x(0)? } else { f(0)? };
+        let rs1 = if int_rs { x(1)? } else { f(1)? };
+        return word(r_type(funct7, rs2, rs1, rm, rd, OP_FP));
+    }
+
+    match mnemonic {
+        "lui" | "auipc" => {
+            count(2)?;
+            let imm = parse_imm(&operands[1]).filter(|imm| (0..1 << 20).contains(imm))
+                .ok_or_else(|| format!("immediate '{}' is out of range", operands[1]))?;
+            word(u_type(imm as u32, x(0)?, if mnemonic == "lui" { LUI } else { AUIPC }))
+        }
+        "li" => {
+            count(2)?;
+            let imm = parse_imm(&operands[1]).ok_or_else(|| format!("invalid immediate '{}'", operands[1]))?;
+            load_immediate(x(0)?, imm)
+        }
+        "la" | "lla" => {
+            count(2)?;
+            Ok(vec![Item::LoadAddress { rd: x(0)?, symbol: operands[1].clone() }])
+        }
+        "mv" => { count(2)?; word(addi(x(0)?, x(1)?, 0)) }
+        "not" => { count(2)?; word(i_type(-1, x(1)?, 4, x(0)?, OP_IMM)) }
+        "neg" => { count(2)?; word(r_type(0x20, x(1)?, 0, 0, x(0)?, OP)) }
+        "seqz" => { count(2)?; word(i_type(1, x(1)?, 3, x(0)?, OP_IMM)) }
+        "snez" => { count(2)?; word(r_type(0, x(1)?, 0, 3, x(0)?, OP)) }
+        "sltz" => { count(2)?; word(r_type(0, 0, x(1)?, 2, x(0)?, OP)) }
+        "sgtz" => { count(2)?; word(r_type(0, x(1)?, 0, 2, x(0)?, OP)) }
+        "fmv.s" => { count(2)?; word(r_type(0x10, f(1)?, f(1)?, 0, f(0)?, OP_FP)) }
+        "fneg.s" => { count(2)?; word(r_type(0x10, f(1)?, f(1)?, 1, f(0)?, OP_FP)) }
+        "fabs.s" => { count(2)?; word(r_type(0x10, f(1)?, f(1)?, 2, f(0)?, OP_FP)) }
+        "nop" => { count(0)?; word(addi(0, 0, 0)) }
+        "ret" => { count(0)?; word(jalr(0, RA, 0)) }
+        "jr" => { count(1)?; word(jalr(0, x(0)?, 0)) }
+        "jalr" => match operands.len() {
+            1 => word(jalr(RA, x(0)?, 0)),
+            _ => {
+                count(2)?;
+                let (offset, base) = memory_operand(&operands[1])?;
+                word(jalr(x(0)?, base, offset))
+            }
+        },
+        "j" => { count(1)?; Ok(vec![Item::Jump { rd: 0, target: operands[0].clone() }]) }
+        "jal" => match operands.len() {
+            1 => Ok(vec![Item::Jump { rd: RA, target: operands[0].clone() }]),
+            _ => { count(2)?; Ok(vec![Item::Jump { rd: x(0)?, target: operands[1].clone() }]) }
+        },
+        "call" => { count(1)?; Ok(vec![Item::Call { link: true, symbol: operands[0].clone() }]) }
+        "tail" => { count(1)?; Ok(vec![Item::Call { link: false, symbol: operands[0].clone() }]) }
+        _ => Err(format!("unsupported instruction '{}'", mnemonic)),
+    }
+}
+
+/// call/tail 展开后的两条指令: auipc 与 jalr, call 经 ra 返回, tail 用 t1 作临时寄存器
+pub fn call_pair(link: bool) -> (u32, u32) {
+    let (temp, rd) = if link { (RA, RA) } else { (T1, 0) };
+    (u_type(0, temp, AUIPC), jalr(rd, temp, 0))
+}
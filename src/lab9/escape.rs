@@ -0,0 +1,40 @@
+//! 逃逸分析: 找出地址被取走(逃逸)的局部变量
+//!
+//! 局部变量在 IR 中是一条 alloc, 如果它只被 load 读取、被 store 写入,
+//! 就可以整个提升到寄存器(mem2reg)中; 一旦地址被存进别的变量、作为实参传出、
+//! 参与 getptr/getelemptr 等指针运算, 就可能经由指针被间接读写, 其 alloc 必须保留在栈上
+//!
+//! ```text
+//! %x = alloc i32
+//! store 1, %x          // 不逃逸: 直接写
+//! %0 = load %x         // 不逃逸: 直接读
+//! store %x, %p         // 逃逸: 地址被存进指针变量 p
+//! call @f(%x)          // 逃逸: 地址传给了其他函数
+//! ```
+use koopa::ir::{FunctionData, Value, ValueKind};
+use std::collections::HashSet;
+
+/// 函数中地址逃逸的 alloc
+pub fn escaping_allocs(func_data: &FunctionData) -> HashSet<Value> {
+    allocs(func_data)
+        .filter(|&alloc| !only_loaded_and_stored(func_data, alloc))
+        .collect()
+}
+
+fn allocs(func_data: &FunctionData) -> impl Iterator<Item = Value> + '_ {
+    func_data.layout().bbs().nodes()
+        .flat_map(|bb_node| bb_node.insts().keys())
+        .copied()
+        .filter(|&inst| matches!(func_data.dfg().value(inst).kind(), ValueKind::Alloc(_)))
+}
+
+// alloc 的每个使用者都只把它当作 load 的源地址或 store 的目标地址
+fn only_loaded_and_stored(func_data: &FunctionData, alloc: Value) -> bool {
+    func_data.dfg().value(alloc).used_by().iter().all(|&user| {
+        match func_data.dfg().value(user).kind() {
+            ValueKind::Load(load) => load.src() == alloc,
+            ValueKind::Store(store) => store.dest() == alloc && store.value() != alloc,
+            _ => false,
+        }
+    })
+}
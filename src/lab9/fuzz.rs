@@ -0,0 +1,205 @@
+//! 随机测试: 生成随机程序(gen), 经完整流水线(打印源码 -> 解析 -> IR生成 -> 校验 -> 循环展开 -> codegen)编译,
+//! 以(循环展开前的) Koopa IR 解释器的结果为参照比较 RISC-V 模拟执行的结果.
+//! 编译器 panic、拒绝合法程序或两者结果不一致时, 用 reduce 把程序缩小到仍有同类错误后报告
+use crate::ast::CompUnit;
+use crate::lab9::codegen::generate_riscv_assembly;
+use crate::lab9::interp::run_program;
+use crate::lab9::irgen::IRGen;
+use crate::lab9::pretty::format_comp_unit;
+use crate::lab9::reduce::{reduce, Predicate};
+use crate::lab9::runtime::ExecError;
+use crate::lab9::rvsim::run_assembly;
+use crate::lab9::tailcall::eliminate_tail_recursion;
+use crate::lab9::unroll::{unroll_loops, DEFAULT_UNROLL_FACTOR};
+use crate::lab9::verify::verify_program;
+use crate::sysy::CompUnitParser;
+use koopa::ir::Type;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+pub mod gen;
+
+/// 解释器与模拟器执行的指令数上限, 生成的程序远小于这个规模
+const MAX_STEPS: u64 = 5_000_000;
+
+/// 确定性的伪随机数生成器(SplitMix64), 同一种子总是生成同一个程序
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// [0, n) 中的随机数
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// [lo, hi] 中的随机数
+    pub fn range(&mut self, lo: i32, hi: i32) -> i32 {
+        lo + self.below((hi - lo + 1) as usize) as i32
+    }
+
+    /// 以 percent% 的概率返回true
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+/// 一个程序暴露出的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    Rejected(String),                          // 前端拒绝了程序(解析或IR生成报错)
+    Panic { stage: &'static str, message: String },
+    InvalidIr(String),                         // IR生成的结果没有通过校验
+    Fault(String),                             // 解释执行出错(越界访问等)
+    Diverged { koopa: String, riscv: String }, // 解释执行与模拟执行的结果不一致
+}
+
+impl Failure {
+    /// 缩小程序时要求错误保持同一类: 同一条报错信息, 同一阶段的同一条 panic 信息, 或同一种不一致
+    pub fn same_kind(&self, other: &Failure) -> bool {
+        match (self, other) {
+            (Self::Rejected(message), Self::Rejected(other_message)) => message == other_message,
+            (Self::Panic { stage, message }, Self::Panic { stage: other_stage, message: other_message }) => {
+                stage == other_stage && message == other_message
+            }
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rejected(msg) => write!(f, "program rejected: {}", msg),
+            Self::Panic { stage, message } => write!(f, "{} panicked: {}", stage, message),
+            Self::InvalidIr(msg) => write!(f, "invalid IR: {}", msg),
+            Self::Fault(msg) => write!(f, "koopa interpreter fault: {}", msg),
+            Self::Diverged { koopa, riscv } => {
+                write!(f, "koopa and riscv disagree\n--- koopa\n{}--- riscv\n{}", koopa, riscv)
+            }
+        }
+    }
+}
+
+/// 检查一个程序的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Skip(String), // 解释执行超过步数上限, 无法判断
+    Fail(Failure),
+}
+
+/// 一个出错的随机程序
+#[derive(Debug, Clone)]
+pub struct FuzzReport {
+    pub seed: u64,
+    pub source: String,    // 生成的程序
+    pub minimized: String, // 缩小后仍有同类错误的程序
+    pub failure: Failure,
+}
+
+/// 依次测试种子 first_seed..first_seed+count 生成的程序, 返回所有出错程序的报告
+/// 每个程序检查完后调用 on_case(种子, 结果)
+pub fn fuzz(first_seed: u64, count: u64, mut on_case: impl FnMut(u64, &Outcome)) -> Vec<FuzzReport> {
+    let mut reports = Vec::new();
+    for seed in first_seed..first_seed.saturating_add(count) {
+        let unit = gen::generate_program(seed);
+        let outcome = check_program(&unit);
+        on_case(seed, &outcome);
+        if let Outcome::Fail(failure) = outcome {
+            let predicate = Predicate::SameFailure(failure.clone());
+            let minimized = reduce(unit.clone(), |candidate| predicate.holds(candidate));
+            reports.push(FuzzReport {
+                seed,
+                source: format_comp_unit(&unit),
+                minimized: format_comp_unit(&minimized),
+                failure,
+            });
+        }
+    }
+    reports
+}
+
+/// 把程序打印为源码后走完整流水线, 比较解释执行与模拟执行的结果
+pub fn check_program(unit: &CompUnit) -> Outcome {
+    Type::set_ptr_size(4);
+    let source = format_comp_unit(unit);
+    let ast = match CompUnitParser::new().parse(&source) {
+        Ok(ast) => ast,
+        Err(err) => return Outcome::Fail(Failure::Rejected(format!("parse: {}", err))),
+    };
+
+    let mut module = match catch_panic("irgen", || IRGen::new().generate_program(vec![ast])) {
+        Ok(Ok(module)) => module,
+        Ok(Err(errors)) => {
+            let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+            return Outcome::Fail(Failure::Rejected(messages.join("; ")));
+        }
+        Err(failure) => return Outcome::Fail(failure),
+    };
+    if let Err(errors) = verify_program(&module.program) {
+        let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        return Outcome::Fail(Failure::InvalidIr(messages.join("; ")));
+    }
+
+    let koopa = match run_program(&module.program, &[], MAX_STEPS) {
+        Ok(execution) => execution.expected_output(),
+        Err(ExecError::StepLimit(limit)) => return Outcome::Skip(format!("step limit of {} exceeded", limit)),
+        Err(ExecError::Fault(msg)) => return Outcome::Fail(Failure::Fault(msg)),
+    };
+    // 以IR生成结果的执行结果为参照, 之后依次运行尾递归消除与循环展开, 每个遍之后都校验IR
+    if let Err(failure) = catch_panic("tailcall", || eliminate_tail_recursion(&mut module.program)) {
+        return Outcome::Fail(failure);
+    }
+    if let Err(errors) = verify_program(&module.program) {
+        let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        return Outcome::Fail(Failure::InvalidIr(messages.join("; ")));
+    }
+    if let Err(failure) = catch_panic("unroll", || unroll_loops(&mut module, DEFAULT_UNROLL_FACTOR)) {
+        return Outcome::Fail(failure);
+    }
+    if let Err(errors) = verify_program(&module.program) {
+        let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        return Outcome::Fail(Failure::InvalidIr(messages.join("; ")));
+    }
+    let asm = match catch_panic("codegen", || generate_riscv_assembly(module)) {
+        Ok(asm) => asm,
+        Err(failure) => return Outcome::Fail(failure),
+    };
+    let riscv = match run_assembly(&asm, &[], MAX_STEPS) {
+        Ok(execution) => execution.expected_output(),
+        Err(err) => format!("error: {}\n", err),
+    };
+    match koopa == riscv {
+        true => Outcome::Pass,
+        false => Outcome::Fail(Failure::Diverged { koopa, riscv }),
+    }
+}
+
+/// 运行 f 并捕获其中的 panic, 期间不打印 panic 信息
+fn catch_panic<T>(stage: &'static str, f: impl FnOnce() -> T) -> Result<T, Failure> {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    panic::set_hook(hook);
+    result.map_err(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|msg| msg.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Failure::Panic { stage, message }
+    })
+}
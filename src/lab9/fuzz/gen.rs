@@ -0,0 +1,579 @@
+//! 随机程序生成器: 直接构造 `ast::CompUnit`, 生成的程序类型正确且必然终止
+//!
+//! - 循环都形如 `{ int i = 0; while (i < N) { i = i + 1; ... } }`, 计数器不会被其他语句修改
+//! - 函数只调用在它之前定义的函数, 没有递归; 按估计的执行代价限制循环中的调用
+//! - 数组下标总在范围内, 除数总不为0, 因此执行结果不依赖未定义行为
+use crate::ast::*;
+use crate::lab9::fuzz::Rng;
+use std::ops::RangeInclusive;
+
+/// 一个程序估计执行的语句数上限
+const MAX_COST: u64 = 20_000;
+/// 语句嵌套的最大深度
+const MAX_DEPTH: usize = 4;
+/// 循环嵌套的最大深度
+const MAX_LOOP_DEPTH: usize = 2;
+/// 数组的最大元素个数
+const MAX_ARRAY_SIZE: usize = 64;
+/// 下标中嵌套数组访问的最大深度
+const MAX_INDEX_DEPTH: usize = 2;
+
+/// 变量/常量/形参
+#[derive(Debug, Clone)]
+struct Var {
+    name: String,
+    dims: Vec<usize>,         // 空表示标量; 数组形参的第一维是调用者保证的最小长度
+    is_const: bool,
+    value: Option<i32>,       // 标量常量的值, 可以出现在常量表达式中
+    writable: bool,           // 常量与循环计数器不能被赋值
+}
+
+/// 已生成的函数
+#[derive(Debug, Clone)]
+struct Func {
+    name: String,
+    returns_int: bool,
+    params: Vec<Vec<usize>>, // 各形参的形状, 含义同 Var::dims
+    cost: u64,               // 一次调用估计执行的语句数
+}
+
+pub struct Generator {
+    rng: Rng,
+    scopes: Vec<Vec<Var>>, // 第0层为全局作用域
+    funcs: Vec<Func>,
+    next_id: usize,
+    returns_int: bool,     // 当前函数是否有返回值
+    loop_depth: usize,
+    in_call: bool,         // 正在生成实参, 实参中不再嵌套调用以免表达式过大
+    index_depth: usize,    // 当前所在下标表达式的嵌套层数
+    multiplier: u64,       // 当前位置估计的执行次数(外层循环次数之积)
+    cost: u64,             // 当前函数到目前为止估计的执行代价
+}
+
+/// 用种子生成一个程序, 同一种子总是得到同一个程序
+pub fn generate_program(seed: u64) -> CompUnit {
+    Generator::new(seed).comp_unit()
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            scopes: vec![Vec::new()],
+            funcs: Vec::new(),
+            next_id: 0,
+            returns_int: true,
+            loop_depth: 0,
+            in_call: false,
+            index_depth: 0,
+            multiplier: 1,
+            cost: 0,
+        }
+    }
+
+    pub fn comp_unit(mut self) -> CompUnit {
+        let mut items = Vec::new();
+
+        // 全局常量、变量与数组
+        for _ in 0..self.rng.range(1, 3) {
+            items.push(CompUnitItem::GlobalDecl(GlobalDecl::Const(self.const_scalar_decl())));
+        }
+        for _ in 0..self.rng.range(1, 3) {
+            let name = self.fresh("g");
+            let init_val = self.rng.chance(50).then(|| InitVal::Exp(self.const_exp(2).0));
+            items.push(global_var(name.clone(), Vec::new(), init_val));
+            self.declare(Var { name, dims: Vec::new(), is_const: false, value: None, writable: true });
+        }
+        for _ in 0..self.rng.range(1, 3) {
+            let name = self.fresh("ga");
+            let (dims, dim_exps) = self.array_dims();
+            let init_val = match self.rng.chance(60) {
+                true => Some(self.init_list(&dims, true)),
+                false => None,
+            };
+            items.push(global_var(name.clone(), dim_exps, init_val));
+            self.declare(Var { name, dims, is_const: false, value: None, writable: true });
+        }
+        if self.rng.chance(40) {
+            items.push(CompUnitItem::GlobalDecl(GlobalDecl::Const(self.const_array_decl())));
+        }
+
+        // 函数, 每个函数只调用它之前的函数
+        for _ in 0..self.rng.range(1, 4) {
+            let func_def = self.func_def();
+            items.push(CompUnitItem::FuncDef(func_def));
+        }
+        items.push(CompUnitItem::FuncDef(self.main_def()));
+        CompUnit { items }
+    }
+
+    // region 声明
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    fn declare(&mut self, var: Var) {
+        self.scopes.last_mut().unwrap().push(var);
+    }
+
+    fn visible(&self) -> impl Iterator<Item = &Var> {
+        self.scopes.iter().flatten()
+    }
+
+    /// 数组各维的长度, 以及声明中的维度表达式(有时使用值合适的常量名)
+    fn array_dims(&mut self) -> (Vec<usize>, Vec<ConstExp>) {
+        let count = self.rng.range(1, 3) as usize;
+        let mut dims = Vec::new();
+        let mut dim_exps = Vec::new();
+        for _ in 0..count {
+            let limit = (MAX_ARRAY_SIZE / dims.iter().product::<usize>().max(1)).clamp(1, 5);
+            let dim = self.rng.range(1, limit as i32);
+            let named = self.visible()
+                .filter(|var| var.value == Some(dim))
+                .map(|var| var.name.clone())
+                .collect::<Vec<_>>();
+            let exp = match named.is_empty() || self.rng.chance(50) {
+                true => number(dim),
+                false => lval(self.rng.choose(&named).clone(), Vec::new()),
+            };
+            dims.push(dim as usize);
+            dim_exps.push(exp);
+        }
+        (dims, dim_exps)
+    }
+
+    fn const_scalar_decl(&mut self) -> ConstDecl {
+        let name = self.fresh("k");
+        let (exp, value) = self.const_exp(2);
+        self.declare(Var { name: name.clone(), dims: Vec::new(), is_const: true, value: Some(value), writable: false });
+        ConstDecl {
+            storage: StorageClass::Default,
+            b_type: BType::Int,
+            const_def_list: vec![ConstDef { ident: name, dimensions: Vec::new(), const_init_val: ConstInitVal::Exp(exp), pos: 0 }],
+        }
+    }
+
+    fn const_array_decl(&mut self) -> ConstDecl {
+        let name = self.fresh("ka");
+        let (dims, dim_exps) = self.array_dims();
+        let init_val = const_init_val(self.init_list(&dims, true));
+        self.declare(Var { name: name.clone(), dims, is_const: true, value: None, writable: false });
+        ConstDecl {
+            storage: StorageClass::Default,
+            b_type: BType::Int,
+            const_def_list: vec![ConstDef { ident: name, dimensions: dim_exps, const_init_val: init_val, pos: 0 }],
+        }
+    }
+
+    /// 嵌套的初始化列表: 每一层按子数组对齐, 每个子数组要么是一个子列表, 要么是按顺序展开的若干元素;
+    /// 只有最后一项可以不完整, 其余元素补0
+    fn init_list(&mut self, dims: &[usize], constant: bool) -> InitVal {
+        let sub_size: usize = dims[1..].iter().product();
+        let slots = self.rng.range(0, dims[0] as i32) as usize;
+        let mut items = Vec::new();
+        for slot in 0..slots {
+            let is_last = slot + 1 == slots;
+            if dims.len() > 1 && self.rng.chance(60) {
+                items.push(self.init_list(&dims[1..], constant));
+            } else {
+                let count = if is_last { self.rng.range(1, sub_size as i32) as usize } else { sub_size };
+                for _ in 0..count {
+                    let exp = if constant { self.const_exp(1).0 } else { self.exp(1) };
+                    items.push(InitVal::Exp(exp));
+                }
+            }
+        }
+        InitVal::List(items)
+    }
+
+    /// 局部声明: 标量变量、常量或数组; 局部变量总是带初值, 避免读到未初始化的值
+    fn local_decl(&mut self) -> Decl {
+        match self.rng.below(5) {
+            0 => Decl::Const(self.const_scalar_decl()),
+            1 => Decl::Const(self.const_array_decl()),
+            2 => {
+                let name = self.fresh("a");
+                let (dims, dim_exps) = self.array_dims();
+                let init_val = self.init_list(&dims, false);
+                self.declare(Var { name: name.clone(), dims, is_const: false, value: None, writable: true });
+                var_decl(name, dim_exps, Some(init_val))
+            }
+            _ => {
+                let name = self.fresh("x");
+                let init_val = InitVal::Exp(self.exp(2));
+                self.declare(Var { name: name.clone(), dims: Vec::new(), is_const: false, value: None, writable: true });
+                var_decl(name, Vec::new(), Some(init_val))
+            }
+        }
+    }
+
+    // endregion 声明
+
+    // region 函数
+
+    fn func_def(&mut self) -> FuncDef {
+        let name = self.fresh("f");
+        let returns_int = self.rng.chance(70);
+        let param_count = match self.rng.chance(20) {
+            true => self.rng.range(8, 12),
+            false => self.rng.range(0, 4),
+        };
+
+        // 数组形参的形状取自某个全局数组的后缀, 保证至少有一个实参可用
+        let global_arrays: Vec<Vec<usize>> = self.scopes[0].iter()
+            .filter(|var| !var.is_const && !var.dims.is_empty())
+            .map(|var| var.dims.clone())
+            .collect();
+        let mut params = Vec::new();
+        let mut param_shapes = Vec::new();
+        self.scopes.push(Vec::new());
+        for _ in 0..param_count {
+            let ident = self.fresh("p");
+            let shape = match global_arrays.is_empty() || self.rng.chance(65) {
+                true => Vec::new(),
+                false => {
+                    let dims = self.rng.choose(&global_arrays).clone();
+                    let start = self.rng.below(dims.len());
+                    let mut shape = dims[start..].to_vec();
+                    shape[0] = self.rng.range(1, shape[0] as i32) as usize;
+                    shape
+                }
+            };
+            params.push(FuncFParam {
+                b_type: BType::Int,
+                pointer: 0,
+                ident: ident.clone(),
+                dimensions: match shape.is_empty() {
+                    true => Vec::new(),
+                    false => std::iter::once(None).chain(shape[1..].iter().map(|&dim| Some(number(dim as i32)))).collect(),
+                },
+                pos: 0,
+            });
+            self.declare(Var { name: ident, dims: shape.clone(), is_const: false, value: None, writable: shape.is_empty() });
+            param_shapes.push(shape);
+        }
+
+        self.returns_int = returns_int;
+        self.cost = 0;
+        let mut items = self.block_items(2..=6, 1);
+        if returns_int {
+            items.push(BlockItem::Stmt(Stmt::Return(Some(self.exp(2)))));
+        }
+        self.scopes.pop();
+        self.funcs.push(Func { name: name.clone(), returns_int, params: param_shapes, cost: self.cost + 1 });
+
+        FuncDef {
+            storage: StorageClass::Default,
+            func_type: if returns_int { FuncType::Int } else { FuncType::Void },
+            id: name,
+            params: (!params.is_empty()).then_some(FuncFParams { params }),
+            block: make_block(items),
+            pos: 0,
+        }
+    }
+
+    /// main 在最后输出所有全局变量, 使结果依赖整个程序的执行
+    fn main_def(&mut self) -> FuncDef {
+        self.returns_int = true;
+        self.cost = 0;
+        self.scopes.push(Vec::new());
+        let mut items = self.block_items(4..=10, 1);
+        self.scopes.pop();
+
+        let globals: Vec<Var> = self.scopes[0].clone();
+        for var in &globals {
+            let indices = var.dims.iter().map(|&dim| number(self.rng.range(0, dim as i32 - 1))).collect();
+            items.push(call_stmt("putint", vec![lval(var.name.clone(), indices)]));
+            items.push(call_stmt("putch", vec![number(32)]));
+        }
+        items.push(call_stmt("putch", vec![number(10)]));
+        items.push(BlockItem::Stmt(Stmt::Return(Some(self.exp(2)))));
+
+        FuncDef {
+            storage: StorageClass::Default,
+            func_type: FuncType::Int,
+            id: "main".to_string(),
+            params: None,
+            block: make_block(items),
+            pos: 0,
+        }
+    }
+
+    // endregion 函数
+
+    // region 语句
+
+    fn block(&mut self, count: RangeInclusive<i32>, depth: usize) -> Block {
+        self.scopes.push(Vec::new());
+        let items = self.block_items(count, depth);
+        self.scopes.pop();
+        make_block(items)
+    }
+
+    /// 项数在 count 范围内随机选取
+    fn block_items(&mut self, count: RangeInclusive<i32>, depth: usize) -> Vec<BlockItem> {
+        let count = self.rng.range(*count.start(), *count.end());
+        (0..count).map(|_| self.block_item(depth)).collect()
+    }
+
+    fn block_item(&mut self, depth: usize) -> BlockItem {
+        self.cost += self.multiplier;
+        let nested = depth < MAX_DEPTH;
+        loop {
+            let stmt = match self.rng.below(12) {
+                0 | 1 => return BlockItem::Decl(self.local_decl()),
+                2 | 3 => self.assign(),
+                4 if nested => {
+                    let cond = self.exp(2);
+                    let then_stmt = Stmt::Block(self.block(1..=3, depth + 1));
+                    let else_stmt = match self.rng.chance(50) {
+                        true => Some(Box::new(Stmt::Block(self.block(1..=3, depth + 1)))),
+                        false => None,
+                    };
+                    Some(Stmt::If(cond, Box::new(then_stmt), else_stmt))
+                }
+                5 if nested && self.loop_depth < MAX_LOOP_DEPTH => Some(self.while_loop(depth)),
+                6 if self.loop_depth > 0 => {
+                    let jump = if self.rng.chance(50) { Stmt::Break } else { Stmt::Continue };
+                    Some(Stmt::If(self.exp(2), Box::new(jump), None))
+                }
+                7 => self.call(false).map(|exp| Stmt::Exp(Some(exp))),
+                8 => Some(Stmt::Exp(Some(call("putint", vec![self.exp(2)])))),
+                9 if depth > 1 => {
+                    let value = self.returns_int.then(|| self.exp(2));
+                    Some(Stmt::If(self.exp(2), Box::new(Stmt::Return(value)), None))
+                }
+                10 if nested => Some(Stmt::Block(self.block(1..=3, depth + 1))),
+                _ => None,
+            };
+            if let Some(stmt) = stmt {
+                return BlockItem::Stmt(stmt);
+            }
+        }
+    }
+
+    /// 给可写的标量或数组元素赋值
+    fn assign(&mut self) -> Option<Stmt> {
+        let targets: Vec<Var> = self.visible().filter(|var| var.writable || (!var.is_const && !var.dims.is_empty())).cloned().collect();
+        if targets.is_empty() {
+            return None;
+        }
+        let target = self.rng.choose(&targets).clone();
+        let indices = target.dims.iter().map(|&dim| self.index(dim)).collect();
+        let value = self.exp(3);
+        Some(Stmt::Assign(LVal { ident: target.name, indices, pos: 0 }, value))
+    }
+
+    fn while_loop(&mut self, depth: usize) -> Stmt {
+        let counter = self.fresh("i");
+        let bound = self.rng.range(1, 5);
+        self.scopes.push(vec![Var { name: counter.clone(), dims: Vec::new(), is_const: false, value: None, writable: false }]);
+        self.loop_depth += 1;
+        let outer_multiplier = self.multiplier;
+        self.multiplier *= bound as u64;
+
+        let cond = Expr::binary(BinaryOp::Lt, lval(counter.clone(), Vec::new()), number(bound));
+        let increment = Stmt::Assign(
+            LVal { ident: counter.clone(), indices: Vec::new(), pos: 0 },
+            Expr::binary(BinaryOp::Add, lval(counter.clone(), Vec::new()), number(1)),
+        );
+        self.scopes.push(Vec::new());
+        let mut body = vec![BlockItem::Stmt(increment)];
+        body.extend(self.block_items(1..=4, depth + 1));
+        self.scopes.pop();
+
+        self.multiplier = outer_multiplier;
+        self.loop_depth -= 1;
+        self.scopes.pop();
+        Stmt::Block(make_block(vec![
+            var_decl_item(counter, number(0)),
+            BlockItem::Stmt(Stmt::While(cond, Box::new(Stmt::Block(make_block(body))))),
+        ]))
+    }
+
+    // endregion 语句
+
+    // region 表达式
+
+    fn exp(&mut self, depth: usize) -> Expr {
+        if depth == 0 || self.rng.chance(25) {
+            return self.leaf();
+        }
+        match self.rng.below(10) {
+            0 => {
+                let op = *self.rng.choose(&[UnaryOp::Minus, UnaryOp::Not, UnaryOp::Plus]);
+                Expr::unary(op, self.exp(depth - 1), 0)
+            }
+            1 => {
+                // 除数为 e % 7 + 8, 取值在 [2, 14] 中
+                let op = if self.rng.chance(50) { BinaryOp::Div } else { BinaryOp::Mod };
+                let divisor = Expr::binary(BinaryOp::Add, Expr::binary(BinaryOp::Mod, self.exp(depth - 1), number(7)), number(8));
+                Expr::binary(op, self.exp(depth - 1), divisor)
+            }
+            2 => self.call(true).unwrap_or_else(|| self.leaf()),
+            _ => {
+                let op = *self.rng.choose(&[
+                    BinaryOp::Add, BinaryOp::Add, BinaryOp::Sub, BinaryOp::Mul,
+                    BinaryOp::Lt, BinaryOp::Gt, BinaryOp::Le, BinaryOp::Ge, BinaryOp::Eq, BinaryOp::Ne,
+                    BinaryOp::And, BinaryOp::Or,
+                ]);
+                Expr::binary(op, self.exp(depth - 1), self.exp(depth - 1))
+            }
+        }
+    }
+
+    fn leaf(&mut self) -> Expr {
+        let vars: Vec<Var> = self.visible().cloned().collect();
+        if vars.is_empty() || self.rng.chance(30) {
+            return self.literal();
+        }
+        let var = self.rng.choose(&vars).clone();
+        let indices = var.dims.iter().map(|&dim| self.index(dim)).collect();
+        lval(var.name, indices)
+    }
+
+    fn literal(&mut self) -> Expr {
+        match self.rng.below(10) {
+            0 => number(*self.rng.choose(&[2147483647, 65536, 1 << 30, 99999])),
+            1 => Expr::unary(UnaryOp::Minus, number(self.rng.range(1, 100)), 0),
+            _ => number(self.rng.range(0, 20)),
+        }
+    }
+
+    /// 长度为 dim 的维度上一定合法的下标: 循环计数器、常数或 (e % dim + dim) % dim
+    fn index(&mut self, dim: usize) -> Expr {
+        let dim = dim as i32;
+        if dim == 1 {
+            return number(0);
+        }
+        match self.rng.below(3) {
+            _ if self.index_depth >= MAX_INDEX_DEPTH => number(self.rng.range(0, dim - 1)),
+            0 => number(self.rng.range(0, dim - 1)),
+            _ => {
+                self.index_depth += 1;
+                let base = self.exp(1);
+                self.index_depth -= 1;
+                let wrapped = Expr::binary(BinaryOp::Add, Expr::binary(BinaryOp::Mod, base, number(dim)), number(dim));
+                Expr::binary(BinaryOp::Mod, wrapped, number(dim))
+            }
+        }
+    }
+
+    /// 常量表达式及其值, 只由字面量与已知值的常量组成
+    fn const_exp(&mut self, depth: usize) -> (Expr, i32) {
+        if depth == 0 || self.rng.chance(40) {
+            let consts: Vec<(String, i32)> = self.visible()
+                .filter_map(|var| var.value.map(|value| (var.name.clone(), value)))
+                .collect();
+            if !consts.is_empty() && self.rng.chance(40) {
+                let (name, value) = self.rng.choose(&consts).clone();
+                return (lval(name, Vec::new()), value);
+            }
+            let value = self.rng.range(0, 20);
+            return (number(value), value);
+        }
+        let (lhs, lhs_value) = self.const_exp(depth - 1);
+        let (rhs, rhs_value) = self.const_exp(depth - 1);
+        let (op, value) = match self.rng.below(3) {
+            0 => (BinaryOp::Add, lhs_value.wrapping_add(rhs_value)),
+            1 => (BinaryOp::Sub, lhs_value.wrapping_sub(rhs_value)),
+            _ => (BinaryOp::Mul, lhs_value.wrapping_mul(rhs_value)),
+        };
+        (Expr::binary(op, lhs, rhs), value)
+    }
+
+    /// 调用一个已生成的函数, 需要返回值时只选有返回值的函数; 实参不可用或代价过高时返回None
+    fn call(&mut self, need_value: bool) -> Option<Expr> {
+        let candidates: Vec<Func> = self.funcs.iter()
+            .filter(|func| !need_value || func.returns_int)
+            .filter(|func| self.cost + self.multiplier * func.cost <= MAX_COST)
+            .cloned()
+            .collect();
+        if candidates.is_empty() || self.in_call {
+            return None;
+        }
+        let func = self.rng.choose(&candidates).clone();
+        self.in_call = true;
+        let args: Option<Vec<Expr>> = func.params.iter().map(|shape| match shape.is_empty() {
+            true => Some(self.exp(1)),
+            false => self.array_arg(shape),
+        }).collect();
+        self.in_call = false;
+        let args = args?;
+        self.cost += self.multiplier * func.cost;
+        Some(call(&func.name, args))
+    }
+
+    /// 形状与数组形参兼容的实参: 整个数组或去掉前几维后的子数组
+    fn array_arg(&mut self, shape: &[usize]) -> Option<Expr> {
+        let candidates: Vec<(Var, usize)> = self.visible()
+            .filter(|var| !var.is_const && var.dims.len() >= shape.len())
+            .filter_map(|var| {
+                let skip = var.dims.len() - shape.len();
+                let compatible = var.dims[skip + 1..] == shape[1..] && var.dims[skip] >= shape[0];
+                compatible.then(|| (var.clone(), skip))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let (var, skip) = self.rng.choose(&candidates).clone();
+        let indices = var.dims[..skip].iter().map(|&dim| self.index(dim)).collect();
+        Some(lval(var.name, indices))
+    }
+
+    // endregion 表达式
+}
+
+// region AST构造
+
+fn number(value: i32) -> Expr {
+    Expr::new(ExprKind::Number(value), 0, 0)
+}
+
+fn lval(ident: String, indices: Vec<Expr>) -> Expr {
+    Expr::new(ExprKind::LVal(LVal { ident, indices, pos: 0 }), 0, 0)
+}
+
+fn call(name: &str, args: Vec<Expr>) -> Expr {
+    Expr::new(ExprKind::Call(name.to_string(), args), 0, 0)
+}
+
+fn call_stmt(name: &str, args: Vec<Expr>) -> BlockItem {
+    BlockItem::Stmt(Stmt::Exp(Some(call(name, args))))
+}
+
+fn make_block(block_item_list: Vec<BlockItem>) -> Block {
+    let item_positions = vec![0; block_item_list.len()];
+    Block { block_item_list, item_positions }
+}
+
+fn var_decl(ident: String, dimensions: Vec<ConstExp>, init_val: Option<InitVal>) -> Decl {
+    Decl::Var(VarDecl {
+        b_type: BType::Int,
+        var_def_list: vec![VarDef { pointer: 0, ident, dimensions, init_val, pos: 0 }],
+    })
+}
+
+fn var_decl_item(ident: String, init: Expr) -> BlockItem {
+    BlockItem::Decl(var_decl(ident, Vec::new(), Some(InitVal::Exp(init))))
+}
+
+fn global_var(ident: String, dimensions: Vec<ConstExp>, init_val: Option<InitVal>) -> CompUnitItem {
+    CompUnitItem::GlobalDecl(GlobalDecl::Var(GlobalVarDecl {
+        storage: StorageClass::Default,
+        b_type: BType::Int,
+        var_def_list: vec![GlobalVarDef { pointer: 0, ident, dimensions, init_val, pos: 0 }],
+    }))
+}
+
+fn const_init_val(init_val: InitVal) -> ConstInitVal {
+    match init_val {
+        InitVal::Exp(exp) => ConstInitVal::Exp(exp),
+        InitVal::List(list) => ConstInitVal::List(list.into_iter().map(const_init_val).collect()),
+    }
+}
+
+// endregion AST构造
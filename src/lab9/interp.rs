@@ -0,0 +1,483 @@
+//! Koopa IR 解释器: 作为参考实现直接执行 irgen 生成的程序, 用于与 codegen + RISC-V 模拟器的结果对比
+//!
+//! 语义与 RISC-V 目标一致: int 运算按补码回绕, x / 0 = -1, x % 0 = x;
+//! 浮点、指针转换与变参调用按 [`crate::lab9::abi`] 中的约定解释
+use crate::lab9::abi::{is_pointer_cast, FloatIntrinsic, VarArg, VariadicCall};
+use crate::lab9::profile::DUMP_FUNCTION;
+use crate::lab9::runtime::{ExecError, ExecResult, Execution, PutfArg, PutfArgKind, Runtime};
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, TypeKind, Value, ValueKind};
+use std::collections::HashMap;
+
+/// 可用内存的上限(全局变量与所有栈帧), 超过视为栈溢出
+const MEMORY_LIMIT: usize = 64 << 20;
+/// 最大调用深度
+const MAX_CALL_DEPTH: usize = 100_000;
+/// 地址0附近不分配, 空指针解引用会报错
+const NULL_GUARD: usize = 16;
+
+/// 执行程序的 main 函数, 最多执行 max_steps 条指令
+pub fn run_program(program: &Program, input: &[u8], max_steps: u64) -> ExecResult<Execution> {
+    let mut interpreter = Interpreter::new(program, input, max_steps);
+    let exit_code = interpreter.run()?;
+    Ok(Execution {
+        stdout: interpreter.runtime.output,
+        exit_code,
+        profile: interpreter.runtime.profile,
+    })
+}
+
+/// 一个函数的活动记录
+struct Frame {
+    func: Function,
+    bb: BasicBlock,
+    insts: Vec<Value>, // 当前块中的指令
+    pc: usize, // 下一条要执行的指令在insts中的位置
+    values: HashMap<Value, i32>,
+    stack_mark: usize, // 进入函数时的内存大小, 返回时释放其后的局部变量
+    result: Option<Value>, // 调用者中接收返回值的call指令
+}
+
+struct Interpreter<'a> {
+    program: &'a Program,
+    memory: Vec<u8>,
+    globals: HashMap<Value, i32>, // 全局变量的地址
+    frames: Vec<Frame>,
+    runtime: Runtime,
+    steps: u64,
+    max_steps: u64,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(program: &'a Program, input: &[u8], max_steps: u64) -> Self {
+        Self {
+            program,
+            memory: vec![0; NULL_GUARD],
+            globals: HashMap::new(),
+            frames: Vec::new(),
+            runtime: Runtime::new(input),
+            steps: 0,
+            max_steps,
+        }
+    }
+
+    fn run(&mut self) -> ExecResult<i32> {
+        for &global in self.program.inst_layout() {
+            let data = self.program.borrow_value(global);
+            let ValueKind::GlobalAlloc(alloc) = data.kind() else { continue };
+            let size = size_of(pointer_base(data.ty()));
+            let address = self.allocate(size)?;
+            self.globals.insert(global, address as i32);
+            self.write_global_init(address, alloc.init())?;
+        }
+
+        let main = self.program.func_layout().iter().copied()
+            .find(|&func| self.program.func(func).name() == "@main")
+            .ok_or_else(|| fault("program has no main function"))?;
+        self.push_frame(main, &[], None)?;
+
+        loop {
+            if self.steps >= self.max_steps {
+                return Err(ExecError::StepLimit(self.max_steps));
+            }
+            self.steps += 1;
+            if let Some(ret) = self.step()? {
+                return Ok(ret & 0xff);
+            }
+        }
+    }
+
+    /// 执行一条指令, main 返回时得到其返回值
+    fn step(&mut self) -> ExecResult<Option<i32>> {
+        let frame = self.frames.last().unwrap();
+        let func_data = self.program.func(frame.func);
+        let inst = *frame.insts.get(frame.pc)
+            .ok_or_else(|| fault(&format!("fell off the end of a basic block in {}", func_data.name())))?;
+        self.frames.last_mut().unwrap().pc += 1;
+
+        let data = func_data.dfg().value(inst);
+        let result = match data.kind() {
+            ValueKind::Alloc(_) => Some(self.allocate(size_of(pointer_base(data.ty())))? as i32),
+            ValueKind::Load(load) => Some(self.load(self.operand(load.src())?)?),
+            ValueKind::Store(store) => {
+                let dest = self.operand(store.dest())? as usize;
+                let value_data = func_data.dfg().values().get(&store.value());
+                match value_data.map(|data| data.kind()) {
+                    // 整个数组的初始化值
+                    Some(ValueKind::Aggregate(_) | ValueKind::ZeroInit(_)) => self.write_local_init(dest, store.value())?,
+                    _ => {
+                        let value = self.operand(store.value())?;
+                        self.store(dest as i32, value)?;
+                    }
+                }
+                None
+            }
+            ValueKind::GetPtr(get_ptr) => {
+                let stride = size_of(pointer_base(&self.type_of(get_ptr.src())));
+                let src = self.operand(get_ptr.src())?;
+                let index = self.operand(get_ptr.index())?;
+                Some(src.wrapping_add(index.wrapping_mul(stride as i32)))
+            }
+            ValueKind::GetElemPtr(get_elem_ptr) => {
+                let stride = match pointer_base(&self.type_of(get_elem_ptr.src())).kind() {
+                    TypeKind::Array(elem, _) => size_of(elem),
+                    _ => return Err(fault("getelemptr on a non-array pointer")),
+                };
+                let src = self.operand(get_elem_ptr.src())?;
+                let index = self.operand(get_elem_ptr.index())?;
+                Some(src.wrapping_add(index.wrapping_mul(stride as i32)))
+            }
+            ValueKind::Binary(binary) => {
+                let lhs = self.operand(binary.lhs())?;
+                let rhs = self.operand(binary.rhs())?;
+                Some(binary_op(binary.op(), lhs, rhs))
+            }
+            ValueKind::Branch(branch) => {
+                let (target, args) = match self.operand(branch.cond())? != 0 {
+                    true => (branch.true_bb(), branch.true_args()),
+                    false => (branch.false_bb(), branch.false_args()),
+                };
+                self.jump(target, args)?;
+                None
+            }
+            ValueKind::Jump(jump) => {
+                self.jump(jump.target(), jump.args())?;
+                None
+            }
+            ValueKind::Call(call) => {
+                let args = call.args().iter().map(|&arg| self.operand(arg)).collect::<ExecResult<Vec<_>>>()?;
+                let callee = self.program.func(call.callee());
+                if callee.layout().entry_bb().is_some() {
+                    self.push_frame(call.callee(), &args, Some(inst))?;
+                    None
+                } else {
+                    self.call_external(callee.name(), &args)?
+                }
+            }
+            ValueKind::Return(ret) => {
+                let value = match ret.value() {
+                    Some(value) => Some(self.operand(value)?),
+                    None => None,
+                };
+                let frame = self.frames.pop().unwrap();
+                self.memory.truncate(frame.stack_mark);
+                match self.frames.last_mut() {
+                    None => return Ok(Some(value.unwrap_or(0))),
+                    Some(caller) => {
+                        if let (Some(result), Some(value)) = (frame.result, value) {
+                            caller.values.insert(result, value);
+                        }
+                    }
+                }
+                None
+            }
+            _ => return Err(fault("unexpected instruction kind")),
+        };
+        if let Some(value) = result {
+            self.frames.last_mut().unwrap().values.insert(inst, value);
+        }
+        Ok(None)
+    }
+
+    fn push_frame(&mut self, func: Function, args: &[i32], result: Option<Value>) -> ExecResult<()> {
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(fault("call stack overflow"));
+        }
+        let func_data = self.program.func(func);
+        let entry = func_data.layout().entry_bb().unwrap();
+        let values = func_data.params().iter().copied().zip(args.iter().copied()).collect();
+        self.frames.push(Frame {
+            func,
+            bb: entry,
+            insts: block_insts(func_data, entry),
+            pc: 0,
+            values,
+            stack_mark: self.memory.len(),
+            result,
+        });
+        Ok(())
+    }
+
+    fn jump(&mut self, target: BasicBlock, args: &[Value]) -> ExecResult<()> {
+        let args = args.iter().map(|&arg| self.operand(arg)).collect::<ExecResult<Vec<_>>>()?;
+        let frame = self.frames.last_mut().unwrap();
+        let params = self.program.func(frame.func).dfg().bb(target).params();
+        for (&param, arg) in params.iter().zip(args) {
+            frame.values.insert(param, arg);
+        }
+        frame.bb = target;
+        frame.insts = block_insts(self.program.func(frame.func), target);
+        frame.pc = 0;
+        Ok(())
+    }
+
+    /// 调用没有函数体的函数: 运行时库、浮点内建函数、指针转换与变参调用
+    fn call_external(&mut self, name: &str, args: &[i32]) -> ExecResult<Option<i32>> {
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        if let Some(intrinsic) = FloatIntrinsic::from_name(name) {
+            return Ok(Some(float_intrinsic(intrinsic, arg(0), arg(1))));
+        }
+        if is_pointer_cast(name) {
+            return Ok(Some(arg(0)));
+        }
+        // 没有可变实参的调用直接使用原函数名
+        let variadic = VariadicCall::from_mangled_name(name)
+            .or_else(|| VariadicCall::new(name.strip_prefix('@').unwrap_or(name), Vec::new()));
+        if let Some(call) = variadic {
+            if call.callee != "putf" {
+                return Err(fault(&format!("unknown variadic function '{}'", call.callee)));
+            }
+            let format = self.read_string(arg(0))?;
+            let mut var_args = call.var_args.iter().zip(&args[call.fixed..]);
+            let memory = &self.memory;
+            self.runtime.putf(&format, |kind| {
+                let Some((&var_arg, &value)) = var_args.next() else { return PutfArg::Int(0) };
+                match (kind, var_arg) {
+                    (PutfArgKind::Double, VarArg::Float) => PutfArg::Double(f32::from_bits(value as u32) as f64),
+                    (PutfArgKind::Str, _) => PutfArg::Str(read_string(memory, value).unwrap_or_default()),
+                    _ => PutfArg::Int(value),
+                }
+            });
+            return Ok(None);
+        }
+
+        let result = match name.strip_prefix('@').unwrap_or(name) {
+            "getint" => Some(self.runtime.getint()),
+            "getch" => Some(self.runtime.getch()),
+            "getfloat" => Some(self.runtime.getfloat().to_bits() as i32),
+            "getarray" | "getfarray" => {
+                let float = name.ends_with("getfarray");
+                let n = self.runtime.getint();
+                for i in 0..n.max(0) {
+                    let value = match float {
+                        true => self.runtime.getfloat().to_bits() as i32,
+                        false => self.runtime.getint(),
+                    };
+                    self.store(arg(0).wrapping_add(i * 4), value)?;
+                }
+                Some(n)
+            }
+            "putint" => {
+                self.runtime.putint(arg(0));
+                None
+            }
+            "putch" => {
+                self.runtime.putch(arg(0));
+                None
+            }
+            "putfloat" => {
+                self.runtime.putfloat(f32::from_bits(arg(0) as u32));
+                None
+            }
+            "putarray" | "putfarray" => {
+                let values = (0..arg(0).max(0)).map(|i| self.load(arg(1).wrapping_add(i * 4))).collect::<ExecResult<Vec<_>>>()?;
+                match name.ends_with("putfarray") {
+                    true => self.runtime.putfarray(&values.iter().map(|&bits| f32::from_bits(bits as u32)).collect::<Vec<_>>()),
+                    false => self.runtime.putarray(&values),
+                }
+                None
+            }
+            "starttime" | "stoptime" => None,
+            DUMP_FUNCTION => {
+                let counts = (0..arg(0).max(0)).map(|i| self.load(arg(1).wrapping_add(i * 4))).collect::<ExecResult<Vec<_>>>()?;
+                self.runtime.profile_dump(counts);
+                None
+            }
+            _ => return Err(fault(&format!("call to undefined function {}", name))),
+        };
+        Ok(result)
+    }
+
+    fn operand(&self, value: Value) -> ExecResult<i32> {
+        if value.is_global() {
+            return self.globals.get(&value).copied().ok_or_else(|| fault("use of a global that is not a variable"));
+        }
+        let frame = self.frames.last().unwrap();
+        let data = self.program.func(frame.func).dfg().value(value);
+        match data.kind() {
+            ValueKind::Integer(int) => Ok(int.value()),
+            ValueKind::ZeroInit(_) | ValueKind::Undef(_) => Ok(0),
+            _ => frame.values.get(&value).copied().ok_or_else(|| fault("use of a value before its definition")),
+        }
+    }
+
+    fn type_of(&self, value: Value) -> Type {
+        if value.is_global() {
+            return self.program.borrow_value(value).ty().clone();
+        }
+        let frame = self.frames.last().unwrap();
+        self.program.func(frame.func).dfg().value(value).ty().clone()
+    }
+
+    fn allocate(&mut self, size: usize) -> ExecResult<usize> {
+        let address = self.memory.len();
+        if address + size > MEMORY_LIMIT {
+            return Err(fault("stack overflow"));
+        }
+        self.memory.resize(address + size, 0);
+        Ok(address)
+    }
+
+    fn check_address(&self, address: i32) -> ExecResult<usize> {
+        let address = address as u32 as usize;
+        if address < NULL_GUARD || !address.is_multiple_of(4) || address + 4 > self.memory.len() {
+            return Err(fault(&format!("invalid memory access at address {:#x}", address)));
+        }
+        Ok(address)
+    }
+
+    fn load(&self, address: i32) -> ExecResult<i32> {
+        let address = self.check_address(address)?;
+        Ok(i32::from_le_bytes(self.memory[address..address + 4].try_into().unwrap()))
+    }
+
+    fn store(&mut self, address: i32, value: i32) -> ExecResult<()> {
+        let address = self.check_address(address)?;
+        self.memory[address..address + 4].copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn write_global_init(&mut self, address: usize, init: Value) -> ExecResult<()> {
+        let data = self.program.borrow_value(init);
+        match data.kind() {
+            ValueKind::Integer(int) => self.store(address as i32, int.value()),
+            ValueKind::Aggregate(aggregate) => {
+                let elems = aggregate.elems().to_vec();
+                drop(data);
+                let stride = match elems.first() {
+                    Some(&elem) => size_of(self.program.borrow_value(elem).ty()),
+                    None => 0,
+                };
+                for (i, elem) in elems.into_iter().enumerate() {
+                    self.write_global_init(address + i * stride, elem)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()), // zeroinit/undef: 内存已清零
+        }
+    }
+
+    /// 局部数组的整体初始化值(store aggregate/zeroinit)
+    fn write_local_init(&mut self, address: usize, init: Value) -> ExecResult<()> {
+        let program = self.program;
+        let dfg = program.func(self.frames.last().unwrap().func).dfg();
+        let data = dfg.value(init);
+        match data.kind() {
+            ValueKind::Integer(int) => self.store(address as i32, int.value()),
+            ValueKind::Aggregate(aggregate) => {
+                let mut offset = address;
+                for &elem in aggregate.elems() {
+                    self.write_local_init(offset, elem)?;
+                    offset += size_of(dfg.value(elem).ty());
+                }
+                Ok(())
+            }
+            _ => {
+                for offset in (0..size_of(data.ty())).step_by(4) {
+                    self.store((address + offset) as i32, 0)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// IR中的字符串是每个元素一个字节的i32数组
+    fn read_string(&self, address: i32) -> ExecResult<Vec<u8>> {
+        read_string(&self.memory, address)
+    }
+}
+
+fn read_string(memory: &[u8], address: i32) -> ExecResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut address = address as u32 as usize;
+    loop {
+        if address < NULL_GUARD || address + 4 > memory.len() {
+            return Err(fault("string is not terminated"));
+        }
+        let value = i32::from_le_bytes(memory[address..address + 4].try_into().unwrap());
+        if value == 0 {
+            return Ok(bytes);
+        }
+        bytes.push(value as u8);
+        address += 4;
+    }
+}
+
+fn block_insts(func_data: &FunctionData, bb: BasicBlock) -> Vec<Value> {
+    match func_data.layout().bbs().node(&bb) {
+        Some(node) => node.insts().keys().copied().collect(),
+        None => Vec::new(),
+    }
+}
+
+fn fault(msg: &str) -> ExecError {
+    ExecError::Fault(msg.to_string())
+}
+
+fn pointer_base(ty: &Type) -> &Type {
+    match ty.kind() {
+        TypeKind::Pointer(base) => base,
+        _ => panic!("Expected pointer type, found '{}'", ty),
+    }
+}
+
+/// 类型占用的字节数(指针为4字节)
+pub fn size_of(ty: &Type) -> usize {
+    match ty.kind() {
+        TypeKind::Int32 | TypeKind::Pointer(_) => 4,
+        TypeKind::Array(elem, len) => size_of(elem) * len,
+        TypeKind::Unit | TypeKind::Function(..) => 0,
+    }
+}
+
+/// 与 RISC-V 指令语义一致的整数运算
+pub fn binary_op(op: BinaryOp, lhs: i32, rhs: i32) -> i32 {
+    match op {
+        BinaryOp::NotEq => (lhs != rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div => if rhs == 0 { -1 } else { lhs.wrapping_div(rhs) },
+        BinaryOp::Mod => if rhs == 0 { lhs } else { lhs.wrapping_rem(rhs) },
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Xor => lhs ^ rhs,
+        BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOp::Shr => ((lhs as u32).wrapping_shr(rhs as u32)) as i32,
+        BinaryOp::Sar => lhs.wrapping_shr(rhs as u32),
+    }
+}
+
+/// 浮点内建函数, 操作数与结果中的float均为位模式
+fn float_intrinsic(intrinsic: FloatIntrinsic, lhs: i32, rhs: i32) -> i32 {
+    let (l, r) = (f32::from_bits(lhs as u32), f32::from_bits(rhs as u32));
+    let float = |value: f32| value.to_bits() as i32;
+    match intrinsic {
+        FloatIntrinsic::Add => float(l + r),
+        FloatIntrinsic::Sub => float(l - r),
+        FloatIntrinsic::Mul => float(l * r),
+        FloatIntrinsic::Div => float(l / r),
+        FloatIntrinsic::Neg => float(-l),
+        FloatIntrinsic::Eq => (l == r) as i32,
+        FloatIntrinsic::Ne => (l != r) as i32,
+        FloatIntrinsic::Lt => (l < r) as i32,
+        FloatIntrinsic::Le => (l <= r) as i32,
+        FloatIntrinsic::Gt => (l > r) as i32,
+        FloatIntrinsic::Ge => (l >= r) as i32,
+        FloatIntrinsic::IntToFloat => float(lhs as f32),
+        FloatIntrinsic::FloatToInt => float_to_int(l),
+    }
+}
+
+/// fcvt.w.s 的语义: 向零取整, 超出范围时饱和, NaN 转换为 i32::MAX
+pub fn float_to_int(value: f32) -> i32 {
+    if value.is_nan() {
+        i32::MAX
+    } else {
+        value as i32
+    }
+}
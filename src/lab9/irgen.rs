@@ -1,8 +1,13 @@
 use koopa::ir::{BasicBlock, Function, FunctionData, Program, Type, TypeKind, Value};
 use koopa::ir::builder::{BasicBlockBuilder, GlobalInstBuilder, LocalInstBuilder, ValueBuilder};
-use crate::ast::{CompUnit, CompUnitItem, FuncDef, FuncType, GlobalDecl, Exp, ConstInitVal, InitVal};
+use crate::ast::{BType, CompUnit, CompUnitItem, Expr, ExprKind, FuncDef, FuncFParam, FuncFParams, FuncType, GlobalDecl, ConstInitVal, InitVal, StorageClass, UnaryOp};
+use crate::lab9::abi::{static_symbol_name, FloatAbi, FloatSig};
+use crate::lab9::irgen::calc::ConstValue;
+use crate::lab9::irgen::error::{CompileError, CompileResult, CompileWarning, LocatedError, WarningKind};
 use crate::lab9::irgen::symbol::{ScopeStack, SymbolInfo};
-use std::collections::HashMap;
+use crate::lab9::irgen::debug::SourceMap;
+use crate::lab9::profile::Profile;
+use std::collections::{HashMap, HashSet};
 
 pub mod symbol;
 pub mod declare;
@@ -11,24 +16,30 @@ pub mod calc;
 pub mod statement;
 pub mod vars;
 pub mod array;
+pub mod float;
+pub mod string;
+pub mod pointer;
+pub mod error;
+pub mod lint;
+pub mod debug;
 mod args;
 
 /// 初始化器枚举，用于处理数组初始化
 #[derive(Debug, Clone)]
 pub enum Initializer {
-    Const(i32),
+    Const(ConstValue),
     Value(Value),
     List(Vec<Initializer>),
 }
 
 impl Initializer {
-    /// 从AST的ConstInitVal创建Initializer（用于全局/局部 常量）
-    pub fn from_const_init_val(init_val: &ConstInitVal, irgen: &IRGen) -> Result<Self, String> {
+    /// 从AST的ConstInitVal创建Initializer（用于全局/局部 常量），元素按声明类型转换
+    pub fn from_const_init_val(init_val: &ConstInitVal, irgen: &mut IRGen, b_type: BType) -> CompileResult<Self> {
         match init_val {
-            // 单个常量表达式,直接计算并返回Self::Const(i32)
+            // 单个常量表达式,直接计算并返回Self::Const(ConstValue)
             ConstInitVal::Exp(const_exp) => {
-                let value = irgen.evaluate_lor_exp(&const_exp.lor_exp);
-                Ok(Self::Const(value))
+                let value = irgen.evaluate_const_exp(const_exp)?;
+                Ok(Self::Const(value.cast(b_type)))
             }
             // 数组初始化列表,递归处理每个元素,最后返回一个Self::Const列表
             // 例如const int arr[2][3][4] = {1, 2, 3, 4, {5}, {6}, {7, 8}};
@@ -45,26 +56,24 @@ impl Initializer {
             ConstInitVal::List(list) => {
                 let inits: Result<Vec<_>, _> = list
                     .iter()
-                    .map(|v| Self::from_const_init_val(v, irgen))
+                    .map(|v| Self::from_const_init_val(v, irgen, b_type))
                     .collect();
                 Ok(Self::List(inits?))
             }
         }
     }
 
-    /// 从AST的InitVal创建Initializer（用于全局变量）
-    pub fn from_global_var_init_val(init_val: &InitVal, irgen: &IRGen) -> Result<Self, String> {
+    /// 从AST的InitVal创建Initializer（用于全局变量），元素按声明类型转换
+    pub fn from_global_var_init_val(init_val: &InitVal, irgen: &mut IRGen, b_type: BType) -> CompileResult<Self> {
         match init_val {
             InitVal::Exp(exp) => {
-                let value = match exp {
-                    Exp::LOr(lor_exp) => irgen.evaluate_lor_exp(lor_exp)
-                };
-                Ok(Self::Const(value))
+                let value = irgen.evaluate_const_exp(exp)?;
+                Ok(Self::Const(value.cast(b_type)))
             }
             InitVal::List(list) => {
                 let inits: Result<Vec<_>, _> = list
                     .iter()
-                    .map(|v| Self::from_global_var_init_val(v, irgen))
+                    .map(|v| Self::from_global_var_init_val(v, irgen, b_type))
                     .collect();
                 Ok(Self::List(inits?))
             }
@@ -72,7 +81,7 @@ impl Initializer {
     }
     
     /// 根据给定类型重塑初始化器
-    pub fn reshape(self, ty: &Type) -> Result<Self, String> {
+    pub fn reshape(self, ty: &Type) -> CompileResult<Self> {
         // 获取维度列表
         let mut lens = Vec::new();
         
@@ -87,7 +96,7 @@ impl Initializer {
                     lens.push(*len);
                     current_ty = base;
                 }
-                _ => return Err("Unsupported type for array initialization".to_string()),
+                _ => return Err(CompileError::InvalidInitializer(format!("cannot initialize a value of type '{}' with a list", ty))),
             }
         }
         
@@ -130,11 +139,12 @@ impl Initializer {
             Self::Value(val) if lens.is_empty() => Ok(Self::Value(val)),
             // 数组需要重塑
             Self::List(l) if !lens.is_empty() => Self::reshape_impl(l, &lens),
-            _ => Err("Invalid initialization".to_string()),
+            Self::List(_) => Err(CompileError::InvalidInitializer("scalar initialized with a list".to_string())),
+            _ => Err(CompileError::InvalidInitializer("array initialized with a scalar".to_string())),
         }
     }
     
-    fn reshape_impl(inits: Vec<Self>, lens: &[(usize, usize)]) -> Result<Self, String> {
+    fn reshape_impl(inits: Vec<Self>, lens: &[(usize, usize)]) -> CompileResult<Self> {
         let mut reshaped: Vec<Vec<Self>> = (0..=lens.len()).map(|_| Vec::new()).collect();
         let mut len = 0;
         
@@ -142,13 +152,13 @@ impl Initializer {
         for init in inits {
             // 元素过多
             if len >= lens.last().unwrap().1 {
-                return Err("Too many initializer elements".to_string());
+                return Err(CompileError::InvalidInitializer("excess elements in array initializer".to_string()));
             }
             match init {
                 Self::List(list) => {
                     // 获取下一级长度列表
                     let next_lens = match reshaped.iter().position(|v| !v.is_empty()) {
-                        Some(0) => return Err("Misaligned initialization".to_string()),
+                        Some(0) => return Err(CompileError::InvalidInitializer("braced list is not aligned to a sub-array".to_string())),
                         Some(i) => &lens[..i],
                         None => &lens[..lens.len() - 1],
                     };
@@ -168,7 +178,7 @@ impl Initializer {
         
         // 填充零
         while len < lens.last().unwrap().1 {
-            reshaped[0].push(Self::Const(0));
+            reshaped[0].push(Self::Const(ConstValue::Int(0)));
             Self::carry(&mut reshaped, lens);
             len += 1;
         }
@@ -186,11 +196,21 @@ impl Initializer {
         }
     }
     
+    /// 常量初始化器(必须先重塑)展开后的元素值, 供常量表达式按下标取值
+    pub fn const_elements(&self, b_type: BType) -> Vec<ConstValue> {
+        match self {
+            Self::Const(value) => vec![value.cast(b_type)],
+            // from_const_init_val 只产生常量, 重塑时补的也是常量0
+            Self::Value(_) => unreachable!("constant initializer holds a non-constant value"),
+            Self::List(list) => list.iter().flat_map(|init| init.const_elements(b_type)).collect(),
+        }
+    }
+
     /// 将初始化器转换为常量值（必须先重塑）
-    pub fn into_const(self, program: &mut Program) -> Result<Value, String> {
+    pub fn into_const(self, program: &mut Program) -> CompileResult<Value> {
         match self {
-            Self::Const(num) => Ok(program.new_value().integer(num)),
-            Self::Value(_) => Err("Cannot evaluate non-constant value".to_string()),
+            Self::Const(num) => Ok(program.new_value().integer(num.to_bits())),
+            Self::Value(_) => Err(CompileError::NotConstant("initializer element".to_string())),
             Self::List(list) => {
                 let values: Result<Vec<_>, _> = list
                     .into_iter()
@@ -227,11 +247,124 @@ pub enum ControlFlowType {
 }
 
 
+/// IR生成的结果：Koopa程序以及Koopa IR本身无法表达的附加信息
+pub struct IrModule {
+    pub program: Program,
+    pub float_abi: FloatAbi, // 浮点形参/返回值信息(Koopa IR中浮点以i32位模式表示)
+    pub extern_globals: HashSet<String>, // 定义在其他目标文件中的全局变量(不含@), 在IR中以零初始化的占位变量表示
+    pub warnings: Vec<CompileWarning>,
+    pub symbols: HashMap<(usize, usize), SymbolInfo>, // (单元, 定义位置) -> 符号信息, 供语言服务器显示类型
+    pub source_map: Option<SourceMap>, // IR到源码的映射, 只在 with_debug_info 时生成
+    pub profile: Option<Profile>, // 各基本块的执行次数(-profile-use), 供块布局等优化参考
+}
+
+impl IrModule {
+    /// 包装从Koopa IR文本解析得到的程序
+    /// 浮点签名从文本中的 `@__sysy_fsig_*` 声明恢复(之后删除这些声明), 其余函数按纯整数签名处理;
+    /// 所有全局变量都在本文件中定义
+    pub fn from_program(mut program: Program) -> Self {
+        let mut float_abi = FloatAbi::sysy_library();
+        let markers: Vec<Function> = program.func_layout().iter().copied()
+            .filter(|&func| float_abi.insert_marker(program.func(func).name()))
+            .collect();
+        for func in markers {
+            program.remove_func(func);
+        }
+        Self {
+            program,
+            float_abi,
+            extern_globals: HashSet::new(),
+            warnings: Vec::new(),
+            symbols: HashMap::new(),
+            source_map: None,
+            profile: None,
+        }
+    }
+
+    /// 输出Koopa IR文本
+    /// 内建函数等按需声明的函数在函数布局中位于调用它的函数之后, 而Koopa IR的解析器要求先声明后使用,
+    /// 因此所有 decl 都移到第一个函数定义之前. 浮点签名写成 `@__sysy_fsig_*` 声明附在其后(见 lab9::abi)
+    pub fn to_koopa_text(&self) -> String {
+        let mut generator = koopa::back::KoopaGenerator::new(Vec::new());
+        generator.generate_on(&self.program).expect("writing to memory should not fail");
+        let text = String::from_utf8(generator.writer()).unwrap();
+
+        let markers: Vec<String> = self.float_abi.marker_names().into_iter()
+            .map(|name| format!("decl @{}()", name))
+            .collect();
+        let mut decls = Vec::new();
+        let mut others = Vec::new();
+        let mut lines = text.lines().peekable();
+        while let Some(line) = lines.next() {
+            if line.starts_with("decl ") {
+                decls.push(line);
+                lines.next_if(|line| line.is_empty());
+            } else {
+                others.push(line);
+            }
+        }
+        let first_fun = others.iter().position(|line| line.starts_with("fun ")).unwrap_or(others.len());
+
+        let mut result = String::new();
+        for line in &others[..first_fun] {
+            result.push_str(line);
+            result.push('\n');
+        }
+        for decl in decls.into_iter().chain(markers.iter().map(String::as_str)) {
+            result.push_str(decl);
+            result.push_str("\n\n");
+        }
+        for line in &others[first_fun..] {
+            result.push_str(line);
+            result.push('\n');
+        }
+        format!("{}\n", result.trim_end())
+    }
+}
+
+/// 等待解析的extern变量声明
+struct ExternVar {
+    unit: usize, // 所在翻译单元
+    ident: String,
+    b_type: BType,
+    pointer: usize,
+    dimensions: Vec<usize>,
+    pos: usize,
+}
+
+/// 函数的源码级签名, 用于比较同一函数的各个声明与定义
+/// Koopa类型中float也是i32, 因此返回值与形参另记元素类型
+#[derive(PartialEq)]
+struct FuncSignature {
+    ret: Option<BType>,          // None 为 void
+    params: Vec<(BType, Type)>,
+}
+
+/// 函数在签名表中的键: (static函数所在单元, 函数名)
+type FuncKey = (Option<usize>, String);
+
 /// 程序级IR生成器，负责整个程序的IR生成
 pub struct IRGen {
     program: Program,
     functions: HashMap<String, Function>, // 函数名到函数句柄的映射
     function_irgen: FunctionIRGen,        // 复用的函数IR生成器
+    float_values: HashSet<Value>,         // float类型的值，以及元素类型为float的指针
+    const_arrays: HashMap<Value, Vec<ConstValue>>, // 常量数组展开后的元素值, 用于常量表达式求值
+    warnings: Vec<CompileWarning>,
+    float_abi: FloatAbi,                  // 函数的浮点签名
+    string_literals: HashMap<Vec<u8>, Value>, // 字符串字面量内容到全局数组的映射
+    const_images: usize,                  // 已生成的局部常量数组镜像个数
+    unit: usize,                          // 当前翻译单元的编号
+    static_functions: HashMap<String, Function>, // 当前单元的static函数
+    called_functions: HashSet<Function>,  // 被调用过的函数
+    global_vars: HashMap<String, (SymbolInfo, (usize, usize))>, // 所有单元中外部链接的全局变量定义及其(单元, 位置)
+    function_signatures: HashMap<FuncKey, (FuncSignature, (usize, usize))>, // 最先出现的声明的签名及其(单元, 位置)
+    pending_externs: Vec<ExternVar>,      // 尚未解析的extern变量声明
+    extern_globals: HashSet<String>,      // 程序中没有定义的extern变量
+    error_pos: usize,                     // 正在生成的声明/语句/表达式的位置, 出错时据此定位
+    errors: Vec<LocatedError>,            // 已报告的错误
+    symbols: HashMap<(usize, usize), SymbolInfo>, // 见 IrModule::symbols
+    source_map: Option<SourceMap>,        // 见 IrModule::source_map
 }
 
 /// 函数级IR生成器，负责单个函数的IR生成
@@ -243,6 +376,7 @@ pub struct FunctionIRGen {
     pub control_flow_stack: Vec<ControlFlowContext>,// if 控制流
     pub pending_jumps: HashMap<BasicBlock, BasicBlock>,// 延迟跳转记录
     pub loop_stack: Vec<LoopContext>,// 循环控制流
+    pub invalid_names: HashSet<String>, // 声明出错的局部符号, 之后对它们的使用不再报告未声明
 }
 
 impl IRGen {
@@ -251,95 +385,315 @@ impl IRGen {
             program: Program::new(),
             functions: HashMap::new(),
             function_irgen: FunctionIRGen::new(),
+            float_values: HashSet::new(),
+            const_arrays: HashMap::new(),
+            warnings: Vec::new(),
+            float_abi: FloatAbi::sysy_library(),
+            string_literals: HashMap::new(),
+            const_images: 0,
+            unit: 0,
+            static_functions: HashMap::new(),
+            called_functions: HashSet::new(),
+            global_vars: HashMap::new(),
+            function_signatures: HashMap::new(),
+            pending_externs: Vec::new(),
+            extern_globals: HashSet::new(),
+            error_pos: 0,
+            errors: Vec::new(),
+            symbols: HashMap::new(),
+            source_map: None,
         }
     }
     
     
-    pub fn generate_koopa_ir(mut self, ast: CompUnit) -> Result<Program, String> {
+    pub fn generate_koopa_ir(self, ast: CompUnit) -> Result<IrModule, Vec<LocatedError>> {
+        self.generate_program(vec![ast])
+    }
+
+    /// 将多个翻译单元生成到同一个Koopa程序中
+    /// 非static的函数与全局变量在所有单元间共享, 函数调用与extern变量据此跨单元解析
+    /// 某个全局声明、函数头或语句出错时跳过它继续生成, 最后返回所有错误
+    pub fn generate_program(mut self, units: Vec<CompUnit>) -> Result<IrModule, Vec<LocatedError>> {
         // 首先添加 SysY 库函数声明
         self.declare_sysy_library_functions();
         
-        // 然后处理全局声明和收集函数定义
-        let mut func_defs = Vec::new();
-        for item in ast.items {
-            match item {
-                CompUnitItem::FuncDef(func_def) => {
-                    func_defs.push(func_def);
-                }
-                CompUnitItem::GlobalDecl(global_decl) => {
-                    self.generate_global_decl(&global_decl)?;
+        // 程序中有定义的外部链接函数, 它们的原型不需要生成函数声明
+        let defined_functions: HashSet<String> = units.iter()
+            .flat_map(|unit| &unit.items)
+            .filter_map(|item| match item {
+                CompUnitItem::FuncDef(func_def) if func_def.storage != StorageClass::Static => Some(func_def.id.clone()),
+                _ => None,
+            })
+            .collect();
+        
+        // 第一遍: 依次处理每个单元的全局声明和函数头(也就是函数头 -> void func_name(int a, int b[]) ),
+        // 并保存各单元的文件作用域
+        let mut unit_scopes = Vec::new();
+        for (unit, ast) in units.iter().enumerate() {
+            self.unit = unit;
+            for item in &ast.items {
+                let result = match item {
+                    CompUnitItem::GlobalDecl(global_decl) => self.generate_global_decl(global_decl),
+                    CompUnitItem::FuncDef(func_def) => {
+                        self.error_pos = func_def.pos;
+                        // 签名冲突时仍然声明函数, 以免调用处再报未声明的错误
+                        let checked = self.check_function_signature(func_def.storage, &func_def.func_type, &func_def.id, &func_def.params);
+                        self.declare_function(func_def.storage, &func_def.func_type, &func_def.id, &func_def.params, true).and(checked)
+                    }
+                    CompUnitItem::FuncDecl(func_decl) => {
+                        self.error_pos = func_decl.pos;
+                        // static原型的定义必在本单元, 其他原型只有在整个程序中都没有定义时才声明为外部函数
+                        let declared = func_decl.storage == StorageClass::Static
+                            || defined_functions.contains(&func_decl.id)
+                            || self.functions.contains_key(&func_decl.id);
+                        let checked = self.check_function_signature(func_decl.storage, &func_decl.func_type, &func_decl.id, &func_decl.params);
+                        match declared {
+                            true => checked,
+                            false => self.declare_function(func_decl.storage, &func_decl.func_type, &func_decl.id, &func_decl.params, false).and(checked),
+                        }
+                    }
+                };
+                if let Err(err) = result {
+                    self.report(err);
                 }
             }
+            let symbols = self.function_irgen.scope_stack.replace_global_scope(HashMap::new());
+            unit_scopes.push((symbols, std::mem::take(&mut self.static_functions)));
         }
         
-        // 检查是否存在main函数
-        let has_main = func_defs.iter().any(|f| f.id == "main");
-        if !has_main {
-            return Err("Program must have a main function".to_string());
+        // 所有单元的全局变量都已定义, 解析extern变量声明
+        for extern_var in std::mem::take(&mut self.pending_externs) {
+            (self.unit, self.error_pos) = (extern_var.unit, extern_var.pos);
+            match self.resolve_extern_var(&extern_var) {
+                Ok(symbol) => {
+                    unit_scopes[extern_var.unit].0.entry(extern_var.ident).or_insert(symbol);
+                }
+                Err(err) => {
+                    // 类型冲突时按声明的类型使用占位变量, 以免使用处再报错
+                    let symbol = self.extern_placeholder(&extern_var);
+                    unit_scopes[extern_var.unit].0.entry(extern_var.ident).or_insert(symbol);
+                    self.report(err);
+                }
+            }
         }
         
-        // 为每个函数创建函数声明(也就是函数头 -> void func_name(int a, int b[]) )
-        for func_def in &func_defs {
-            let func_name = format!("@{}", func_def.id);
-            let return_type = match func_def.func_type {
-                FuncType::Int => Type::get_i32(),
-                FuncType::Void => Type::get_unit(),
-            };
-            
-            // 处理参数类型
-            let param_types = match &func_def.params {
-                Some(params) => {
-                    let mut types = Vec::new();
-                    for param in &params.params {
-                        let param_type = match param.dimensions.is_empty() {
-                            // 标量参数
-                            true => Type::get_i32(),
-                            
-                            // 数组参数：根据维度创建指针类型
-                            false =>  {
-                                // 对于 int arr[] -> *i32
-                                // 对于 int arr[][10] -> *[i32, 10]
-                                // 对于 int arr[][10][20] -> *[[i32, 20], 10]
-                                let mut base_type = Type::get_i32();
-                                
-                                // 从第二维开始（第一维在函数参数中被忽略）
-                                for dim_opt in param.dimensions.iter().skip(1).rev() {
-                                    if let Some(dim_exp) = dim_opt {
-                                        let dim_value = self.evaluate_lor_exp(&dim_exp.lor_exp);
-                                        base_type = Type::get_array(base_type, dim_value as usize);
-                                    } else {
-                                        panic!("Array parameter dimension cannot be empty except for the first dimension");
-                                    }
-                                }
-                                
-                                // 创建指针类型
-                                Type::get_pointer(base_type)
-                            }
-                        };
-                        types.push((Some(format!("@{}", param.ident)), param_type));
+        // 第二遍: 生成每个函数体的IR
+        for (unit, (ast, (symbols, static_functions))) in units.iter().zip(unit_scopes).enumerate() {
+            self.unit = unit;
+            self.function_irgen.scope_stack.replace_global_scope(symbols);
+            self.static_functions = static_functions;
+            for item in &ast.items {
+                if let CompUnitItem::FuncDef(func_def) = item {
+                    self.error_pos = func_def.pos;
+                    if let Err(err) = self.generate_function_ir(func_def) {
+                        self.report(err);
                     }
-                    types
                 }
-                None => vec![],
-            };
-            
-            let function = self.program.new_func(FunctionData::with_param_names(
-                func_name.clone(),
-                param_types,
-                return_type,
-            ));
-            
-            self.functions.insert(func_def.id.clone(), function);
+            }
+            self.check_unused_functions(ast);
+        }
+        
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        self.warnings.sort_by_key(|warning| (warning.unit, warning.pos));
+        Ok(IrModule {
+            program: self.program,
+            float_abi: self.float_abi,
+            extern_globals: self.extern_globals,
+            warnings: self.warnings,
+            symbols: self.symbols,
+            source_map: self.source_map,
+            profile: None,
+        })
+    }
+
+    /// 为错误附上当前单元与正在生成的声明/语句/表达式的位置
+    fn locate(&self, error: CompileError) -> LocatedError {
+        LocatedError { unit: self.unit, pos: self.error_pos, error }
+    }
+
+    /// 记录一个错误, 由调用者跳过出错的部分继续生成
+    pub fn report(&mut self, error: CompileError) {
+        if let CompileError::UndefinedSymbol(name) = &error {
+            if self.function_irgen.invalid_names.contains(name) {
+                return;
+            }
+        }
+        let error = self.locate(error);
+        self.errors.push(error);
+    }
+
+    /// 以表达式的起始位置作为出错位置运行 generate, 成功后恢复原来的位置
+    /// 出错时不恢复, 因此错误定位到最内层出错的子表达式
+    pub fn at_exp<T>(&mut self, exp: &Expr, generate: impl FnOnce(&mut Self) -> CompileResult<T>) -> CompileResult<T> {
+        let outer_pos = std::mem::replace(&mut self.error_pos, exp.span.start);
+        let result = generate(self)?;
+        self.error_pos = outer_pos;
+        Ok(result)
+    }
+
+    /// 创建函数(定义或外部函数声明)并登记到函数表
+    fn declare_function(
+        &mut self,
+        storage: StorageClass,
+        func_type: &FuncType,
+        id: &str,
+        params: &Option<FuncFParams>,
+        is_definition: bool,
+    ) -> CompileResult<()> {
+        let redefined = match storage {
+            StorageClass::Static => self.static_functions.contains_key(id),
+            _ => self.functions.contains_key(id),
+        };
+        if redefined {
+            return Err(CompileError::Redefinition(id.to_string()));
+        }
+        let func_name = self.global_symbol_name(storage, id);
+        let return_type = match func_type {
+            FuncType::Int | FuncType::Float => Type::get_i32(), // float以i32位模式返回
+            FuncType::Void => Type::get_unit(),
+        };
+        
+        // 记录浮点签名(只有标量float形参需要经由浮点寄存器传递)
+        let float_params = params.iter()
+            .flat_map(|params| &params.params)
+            .map(|param| param.b_type == BType::Float && param.dimensions.is_empty() && param.pointer == 0)
+            .collect();
+        self.float_abi.insert(&func_name[1..], FloatSig {
+            params: float_params,
+            ret: matches!(func_type, FuncType::Float),
+        });
+        
+        // 处理参数类型
+        let mut param_types = Vec::new();
+        for param in params.iter().flat_map(|params| &params.params) {
+            param_types.push((Some(format!("@{}", param.ident)), self.param_type(param)?));
         }
         
-        // 生成每个函数体的IR
-        for func_def in &func_defs {
-            self.generate_function_ir(func_def)?;
+        let function_data = match is_definition {
+            true => FunctionData::with_param_names(func_name, param_types, return_type),
+            false => FunctionData::new_decl(func_name, param_types.into_iter().map(|(_, ty)| ty).collect(), return_type),
+        };
+        let function = self.program.new_func(function_data);
+        
+        match storage {
+            StorageClass::Static => self.static_functions.insert(id.to_string(), function),
+            _ => self.functions.insert(id.to_string(), function),
+        };
+        Ok(())
+    }
+
+    /// 按名字查找函数, 本单元的static函数优先
+    pub fn lookup_function(&self, id: &str) -> Option<Function> {
+        self.static_functions.get(id).or_else(|| self.functions.get(id)).copied()
+    }
+
+    /// 全局符号在IR中的名字, static符号加上本单元的前缀以保证唯一
+    fn global_symbol_name(&self, storage: StorageClass, id: &str) -> String {
+        match storage {
+            StorageClass::Static => format!("@{}", static_symbol_name(self.unit, id)),
+            _ => format!("@{}", id),
+        }
+    }
+
+    /// 形参的Koopa类型: 标量(包括指针)参数为其本身的类型, 数组参数为指向元素(去掉第一维)的指针
+    /// ```text
+    /// int arr[] -> *i32    int arr[][10] -> *[i32, 10]    int arr[][10][20] -> *[[i32, 20], 10]
+    /// ```
+    fn param_type(&mut self, param: &FuncFParam) -> CompileResult<Type> {
+        if param.dimensions.is_empty() {
+            return Ok(Self::scalar_type(param.pointer));
+        }
+        let mut base_type = Type::get_i32();
+        for dim_opt in param.dimensions.iter().skip(1).rev() {
+            let Some(dim_exp) = dim_opt else {
+                return Err(CompileError::InvalidSubscript(format!(
+                    "only the first dimension of array parameter '{}' may be omitted", param.ident
+                )));
+            };
+            base_type = Type::get_array(base_type, self.evaluate_array_dim(&param.ident, dim_exp)?);
+        }
+        Ok(Type::get_pointer(base_type))
+    }
+
+    /// 同一函数的所有原型与定义(static函数只在本单元内)的返回类型和形参类型必须一致
+    fn check_function_signature(&mut self, storage: StorageClass, func_type: &FuncType, id: &str, params: &Option<FuncFParams>) -> CompileResult<()> {
+        let ret = match func_type {
+            FuncType::Int => Some(BType::Int),
+            FuncType::Float => Some(BType::Float),
+            FuncType::Void => None,
+        };
+        let mut param_types = Vec::new();
+        for param in params.iter().flat_map(|params| &params.params) {
+            param_types.push((param.b_type, self.param_type(param)?));
+        }
+        let signature = FuncSignature { ret, params: param_types };
+
+        let unit = (storage == StorageClass::Static).then_some(self.unit);
+        let location = (self.unit, self.error_pos);
+        match self.function_signatures.get(&(unit, id.to_string())) {
+            Some((previous, _)) if *previous == signature => Ok(()),
+            Some(&(_, previous)) => Err(CompileError::ConflictingTypes { name: id.to_string(), previous }),
+            None => {
+                self.function_signatures.insert((unit, id.to_string()), (signature, location));
+                Ok(())
+            }
+        }
+    }
+
+    /// 登记外部链接的全局变量定义, 供其他单元的extern声明引用
+    fn define_external_global(&mut self, ident: &str, symbol_info: SymbolInfo) -> CompileResult<()> {
+        let location = (self.unit, self.error_pos);
+        if self.global_vars.insert(ident.to_string(), (symbol_info, location)).is_some() {
+            return Err(CompileError::Redefinition(ident.to_string()));
+        }
+        Ok(())
+    }
+
+    /// 将extern变量声明解析为某个单元中的定义; 整个程序中都没有定义时, 它来自其他目标文件
+    fn resolve_extern_var(&mut self, extern_var: &ExternVar) -> CompileResult<SymbolInfo> {
+        // extern声明的完整类型(含指针层数与各维长度)须与定义一致
+        let ty = Self::extern_type(extern_var);
+        if let Some((symbol, previous)) = self.global_vars.get(&extern_var.ident) {
+            let ptr = symbol.ptr().unwrap();
+            let type_matches = matches!(symbol, SymbolInfo::GlobalVar(_) | SymbolInfo::GlobalArray(..) | SymbolInfo::GlobalConstArray(..))
+                && self.program.borrow_value(ptr).ty().kind() == Type::get_pointer(ty).kind()
+                && self.is_float(ptr) == (extern_var.b_type == BType::Float);
+            if !type_matches {
+                return Err(CompileError::ConflictingTypes { name: extern_var.ident.clone(), previous: *previous });
+            }
+            return Ok(symbol.clone());
         }
         
-        Ok(self.program)
+        self.extern_globals.insert(extern_var.ident.clone());
+        let symbol = self.extern_placeholder(extern_var);
+        self.global_vars.insert(extern_var.ident.clone(), (symbol.clone(), (extern_var.unit, extern_var.pos)));
+        Ok(symbol)
     }
     
+    fn extern_type(extern_var: &ExternVar) -> Type {
+        let mut ty = Self::scalar_type(extern_var.pointer);
+        for &dim in extern_var.dimensions.iter().rev() {
+            ty = Type::get_array(ty, dim);
+        }
+        ty
+    }
+
+    /// 按extern声明的类型创建用零初始化的全局变量占位, codegen 不会为其生成数据, 只按名字引用
+    fn extern_placeholder(&mut self, extern_var: &ExternVar) -> SymbolInfo {
+        let init_value = self.program.new_value().zero_init(Self::extern_type(extern_var));
+        let global_var_ptr = self.program.new_value().global_alloc(init_value);
+        self.program.set_value_name(global_var_ptr, Some(format!("@{}", extern_var.ident)));
+        if extern_var.b_type == BType::Float {
+            self.mark_float(global_var_ptr);
+        }
+        match extern_var.dimensions.is_empty() {
+            true => SymbolInfo::GlobalVar(global_var_ptr),
+            false => SymbolInfo::GlobalArray(global_var_ptr, extern_var.dimensions.clone()),
+        }
+    }
+
     /// 声明 SysY 库函数
     fn declare_sysy_library_functions(&mut self) {
         let library_functions = [
@@ -349,6 +703,11 @@ impl IRGen {
             ("putint", vec![(None, Type::get_i32())], Type::get_unit()),
             ("putch", vec![(None, Type::get_i32())], Type::get_unit()),
             ("putarray", vec![(None, Type::get_i32()), (None, Type::get_pointer(Type::get_i32()))], Type::get_unit()),
+            ("getfloat", vec![], Type::get_i32()),
+            ("getfarray", vec![(None, Type::get_pointer(Type::get_i32()))], Type::get_i32()),
+            ("putfloat", vec![(None, Type::get_i32())], Type::get_unit()),
+            ("putfarray", vec![(None, Type::get_i32()), (None, Type::get_pointer(Type::get_i32()))], Type::get_unit()),
+            ("putf", vec![(None, Type::get_pointer(Type::get_i32()))], Type::get_unit()), // 变参, 见 generate_variadic_call
             ("starttime", vec![], Type::get_unit()),
             ("stoptime", vec![], Type::get_unit()),
         ];
@@ -367,86 +726,130 @@ impl IRGen {
     }
     
     /// 处理全局声明
-    fn generate_global_decl(&mut self, global_decl: &GlobalDecl) -> Result<(), String> {
+    fn generate_global_decl(&mut self, global_decl: &GlobalDecl) -> CompileResult<()> {
         match global_decl {
             GlobalDecl::Const(const_decl) => {
+                if const_decl.storage == StorageClass::Extern {
+                    return Err(CompileError::Unsupported("extern constant".to_string()));
+                }
                 for def in &const_decl.const_def_list {
+                    self.error_pos = def.pos;
                     match def.dimensions.is_empty() {
                         // 标量常量
                         true => {
-                            let initializer = Initializer::from_const_init_val(&def.const_init_val, self)?;
+                            let initializer = Initializer::from_const_init_val(&def.const_init_val, self, const_decl.b_type)?;
                             let value = match initializer {
                                 Initializer::Const(value) => value,
-                                _ => return Err("Invalid constant initialization".to_string()),
+                                _ => return Err(CompileError::InvalidInitializer("scalar initialized with a list".to_string())),
                             };
                             
-                            if let Err(err) = self.function_irgen.scope_stack.define(def.ident.clone(), SymbolInfo::Const(value)) {
-                                return Err(err);
-                            }
+                            self.symbols.insert((self.unit, def.pos), SymbolInfo::Const(value));
+                            self.function_irgen.scope_stack.define(def.ident.clone(), SymbolInfo::Const(value))?;
                         }
                         
                         // 数组常量
                         false => {
-                            let global_name = format!("@{}", def.ident);
+                            let global_name = self.global_symbol_name(const_decl.storage, &def.ident);
                             
                             // 构建数组类型
                             let mut ty = Type::get_i32();
                             for dim_exp in def.dimensions.iter().rev() {
-                                let dim_size = self.evaluate_lor_exp(&dim_exp.lor_exp);
-                                ty = Type::get_array(ty, dim_size as usize);
+                                let dim_size = self.evaluate_array_dim(&def.ident, dim_exp)?;
+                                ty = Type::get_array(ty, dim_size);
                             }
                             
                             // 创建初始化器并重塑
-                            let initializer = Initializer::from_const_init_val(&def.const_init_val, self)?;
+                            let initializer = Initializer::from_const_init_val(&def.const_init_val, self, const_decl.b_type)?;
                             let reshaped = initializer.reshape(&ty)?;
+                            let elements = reshaped.const_elements(const_decl.b_type);
                             let init_value = reshaped.into_const(&mut self.program)?;
                             
                             // 创建全局常量数组（和变量数组一样分配内存）
                             let global_var_ptr = self.program.new_value().global_alloc(init_value);
                             self.program.set_value_name(global_var_ptr, Some(global_name));
+                            self.const_arrays.insert(global_var_ptr, elements);
+                            if const_decl.b_type == BType::Float {
+                                self.mark_float(global_var_ptr);
+                            }
                             
                             // 计算数组维度
                             let mut dimensions = Vec::new();
                             for dim_exp in &def.dimensions {
-                                let dim_size = self.evaluate_lor_exp(&dim_exp.lor_exp);
-                                dimensions.push(dim_size as usize);
+                                dimensions.push(self.evaluate_array_dim(&def.ident, dim_exp)?);
                             }
                             
                             // 存入符号表
-                            if let Err(err) = self.function_irgen.scope_stack.define(
-                                def.ident.clone(), 
-                                SymbolInfo::GlobalConstArray(global_var_ptr, dimensions)
-                            ) {
-                                return Err(err);
+                            let symbol_info = SymbolInfo::GlobalConstArray(global_var_ptr, dimensions);
+                            if const_decl.storage == StorageClass::Default {
+                                self.define_external_global(&def.ident, symbol_info.clone())?;
                             }
+                            self.symbols.insert((self.unit, def.pos), symbol_info.clone());
+                            self.function_irgen.scope_stack.define(def.ident.clone(), symbol_info)?;
                         }
                     }
                 }
             }
+            GlobalDecl::Var(var_decl) if var_decl.storage == StorageClass::Extern => {
+                // extern变量在所有单元处理完后再解析
+                for def in &var_decl.var_def_list {
+                    self.error_pos = def.pos;
+                    if def.init_val.is_some() {
+                        return Err(CompileError::InvalidInitializer(format!("extern variable '{}' has an initializer", def.ident)));
+                    }
+                    let dimensions = def.dimensions.iter()
+                        .map(|dim_exp| self.evaluate_array_dim(&def.ident, dim_exp))
+                        .collect::<CompileResult<_>>()?;
+                    self.pending_externs.push(ExternVar {
+                        unit: self.unit,
+                        ident: def.ident.clone(),
+                        b_type: var_decl.b_type,
+                        pointer: def.pointer,
+                        dimensions,
+                        pos: def.pos,
+                    });
+                }
+            }
             GlobalDecl::Var(var_decl) => {
                 for def in &var_decl.var_def_list {
-                    let global_name = format!("@{}", def.ident);
+                    self.error_pos = def.pos;
+                    let global_name = self.global_symbol_name(var_decl.storage, &def.ident);
+                    if def.pointer > 0 && !def.dimensions.is_empty() {
+                        return Err(CompileError::Unsupported(format!("array of pointers '{}'", def.ident)));
+                    }
                     
                     // 构建数组类型(注释同上)
                     let ty = match def.dimensions.is_empty() {
-                        // 标量
-                        true => Type::get_i32(),
+                        // 标量(包括指针)
+                        true => Self::scalar_type(def.pointer),
                         
                         // 数组
                         false => {
                             let mut ty = Type::get_i32();
                             for dim_exp in def.dimensions.iter().rev() {
-                                let dim_size = self.evaluate_lor_exp(&dim_exp.lor_exp);
-                                ty = Type::get_array(ty, dim_size as usize);
+                                let dim_size = self.evaluate_array_dim(&def.ident, dim_exp)?;
+                                ty = Type::get_array(ty, dim_size);
                             }
                             ty
                         }
                     };
                     
+                    // 全局初始值在编译时求值, 不支持引用其他全局变量的地址
+                    if let Some(address_of) = def.init_val.as_ref().and_then(find_address_of) {
+                        self.error_pos = address_of.span.start;
+                        return Err(CompileError::Unsupported("taking an address in a global initializer".to_string()));
+                    }
+
                     // 创建初始化值
                     let init_value = match &def.init_val {
+                        // 全局指针的初值不能引用其他全局变量的地址, 只能是空指针
+                        Some(init_val) if def.pointer > 0 => {
+                            match Initializer::from_global_var_init_val(init_val, self, BType::Int)? {
+                                Initializer::Const(ConstValue::Int(0)) => self.program.new_value().zero_init(ty),
+                                _ => return Err(CompileError::InvalidInitializer(format!("global pointer '{}' can only be initialized to 0", def.ident))),
+                            }
+                        }
                         Some(init_val) => {
-                            let initializer = Initializer::from_global_var_init_val(init_val, self)?;
+                            let initializer = Initializer::from_global_var_init_val(init_val, self, var_decl.b_type)?;
                             let reshaped = initializer.reshape(&ty)?;
                             reshaped.into_const(&mut self.program)?
                         }
@@ -459,6 +862,9 @@ impl IRGen {
                     // 创建全局变量
                     let global_var_ptr = self.program.new_value().global_alloc(init_value);
                     self.program.set_value_name(global_var_ptr, Some(global_name));
+                    if var_decl.b_type == BType::Float {
+                        self.mark_float(global_var_ptr);
+                    }
                     
                     // 将全局变量添加到符号表
                     // 全局变量不需要考虑和局部变量重名,被作用域shadow了
@@ -471,16 +877,17 @@ impl IRGen {
                             // 计算数组维度
                             let mut dimensions = Vec::new();
                             for dim_exp in &def.dimensions {
-                                let dim_size = self.evaluate_lor_exp(&dim_exp.lor_exp);
-                                dimensions.push(dim_size as usize);
+                                dimensions.push(self.evaluate_array_dim(&def.ident, dim_exp)?);
                             }
                             SymbolInfo::GlobalArray(global_var_ptr, dimensions)
                         }
                     };
                     
-                    if let Err(err) = self.function_irgen.scope_stack.define(def.ident.clone(), symbol_info) {
-                        return Err(err);
+                    if var_decl.storage == StorageClass::Default {
+                        self.define_external_global(&def.ident, symbol_info.clone())?;
                     }
+                    self.symbols.insert((self.unit, def.pos), symbol_info.clone());
+                    self.function_irgen.scope_stack.define(def.ident.clone(), symbol_info)?;
                 }
             }
         }
@@ -488,23 +895,32 @@ impl IRGen {
     }
     
     /// 生成单个函数的IR
-    fn generate_function_ir(&mut self, func_def: &FuncDef) -> Result<(), String> {
-        let function = *self.functions.get(&func_def.id).unwrap();
+    /// 函数体中出错的语句已在所在的块中跳过, 其余错误(如形参重名)时丢弃函数内的作用域, 以便继续生成后续函数
+    fn generate_function_ir(&mut self, func_def: &FuncDef) -> CompileResult<()> {
+        // 函数头出错(例如重定义)时函数表中没有对应的函数, 错误已经报告过
+        let Some(function) = self.lookup_function(&func_def.id) else {
+            return Ok(());
+        };
         
         // 切换到新函数
         self.function_irgen.switch_to_function(function);
+        self.record_function(function, &func_def.id, func_def.pos);
         
-        // 生成函数体
-        self.generate_function_body(func_def)?;
+        // 生成函数体, 不属于任何语句的指令(形参的保存、补上的ret)记到函数头
+        let result = self.generate_function_body(func_def);
+        if result.is_err() {
+            self.function_irgen.scope_stack.exit_to_global_scope();
+        }
+        self.record_instruction_positions(func_def.pos);
         
         // 完成函数处理
         self.function_irgen.finish_function();
         
-        Ok(())
+        result
     }
     
     /// 生成函数体
-    fn generate_function_body(&mut self, func_def: &FuncDef) -> Result<(), String> {
+    fn generate_function_body(&mut self, func_def: &FuncDef) -> CompileResult<()> {
         // 进入函数作用域
         self.function_irgen.scope_stack.enter_scope();
         
@@ -533,13 +949,14 @@ impl IRGen {
                     
                     // 检查是否为数组参数
                     match param.dimensions.is_empty() {
-                        // 标量参数
+                        // 标量参数(包括指针参数)
                         true => {                        
                             let param_ptr = {
                                 let func_data = self.function_data_mut();
 
                                 // 为参数分配栈空间
-                                let param_ptr = func_data.dfg_mut().new_value().alloc(Type::get_i32());
+                                let param_type = func_data.dfg().value(*param_value).ty().clone();
+                                let param_ptr = func_data.dfg_mut().new_value().alloc(param_type);
                                 func_data.dfg_mut().set_value_name(param_ptr, Some(unique_name));
                                 func_data.layout_mut().bb_mut(entry).insts_mut().push_key_back(param_ptr).unwrap();
 
@@ -549,11 +966,12 @@ impl IRGen {
 
                                 param_ptr
                             };
+                            if param.b_type == BType::Float {
+                                self.mark_float(param_ptr);
+                            }
 
                             // 存储为标量变量
-                            if let Err(err) = self.function_irgen.scope_stack.define(param.ident.clone(), SymbolInfo::Var(param_ptr)) {
-                                panic!("{}", err);
-                            }
+                            self.define_param(&param.ident, SymbolInfo::Var(param_ptr), param.pos)?;
                         }
                         
                         // 数组参数 - 数组参数在函数中实际上是指针
@@ -564,7 +982,6 @@ impl IRGen {
                                 
                                 // 使用参数句柄获取参数，再获取参数的类型
                                 let param_type = func_data.dfg().value(*param_value).ty().clone();
-                                println!("Debug: construct param_type: {:}", param_type);
                                 
                                 // 为局部变量分配栈空间
                                 let param_ptr = func_data.dfg_mut().new_value().alloc(param_type);
@@ -577,14 +994,16 @@ impl IRGen {
                                 
                                 param_ptr
                             };
+                            if param.b_type == BType::Float {
+                                self.mark_float(param_ptr);
+                            }
                             
                             // 计算形参数组的维度信息
                             let mut dimensions = Vec::new();
                             for dim_opt in &param.dimensions {
                                 match dim_opt {
                                     Some(dim_exp) => {
-                                        let dim_value = self.evaluate_lor_exp(&dim_exp.lor_exp) as usize;
-                                        dimensions.push(dim_value);
+                                        dimensions.push(self.evaluate_array_dim(&param.ident, dim_exp)?);
                                     }
                                     None => {
                                         // 第一维为None表示不定长，这里用0表示
@@ -594,12 +1013,11 @@ impl IRGen {
                             }
                             
                             // 存储为函数数组参数类型
-                            if let Err(err) = self.function_irgen.scope_stack.define(
-                                param.ident.clone(), 
-                                SymbolInfo::ParamArray(param_ptr, dimensions)// 注意这里的param_ptr是*param_value类型，相当于在上述处理中多了一层指针
-                            ) {
-                                panic!("{}", err);
-                            }
+                            self.define_param(
+                                &param.ident, 
+                                SymbolInfo::ParamArray(param_ptr, dimensions),// 注意这里的param_ptr是*param_value类型，相当于在上述处理中多了一层指针
+                                param.pos
+                            )?;
                         }
                     }
                 }
@@ -607,18 +1025,21 @@ impl IRGen {
         }
         
         // 生成函数体
-        self.generate_block(&func_def.block);
+        self.generate_block(&func_def.block)?;
         
         // 处理所有延迟跳转
         self.process_pending_jumps();
         
-        // 确保函数有终结指令
+        // 确保函数有终结指令, 非void函数(main除外)执行到末尾时补上的返回值是没有意义的, 需要报告
         if let Some(bb) = self.function_irgen.current_bb {
+            if func_def.id != "main" && self.falls_off_end(bb) {
+                self.warn(func_def.pos, WarningKind::MissingReturn(func_def.id.clone()));
+            }
             self.ensure_terminator(bb);
         }
         
         // 退出函数作用域
-        self.function_irgen.scope_stack.exit_scope();
+        self.exit_scope();
         
         Ok(())
     }
@@ -680,6 +1101,21 @@ impl IRGen {
         }
     }
     
+    /// 当前基本块没有终结指令时跳转到 target
+    pub fn jump_if_unterminated(&mut self, target: BasicBlock) {
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let has_terminator = func_data.layout().bbs().node(&current_bb).unwrap().insts().back_key()
+            .is_some_and(|inst| matches!(func_data.dfg().value(*inst).kind(),
+                koopa::ir::ValueKind::Return(_) |
+                koopa::ir::ValueKind::Jump(_) |
+                koopa::ir::ValueKind::Branch(_)));
+        if !has_terminator {
+            let jump_inst = func_data.dfg_mut().new_value().jump(target);
+            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(jump_inst).unwrap();
+        }
+    }
+
     /// 确保指定基本块有终结指令
     pub fn ensure_terminator(&mut self, bb: BasicBlock) {
         let func_data = self.function_data_mut();
@@ -727,6 +1163,7 @@ impl FunctionIRGen {
             control_flow_stack: Vec::new(),
             pending_jumps: HashMap::new(),
             loop_stack: Vec::new(),
+            invalid_names: HashSet::new(),
         }
     }
     
@@ -738,6 +1175,7 @@ impl FunctionIRGen {
         self.control_flow_stack.clear();
         self.pending_jumps.clear();
         self.loop_stack.clear();
+        self.invalid_names.clear();
         // 注意：scope_stack 不重置，保持全局符号表
     }
     
@@ -749,6 +1187,7 @@ impl FunctionIRGen {
         self.control_flow_stack.clear();
         self.pending_jumps.clear();
         self.loop_stack.clear();
+        self.invalid_names.clear();
         // scope_stack 保持不变，全局符号表继续存在
     }
     
@@ -756,4 +1195,22 @@ impl FunctionIRGen {
     fn current_function(&self) -> Function {
         self.current_function.expect("No current function set")
     }
-}
\ No newline at end of file
+}
+
+/// 初始值中第一个取地址表达式
+fn find_address_of(init_val: &InitVal) -> Option<&Expr> {
+    fn visit(exp: &Expr) -> Option<&Expr> {
+        match &exp.kind {
+            ExprKind::Unary(UnaryOp::AddrOf, _) => Some(exp),
+            ExprKind::Unary(_, operand) => visit(operand),
+            ExprKind::Binary(_, lhs, rhs) => visit(lhs).or_else(|| visit(rhs)),
+            ExprKind::Call(_, args) => args.iter().find_map(visit),
+            ExprKind::LVal(lval) => lval.indices.iter().find_map(visit),
+            ExprKind::Number(_) | ExprKind::Float(_) | ExprKind::Str(_) => None,
+        }
+    }
+    match init_val {
+        InitVal::Exp(exp) => visit(exp),
+        InitVal::List(list) => list.iter().find_map(find_address_of),
+    }
+}
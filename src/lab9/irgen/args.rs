@@ -1,14 +1,19 @@
 use koopa::ir::builder::{LocalInstBuilder, ValueBuilder};
 use koopa::ir::Value;
-use crate::ast::{AddExp, EqExp, Exp, LAndExp, LOrExp, LVal, MulExp, PrimaryExp, RelExp, UnaryExp};
+use crate::ast::{Expr, ExprKind, LVal};
+use crate::lab9::irgen::error::{CompileError, CompileResult};
 use crate::lab9::irgen::IRGen;
 use crate::lab9::irgen::symbol::SymbolInfo;
 
 impl IRGen {
-    // 在 IRGen impl 块中添加
-    pub fn generate_arg_exp(&mut self, exp: &Exp) -> Value {
+    /// 生成实参的IR, 数组(或子数组)实参传递其地址, 出错时定位到该实参
+    pub fn generate_arg_exp(&mut self, exp: &Expr) -> CompileResult<Value> {
+        self.at_exp(exp, |irgen| irgen.generate_arg_exp_at(exp))
+    }
+
+    fn generate_arg_exp_at(&mut self, exp: &Expr) -> CompileResult<Value> {
         // 首先尝试解析是否为 LVal
-        if let Some(lval) = self.try_extract_lval(exp) {
+        if let ExprKind::LVal(lval) = &exp.kind {
             // 查询符号表获取类型信息
             let symbol_info = self.function_irgen.scope_stack.lookup(&lval.ident).cloned();
 
@@ -16,33 +21,32 @@ impl IRGen {
                 // 情况1：数组类型且无索引 -> 数组传参
                 Some(SymbolInfo::LocalArray(_, _)) |
                 Some(SymbolInfo::GlobalArray(_, _)) |
+                Some(SymbolInfo::LocalConstArray(_, _)) |
+                Some(SymbolInfo::GlobalConstArray(_, _)) |
                 Some(SymbolInfo::ParamArray(_, _)) => {
                     return if lval.indices.is_empty() {
                         // 数组传参：返回数组首地址
-                        self.generate_lval_as_param(&lval)
+                        self.generate_lval_as_param(lval)
                     } else {
                         // 数组访问作为参数：需要判断是否为子数组传参
-                        self.generate_lval_as_arg(&lval)
+                        self.generate_lval_as_arg(lval)
                     }
                 }
 
                 // 情况2：普通变量
                 Some(SymbolInfo::Var(_)) | Some(SymbolInfo::GlobalVar(_)) => {
-                    if !lval.indices.is_empty() {
-                        panic!("Cannot index into scalar variable '{}'", lval.ident);
-                    }
-                    return self.generate_lval_load(&lval);
+                    return self.generate_lval_load(lval);
                 }
 
                 // 情况3：常量
                 Some(SymbolInfo::Const(_)) => {
                     if !lval.indices.is_empty() {
-                        panic!("Cannot index into constant '{}'", lval.ident);
+                        return Err(CompileError::InvalidSubscript(format!("'{}' is a scalar constant", lval.ident)));
                     }
-                    return self.generate_lval_load(&lval);
+                    return self.generate_lval_load(lval);
                 }
 
-                _ => panic!("Identifier '{}' not found", lval.ident),
+                _ => return Err(CompileError::UndefinedSymbol(lval.ident.clone())),
             }
         }
 
@@ -51,7 +55,7 @@ impl IRGen {
     }
 
     // 处理数组访问作为参数的情况
-    pub fn generate_lval_as_arg(&mut self, lval: &LVal) -> Value {
+    pub fn generate_lval_as_arg(&mut self, lval: &LVal) -> CompileResult<Value> {
         let symbol_info = self.function_irgen.scope_stack.lookup(&lval.ident).cloned();
         
         match symbol_info {
@@ -64,22 +68,22 @@ impl IRGen {
                 
                 if remaining_dims > 1 {
                     // 剩余维度大于1，需要降维处理
-                    let array_ptr = self.generate_array_access_ptr(ptr, &lval.indices);
+                    let array_ptr = self.generate_array_access_ptr(ptr, &lval.indices)?;
                     let current_bb = self.current_bb();
                     let func_data = self.function_data_mut();
                     let zero = func_data.dfg_mut().new_value().integer(0);
                     let first_elem_ptr = func_data.dfg_mut().new_value().get_elem_ptr(array_ptr, zero);
                     func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(first_elem_ptr).unwrap();
-                    first_elem_ptr
+                    Ok(first_elem_ptr)
                 } else if remaining_dims == 1 {
                     // 剩余维度等于1，返回子数组首元素指针
-                    let array_ptr = self.generate_array_access_ptr(ptr, &lval.indices);
+                    let array_ptr = self.generate_array_access_ptr(ptr, &lval.indices)?;
                     let current_bb = self.current_bb();
                     let func_data = self.function_data_mut();
                     let zero = func_data.dfg_mut().new_value().integer(0);
                     let first_elem_ptr = func_data.dfg_mut().new_value().get_elem_ptr(array_ptr, zero);
                     func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(first_elem_ptr).unwrap();
-                    first_elem_ptr
+                    Ok(first_elem_ptr)
                 } else {
                     // 没有剩余维度，返回标量值
                     self.generate_lval_load(lval)
@@ -92,22 +96,22 @@ impl IRGen {
                 
                 if remaining_dims > 1 {
                     // 剩余维度大于1，需要降维处理
-                    let array_ptr = self.generate_param_array_access_ptr(param_ptr, &lval.indices);
+                    let array_ptr = self.generate_param_array_access_ptr(param_ptr, &lval.indices)?;
                     let current_bb = self.current_bb();
                     let func_data = self.function_data_mut();
                     let zero = func_data.dfg_mut().new_value().integer(0);
                     let first_elem_ptr = func_data.dfg_mut().new_value().get_elem_ptr(array_ptr, zero);
                     func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(first_elem_ptr).unwrap();
-                    first_elem_ptr
+                    Ok(first_elem_ptr)
                 } else if remaining_dims == 1 {
                     // 剩余维度等于1，返回子数组首元素指针
-                    let array_ptr = self.generate_param_array_access_ptr(param_ptr, &lval.indices);
+                    let array_ptr = self.generate_param_array_access_ptr(param_ptr, &lval.indices)?;
                     let current_bb = self.current_bb();
                     let func_data = self.function_data_mut();
                     let zero = func_data.dfg_mut().new_value().integer(0);
                     let first_elem_ptr = func_data.dfg_mut().new_value().get_elem_ptr(array_ptr, zero);
                     func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(first_elem_ptr).unwrap();
-                    first_elem_ptr
+                    Ok(first_elem_ptr)
                 } else {
                     // 没有剩余维度，返回标量值
                     self.generate_lval_load(lval)
@@ -122,9 +126,9 @@ impl IRGen {
     }
     
     // 处理参数数组的访问，返回指针而不是值
-    pub fn generate_param_array_access_ptr(&mut self, param_ptr: Value, indices: &[crate::ast::Exp]) -> Value {
+    pub fn generate_param_array_access_ptr(&mut self, param_ptr: Value, indices: &[Expr]) -> CompileResult<Value> {
         // 先计算所有索引值
-        let index_values: Vec<Value> = indices.iter().map(|exp| self.generate_exp(exp)).collect();
+        let index_values: Vec<Value> = indices.iter().map(|exp| self.generate_exp(exp)).collect::<CompileResult<_>>()?;
         
         let current_bb = self.current_bb();
         let func_data = self.function_data_mut();
@@ -147,11 +151,11 @@ impl IRGen {
             func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(current_ptr).unwrap();
         }
         
-        current_ptr
+        Ok(current_ptr)
     }
 
     // 处理数组作为参数传递时返回数组首地址
-    pub fn generate_lval_as_param(&mut self, lval: &LVal) -> Value {
+    pub fn generate_lval_as_param(&mut self, lval: &LVal) -> CompileResult<Value> {
         let symbol_info = self.function_irgen.scope_stack.lookup(&lval.ident).cloned();
         
         match symbol_info {
@@ -163,7 +167,7 @@ impl IRGen {
                 let zero = func_data.dfg_mut().new_value().integer(0);
                 let first_elem_ptr = func_data.dfg_mut().new_value().get_elem_ptr(ptr, zero);
                 func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(first_elem_ptr).unwrap();
-                first_elem_ptr
+                Ok(first_elem_ptr)
             }
             
             // 参数数组：返回加载后的指针值
@@ -172,35 +176,11 @@ impl IRGen {
                 let func_data = self.function_data_mut();
                 let ptr_value = func_data.dfg_mut().new_value().load(param_ptr);
                 func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(ptr_value).unwrap();
-                ptr_value
+                Ok(ptr_value)
             }
             
             // 非数组类型：回退到普通的 load 操作
             _ => self.generate_lval_load(lval)
         }
     }
-
-    // 辅助方法：尝试从表达式中提取 LVal - 修复生命周期问题
-    fn try_extract_lval(&self, exp: &Exp) -> Option<LVal> {
-        // 需要层层解析 Exp -> LOrExp -> LAndExp -> EqExp -> RelExp -> AddExp -> MulExp -> UnaryExp -> PrimaryExp -> LVal
-        let Exp::LOr(lor_exp) = exp;
-        if let LOrExp::LAnd(land_exp) = lor_exp.as_ref() {
-            if let LAndExp::Eq(eq_exp) = land_exp.as_ref() {
-                if let EqExp::Rel(rel_exp) = eq_exp.as_ref() {
-                    if let RelExp::Add(add_exp) = rel_exp.as_ref() {
-                        if let AddExp::Mul(mul_exp) = add_exp.as_ref() {
-                            if let MulExp::Unary(unary_exp) = mul_exp.as_ref() {
-                                if let UnaryExp::Primary(primary_exp) = unary_exp.as_ref() {
-                                    if let PrimaryExp::LVal(lval) = primary_exp {
-                                        return Some(lval.clone());
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        None
-    }
-}
\ No newline at end of file
+}
@@ -1,45 +1,51 @@
-use crate::ast::{ConstInitVal, InitVal};
+use crate::ast::{BType, ConstInitVal, InitVal};
+use crate::lab9::irgen::calc::ConstValue;
+use crate::lab9::irgen::error::{CompileError, CompileResult};
 use crate::lab9::irgen::IRGen;
-use koopa::ir::{Type, TypeKind, Value};
-use koopa::ir::builder::{LocalInstBuilder, ValueBuilder};
+use crate::lab9::abi::CONST_IMAGE_PREFIX;
+use koopa::ir::{BinaryOp, Type, TypeKind, Value, ValueKind};
+use koopa::ir::builder::{BasicBlockBuilder, GlobalInstBuilder, LocalInstBuilder, ValueBuilder};
+
+/// 零元素不少于这个数目时, 局部数组先用循环整体清零, 再只存入非零元素
+const ZERO_FILL_THRESHOLD: usize = 16;
 
 /// 局部数组初始化器枚举，用于处理局部数组初始化
 #[derive(Debug, Clone)]
 pub enum LocalInitializer {
-    Const(i32),
+    Const(ConstValue),
     Value(Value),
     List(Vec<LocalInitializer>),
 }
 
 impl LocalInitializer {
-    /// 从AST的ConstInitVal创建LocalInitializer（用于局部常量数组）
-    pub fn from_const_init_val(init_val: &ConstInitVal, irgen: &mut IRGen) -> Result<Self, String> {
+    /// 从AST的ConstInitVal创建LocalInitializer（用于局部常量数组），元素按数组元素类型转换
+    pub fn from_const_init_val(init_val: &ConstInitVal, irgen: &mut IRGen, b_type: BType) -> CompileResult<Self> {
         match init_val {
             ConstInitVal::Exp(const_exp) => {
-                let value = irgen.evaluate_lor_exp(&const_exp.lor_exp);
-                Ok(Self::Const(value))
+                let value = irgen.evaluate_const_exp(const_exp)?;
+                Ok(Self::Const(value.cast(b_type)))
             }
             ConstInitVal::List(list) => {
                 let inits: Result<Vec<_>, _> = list
                     .iter()
-                    .map(|v| Self::from_const_init_val(v, irgen))
+                    .map(|v| Self::from_const_init_val(v, irgen, b_type))
                     .collect();
                 Ok(Self::List(inits?))
             }
         }
     }
 
-    /// 从AST的InitVal创建LocalInitializer（用于局部变量数组）
-    pub fn from_init_val(init_val: &InitVal, irgen: &mut IRGen) -> Result<Self, String> {
+    /// 从AST的InitVal创建LocalInitializer（用于局部变量数组），元素按数组元素类型转换
+    pub fn from_init_val(init_val: &InitVal, irgen: &mut IRGen, b_type: BType) -> CompileResult<Self> {
         match init_val {
             InitVal::Exp(exp) => {
-                let value = irgen.generate_exp(exp);
-                Ok(Self::Value(value))
+                let value = irgen.generate_exp(exp)?;
+                Ok(Self::Value(irgen.convert_value(value, b_type)?))
             }
             InitVal::List(list) => {
                 let inits: Result<Vec<_>, _> = list
                     .iter()
-                    .map(|v| Self::from_init_val(v, irgen))
+                    .map(|v| Self::from_init_val(v, irgen, b_type))
                     .collect();
                 Ok(Self::List(inits?))
             }
@@ -47,7 +53,7 @@ impl LocalInitializer {
     }
     
     /// 根据给定类型重塑初始化器
-    pub fn reshape(self, ty: &Type) -> Result<Self, String> {
+    pub fn reshape(self, ty: &Type) -> CompileResult<Self> {
         // 获取维度列表
         let mut lens = Vec::new();
         
@@ -59,7 +65,7 @@ impl LocalInitializer {
                     lens.push(*len);
                     current_ty = base;
                 }
-                _ => return Err("Unsupported type for array initialization".to_string()),
+                _ => return Err(CompileError::InvalidInitializer(format!("cannot initialize a value of type '{}' with a list", ty))),
             }
         }
         
@@ -79,24 +85,24 @@ impl LocalInitializer {
             Self::Const(val) if lens.is_empty() => Ok(Self::Const(val)),
             Self::Value(val) if lens.is_empty() => Ok(Self::Value(val)),
             Self::List(l) if !lens.is_empty() => Self::reshape_impl(l, &lens),
-            _ => Err("Invalid initialization".to_string()),
+            Self::List(_) => Err(CompileError::InvalidInitializer("scalar initialized with a list".to_string())),
+            _ => Err(CompileError::InvalidInitializer("array initialized with a scalar".to_string())),
         }
     }
     
-    fn reshape_impl(inits: Vec<Self>, lens: &[(usize, usize)]) -> Result<Self, String> {
+    fn reshape_impl(inits: Vec<Self>, lens: &[(usize, usize)]) -> CompileResult<Self> {
         let mut reshaped: Vec<Vec<Self>> = (0..=lens.len()).map(|_| Vec::new()).collect();
         let mut len = 0;
         
         // 处理初始化器元素
         for init in inits {
             if len >= lens.last().unwrap().1 {
-                // 如果初始化值超过数组大小，忽略多余的值（符合C语言标准）
-                break;
+                return Err(CompileError::InvalidInitializer("excess elements in array initializer".to_string()));
             }
             match init {
                 Self::List(list) => {
                     let next_lens = match reshaped.iter().position(|v| !v.is_empty()) {
-                        Some(0) => return Err("Misaligned initialization".to_string()),
+                        Some(0) => return Err(CompileError::InvalidInitializer("braced list is not aligned to a sub-array".to_string())),
                         Some(i) => &lens[..i],
                         None => &lens[..lens.len() - 1],
                     };
@@ -114,7 +120,7 @@ impl LocalInitializer {
         
         // 填充零
         while len < lens.last().unwrap().1 {
-            reshaped[0].push(Self::Const(0));
+            reshaped[0].push(Self::Const(ConstValue::Int(0)));
             Self::carry(&mut reshaped, lens);
             len += 1;
         }
@@ -131,12 +137,22 @@ impl LocalInitializer {
         }
     }
 
+    /// 常量初始化器(必须先重塑)展开后的元素值, 供常量表达式按下标取值
+    pub fn const_elements(&self, b_type: BType) -> Vec<ConstValue> {
+        match self {
+            Self::Const(value) => vec![value.cast(b_type)],
+            // from_const_init_val 只产生常量, 重塑时补的也是常量0
+            Self::Value(_) => unreachable!("constant initializer holds a non-constant value"),
+            Self::List(list) => list.iter().flat_map(|init| init.const_elements(b_type)).collect(),
+        }
+    }
+
     /// 将初始化器扁平化为值列表（用于局部数组初始化）
     pub fn flatten(self, irgen: &mut IRGen) -> Vec<Value> {
         match self {
             Self::Const(val) => {
                 let func_data = irgen.function_data_mut();
-                let const_val = func_data.dfg_mut().new_value().integer(val);
+                let const_val = func_data.dfg_mut().new_value().integer(val.to_bits());
                 vec![const_val]
             }
             Self::Value(val) => vec![val],
@@ -157,139 +173,139 @@ impl IRGen {
         zero
     }
 
-    /// 评估常量初始化列表，展开为一维数组
-    pub fn evaluate_const_init_list(&mut self, init_list: &Vec<crate::ast::ConstInitVal>, dimensions: &[usize]) -> Vec<i32> {
-        let total_size = dimensions.iter().product::<usize>();
-        let mut flat_values = Vec::new();
-        
-        self.flatten_const_init_list(init_list, &mut flat_values);
-        
-        // 如果初始化值不足，用0填充到总大小
-        while flat_values.len() < total_size {
-            flat_values.push(0);
+    /// 初始化局部数组: 零元素较多时先用循环把整个数组清零, 之后只存入非零元素
+    pub fn initialize_local_array(&mut self, array_ptr: Value, values: &[Value], dimensions: &[usize]) {
+        let base = self.first_element_ptr(array_ptr, dimensions.len());
+        let zeros: Vec<bool> = {
+            let dfg = self.function_data_mut().dfg();
+            values.iter()
+                .map(|&value| matches!(dfg.value(value).kind(), ValueKind::Integer(int) if int.value() == 0))
+                .collect()
+        };
+        let zero_fill = zeros.iter().filter(|&&zero| zero).count() >= ZERO_FILL_THRESHOLD;
+        if zero_fill {
+            self.generate_fill_loop(base, None, values.len());
         }
-        
-        // 如果初始化值过多，截断到总大小
-        flat_values.truncate(total_size);
-        
-        flat_values
-    }
 
-    /// 递归展开常量初始化列表为一维数组
-    fn flatten_const_init_list(&mut self, init_list: &Vec<crate::ast::ConstInitVal>, result: &mut Vec<i32>) {
-        for init_val in init_list {
-            match init_val {
-                crate::ast::ConstInitVal::Exp(exp) => {
-                    let value = self.evaluate_lor_exp(&exp.lor_exp);
-                    result.push(value);
-                }
-                crate::ast::ConstInitVal::List(sub_list) => {
-                    // 递归处理嵌套列表
-                    self.flatten_const_init_list(sub_list, result);
-                }
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        for (index, (&value, &zero)) in values.iter().zip(&zeros).enumerate() {
+            if zero_fill && zero {
+                continue;
             }
+            // 按展开后的下标用 getptr 计算元素地址
+            let ptr = match index {
+                0 => base,
+                _ => {
+                    let index = func_data.dfg_mut().new_value().integer(index as i32);
+                    let ptr = func_data.dfg_mut().new_value().get_ptr(base, index);
+                    func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(ptr).unwrap();
+                    ptr
+                }
+            };
+            let store_inst = func_data.dfg_mut().new_value().store(value, ptr);
+            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(store_inst).unwrap();
         }
     }
-    
-    /// 初始化局部数组，使用 getelemptr 和 store 指令
-    pub fn initialize_local_array(&mut self, array_ptr: Value, values: &[Value], dimensions: &[usize]) {
-        let mut flat_index = 0;
-        let mut indices = vec![0; dimensions.len()];
-        
-        // 使用多重循环遍历所有数组位置
-        self.initialize_array_recursive(array_ptr, values, dimensions, &mut indices, 0, &mut flat_index);
-    }
-
-    /// 递归初始化数组的各个元素
-    fn initialize_array_recursive(
-        &mut self, 
-        array_ptr: Value, 
-        values: &[Value], 
-        dimensions: &[usize], 
-        indices: &mut Vec<usize>, 
-        depth: usize, 
-        flat_index: &mut usize
-    ) {
-        if depth == dimensions.len() {
-            // 到达最深层，存储值
-            if *flat_index < values.len() {
-                let current_bb = self.current_bb();
-                let func_data = self.function_data_mut();
 
-                // 计算元素地址：从外层到内层逐步使用 getelemptr
-                let mut ptr = array_ptr;
-                for (_dim_idx, &index) in indices.iter().enumerate() {
-                    let idx_value = func_data.dfg_mut().new_value().integer(index as i32);
-                    let elem_ptr = func_data.dfg_mut().new_value().get_elem_ptr(ptr, idx_value);
-                    func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(elem_ptr).unwrap();
-                    ptr = elem_ptr;
-                }
+    /// 初始化局部常量数组: 元素值放在只读的全局数组(常量镜像)中, 用循环复制到栈上
+    pub fn initialize_local_const_array(&mut self, array_ptr: Value, elements: &[ConstValue], dimensions: &[usize]) {
+        let init = self.const_image_init(elements, dimensions);
+        let image = self.program.new_value().global_alloc(init);
+        let name = format!("@{}{}", CONST_IMAGE_PREFIX, self.const_images);
+        self.program.set_value_name(image, Some(name));
+        self.const_images += 1;
 
-                // 存储值
-                let store_inst = func_data.dfg_mut().new_value().store(values[*flat_index], ptr);
-                func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(store_inst).unwrap();
+        let src = self.first_element_ptr(image, dimensions.len());
+        let dest = self.first_element_ptr(array_ptr, dimensions.len());
+        self.generate_fill_loop(dest, Some(src), elements.len());
+    }
 
-                *flat_index += 1;
-            }
-        } else {
-            // 递归处理下一维
-            for i in 0..dimensions[depth] {
-                indices[depth] = i;
-                self.initialize_array_recursive(array_ptr, values, dimensions, indices, depth + 1, flat_index);
+    /// 常量镜像的初始值: 按维度嵌套的 aggregate
+    fn const_image_init(&mut self, elements: &[ConstValue], dimensions: &[usize]) -> Value {
+        match dimensions.split_first() {
+            None => self.program.new_value().integer(elements[0].to_bits()),
+            Some((_, inner)) => {
+                let inner_size = inner.iter().product::<usize>();
+                let elems = elements.chunks(inner_size)
+                    .map(|chunk| self.const_image_init(chunk, inner))
+                    .collect();
+                self.program.new_value().aggregate(elems)
             }
         }
     }
 
-    /// 初始化局部常量数组，使用 getelemptr 和 store 指令
-    pub fn initialize_local_const_array(&mut self, array_ptr: Value, values: &[i32], dimensions: &[usize]) {
-        let mut flat_index = 0;
-        let mut indices = vec![0; dimensions.len()];
-        
-        // 使用多重循环遍历所有数组位置
-        self.initialize_const_array_recursive(array_ptr, values, dimensions, &mut indices, 0, &mut flat_index);
+    /// 数组首元素的指针(*i32): 每一维 getelemptr 0
+    fn first_element_ptr(&mut self, array_ptr: Value, depth: usize) -> Value {
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let mut ptr = array_ptr;
+        for _ in 0..depth {
+            let zero = func_data.dfg_mut().new_value().integer(0);
+            ptr = func_data.dfg_mut().new_value().get_elem_ptr(ptr, zero);
+            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(ptr).unwrap();
+        }
+        ptr
     }
 
-    /// 递归初始化常量数组的各个元素
-    fn initialize_const_array_recursive(
-        &mut self, 
-        array_ptr: Value, 
-        values: &[i32], 
-        dimensions: &[usize], 
-        indices: &mut Vec<usize>, 
-        depth: usize, 
-        flat_index: &mut usize
-    ) {
-        if depth == dimensions.len() {
-            // 到达最深层，存储值
-            if *flat_index < values.len() {
-                // 先创建常量值
-                let const_value = self.program.new_value().integer(values[*flat_index]);
-                
-                let current_bb = self.current_bb();
-                let func_data = self.function_data_mut();
+    /// 用计数循环填充从 dest 开始的 len 个元素: 给出 src 时逐个复制 src 中的元素, 否则写入0.
+    /// 循环结束后当前基本块为 %init_end_N
+    fn generate_fill_loop(&mut self, dest: Value, src: Option<Value>, len: usize) {
+        self.function_irgen.bb_counter += 1;
+        let bb_counter = self.function_irgen.bb_counter;
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
 
-                // 计算元素地址：从外层到内层逐步使用 getelemptr
-                let mut ptr = array_ptr;
-                for (_dim_idx, &index) in indices.iter().enumerate() {
-                    let idx_value = func_data.dfg_mut().new_value().integer(index as i32);
-                    let elem_ptr = func_data.dfg_mut().new_value().get_elem_ptr(ptr, idx_value);
-                    func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(elem_ptr).unwrap();
-                    ptr = elem_ptr;
-                }
+        let init_header = func_data.dfg_mut().new_bb().basic_block(Some(format!("%init_header_{}", bb_counter)));
+        let init_body = func_data.dfg_mut().new_bb().basic_block(Some(format!("%init_body_{}", bb_counter)));
+        let init_end = func_data.dfg_mut().new_bb().basic_block(Some(format!("%init_end_{}", bb_counter)));
+        func_data.layout_mut().bbs_mut().push_key_back(init_header).unwrap();
+        func_data.layout_mut().bbs_mut().push_key_back(init_body).unwrap();
+        func_data.layout_mut().bbs_mut().push_key_back(init_end).unwrap();
 
-                // 存储常量值
-                let store_inst = func_data.dfg_mut().new_value().store(const_value, ptr);
-                func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(store_inst).unwrap();
+        // 计数器从0开始
+        let counter = func_data.dfg_mut().new_value().alloc(Type::get_i32());
+        let zero = func_data.dfg_mut().new_value().integer(0);
+        let init_counter = func_data.dfg_mut().new_value().store(zero, counter);
+        let jump_inst = func_data.dfg_mut().new_value().jump(init_header);
+        for inst in [counter, init_counter, jump_inst] {
+            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(inst).unwrap();
+        }
 
-                *flat_index += 1;
-            }
-        } else {
-            // 递归处理下一维
-            for i in 0..dimensions[depth] {
-                indices[depth] = i;
-                self.initialize_const_array_recursive(array_ptr, values, dimensions, indices, depth + 1, flat_index);
+        // 循环头: 计数器 < len
+        let index = func_data.dfg_mut().new_value().load(counter);
+        let len_value = func_data.dfg_mut().new_value().integer(len as i32);
+        let cond = func_data.dfg_mut().new_value().binary(BinaryOp::Lt, index, len_value);
+        let branch_inst = func_data.dfg_mut().new_value().branch(cond, init_body, init_end);
+        for inst in [index, cond, branch_inst] {
+            func_data.layout_mut().bb_mut(init_header).insts_mut().push_key_back(inst).unwrap();
+        }
+
+        // 循环体: 写入一个元素, 计数器加一
+        let mut insts = Vec::new();
+        let index = func_data.dfg_mut().new_value().load(counter);
+        insts.push(index);
+        let value = match src {
+            Some(src) => {
+                let src_ptr = func_data.dfg_mut().new_value().get_ptr(src, index);
+                let value = func_data.dfg_mut().new_value().load(src_ptr);
+                insts.extend([src_ptr, value]);
+                value
             }
+            None => func_data.dfg_mut().new_value().integer(0),
+        };
+        let dest_ptr = func_data.dfg_mut().new_value().get_ptr(dest, index);
+        let store_inst = func_data.dfg_mut().new_value().store(value, dest_ptr);
+        let one = func_data.dfg_mut().new_value().integer(1);
+        let next = func_data.dfg_mut().new_value().binary(BinaryOp::Add, index, one);
+        let store_next = func_data.dfg_mut().new_value().store(next, counter);
+        let jump_inst = func_data.dfg_mut().new_value().jump(init_header);
+        insts.extend([dest_ptr, store_inst, next, store_next, jump_inst]);
+        for inst in insts {
+            func_data.layout_mut().bb_mut(init_body).insts_mut().push_key_back(inst).unwrap();
         }
+
+        self.function_irgen.current_bb = Some(init_end);
     }
 
     /// 为局部数组分配内存
@@ -1,26 +1,53 @@
-use crate::ast::{Block, BlockItem};
+use crate::ast::{Block, BlockItem, Decl};
+use crate::lab9::irgen::error::{CompileResult, WarningKind};
 use crate::lab9::irgen::IRGen;
 
 impl IRGen {
-    pub fn generate_block(&mut self, block: &Block) -> bool {
+    /// 生成语句块, 出错的语句记录错误后跳过, 因此块本身总是成功
+    pub fn generate_block(&mut self, block: &Block) -> CompileResult<bool> {
         // 进入新的作用域{}
         self.function_irgen.scope_stack.enter_scope();
         
         let mut has_return = false;
-        for block_item in &block.block_item_list {
+        let outer_pos = self.error_pos; // 包含这个块的语句(或函数头)的位置
+        for (block_item, &pos) in block.block_item_list.iter().zip(&block.item_positions) {
             if has_return {
+                // return/break/continue之后的语句不会被执行, 也不生成IR
+                self.warn(pos, WarningKind::UnreachableCode);
                 break;
             }
-            match block_item {
-                BlockItem::Decl(decl) => self.generate_decl(decl), 
-                BlockItem::Stmt(stmt) => {
-                    has_return = self.generate_stmt(stmt);
+            // 此前生成的指令(如 while 的条件)属于外层语句
+            self.record_instruction_positions(outer_pos);
+            self.error_pos = pos;
+            let scope_depth = self.function_irgen.scope_stack.depth();
+            let (loops, control_flows) = (self.function_irgen.loop_stack.len(), self.function_irgen.control_flow_stack.len());
+            let result = match block_item {
+                BlockItem::Decl(decl) => self.generate_decl(decl).map(|()| false),
+                BlockItem::Stmt(stmt) => self.generate_stmt(stmt),
+            };
+            match result {
+                Ok(returns) => has_return = returns,
+                // 跳过出错的语句继续生成, 一次报告函数中的所有错误; 丢弃该语句中途进入的作用域与控制流上下文
+                Err(err) => {
+                    self.report(err);
+                    if let BlockItem::Decl(decl) = block_item {
+                        let names: Vec<String> = match decl {
+                            Decl::Const(const_decl) => const_decl.const_def_list.iter().map(|def| def.ident.clone()).collect(),
+                            Decl::Var(var_decl) => var_decl.var_def_list.iter().map(|def| def.ident.clone()).collect(),
+                        };
+                        self.function_irgen.invalid_names.extend(names);
+                    }
+                    self.function_irgen.scope_stack.exit_to_depth(scope_depth);
+                    self.function_irgen.loop_stack.truncate(loops);
+                    self.function_irgen.control_flow_stack.truncate(control_flows);
                 }
             }
+            self.record_instruction_positions(pos);
         }
+        self.error_pos = outer_pos;
         
         // 退出当前作用域{}
-        self.function_irgen.scope_stack.exit_scope();
-        has_return
+        self.exit_scope();
+        Ok(has_return)
     }
 }
\ No newline at end of file
@@ -1,154 +1,215 @@
-//! ```text
-//! Exp (最低优先级)
-//! └── LOrExp      (逻辑或 ||)
-//!     └── LAndExp  (逻辑与 &&)
-//!         └── EqExp    (相等比较 == !=)
-//!             └── RelExp   (关系比较 < > <= >=)
-//!                 └── AddExp   (加减 + -)
-//!                     └── MulExp   (乘除模 * / %)
-//!                         └── UnaryExp (一元运算 + - !)
-//!                             └── PrimaryExp (最高优先级)
-//! ```
-use crate::ast::{AddExp, EqExp, EqOp, Exp, LAndExp, LOrExp, MulDivOp, MulExp, PlusSubOp, PrimaryExp, RelExp, RelOp, UnaryExp, UnaryOp};
+//! 常量表达式求值
+use crate::ast::{BType, BinaryOp, Expr, ExprKind, UnaryOp};
 use crate::lab9::irgen::symbol::SymbolInfo;
+use crate::lab9::irgen::error::{CompileError, CompileResult, WarningKind};
 use crate::lab9::irgen::IRGen;
+use koopa::ir::Value;
 
-impl IRGen {
-    pub fn evaluate_exp(&self, exp: &Exp) -> i32 {
-        match exp {
-            Exp::LOr(lor_exp) => self.evaluate_lor_exp(lor_exp),
+/// 常量表达式的值, 运算时遵循 C 的隐式类型转换(int 与 float 混合运算时提升为 float)
+/// int 运算溢出时按补码回绕, 结果与 RISC-V 的 add/sub/mul/div/rem 指令一致
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstValue {
+    Int(i32),
+    Float(f32),
+}
+
+impl ConstValue {
+    /// 转换为 int (float 向零取整)
+    pub fn as_i32(self) -> i32 {
+        match self {
+            Self::Int(value) => value,
+            Self::Float(value) => value as i32,
         }
     }
 
-    pub fn evaluate_lor_exp(&self, lor_exp: &LOrExp) -> i32 {
-        match lor_exp {
-            LOrExp::LAnd(land_exp) => self.evaluate_land_exp(land_exp),
-            LOrExp::LOr(left, right) => {
-                let left_val = self.evaluate_lor_exp(left);
-                if left_val != 0 { // 短路返回
-                    1
-                } else {
-                    let right_val = self.evaluate_land_exp(right);
-                    if right_val != 0 { 1 } else { 0 }
-                }
-            }
+    pub fn as_f32(self) -> f32 {
+        match self {
+            Self::Int(value) => value as f32,
+            Self::Float(value) => value,
         }
     }
 
-    pub fn evaluate_land_exp(&self, land_exp: &LAndExp) -> i32 {
-        match land_exp {
-            LAndExp::Eq(eq_exp) => self.evaluate_eq_exp(eq_exp),
-            LAndExp::LAnd(left, right) => {
-                let left_val = self.evaluate_land_exp(left);
-                if left_val == 0 { // 短路返回
-                    0
-                } else {
-                    let right_val = self.evaluate_eq_exp(right);
-                    if right_val != 0 { 1 } else { 0 }
-                }
-            }
+    /// 按目标类型转换
+    pub fn cast(self, b_type: BType) -> Self {
+        match b_type {
+            BType::Int => Self::Int(self.as_i32()),
+            BType::Float => Self::Float(self.as_f32()),
         }
     }
 
-    pub fn evaluate_eq_exp(&self, eq_exp: &EqExp) -> i32 {
-        match eq_exp {
-            EqExp::Rel(rel_exp) => self.evaluate_rel_exp(rel_exp),
-            EqExp::Eq(left, op, right) => {
-                let left_val = self.evaluate_eq_exp(left);
-                let right_val = self.evaluate_rel_exp(right);
-                match op {
-                    EqOp::Eq => (left_val == right_val) as i32,
-                    EqOp::Ne => (left_val != right_val) as i32,
-                }
-            }
+    /// IR中的表示: int 为其本身, float 为位模式
+    pub fn to_bits(self) -> i32 {
+        match self {
+            Self::Int(value) => value,
+            Self::Float(value) => value.to_bits() as i32,
         }
     }
 
-    pub fn evaluate_rel_exp(&self, rel_exp: &RelExp) -> i32 {
-        match rel_exp {
-            RelExp::Add(add_exp) => self.evaluate_add_exp(add_exp),
-            RelExp::Rel(left, op, right) => {
-                let left_val = self.evaluate_rel_exp(left);
-                let right_val = self.evaluate_add_exp(right);
-                match op {
-                    RelOp::Lt => (left_val <  right_val) as i32,
-                    RelOp::Gt => (left_val >  right_val) as i32,
-                    RelOp::Le => (left_val <= right_val) as i32,
-                    RelOp::Ge => (left_val >= right_val) as i32,
-                }
-            }
+    pub fn is_true(self) -> bool {
+        match self {
+            Self::Int(value) => value != 0,
+            Self::Float(value) => value != 0.0,
         }
     }
 
-    pub fn evaluate_add_exp(&self, add_exp: &AddExp) -> i32 {
-        match add_exp {
-            AddExp::Mul(mul_exp) => self.evaluate_mul_exp(mul_exp), 
-            AddExp::AddMul(left, op, right) => {
-                let left_val = self.evaluate_add_exp(left);
-                let right_val = self.evaluate_mul_exp(right);
-                match op {
-                    PlusSubOp::Plus => left_val + right_val, 
-                    PlusSubOp::Minus => left_val - right_val, 
-                }
+    fn from_bool(value: bool) -> Self {
+        Self::Int(value as i32)
+    }
+
+    /// 二元算术运算, 任一侧为 float 时按 float 计算
+    /// int_op 返回回绕后的结果以及是否溢出
+    fn arith(self, rhs: Self, int_op: fn(i32, i32) -> (i32, bool), float_op: fn(f32, f32) -> f32) -> (Self, bool) {
+        match (self, rhs) {
+            (Self::Int(l), Self::Int(r)) => {
+                let (value, overflow) = int_op(l, r);
+                (Self::Int(value), overflow)
             }
+            (l, r) => (Self::Float(float_op(l.as_f32(), r.as_f32())), false),
         }
     }
 
-    pub fn evaluate_mul_exp(&self, mul_exp: &MulExp) -> i32 {
-        match mul_exp {
-            MulExp::Unary(unary_exp) => self.evaluate_unary_exp(unary_exp),
-            MulExp::MulDiv(left, op, right) => {
-                let left_val = self.evaluate_mul_exp(left);
-                let right_val = self.evaluate_unary_exp(right);
-                match op {
-                    MulDivOp::Mul => left_val * right_val,
-                    MulDivOp::Div => left_val / right_val,
-                    MulDivOp::Mod => left_val % right_val,
-                }
-            }
+    /// 比较运算, 结果总是 int
+    fn compare(self, rhs: Self, int_op: fn(&i32, &i32) -> bool, float_op: fn(&f32, &f32) -> bool) -> Self {
+        match (self, rhs) {
+            (Self::Int(l), Self::Int(r)) => Self::from_bool(int_op(&l, &r)),
+            (l, r) => Self::from_bool(float_op(&l.as_f32(), &r.as_f32())),
         }
     }
+}
 
-    pub fn evaluate_unary_exp(&self, unary_exp: &UnaryExp) -> i32 {
-        match unary_exp {
-            UnaryExp::Primary(primary) => self.evaluate_primary_exp(primary),
-            UnaryExp::Unary(op, exp) => {
-                let val = self.evaluate_unary_exp(exp);
-                match op {
-                    UnaryOp::Plus => val,
-                    UnaryOp::Minus => -val,
-                    UnaryOp::Not => if val == 0 { 1 } else { 0 },
-                }
-            }
-            UnaryExp::FuncCall(_, _) => {0}
+impl IRGen {
+    /// 求值数组 array 的一个维度, 维度必须是正整数
+    pub fn evaluate_array_dim(&mut self, array: &str, exp: &Expr) -> CompileResult<usize> {
+        let size = match self.evaluate_const_exp(exp)? {
+            ConstValue::Int(size) => size,
+            ConstValue::Float(_) => return Err(CompileError::NonIntegerArraySize(array.to_string())),
+        };
+        if size <= 0 {
+            return Err(CompileError::InvalidArraySize { array: array.to_string(), size });
         }
+        Ok(size as usize)
+    }
+
+    /// 求值常量表达式, 错误与警告定位到产生它的子表达式
+    /// 同一表达式可能被求值多次(如数组维度), 相同的警告只记录一次
+    pub fn evaluate_const_exp(&mut self, exp: &Expr) -> CompileResult<ConstValue> {
+        self.at_exp(exp, |irgen| irgen.evaluate_const_exp_at(exp))
     }
 
-    pub fn evaluate_primary_exp(&self, primary: &PrimaryExp) -> i32 {
-        match primary {
-            PrimaryExp::Number(num) => *num,
-            PrimaryExp::Paren(exp) => self.evaluate_exp(exp),
-            PrimaryExp::LVal(lval) => {
-                match self.function_irgen.scope_stack.lookup(&lval.ident) {
+    fn evaluate_const_exp_at(&mut self, exp: &Expr) -> CompileResult<ConstValue> {
+        match &exp.kind {
+            ExprKind::Number(num) => Ok(ConstValue::Int(*num)),
+            ExprKind::Float(num) => Ok(ConstValue::Float(*num)),
+            ExprKind::Str(_) => Err(CompileError::NotConstant("string literal".to_string())),
+            ExprKind::Call(func_name, _) => Err(CompileError::NotConstant(format!("call to function '{}'", func_name))),
+            ExprKind::LVal(lval) => {
+                match self.function_irgen.scope_stack.lookup(&lval.ident).cloned() {
                     Some(SymbolInfo::Const(value)) => {
                         if !lval.indices.is_empty() {
-                            panic!("Cannot index into scalar constant '{}'", lval.ident);
+                            return Err(CompileError::InvalidSubscript(format!("'{}' is a scalar constant", lval.ident)));
                         }
-                        *value
+                        Ok(value)
                     }
                     
-                    Some(SymbolInfo::Var(_)) => panic!("Cannot use variable '{}' in constant expression", lval.ident),
-                    Some(SymbolInfo::GlobalVar(_)) => panic!("Cannot use global variable '{}' in constant expression", lval.ident),
+                    Some(SymbolInfo::LocalConstArray(ptr, dimensions)) |
+                    Some(SymbolInfo::GlobalConstArray(ptr, dimensions)) => {
+                        self.evaluate_const_array_elem(&lval.ident, ptr, &dimensions, &lval.indices)
+                    }
                     
-                    Some(SymbolInfo::LocalConstArray(_, _)) => panic!("Cannot use local constant array '{}' in constant expression", lval.ident),
-                    Some(SymbolInfo::GlobalConstArray(_, _)) => panic!("Cannot use global constant array '{}' in constant expression", lval.ident),
-                    Some(SymbolInfo::LocalArray(_, _)) => panic!("Cannot use array '{}' in constant expression", lval.ident),
-                    Some(SymbolInfo::GlobalArray(_, _)) => panic!("Cannot use global array '{}' in constant expression", lval.ident),
-                    Some(SymbolInfo::ParamArray(_, _)) => panic!("Cannot use parameter array '{}' in constant expression", lval.ident),
+                    Some(SymbolInfo::Var(_)) | Some(SymbolInfo::GlobalVar(_)) => {
+                        Err(CompileError::NotConstant(format!("variable '{}'", lval.ident)))
+                    }
+                    Some(SymbolInfo::LocalArray(_, _)) | Some(SymbolInfo::GlobalArray(_, _)) | Some(SymbolInfo::ParamArray(_, _)) => {
+                        Err(CompileError::NotConstant(format!("array '{}'", lval.ident)))
+                    }
                     
-                    None => panic!("Identifier '{}' not found", lval.ident),
+                    None => Err(CompileError::UndefinedSymbol(lval.ident.clone())),
+                }
+            }
+            // 先于操作数检查, 错误定位到运算符而不是其中的变量
+            ExprKind::Unary(UnaryOp::Deref | UnaryOp::AddrOf, _) => Err(CompileError::NotConstant("pointer expression".to_string())),
+            ExprKind::Unary(op, operand) => {
+                let val = self.evaluate_const_exp(operand)?;
+                Ok(match op {
+                    UnaryOp::Plus => val,
+                    UnaryOp::Minus => match val {
+                        ConstValue::Int(v) => {
+                            let (value, overflow) = v.overflowing_neg();
+                            if overflow {
+                                self.warn(exp.span.start, WarningKind::IntegerOverflow(value));
+                            }
+                            ConstValue::Int(value)
+                        }
+                        ConstValue::Float(v) => ConstValue::Float(-v),
+                    },
+                    UnaryOp::Not => ConstValue::from_bool(!val.is_true()),
+                    UnaryOp::Deref | UnaryOp::AddrOf => unreachable!("checked above"),
+                })
+            }
+            ExprKind::Binary(op, left, right) => {
+                let left_val = self.evaluate_const_exp(left)?;
+                // 短路求值
+                match op {
+                    BinaryOp::Or if left_val.is_true() => return Ok(ConstValue::Int(1)),
+                    BinaryOp::And if !left_val.is_true() => return Ok(ConstValue::Int(0)),
+                    _ => {}
                 }
+                let right_val = self.evaluate_const_exp(right)?;
+                self.evaluate_const_binary(exp, *op, left_val, right_val)
+            }
+        }
+    }
+
+    fn evaluate_const_binary(&mut self, exp: &Expr, op: BinaryOp, left_val: ConstValue, right_val: ConstValue) -> CompileResult<ConstValue> {
+        if op == BinaryOp::Mod && !matches!((left_val, right_val), (ConstValue::Int(_), ConstValue::Int(_))) {
+            return Err(CompileError::InvalidOperand("operator '%' cannot be applied to float operands".to_string()));
+        }
+        // 整数除以0不中断编译, 结果与 RISC-V 一致: x / 0 = -1, x % 0 = x
+        if let (BinaryOp::Div | BinaryOp::Mod, ConstValue::Int(l), ConstValue::Int(0)) = (op, left_val, right_val) {
+            self.warn(exp.span.start, WarningKind::DivisionByZero);
+            return Ok(ConstValue::Int(match op {
+                BinaryOp::Div => -1,
+                _ => l,
+            }));
+        }
+        let (value, overflow) = match op {
+            BinaryOp::Or | BinaryOp::And => (ConstValue::from_bool(right_val.is_true()), false),
+            BinaryOp::Eq => (left_val.compare(right_val, i32::eq, f32::eq), false),
+            BinaryOp::Ne => (left_val.compare(right_val, i32::ne, f32::ne), false),
+            BinaryOp::Lt => (left_val.compare(right_val, i32::lt, f32::lt), false),
+            BinaryOp::Gt => (left_val.compare(right_val, i32::gt, f32::gt), false),
+            BinaryOp::Le => (left_val.compare(right_val, i32::le, f32::le), false),
+            BinaryOp::Ge => (left_val.compare(right_val, i32::ge, f32::ge), false),
+            BinaryOp::Add => left_val.arith(right_val, i32::overflowing_add, |l, r| l + r),
+            BinaryOp::Sub => left_val.arith(right_val, i32::overflowing_sub, |l, r| l - r),
+            BinaryOp::Mul => left_val.arith(right_val, i32::overflowing_mul, |l, r| l * r),
+            BinaryOp::Div => left_val.arith(right_val, i32::overflowing_div, |l, r| l / r),
+            BinaryOp::Mod => left_val.arith(right_val, i32::overflowing_rem, |l, r| l % r),
+        };
+        if overflow {
+            self.warn(exp.span.start, WarningKind::IntegerOverflow(value.as_i32()));
+        }
+        Ok(value)
+    }
+
+    /// 常量数组元素: 下标必须都是常量表达式, 且要访问到标量元素
+    fn evaluate_const_array_elem(&mut self, ident: &str, ptr: Value, dimensions: &[usize], indices: &[Expr]) -> CompileResult<ConstValue> {
+        if indices.len() != dimensions.len() {
+            return Err(CompileError::NotConstant(format!("partially indexed constant array '{}'", ident)));
+        }
+
+        // 按行优先计算元素在展开后数组中的位置
+        let mut offset = 0;
+        for (index_exp, &dim) in indices.iter().zip(dimensions) {
+            let index = match self.evaluate_const_exp(index_exp)? {
+                ConstValue::Int(index) => index,
+                ConstValue::Float(_) => return Err(CompileError::InvalidSubscript(format!("subscript of '{}' is not an integer", ident))),
+            };
+            if index < 0 || index as usize >= dim {
+                return Err(CompileError::IndexOutOfBounds { array: ident.to_string(), index, dimension: dim });
             }
+            offset = offset * dim + index as usize;
         }
+        Ok(self.const_arrays[&ptr][offset])
     }
-}
\ No newline at end of file
+}
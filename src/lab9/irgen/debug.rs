@@ -0,0 +1,67 @@
+//! 调试信息(-g): 记录指令、函数与局部变量在源码中的位置, 供后端生成 .loc 与 DWARF
+use std::collections::HashMap;
+use koopa::ir::{Function, Value};
+use crate::lab9::irgen::symbol::SymbolInfo;
+use crate::lab9::irgen::IRGen;
+
+/// 有名字的源码实体, 位置为(单元, 预处理后源码中的偏移), 由调用者换算为行号
+#[derive(Debug, Clone)]
+pub struct DebugSymbol {
+    pub name: String,
+    pub unit: usize,
+    pub pos: usize,
+}
+
+/// 栈上的局部变量或形参
+#[derive(Debug, Clone)]
+pub struct DebugVariable {
+    pub symbol: DebugSymbol,
+    pub is_param: bool,
+    pub is_float: bool, // 标量元素类型为float
+}
+
+/// IR到源码的映射
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    pub instructions: HashMap<Value, (usize, usize)>, // 指令 -> 产生它的(最内层)语句的位置
+    pub functions: HashMap<Function, DebugSymbol>,
+    pub variables: HashMap<Value, DebugVariable>,     // 栈槽(alloc) -> 变量
+}
+
+impl IRGen {
+    /// 生成调试信息所需的源码映射(见 IrModule::source_map)
+    pub fn with_debug_info(mut self) -> Self {
+        self.source_map = Some(SourceMap::default());
+        self
+    }
+
+    /// 把当前函数中还没有位置的指令都记到pos处
+    /// 嵌套的语句先于外层语句生成完, 因此每条指令记录的是包含它的最内层语句
+    pub fn record_instruction_positions(&mut self, pos: usize) {
+        let (Some(source_map), Some(function)) = (&mut self.source_map, self.function_irgen.current_function) else {
+            return;
+        };
+        for (_, bb_node) in self.program.func(function).layout().bbs() {
+            for &inst in bb_node.insts().keys() {
+                source_map.instructions.entry(inst).or_insert((self.unit, pos));
+            }
+        }
+    }
+
+    pub fn record_function(&mut self, function: Function, name: &str, pos: usize) {
+        if let Some(source_map) = &mut self.source_map {
+            let symbol = DebugSymbol { name: name.to_string(), unit: self.unit, pos };
+            source_map.functions.insert(function, symbol);
+        }
+    }
+
+    /// 记录局部变量的栈槽, 常量标量没有栈槽
+    pub fn record_variable(&mut self, name: &str, info: &SymbolInfo, pos: usize, is_param: bool) {
+        let (Some(source_map), Some(ptr)) = (&mut self.source_map, info.ptr()) else {
+            return;
+        };
+        let symbol = DebugSymbol { name: name.to_string(), unit: self.unit, pos };
+        let is_float = self.float_values.contains(&ptr);
+        source_map.variables.insert(ptr, DebugVariable { symbol, is_param, is_float });
+    }
+}
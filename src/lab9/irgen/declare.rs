@@ -1,4 +1,5 @@
-use crate::ast::{ConstInitVal, Decl, InitVal};
+use crate::ast::{BType, ConstInitVal, Decl, InitVal};
+use crate::lab9::irgen::error::{CompileError, CompileResult};
 use crate::lab9::irgen::symbol::SymbolInfo;
 use crate::lab9::irgen::IRGen;
 use koopa::ir::builder::LocalInstBuilder;
@@ -6,7 +7,7 @@ use koopa::ir::Type;
 
 // 处理声明(常量与变量)
 impl IRGen {
-    pub fn generate_decl(&mut self, decl: &Decl) {
+    pub fn generate_decl(&mut self, decl: &Decl) -> CompileResult<()> {
         match decl {
             // const int a = 1, b = 2 + 3, c = (a > b);
             Decl::Const(const_decl) => {
@@ -15,25 +16,22 @@ impl IRGen {
                         // 普通常量
                         let value = match &def.const_init_val {
                             ConstInitVal::Exp(const_exp) => {
-                                self.evaluate_lor_exp(&const_exp.lor_exp)
+                                self.evaluate_const_exp(const_exp)?.cast(const_decl.b_type)
                             }
                             ConstInitVal::List(_) => {
-                                panic!("Scalar constant cannot have list initializer")
+                                return Err(CompileError::InvalidInitializer("scalar initialized with a list".to_string()))
                             }
                         };
                         
                         // 检查是否重复定义并存入符号表
-                        if let Err(err) = self.function_irgen.scope_stack.define(def.ident.clone(), SymbolInfo::Const(value)) {
-                            panic!("{}", err)
-                        }
+                        self.define_local(&def.ident, SymbolInfo::Const(value), def.pos)?;
                     } else {
                         // 常量数组 - 与变量数组采用相同的处理方式
                         let unique_name = self.function_irgen.scope_stack.generate_unique_name(&def.ident);
 
                         let mut dimensions = Vec::new();
                         for dim_exp in &def.dimensions {
-                            let dim_value = self.evaluate_lor_exp(&dim_exp.lor_exp);
-                            dimensions.push(dim_value as usize);
+                            dimensions.push(self.evaluate_array_dim(&def.ident, dim_exp)?);
                         }
 
                         // 创建数组类型
@@ -49,26 +47,27 @@ impl IRGen {
                         let alloc_inst = func_data.dfg_mut().new_value().alloc(array_type.clone());
                         func_data.dfg_mut().set_value_name(alloc_inst, Some(unique_name));
                         func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(alloc_inst).unwrap();
+                        if const_decl.b_type == BType::Float {
+                            self.mark_float(alloc_inst);
+                        }
 
                         // 处理初始化值
                         use crate::lab9::irgen::array::LocalInitializer;
                         
-                        let initializer = LocalInitializer::from_const_init_val(&def.const_init_val, self)
-                            .unwrap_or_else(|e| panic!("Failed to create local initializer: {}", e));
-                        let reshaped = initializer.reshape(&array_type)
-                            .unwrap_or_else(|e| panic!("Failed to reshape initializer: {}", e));
-                        let init_values = reshaped.flatten(self);
-                        
-                        // 使用 getelemptr 和 store 指令初始化数组
-                        self.initialize_local_array(alloc_inst, &init_values, &dimensions);
+                        let initializer = LocalInitializer::from_const_init_val(&def.const_init_val, self, const_decl.b_type)?;
+                        let reshaped = initializer.reshape(&array_type)?;
+                        let elements = reshaped.const_elements(const_decl.b_type);
+
+                        // 从只读的常量镜像复制初始值
+                        self.initialize_local_const_array(alloc_inst, &elements, &dimensions);
+                        self.const_arrays.insert(alloc_inst, elements);
                         
                         // 存入符号表 - 存储指针和维度信息
-                        if let Err(err) = self.function_irgen.scope_stack.define(
-                            def.ident.clone(), 
-                            SymbolInfo::LocalConstArray(alloc_inst, dimensions)
-                        ) {
-                            panic!("{}", err)
-                        }
+                        self.define_local(
+                            &def.ident, 
+                            SymbolInfo::LocalConstArray(alloc_inst, dimensions),
+                            def.pos
+                        )?;
                     }
                 }
             }
@@ -77,25 +76,33 @@ impl IRGen {
             Decl::Var(var_decl) => {
                 for def in &var_decl.var_def_list {
                     let unique_name = self.function_irgen.scope_stack.generate_unique_name(&def.ident);
+                    if def.pointer > 0 && !def.dimensions.is_empty() {
+                        return Err(CompileError::Unsupported(format!("array of pointers '{}'", def.ident)));
+                    }
 
                     if def.dimensions.is_empty() {
-                        // 普通变量
+                        // 普通变量(包括指针变量)
+                        let ty = Self::scalar_type(def.pointer);
                         let current_bb = self.current_bb();
                         let func_data = self.function_data_mut();
 
                         // 为变量分配内存(简单起见，全部分配到栈上-无寄存器分配策略)
-                        let alloc_ptr = func_data.dfg_mut().new_value().alloc(Type::get_i32());// 返回这个变量的指针
+                        let alloc_ptr = func_data.dfg_mut().new_value().alloc(ty.clone());// 返回这个变量的指针
                         func_data.dfg_mut().set_value_name(alloc_ptr, Some(unique_name));
                         func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(alloc_ptr).unwrap();
+                        if var_decl.b_type == BType::Float {
+                            self.mark_float(alloc_ptr);
+                        }
                         
                         // 如果有初始化值，生成store指令
                         if let Some(init_val) = &def.init_val {
                             let init_value = match init_val {
                                 InitVal::Exp(exp) => {
-                                    self.generate_exp(exp)
+                                    let value = self.generate_exp(exp)?;
+                                    self.convert_to_type(value, &ty, var_decl.b_type)?
                                 }
                                 InitVal::List(_) => {
-                                    panic!("Scalar variable cannot have list initializer")
+                                    return Err(CompileError::InvalidInitializer("scalar initialized with a list".to_string()))
                                 }
                             };
                             
@@ -107,15 +114,12 @@ impl IRGen {
                         }
                         
                         // 检查重复定义并存入符号表
-                        if let Err(err) = self.function_irgen.scope_stack.define(def.ident.clone(), SymbolInfo::Var(alloc_ptr)) {
-                            panic!("{}", err)
-                        }
+                        self.define_local(&def.ident, SymbolInfo::Var(alloc_ptr), def.pos)?;
                     } else {
                         // 数组变量 - 使用 getelemptr 和 store 指令初始化
                         let mut dimensions = Vec::new();
                         for dim_exp in &def.dimensions {
-                            let dim_value = self.evaluate_lor_exp(&dim_exp.lor_exp);
-                            dimensions.push(dim_value as usize);
+                            dimensions.push(self.evaluate_array_dim(&def.ident, dim_exp)?);
                         }
                         
                         // 创建数组类型
@@ -131,15 +135,16 @@ impl IRGen {
                         let alloc_inst = func_data.dfg_mut().new_value().alloc(array_type.clone());
                         func_data.dfg_mut().set_value_name(alloc_inst, Some(unique_name));
                         func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(alloc_inst).unwrap();
+                        if var_decl.b_type == BType::Float {
+                            self.mark_float(alloc_inst);
+                        }
                         
                         // 处理初始化值
                         if let Some(init_val) = &def.init_val {
                             use crate::lab9::irgen::array::LocalInitializer;
                             
-                            let initializer = LocalInitializer::from_init_val(init_val, self)
-                                .unwrap_or_else(|e| panic!("Failed to create local initializer: {}", e));
-                            let reshaped = initializer.reshape(&array_type)
-                                .unwrap_or_else(|e| panic!("Failed to reshape initializer: {}", e));
+                            let initializer = LocalInitializer::from_init_val(init_val, self, var_decl.b_type)?;
+                            let reshaped = initializer.reshape(&array_type)?;
                             let init_values = reshaped.flatten(self);
                             
                             // 使用 getelemptr 和 store 指令初始化数组
@@ -147,16 +152,16 @@ impl IRGen {
                         }
                         
                         // 存入符号表
-                        if let Err(err) = self.function_irgen.scope_stack.define(
-                            def.ident.clone(), 
-                            SymbolInfo::LocalArray(alloc_inst, dimensions)
-                        ) {
-                            panic!("{}", err)
-                        }
+                        self.define_local(
+                            &def.ident, 
+                            SymbolInfo::LocalArray(alloc_inst, dimensions),
+                            def.pos
+                        )?;
                     }
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -0,0 +1,208 @@
+//! IR生成阶段的编译错误与警告
+use std::collections::HashMap;
+use std::fmt;
+
+/// 源程序中的语义错误, IR生成遇到错误时逐层返回, 不会 panic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    UndefinedSymbol(String),    // 未定义的标识符
+    UndefinedFunction(String),  // 未定义的函数
+    Redefinition(String),       // 同一作用域内重复定义
+    ConflictingTypes { name: String, previous: (usize, usize) }, // 同一符号的多个声明类型不一致, previous 为先前声明的(单元, 位置)
+    NotConstant(String),        // 常量表达式中出现了非常量, 内容为出错的部分
+    IndexOutOfBounds { array: String, index: i32, dimension: usize },
+    InvalidArraySize { array: String, size: i32 }, // 数组维度不是正数
+    NonIntegerArraySize(String), // 数组维度不是整数(浮点常量表达式)
+    InvalidSubscript(String),   // 下标访问不合法(对标量下标、下标不是整数等)
+    AssignToConstant(String),
+    AssignToArray(String),
+    InvalidInitializer(String), // 初始化列表与被初始化对象的类型不匹配
+    InvalidOperand(String),     // 运算符不能作用于该类型的操作数
+    IncompatibleTypes(String),  // 无法进行的隐式类型转换
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    WrongArgumentCount { function: String, expected: usize, found: usize },
+    VoidValue(String),          // 使用了 void 函数调用的值, 内容为函数名
+    ReturnValueInVoid(String),  // void 函数中带值的 return, 内容为函数名
+    MissingReturnValue(String), // 非 void 函数中不带值的 return, 内容为函数名
+    Unsupported(String),        // 尚不支持的语言特性
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndefinedSymbol(name) => write!(f, "use of undeclared identifier '{}'", name),
+            Self::UndefinedFunction(name) => write!(f, "call to undeclared function '{}'", name),
+            Self::Redefinition(name) => write!(f, "redefinition of '{}'", name),
+            Self::ConflictingTypes { name, .. } => write!(f, "conflicting types for '{}'", name),
+            Self::NotConstant(what) => write!(f, "{} is not a constant expression", what),
+            Self::IndexOutOfBounds { array, index, dimension } => {
+                write!(f, "index {} is out of bounds for array '{}' with dimension {}", index, array, dimension)
+            }
+            Self::InvalidArraySize { array, size } => write!(f, "array '{}' has non-positive size {}", array, size),
+            Self::NonIntegerArraySize(array) => write!(f, "size of array '{}' has non-integer type", array),
+            Self::InvalidSubscript(message) => write!(f, "invalid subscript: {}", message),
+            Self::AssignToConstant(name) => write!(f, "cannot assign to constant '{}'", name),
+            Self::AssignToArray(name) => write!(f, "cannot assign to array '{}'", name),
+            Self::InvalidInitializer(message) => write!(f, "invalid initializer: {}", message),
+            Self::InvalidOperand(message) => write!(f, "invalid operand: {}", message),
+            Self::IncompatibleTypes(message) => write!(f, "incompatible types: {}", message),
+            Self::BreakOutsideLoop => write!(f, "'break' statement not in loop"),
+            Self::ContinueOutsideLoop => write!(f, "'continue' statement not in loop"),
+            Self::WrongArgumentCount { function, expected, found } => {
+                write!(f, "function '{}' expects {} arguments, but {} were given", function, expected, found)
+            }
+            Self::VoidValue(function) => write!(f, "void value returned by '{}' is used", function),
+            Self::ReturnValueInVoid(function) => write!(f, "void function '{}' should not return a value", function),
+            Self::MissingReturnValue(function) => write!(f, "non-void function '{}' should return a value", function),
+            Self::Unsupported(what) => write!(f, "{} is not supported", what),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// 带位置的编译错误, 位置为出错的(最内层)子表达式的起始位置, 不在表达式中时为出错的声明或语句的起始位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocatedError {
+    pub unit: usize,
+    pub pos: usize,
+    pub error: CompileError,
+}
+
+impl LocatedError {
+    /// 附注: 与错误相关的另一处位置(单元, 偏移)及其说明
+    pub fn note(&self) -> Option<((usize, usize), String)> {
+        match &self.error {
+            CompileError::ConflictingTypes { name, previous } => Some((*previous, format!("previous declaration of '{}' is here", name))),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LocatedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+pub type CompileResult<T> = Result<T, CompileError>;
+
+/// 不影响IR生成, 但很可能是写错了的代码
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WarningKind {
+    DivisionByZero,       // 除以常量0或对常量0取模
+    IntegerOverflow(i32), // 常量之间的整数运算溢出, 内容为回绕后的结果
+    UnusedVariable(String),  // 从未使用的局部变量或常量
+    UnusedParameter(String), // 从未使用的形参
+    UnusedFunction(String),  // 从未调用的static函数
+    UnreachableCode,         // return/break/continue之后的语句
+    Shadow(String),          // 局部符号遮蔽了外层作用域的同名符号
+    MissingReturn(String),   // 非void函数可能执行到函数末尾而没有返回值
+}
+
+/// 所有警告选项及其是否默认开启
+const WARNING_FLAGS: [(&str, bool); 8] = [
+    ("div-by-zero", true),
+    ("overflow", true),
+    ("unused-variable", true),
+    ("unused-parameter", false),
+    ("unused-function", true),
+    ("unreachable-code", true),
+    ("shadow", false),
+    ("return-type", true),
+];
+
+impl WarningKind {
+    /// 控制该警告的选项名(-W<name>)
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Self::DivisionByZero => "div-by-zero",
+            Self::IntegerOverflow(_) => "overflow",
+            Self::UnusedVariable(_) => "unused-variable",
+            Self::UnusedParameter(_) => "unused-parameter",
+            Self::UnusedFunction(_) => "unused-function",
+            Self::UnreachableCode => "unreachable-code",
+            Self::Shadow(_) => "shadow",
+            Self::MissingReturn(_) => "return-type",
+        }
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::IntegerOverflow(result) => write!(f, "integer overflow in expression, result wraps to {}", result),
+            Self::UnusedVariable(name) => write!(f, "unused variable '{}'", name),
+            Self::UnusedParameter(name) => write!(f, "unused parameter '{}'", name),
+            Self::UnusedFunction(name) => write!(f, "'{}' defined but not used", name),
+            Self::UnreachableCode => write!(f, "statement will never be executed"),
+            Self::Shadow(name) => write!(f, "declaration of '{}' shadows a previous declaration", name),
+            Self::MissingReturn(name) => write!(f, "control reaches end of non-void function '{}'", name),
+        }
+    }
+}
+
+/// 编译警告及其位置: 所在翻译单元与(预处理后)源码中的偏移, 由调用者换算为行列号
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileWarning {
+    pub unit: usize,
+    pub pos: usize,
+    pub kind: WarningKind,
+}
+
+/// 警告选项: -W<name> 开启, -Wno-<name> 关闭, -Wall 开启全部, -w 关闭全部, -Werror 把警告当作错误
+#[derive(Debug, Clone, Default)]
+pub struct WarningOptions {
+    flags: HashMap<&'static str, bool>, // 显式开启或关闭的警告
+    all: bool,
+    none: bool,
+    pub werror: bool,
+}
+
+impl WarningOptions {
+    /// 解析一个以 -W 或 -w 开头的命令行选项, 不认识的选项返回 Err
+    pub fn parse(&mut self, option: &str) -> Result<(), String> {
+        match option {
+            "-w" => self.none = true,
+            "-Wall" => self.all = true,
+            "-Werror" => self.werror = true,
+            "-Wno-error" => self.werror = false,
+            _ => {
+                let (name, enabled) = match option.strip_prefix("-Wno-") {
+                    Some(name) => (name, false),
+                    None => (option.strip_prefix("-W").unwrap_or(option), true),
+                };
+                let Some(&(flag, _)) = WARNING_FLAGS.iter().find(|(flag, _)| *flag == name) else {
+                    return Err(format!("unknown warning option '{}'", option));
+                };
+                self.flags.insert(flag, enabled);
+            }
+        }
+        Ok(())
+    }
+
+    /// 是否报告该警告
+    pub fn is_enabled(&self, kind: &WarningKind) -> bool {
+        if self.none {
+            return false;
+        }
+        let flag = kind.flag();
+        match self.flags.get(flag) {
+            Some(&enabled) => enabled,
+            None => self.all || WARNING_FLAGS.iter().any(|&(name, default)| name == flag && default),
+        }
+    }
+
+    /// 按选项报告警告: 不报告时返回None, 否则返回是否作为错误(-Werror)以及不含位置的诊断信息
+    pub fn diagnose(&self, kind: &WarningKind) -> Option<(bool, String)> {
+        if !self.is_enabled(kind) {
+            return None;
+        }
+        Some(match self.werror {
+            true => (true, format!("error: {} [-Werror={}]", kind, kind.flag())),
+            false => (false, format!("warning: {} [-W{}]", kind, kind.flag())),
+        })
+    }
+}
@@ -0,0 +1,186 @@
+//! 浮点数相关的IR生成
+//! 浮点值在IR中以i32位模式表示, 运算通过内建函数调用完成(见 lab9::abi)
+use crate::ast::BType;
+use crate::lab9::abi::FloatIntrinsic;
+use crate::lab9::irgen::calc::ConstValue;
+use crate::lab9::irgen::error::{CompileError, CompileResult};
+use crate::lab9::irgen::IRGen;
+use koopa::ir::builder::{LocalInstBuilder, ValueBuilder};
+use koopa::ir::{BinaryOp, Function, FunctionData, Type, TypeKind, Value, ValueKind};
+
+impl IRGen {
+    /// 值是否为float; 对指针(alloc/全局变量/数组参数)而言表示其标量元素类型为float
+    pub fn is_float(&self, value: Value) -> bool {
+        self.float_values.contains(&value)
+    }
+
+    pub fn mark_float(&mut self, value: Value) {
+        self.float_values.insert(value);
+    }
+
+    /// 生成浮点常量(位模式)
+    pub fn generate_float_const(&mut self, value: f32) -> Value {
+        let func_data = self.function_data_mut();
+        let bits = func_data.dfg_mut().new_value().integer(value.to_bits() as i32);
+        self.mark_float(bits);
+        bits
+    }
+
+    /// 获取浮点内建函数的句柄, 首次使用时才在程序中声明
+    fn float_intrinsic(&mut self, intrinsic: FloatIntrinsic) -> Function {
+        if let Some(&function) = self.functions.get(intrinsic.name()) {
+            return function;
+        }
+        let params = vec![Type::get_i32(); intrinsic.arity()];
+        let function = self.program.new_func(FunctionData::new_decl(
+            format!("@{}", intrinsic.name()),
+            params,
+            Type::get_i32(),
+        ));
+        self.functions.insert(intrinsic.name().to_string(), function);
+        function
+    }
+
+    /// 在当前基本块生成浮点内建函数调用
+    pub fn call_float_intrinsic(&mut self, intrinsic: FloatIntrinsic, args: Vec<Value>) -> Value {
+        let function = self.float_intrinsic(intrinsic);
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let call_inst = func_data.dfg_mut().new_value().call(function, args);
+        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(call_inst).unwrap();
+        if intrinsic.returns_float() {
+            self.mark_float(call_inst);
+        }
+        call_inst
+    }
+
+    /// 隐式类型转换: 将值转换为目标类型
+    pub fn convert_value(&mut self, value: Value, b_type: BType) -> CompileResult<Value> {
+        if self.is_pointer(value) {
+            let target = match b_type {
+                BType::Int => "int",
+                BType::Float => "float",
+            };
+            return Err(CompileError::IncompatibleTypes(format!("cannot convert a pointer to '{}'", target)));
+        }
+
+        // 常量直接在编译期转换
+        let is_float = self.is_float(value);
+        if let ValueKind::Integer(int) = self.function_data_mut().dfg().value(value).kind() {
+            let constant = match is_float {
+                true => ConstValue::Float(f32::from_bits(int.value() as u32)),
+                false => ConstValue::Int(int.value()),
+            };
+            return Ok(match constant.cast(b_type) {
+                ConstValue::Int(value) => self.function_data_mut().dfg_mut().new_value().integer(value),
+                ConstValue::Float(value) => self.generate_float_const(value),
+            });
+        }
+
+        Ok(match (is_float, b_type) {
+            (false, BType::Float) => self.call_float_intrinsic(FloatIntrinsic::IntToFloat, vec![value]),
+            (true, BType::Int) => self.call_float_intrinsic(FloatIntrinsic::FloatToInt, vec![value]),
+            _ => value,
+        })
+    }
+
+    /// 浮点二元运算: 两侧先统一转换为float
+    pub fn generate_float_binary_op(&mut self, intrinsic: FloatIntrinsic, left: Value, right: Value) -> CompileResult<Value> {
+        let left = self.convert_value(left, BType::Float)?;
+        let right = self.convert_value(right, BType::Float)?;
+        Ok(self.call_float_intrinsic(intrinsic, vec![left, right]))
+    }
+
+    /// 条件值: float 需要与 0.0 比较得到 int, 指针取其地址值, int 原样返回
+    pub fn generate_condition(&mut self, value: Value) -> Value {
+        if self.is_pointer(value) {
+            return self.pointer_to_int(value);
+        }
+        if !self.is_float(value) {
+            return value;
+        }
+        let zero = self.generate_float_const(0.0);
+        self.call_float_intrinsic(FloatIntrinsic::Ne, vec![value, zero])
+    }
+
+    /// 对 float 的逻辑非: x == 0.0
+    pub fn generate_float_not(&mut self, value: Value) -> Value {
+        let zero = self.generate_float_const(0.0);
+        self.call_float_intrinsic(FloatIntrinsic::Eq, vec![value, zero])
+    }
+
+    /// 当前函数在源码中的名字(不含@)
+    pub fn current_function_name(&mut self) -> String {
+        self.function_data_mut().name()[1..].to_string()
+    }
+
+    /// 当前函数的返回类型, void 函数返回 None
+    pub fn current_return_type(&mut self) -> Option<BType> {
+        let func_data = self.function_data_mut();
+        let returns_unit = match func_data.ty().kind() {
+            TypeKind::Function(_, ret) => ret.is_unit(),
+            _ => panic!("Function should have function type"),
+        };
+        if returns_unit {
+            return None;
+        }
+        let name = func_data.name().to_string();
+        if self.float_abi.get(&name).ret {
+            Some(BType::Float)
+        } else {
+            Some(BType::Int)
+        }
+    }
+
+    /// 整数二元运算与浮点二元运算的分派: 任一操作数为float时走内建函数
+    /// 指针的float标记表示其指向float, 因此先处理指针运算
+    pub fn generate_arith_op(&mut self, op: BinaryOp, left: Value, right: Value) -> CompileResult<Value> {
+        if let Some(value) = self.generate_pointer_op(op, left, right)? {
+            return Ok(value);
+        }
+        if self.is_float(left) || self.is_float(right) {
+            let intrinsic = match op {
+                BinaryOp::Add => FloatIntrinsic::Add,
+                BinaryOp::Sub => FloatIntrinsic::Sub,
+                BinaryOp::Mul => FloatIntrinsic::Mul,
+                BinaryOp::Div => FloatIntrinsic::Div,
+                BinaryOp::Eq => FloatIntrinsic::Eq,
+                BinaryOp::NotEq => FloatIntrinsic::Ne,
+                BinaryOp::Lt => FloatIntrinsic::Lt,
+                BinaryOp::Le => FloatIntrinsic::Le,
+                BinaryOp::Gt => FloatIntrinsic::Gt,
+                BinaryOp::Ge => FloatIntrinsic::Ge,
+                _ => return Err(CompileError::InvalidOperand(format!("operator '{}' cannot be applied to float operands", operator_symbol(op)))),
+            };
+            return self.generate_float_binary_op(intrinsic, left, right);
+        }
+
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let inst = func_data.dfg_mut().new_value().binary(op, left, right);
+        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(inst).unwrap();
+        Ok(inst)
+    }
+}
+
+/// Koopa二元运算对应的源码运算符, 用于错误信息
+pub fn operator_symbol(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::NotEq => "!=",
+        BinaryOp::Eq => "==",
+        BinaryOp::Gt => ">",
+        BinaryOp::Lt => "<",
+        BinaryOp::Ge => ">=",
+        BinaryOp::Le => "<=",
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::And => "&",
+        BinaryOp::Or => "|",
+        BinaryOp::Xor => "^",
+        BinaryOp::Shl => "<<",
+        BinaryOp::Shr | BinaryOp::Sar => ">>",
+    }
+}
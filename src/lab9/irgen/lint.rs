@@ -0,0 +1,132 @@
+//! IR生成过程中顺带完成的检查: 未使用的符号、不可达语句、符号遮蔽、缺少返回值, 以及非常量表达式中的常量溢出与除以0
+use std::collections::HashSet;
+use koopa::ir::{BasicBlock, TypeKind, ValueKind};
+use crate::ast::{BinaryOp, CompUnit, CompUnitItem, Expr, ExprKind, StorageClass};
+use crate::lab9::irgen::calc::ConstValue;
+use crate::lab9::irgen::error::{CompileResult, CompileWarning, WarningKind};
+use crate::lab9::irgen::symbol::SymbolInfo;
+use crate::lab9::irgen::IRGen;
+
+impl IRGen {
+    /// 在当前翻译单元的pos处报告警告, 相同的警告只报告一次
+    pub fn warn(&mut self, pos: usize, kind: WarningKind) {
+        let warning = CompileWarning {
+            unit: self.unit,
+            pos,
+            kind,
+        };
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// 非常量上下文中的整数运算同样报告溢出与除以0(常量表达式在求值时报告):
+    /// 整个表达式是常量时按常量求值, 否则只检查除数是否为常量0
+    pub fn check_constant_arith(&mut self, exp: &Expr) {
+        // 按常量求值只为发现警告, 失败时不是错误, 出错位置也要恢复
+        let error_pos = self.error_pos;
+        if self.evaluate_const_exp(exp).is_err() {
+            if let ExprKind::Binary(BinaryOp::Div | BinaryOp::Mod, _, right) = &exp.kind {
+                if let Ok(ConstValue::Int(0)) = self.evaluate_const_exp(right) {
+                    self.warn(exp.span.start, WarningKind::DivisionByZero);
+                }
+            }
+        }
+        self.error_pos = error_pos;
+    }
+
+    /// 定义局部变量或常量
+    pub fn define_local(&mut self, ident: &str, info: SymbolInfo, pos: usize) -> CompileResult<()> {
+        self.record_variable(ident, &info, pos, false);
+        self.define_tracked(ident, info, pos, WarningKind::UnusedVariable(ident.to_string()))
+    }
+
+    /// 定义形参
+    pub fn define_param(&mut self, ident: &str, info: SymbolInfo, pos: usize) -> CompileResult<()> {
+        self.record_variable(ident, &info, pos, true);
+        self.define_tracked(ident, info, pos, WarningKind::UnusedParameter(ident.to_string()))
+    }
+
+    /// 在当前作用域定义符号: 检查是否遮蔽外层的同名符号, 并记录定义位置以便作用域结束时报告未使用的符号
+    fn define_tracked(&mut self, ident: &str, info: SymbolInfo, pos: usize, unused: WarningKind) -> CompileResult<()> {
+        self.symbols.insert((self.unit, pos), info.clone());
+        let scope_stack = &mut self.function_irgen.scope_stack;
+        let shadows = scope_stack.is_defined_in_outer_scope(ident);
+        scope_stack.define(ident.to_string(), info)?;
+        scope_stack.track_usage(ident.to_string(), pos, unused);
+        if shadows {
+            self.warn(pos, WarningKind::Shadow(ident.to_string()));
+        }
+        Ok(())
+    }
+
+    /// 退出当前作用域, 报告其中从未使用过的局部符号
+    pub fn exit_scope(&mut self) {
+        for (pos, kind) in self.function_irgen.scope_stack.exit_scope() {
+            self.warn(pos, kind);
+        }
+    }
+
+    /// 报告当前单元中从未被调用的static函数
+    pub fn check_unused_functions(&mut self, ast: &CompUnit) {
+        for item in &ast.items {
+            let CompUnitItem::FuncDef(func_def) = item else { continue };
+            if func_def.storage != StorageClass::Static {
+                continue;
+            }
+            let used = match self.static_functions.get(&func_def.id) {
+                Some(function) => self.called_functions.contains(function),
+                None => true, // 函数头出错, 错误已经报告过
+            };
+            if !used {
+                self.warn(func_def.pos, WarningKind::UnusedFunction(func_def.id.clone()));
+            }
+        }
+    }
+
+    /// 非void函数的基本块bb没有终结指令, 且从入口可达(即控制流会执行到函数末尾)
+    /// 条件为整数常量的分支只沿实际会走的一边继续, 这样 while (1) 之后的代码不会被当作可达
+    pub fn falls_off_end(&mut self, bb: BasicBlock) -> bool {
+        let func_data = self.function_data_mut();
+        let returns_unit = match func_data.ty().kind() {
+            TypeKind::Function(_, ret) => ret.is_unit(),
+            _ => panic!("Function should have function type"),
+        };
+        if returns_unit {
+            return false;
+        }
+
+        let Some(entry) = func_data.layout().entry_bb() else {
+            return false;
+        };
+        let mut reachable = HashSet::new();
+        let mut worklist = vec![entry];
+        while let Some(current) = worklist.pop() {
+            if !reachable.insert(current) {
+                continue;
+            }
+            let Some(&last) = func_data.layout().bbs().node(&current).and_then(|node| node.insts().back_key()) else {
+                continue;
+            };
+            match func_data.dfg().value(last).kind() {
+                ValueKind::Jump(jump) => worklist.push(jump.target()),
+                ValueKind::Branch(branch) => match func_data.dfg().value(branch.cond()).kind() {
+                    ValueKind::Integer(cond) if cond.value() != 0 => worklist.push(branch.true_bb()),
+                    ValueKind::Integer(_) => worklist.push(branch.false_bb()),
+                    _ => worklist.extend([branch.true_bb(), branch.false_bb()]),
+                },
+                _ => {}
+            }
+        }
+        if !reachable.contains(&bb) {
+            return false;
+        }
+
+        let has_terminator = func_data.layout().bbs().node(&bb)
+            .and_then(|node| node.insts().back_key())
+            .map(|inst| matches!(func_data.dfg().value(*inst).kind(),
+                ValueKind::Return(_) | ValueKind::Jump(_) | ValueKind::Branch(_)))
+            .unwrap_or(false);
+        !has_terminator
+    }
+}
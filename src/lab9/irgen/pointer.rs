@@ -0,0 +1,301 @@
+//! 指针相关的IR生成: 取地址、解引用、指针运算与比较
+//!
+//! 指针变量是元素类型为指针的 alloc(`int *p` 对应 `alloc *i32`), 与普通标量共用 `SymbolInfo::Var`;
+//! 对指针而言 float 标记表示其最终指向的标量为 float, 读取与运算时随值传递
+//! 指针与整数的转换见 lab9::abi
+use crate::ast::{BType, Expr, ExprKind, LVal, UnaryOp};
+use crate::lab9::abi::pointer_cast_name;
+use crate::lab9::irgen::error::{CompileError, CompileResult};
+use crate::lab9::irgen::float::operator_symbol;
+use crate::lab9::irgen::symbol::SymbolInfo;
+use crate::lab9::irgen::IRGen;
+use koopa::ir::builder::{LocalInstBuilder, ValueBuilder};
+use koopa::ir::{BinaryOp, FunctionData, Type, TypeKind, Value, ValueKind};
+
+impl IRGen {
+    /// 声明符的标量类型: `pointer` 个'*'修饰的 i32(float 同样以 i32 表示)
+    pub fn scalar_type(pointer: usize) -> Type {
+        (0..pointer).fold(Type::get_i32(), |ty, _| Type::get_pointer(ty))
+    }
+
+    /// 值的类型, 值可能是当前函数中的指令, 也可能是全局变量
+    pub fn value_type(&mut self, value: Value) -> Type {
+        let func_data = self.function_data_mut();
+        if func_data.dfg().values().contains_key(&value) {
+            return func_data.dfg().value(value).ty().clone();
+        }
+        self.program.borrow_value(value).ty().clone()
+    }
+
+    pub fn is_pointer(&mut self, value: Value) -> bool {
+        matches!(self.value_type(value).kind(), TypeKind::Pointer(_))
+    }
+
+    /// 指针所指向的类型
+    fn pointee_type(&mut self, ptr: Value) -> CompileResult<Type> {
+        match self.value_type(ptr).kind() {
+            TypeKind::Pointer(base) => Ok(base.clone()),
+            _ => Err(CompileError::InvalidOperand("indirection requires a pointer operand".to_string())),
+        }
+    }
+
+    /// 符号对应的存储位置中存放的是否为指针(即符号是指针变量)
+    pub fn holds_pointer(&mut self, ptr: Value) -> CompileResult<bool> {
+        Ok(matches!(self.pointee_type(ptr)?.kind(), TypeKind::Pointer(_)))
+    }
+
+    /// 指针与i32之间的转换, 转换函数首次使用时才在程序中声明
+    fn cast_pointer(&mut self, value: Value, to_int: bool, ptr_ty: &Type) -> Value {
+        let name = pointer_cast_name(to_int, ptr_ty);
+        let function = match self.functions.get(&name) {
+            Some(&function) => function,
+            None => {
+                let (param, ret) = match to_int {
+                    true => (ptr_ty.clone(), Type::get_i32()),
+                    false => (Type::get_i32(), ptr_ty.clone()),
+                };
+                let function = self.program.new_func(FunctionData::new_decl(format!("@{}", name), vec![param], ret));
+                self.functions.insert(name, function);
+                function
+            }
+        };
+
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let call_inst = func_data.dfg_mut().new_value().call(function, vec![value]);
+        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(call_inst).unwrap();
+        call_inst
+    }
+
+    /// 指针的地址值, 用于比较与条件判断
+    pub fn pointer_to_int(&mut self, ptr: Value) -> Value {
+        let ty = self.value_type(ptr);
+        self.cast_pointer(ptr, true, &ty)
+    }
+
+    /// 赋值/传参/初始化时的隐式转换
+    /// 目标为指针时只接受同类型的指针或空指针常量0, 否则按基本类型转换
+    pub fn convert_to_type(&mut self, value: Value, ty: &Type, b_type: BType) -> CompileResult<Value> {
+        if !matches!(ty.kind(), TypeKind::Pointer(_)) {
+            return self.convert_value(value, b_type);
+        }
+
+        if self.is_pointer(value) {
+            if self.value_type(value) != *ty || self.is_float(value) != (b_type == BType::Float) {
+                let from = source_type_name(&self.value_type(value), self.is_float(value));
+                let to = source_type_name(ty, b_type == BType::Float);
+                return Err(CompileError::IncompatibleTypes(format!("cannot convert '{}' to '{}'", from, to)));
+            }
+            return Ok(value);
+        }
+        let is_null = !self.is_float(value) && matches!(
+            self.function_data_mut().dfg().value(value).kind(),
+            ValueKind::Integer(int) if int.value() == 0
+        );
+        if !is_null {
+            return Err(CompileError::IncompatibleTypes(format!("cannot convert a non-null integer to '{}'", source_type_name(ty, b_type == BType::Float))));
+        }
+        let null = self.cast_pointer(value, false, ty);
+        if b_type == BType::Float {
+            self.mark_float(null);
+        }
+        Ok(null)
+    }
+
+    /// 将值存入指针所指的位置, 值按该位置的类型做隐式转换
+    pub fn generate_store(&mut self, value: Value, dest: Value) -> CompileResult<()> {
+        let ty = self.pointee_type(dest)?;
+        if matches!(ty.kind(), TypeKind::Array(_, _)) {
+            return Err(CompileError::IncompatibleTypes(format!("cannot assign to an lvalue of array type '{}'", ty)));
+        }
+        let b_type = if self.is_float(dest) { BType::Float } else { BType::Int };
+        let value = self.convert_to_type(value, &ty, b_type)?;
+
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let store_inst = func_data.dfg_mut().new_value().store(value, dest);
+        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(store_inst).unwrap();
+        Ok(())
+    }
+
+    /// 解引用: 指向标量(或指针)时读取其值, 指向数组时得到数组首元素的指针
+    pub fn generate_deref(&mut self, ptr: Value) -> CompileResult<Value> {
+        let is_array = matches!(self.pointee_type(ptr)?.kind(), TypeKind::Array(_, _));
+
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let value = match is_array {
+            true => {
+                let zero = func_data.dfg_mut().new_value().integer(0);
+                func_data.dfg_mut().new_value().get_elem_ptr(ptr, zero)
+            }
+            false => func_data.dfg_mut().new_value().load(ptr),
+        };
+        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(value).unwrap();
+
+        if self.is_float(ptr) {
+            self.mark_float(value);
+        }
+        Ok(value)
+    }
+
+    /// 取地址: 操作数必须是左值或解引用表达式
+    pub fn generate_address_of(&mut self, exp: &Expr) -> CompileResult<Value> {
+        match &exp.kind {
+            // &*p 就是 p 本身
+            ExprKind::Unary(UnaryOp::Deref, inner) => {
+                let ptr = self.generate_exp(inner)?;
+                if !self.is_pointer(ptr) {
+                    return Err(CompileError::InvalidOperand("indirection requires a pointer operand".to_string()));
+                }
+                Ok(ptr)
+            }
+            ExprKind::LVal(lval) => self.generate_lval_address(lval),
+            _ => Err(CompileError::InvalidOperand("cannot take the address of an rvalue".to_string())),
+        }
+    }
+
+    /// 左值的地址
+    ///
+    /// 局部变量的地址一旦被取走, 就可能经由指针被读写, 其 alloc 必须保留在栈上(见 lab9::escape)
+    pub fn generate_lval_address(&mut self, lval: &LVal) -> CompileResult<Value> {
+        let symbol_info = self.function_irgen.scope_stack.lookup(&lval.ident).cloned();
+        let address = match symbol_info {
+            Some(SymbolInfo::Const(_)) => {
+                return Err(CompileError::InvalidOperand(format!("cannot take the address of constant '{}'", lval.ident)));
+            }
+            Some(SymbolInfo::Var(ptr)) | Some(SymbolInfo::GlobalVar(ptr)) => match lval.indices.is_empty() {
+                true => ptr,
+                false => self.generate_pointer_index_ptr(ptr, lval)?,
+            },
+            Some(SymbolInfo::LocalConstArray(ptr, _)) |
+            Some(SymbolInfo::GlobalConstArray(ptr, _)) |
+            Some(SymbolInfo::LocalArray(ptr, _)) |
+            Some(SymbolInfo::GlobalArray(ptr, _)) => self.generate_array_access_ptr(ptr, &lval.indices)?,
+            Some(SymbolInfo::ParamArray(ptr, _)) => match lval.indices.is_empty() {
+                true => ptr,
+                false => self.generate_param_array_access_ptr(ptr, &lval.indices)?,
+            },
+            None => return Err(CompileError::UndefinedSymbol(lval.ident.clone())),
+        };
+
+        let is_float = symbol_info.as_ref().and_then(SymbolInfo::ptr).is_some_and(|ptr| self.is_float(ptr));
+        if is_float {
+            self.mark_float(address);
+        }
+        Ok(address)
+    }
+
+    /// 指针变量的下标访问 `p[i][j]`, 即 `*(*(p + i) + j)`, 返回元素的地址
+    fn generate_pointer_index_ptr(&mut self, var_ptr: Value, lval: &LVal) -> CompileResult<Value> {
+        let indexes: Vec<Value> = lval.indices.iter().map(|exp| self.generate_exp(exp)).collect::<CompileResult<_>>()?;
+
+        let mut current_ptr = var_ptr;
+        for index in indexes {
+            if !self.holds_pointer(current_ptr)? {
+                return Err(CompileError::InvalidSubscript(format!("'{}' is not an array or pointer", lval.ident)));
+            }
+            let current_bb = self.current_bb();
+            let func_data = self.function_data_mut();
+            let loaded = func_data.dfg_mut().new_value().load(current_ptr);
+            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(loaded).unwrap();
+            current_ptr = func_data.dfg_mut().new_value().get_ptr(loaded, index);
+            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(current_ptr).unwrap();
+        }
+        Ok(current_ptr)
+    }
+
+    /// 有指针操作数的二元运算, 两侧都不是指针时返回 None
+    /// - 指针 ± 整数: getptr, 按元素大小偏移
+    /// - 指针 - 指针: 地址差除以元素大小
+    /// - 比较: 比较地址, 指针可以与整数(通常是0)比较
+    pub fn generate_pointer_op(&mut self, op: BinaryOp, left: Value, right: Value) -> CompileResult<Option<Value>> {
+        let left_is_pointer = self.is_pointer(left);
+        let right_is_pointer = self.is_pointer(right);
+        if !left_is_pointer && !right_is_pointer {
+            return Ok(None);
+        }
+
+        let value = match op {
+            BinaryOp::Add | BinaryOp::Sub if left_is_pointer != right_is_pointer => {
+                let (ptr, offset) = if left_is_pointer { (left, right) } else { (right, left) };
+                if op == BinaryOp::Sub && right_is_pointer {
+                    return Err(CompileError::InvalidOperand("cannot subtract a pointer from an integer".to_string()));
+                }
+                if self.is_float(offset) {
+                    return Err(CompileError::InvalidOperand("pointer offset must be an integer".to_string()));
+                }
+                let offset = match op {
+                    BinaryOp::Sub => {
+                        let zero = self.function_data_mut().dfg_mut().new_value().integer(0);
+                        self.generate_arith_op(BinaryOp::Sub, zero, offset)?
+                    }
+                    _ => offset,
+                };
+                let current_bb = self.current_bb();
+                let func_data = self.function_data_mut();
+                let get_ptr = func_data.dfg_mut().new_value().get_ptr(ptr, offset);
+                func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(get_ptr).unwrap();
+                if self.is_float(ptr) {
+                    self.mark_float(get_ptr);
+                }
+                get_ptr
+            }
+            BinaryOp::Sub => {
+                if self.value_type(left) != self.value_type(right) {
+                    return Err(CompileError::IncompatibleTypes(format!(
+                        "cannot subtract '{}' from '{}'", self.value_type(right), self.value_type(left)
+                    )));
+                }
+                let elem_size = self.pointee_type(left)?.size() as i32;
+                let left = self.pointer_to_int(left);
+                let right = self.pointer_to_int(right);
+                let diff = self.generate_arith_op(BinaryOp::Sub, left, right)?;
+                match elem_size {
+                    1 => diff,
+                    _ => {
+                        let size = self.function_data_mut().dfg_mut().new_value().integer(elem_size);
+                        self.generate_arith_op(BinaryOp::Div, diff, size)?
+                    }
+                }
+            }
+            BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                let left = if left_is_pointer { self.pointer_to_int(left) } else { left };
+                let right = if right_is_pointer { self.pointer_to_int(right) } else { right };
+                if self.is_float(left) || self.is_float(right) {
+                    return Err(CompileError::InvalidOperand("cannot compare a pointer with a float".to_string()));
+                }
+                self.generate_arith_op(op, left, right)?
+            }
+            _ => return Err(CompileError::InvalidOperand(format!("operator '{}' cannot be applied to pointer operands", operator_symbol(op)))),
+        };
+        Ok(Some(value))
+    }
+}
+
+/// 指针类型在源码中的写法, 如 `float *`, `int (*)[3]`
+/// Koopa IR 中 int 与 float 都是 i32, 元素类型由 is_float 给出
+fn source_type_name(ty: &Type, is_float: bool) -> String {
+    let base = if is_float { "float" } else { "int" };
+    let mut pointers = 0;
+    let mut dims = String::new();
+    let mut ty = ty.clone();
+    loop {
+        let next = match ty.kind() {
+            TypeKind::Pointer(next) if dims.is_empty() => {
+                pointers += 1;
+                next.clone()
+            }
+            TypeKind::Array(next, len) => {
+                dims.push_str(&format!("[{}]", len));
+                next.clone()
+            }
+            _ => break,
+        };
+        ty = next;
+    }
+    match dims.is_empty() {
+        true => format!("{} {}", base, "*".repeat(pointers)),
+        false => format!("{} ({}){}", base, "*".repeat(pointers), dims),
+    }
+}
@@ -1,10 +1,11 @@
-use crate::ast::Stmt;
+use crate::ast::{Expr, ExprKind, Stmt};
 
+use crate::lab9::irgen::error::{CompileError, CompileResult};
 use crate::lab9::irgen::{ControlFlowType, IRGen, LoopContext};
 use koopa::ir::builder::{BasicBlockBuilder, LocalInstBuilder};
 
 impl IRGen {
-    pub fn generate_stmt(&mut self, stmt: &Stmt) -> bool{
+    pub fn generate_stmt(&mut self, stmt: &Stmt) -> CompileResult<bool> {
         match stmt {
             Stmt::Break => {
                 if let Some(loop_context) = self.function_irgen.loop_stack.last() {
@@ -15,9 +16,9 @@ impl IRGen {
 
                     let jump_inst = func_data.dfg_mut().new_value().jump(loop_end);
                     func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(jump_inst).unwrap();
-                    true // 表示已添加终结指令
+                    Ok(true) // 表示已添加终结指令
                 } else {
-                    panic!("break statement outside of loop");
+                    Err(CompileError::BreakOutsideLoop)
                 }
             }
             
@@ -30,9 +31,9 @@ impl IRGen {
                     
                     let jump_inst = func_data.dfg_mut().new_value().jump(loop_header);
                     func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(jump_inst).unwrap();
-                    true // 表示已添加终结指令
+                    Ok(true) // 表示已添加终结指令
                 } else {
-                    panic!("continue statement outside of loop");
+                    Err(CompileError::ContinueOutsideLoop)
                 }
             }
             
@@ -75,7 +76,8 @@ impl IRGen {
                 
                 // 设置当前基本块=循环头，生成条件判断
                 self.function_irgen.current_bb = Some(loop_header);
-                let cond_value = self.generate_exp(cond);
+                let cond_value = self.generate_exp(cond)?;
+                let cond_value = self.generate_condition(cond_value);
                 
                 // 生成条件分支
                 {
@@ -90,11 +92,11 @@ impl IRGen {
                 
                 // 生成循环体语句
                 let has_terminator = if let Stmt::Block(block) = stmt.as_ref() {
-                    self.generate_block(block)
+                    self.generate_block(block)?
                 } else {
                     self.function_irgen.scope_stack.enter_scope();
-                    let result = self.generate_stmt(stmt);
-                    self.function_irgen.scope_stack.exit_scope();
+                    let result = self.generate_stmt(stmt)?;
+                    self.exit_scope();
                     result
                 };                
                 // 如果循环体没有终结指令，添加跳转到循环头
@@ -115,12 +117,13 @@ impl IRGen {
                 self.function_irgen.loop_stack.pop();
                 self.pop_control_flow();
                 
-                false // while语句本身不是终结指令
+                Ok(false) // while语句本身不是终结指令
             }
             
             Stmt::If(cond, then_stmt, else_stmt) => {
                 // 生成条件表达式的值
-                let cond_value = self.generate_exp(cond);
+                let cond_value = self.generate_exp(cond)?;
+                let cond_value = self.generate_condition(cond_value);
                 
                 self.function_irgen.bb_counter += 1;
                 let bb_counter = self.function_irgen.bb_counter;
@@ -154,65 +157,30 @@ impl IRGen {
                 // 处理then分支
                 self.function_irgen.current_bb = Some(then_bb);
                 if let Stmt::Block(block) = then_stmt.as_ref() {
-                    self.generate_block(block);
+                    self.generate_block(block)?;
                 } else {
                     // 如果不是块语句，需要创建一个临时作用域
                     self.function_irgen.scope_stack.enter_scope();
-                    self.generate_stmt(then_stmt);
-                    self.function_irgen.scope_stack.exit_scope();
-                }
-                // 检查then_bb是否已有终结指令，如果没有则添加跳转
-                {
-                    let func_data = self.function_data_mut();
-                    let bb_data = func_data.layout().bbs().node(&then_bb).unwrap();
-                    let has_terminator = bb_data.insts().back_key()
-                        .map(|inst| {
-                            let value_data = func_data.dfg().value(*inst);
-                            matches!(value_data.kind(), 
-                                koopa::ir::ValueKind::Return(_) | 
-                                koopa::ir::ValueKind::Jump(_) | 
-                                koopa::ir::ValueKind::Branch(_))
-                        })
-                        .unwrap_or(false);
-                    
-                    if !has_terminator {
-                        let jump_inst = func_data.dfg_mut().new_value().jump(end_bb);
-                        func_data.layout_mut().bb_mut(then_bb).insts_mut().push_key_back(jump_inst).unwrap();
-                    }
+                    self.generate_stmt(then_stmt)?;
+                    self.exit_scope();
                 }
+                // then分支结束时所在的块(分支中的短路求值与嵌套语句会切换当前块)没有终结指令时跳转到end_bb
+                self.jump_if_unterminated(end_bb);
                 
                 // 处理else分支
                 self.function_irgen.current_bb = Some(else_bb);
                 match else_stmt {
                     Some(stmt) => {
                         if let Stmt::Block(block) = stmt.as_ref() {
-                            self.generate_block(block);
+                            self.generate_block(block)?;
                         } else {
                             // 如果不是块语句，需要创建一个临时作用域
                             self.function_irgen.scope_stack.enter_scope();
-                            self.generate_stmt(stmt);
-                            self.function_irgen.scope_stack.exit_scope();
+                            self.generate_stmt(stmt)?;
+                            self.exit_scope();
                         }
                         
-                        // 检查else_bb是否已有终结指令，如果没有则添加跳转
-                        {
-                            let func_data = self.function_data_mut();
-                            let bb_data = func_data.layout().bbs().node(&else_bb).unwrap();
-                            let has_terminator = bb_data.insts().back_key()
-                                .map(|inst| {
-                                    let value_data = func_data.dfg().value(*inst);
-                                    matches!(value_data.kind(), 
-                                    koopa::ir::ValueKind::Return(_) | 
-                                    koopa::ir::ValueKind::Jump(_) | 
-                                    koopa::ir::ValueKind::Branch(_))
-                                })
-                                .unwrap_or(false);
-
-                            if !has_terminator {
-                                let jump_inst = func_data.dfg_mut().new_value().jump(end_bb);
-                                func_data.layout_mut().bb_mut(else_bb).insts_mut().push_key_back(jump_inst).unwrap();
-                            }
-                        }
+                        self.jump_if_unterminated(end_bb);
                     }
                     None => {
                         // 空的else分支，直接跳转到end_bb
@@ -231,23 +199,34 @@ impl IRGen {
                 // 弹出控制流上下文
                 self.pop_control_flow();
                 
-                false
+                Ok(false)
             }
             
             Stmt::Assign(lval, exp) => {
                 // 根据右侧表达式求值
-                let value = self.generate_exp(exp);
+                let value = self.generate_exp(exp)?;
                 
                 // 生成左值的存储指令
-                self.generate_lval_store(lval, value);
-                false
+                self.generate_lval_store(lval, value)?;
+                Ok(false)
+            }
+
+            Stmt::DerefAssign(target, exp) => {
+                let value = self.generate_exp(exp)?;
+                let dest = self.generate_exp(target)?;
+                self.generate_store(value, dest)?;
+                Ok(false)
             }
             
             Stmt::Return(exp_opt) => {
                 match exp_opt {
                     Some(exp) => {
-                        // `return 1`有返回值的return语句
-                        let value = self.generate_exp(exp);
+                        // `return 1`有返回值的return语句，按函数返回类型做隐式转换
+                        let value = self.generate_exp(exp)?;
+                        let value = match self.current_return_type() {
+                            Some(b_type) => self.convert_value(value, b_type)?,
+                            None => return Err(CompileError::ReturnValueInVoid(self.current_function_name())),
+                        };
                         
                         let current_bb = self.current_bb();
                         let func_data = self.function_data_mut();
@@ -257,6 +236,9 @@ impl IRGen {
                     }
                     None => {
                         // `return` 无返回值的return语句
+                        if self.current_return_type().is_some() {
+                            return Err(CompileError::MissingReturnValue(self.current_function_name()));
+                        }
                         let current_bb = self.current_bb();
                         let func_data = self.function_data_mut();
                         
@@ -264,20 +246,24 @@ impl IRGen {
                         func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(ret_inst).unwrap();
                     }
                 }
-                true
+                Ok(true)
             }
             
             Stmt::Exp(exp_opt) => {
                 match exp_opt {
+                    // 函数调用语句可以是 void 调用
+                    Some(Expr { kind: ExprKind::Call(func_name, params), .. }) => {
+                        self.generate_call(func_name, params)?;
+                    }
                     Some(exp) => {
                         // `1+2;`表达式语句，生成IR但是丢弃结果
-                        self.generate_exp(exp);
+                        self.generate_exp(exp)?;
                     }
                     None => {
                         // `;`空语句
                     }
                 }
-                false
+                Ok(false)
             }
             
             Stmt::Block(block) => {
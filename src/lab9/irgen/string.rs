@@ -0,0 +1,100 @@
+//! 字符串字面量与变参函数调用的IR生成
+//! 约定见 lab9::abi
+use crate::ast::{Expr, ExprKind};
+use crate::lab9::abi::{VarArg, VariadicCall, STRING_PREFIX};
+use crate::lab9::irgen::error::{CompileError, CompileResult};
+use crate::lab9::irgen::IRGen;
+use koopa::ir::builder::{GlobalInstBuilder, LocalInstBuilder, ValueBuilder};
+use koopa::ir::{FunctionData, Type, Value};
+
+impl IRGen {
+    /// 生成字符串字面量, 返回指向首字节的指针(*i32)
+    /// 相同内容的字面量共用同一个全局数组
+    pub fn generate_string_literal(&mut self, bytes: &[u8]) -> Value {
+        let global = match self.string_literals.get(bytes) {
+            Some(&global) => global,
+            None => {
+                // 以'\0'结尾, 每个字节占一个i32元素
+                let elems = bytes.iter()
+                    .chain(std::iter::once(&0))
+                    .map(|&byte| self.program.new_value().integer(byte as i32))
+                    .collect();
+                let init = self.program.new_value().aggregate(elems);
+                let global = self.program.new_value().global_alloc(init);
+                let name = format!("@{}{}", STRING_PREFIX, self.string_literals.len());
+                self.program.set_value_name(global, Some(name));
+                self.string_literals.insert(bytes.to_vec(), global);
+                global
+            }
+        };
+
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let zero = func_data.dfg_mut().new_value().integer(0);
+        let ptr = func_data.dfg_mut().new_value().get_elem_ptr(global, zero);
+        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(ptr).unwrap();
+        ptr
+    }
+
+    /// 变参函数调用: 字符串实参传递首字节指针, 其余实参按值传递
+    /// call 为被调用的变参库函数(尚未填入可变参数)
+    pub fn generate_variadic_call(&mut self, mut call: VariadicCall, params: &[Expr]) -> CompileResult<Value> {
+        let mut args = Vec::new();
+        let mut var_args = Vec::new();
+        for param_exp in params {
+            let arg_value = match &param_exp.kind {
+                ExprKind::Str(bytes) => self.generate_string_literal(bytes),
+                _ => self.generate_exp(param_exp)?,
+            };
+            let kind = if self.is_pointer(arg_value) {
+                VarArg::Pointer
+            } else if self.is_float(arg_value) {
+                VarArg::Float
+            } else {
+                VarArg::Int
+            };
+            var_args.push(kind);
+            args.push(arg_value);
+        }
+
+        if args.len() < call.fixed {
+            return Err(CompileError::WrongArgumentCount {
+                function: call.callee.to_string(),
+                expected: call.fixed,
+                found: args.len(),
+            });
+        }
+        call.var_args = var_args.split_off(call.fixed);
+
+        // 没有可变参数时直接调用库函数本身, 否则调用按实参形态声明的定长函数
+        let function = if call.var_args.is_empty() {
+            self.functions[call.callee]
+        } else {
+            let name = call.mangled_name();
+            match self.functions.get(&name) {
+                Some(&function) => function,
+                None => {
+                    let mut param_types = vec![Type::get_pointer(Type::get_i32()); call.fixed];
+                    param_types.extend(call.var_args.iter().map(|kind| match kind {
+                        VarArg::Pointer => Type::get_pointer(Type::get_i32()),
+                        VarArg::Int | VarArg::Float => Type::get_i32(),
+                    }));
+                    let function = self.program.new_func(FunctionData::new_decl(
+                        format!("@{}", name),
+                        param_types,
+                        Type::get_unit(),
+                    ));
+                    self.functions.insert(name, function);
+                    function
+                }
+            }
+        };
+
+        let current_bb = self.current_bb();
+        let func_data = self.function_data_mut();
+        let call_inst = func_data.dfg_mut().new_value().call(function, args);
+        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(call_inst).unwrap();
+        Ok(call_inst)
+    }
+
+}
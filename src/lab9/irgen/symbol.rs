@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 use koopa::ir::Value;
+use crate::lab9::irgen::calc::ConstValue;
+use crate::lab9::irgen::error::{CompileError, CompileResult, WarningKind};
 
 /// 符号信息：区分常量、局部变量、全局变量和数组
 #[derive(Debug, Clone)]
 pub enum SymbolInfo {
-    Const(i32),                          // 全局/局部 常量
+    Const(ConstValue),                   // 全局/局部 常量
     Var(Value),                          // 局部变量
     GlobalVar(Value),                    // 全局变量
 
@@ -19,17 +21,32 @@ pub enum SymbolInfo {
     ParamArray(Value, Vec<usize>),       // 函数参数数组
 }
 
-// 作用域栈：支持嵌套作用域的符号表
+impl SymbolInfo {
+    /// 符号对应的存储位置(指针), 常量没有存储位置
+    pub fn ptr(&self) -> Option<Value> {
+        match self {
+            SymbolInfo::Const(_) => None,
+            SymbolInfo::Var(ptr) | SymbolInfo::GlobalVar(ptr) => Some(*ptr),
+            SymbolInfo::LocalConstArray(ptr, _) | SymbolInfo::LocalArray(ptr, _) |
+            SymbolInfo::GlobalConstArray(ptr, _) | SymbolInfo::GlobalArray(ptr, _) |
+            SymbolInfo::ParamArray(ptr, _) => Some(*ptr),
+        }
+    }
+}
+
+// 作用域栈：支持嵌套作用域的符号表, T为符号携带的信息(语言服务器用它记录定义位置)
 #[derive(Debug)]
-pub struct ScopeStack {
-    scopes: Vec<HashMap<String, SymbolInfo>>,  // 作用域栈，每层是一个符号表
+pub struct ScopeStack<T = SymbolInfo> {
+    scopes: Vec<HashMap<String, T>>,  // 作用域栈，每层是一个符号表
+    unused: Vec<HashMap<String, (usize, WarningKind)>>, // 与scopes对应: 尚未使用的局部符号的定义位置及其警告
     var_counter: HashMap<String, usize>,       // 变量重命名计数器
 }
 
-impl ScopeStack {
+impl<T> ScopeStack<T> {
     pub fn new() -> Self {
         Self {
             scopes: vec![HashMap::new()], // 初始化全局符号表
+            unused: vec![HashMap::new()],
             var_counter: HashMap::new(),
         }
     }
@@ -37,49 +54,76 @@ impl ScopeStack {
     // 进入新作用域，压栈一个符号表
     pub fn enter_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        self.unused.push(HashMap::new());
     }
     
-    // 退出当前作用域，出栈一个符号表
-    pub fn exit_scope(&mut self) {
-        if self.scopes.len() > 1 {
-            self.scopes.pop();
+    // 退出当前作用域，出栈一个符号表, 返回其中从未使用过的符号(按定义位置排序)
+    pub fn exit_scope(&mut self) -> Vec<(usize, WarningKind)> {
+        if self.scopes.len() <= 1 {
+            return Vec::new();
         }
+        self.scopes.pop();
+        let mut unused: Vec<_> = self.unused.pop().unwrap_or_default().into_values().collect();
+        unused.sort_by_key(|(pos, _)| *pos);
+        unused
     }
     
     // 在当前定义域定义符号
-    pub fn define(&mut self, name: String, info: SymbolInfo) -> Result<(), String> {
-        if let Some(current_scope) = self.scopes.last_mut() {
-            if current_scope.contains_key(&name) {
-                return Err(format!("Symbol '{}' already defined in current scope", name))
-            }
-            current_scope.insert(name, info);
-            Ok(())
-        } else {
-            Err("No active scope".to_string())
+    pub fn define(&mut self, name: String, info: T) -> CompileResult<()> {
+        let current_scope = self.scopes.last_mut().expect("No active scope");
+        if current_scope.contains_key(&name) {
+            return Err(CompileError::Redefinition(name));
         }
+        current_scope.insert(name, info);
+        Ok(())
     }
     
     // 在全局作用域定义符号（用于全局变量和常量）
-    pub fn define_global(&mut self, name: String, info: SymbolInfo) -> Result<(), String> {
-        if let Some(global_scope) = self.scopes.first_mut() {
-            if global_scope.contains_key(&name) {
-                return Err(format!("Global symbol '{}' already defined", name))
-            }
-            global_scope.insert(name, info);
-            Ok(())
-        } else {
-            Err("No global scope available".to_string())
+    pub fn define_global(&mut self, name: String, info: T) -> CompileResult<()> {
+        let global_scope = self.scopes.first_mut().expect("No global scope available");
+        if global_scope.contains_key(&name) {
+            return Err(CompileError::Redefinition(name));
         }
+        global_scope.insert(name, info);
+        Ok(())
     }
     
-    // 从内层向外层作用域查找符号
-    pub fn lookup(&self, name: &str) -> Option<&SymbolInfo> {
-        for scope in self.scopes.iter().rev() {
-            if let Some(info) = scope.get(name) {
-                return Some(info)
-            }
-        }
-        None
+    // 作用域的层数, 全局作用域为1
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+    
+    // 出错时丢弃 depth 层以内的所有作用域
+    pub fn exit_to_depth(&mut self, depth: usize) {
+        self.scopes.truncate(depth);
+        self.unused.truncate(depth);
+    }
+    
+    // 出错时丢弃函数内的所有作用域, 回到全局作用域
+    pub fn exit_to_global_scope(&mut self) {
+        self.exit_to_depth(1);
+    }
+    
+    // 记录当前作用域中刚定义的符号, 作用域结束前没有被查找过则报告kind
+    pub fn track_usage(&mut self, name: String, pos: usize, kind: WarningKind) {
+        self.unused.last_mut().expect("No active scope").insert(name, (pos, kind));
+    }
+    
+    // 外层作用域(不含当前作用域)中是否有同名符号
+    pub fn is_defined_in_outer_scope(&self, name: &str) -> bool {
+        self.scopes[..self.scopes.len() - 1].iter().any(|scope| scope.contains_key(name))
+    }
+    
+    // 替换全局作用域的符号表并返回原来的符号表(用于在多个翻译单元的文件作用域间切换)
+    pub fn replace_global_scope(&mut self, scope: HashMap<String, T>) -> HashMap<String, T> {
+        std::mem::replace(&mut self.scopes[0], scope)
+    }
+    
+    // 从内层向外层作用域查找符号, 并将其标记为已使用
+    pub fn lookup(&mut self, name: &str) -> Option<&T> {
+        let index = self.scopes.iter().rposition(|scope| scope.contains_key(name))?;
+        self.unused[index].remove(name);
+        self.scopes[index].get(name)
     }
     
     /// 生成唯一的变量名(用于koopaIR)
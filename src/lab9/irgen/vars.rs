@@ -1,68 +1,99 @@
-//! ```text
-//! Exp (最低优先级)
-//! └── LOrExp      (逻辑或 ||)
-//!     └── LAndExp  (逻辑与 &&)
-//!         └── EqExp    (相等比较 == !=)
-//!             └── RelExp   (关系比较 < > <= >=)
-//!                 └── AddExp   (加减 + -)
-//!                     └── MulExp   (乘除模 * / %)
-//!                         └── UnaryExp (一元运算 + - !)
-//!                             └── PrimaryExp (最高优先级)
-//! ```
-
-use crate::ast::{AddExp, EqExp, EqOp, Exp, LAndExp, LOrExp, LVal, MulDivOp, MulExp, PlusSubOp, PrimaryExp, RelExp, RelOp, UnaryExp, UnaryOp};
+//! 表达式的IR生成
+
+use crate::ast::{self, BType, Expr, ExprKind, LVal, UnaryOp};
+use crate::lab9::abi::{FloatIntrinsic, VariadicCall};
+use crate::lab9::irgen::calc::ConstValue;
+use crate::lab9::irgen::error::{CompileError, CompileResult};
 use crate::lab9::irgen::symbol::SymbolInfo;
 use crate::lab9::irgen::IRGen;
 use koopa::ir::builder::{BasicBlockBuilder, LocalInstBuilder, ValueBuilder};
-use koopa::ir::{BinaryOp, Type, Value};
+use koopa::ir::{BinaryOp, Type, TypeKind, Value};
 
 impl IRGen {
-    pub fn generate_exp(&mut self, exp: &Exp) -> Value {
-        match exp { 
-            Exp::LOr(lor_exp) => self.generate_lor_exp(lor_exp)
-        }
+    /// 生成表达式的IR, 出错时定位到出错的子表达式
+    pub fn generate_exp(&mut self, exp: &Expr) -> CompileResult<Value> {
+        self.at_exp(exp, |irgen| irgen.generate_exp_at(exp))
     }
 
-    /// 生成lor表达式的ir - 实现短路求值
-    fn generate_lor_exp(&mut self, lor_exp: &LOrExp) -> Value {
-        match lor_exp {
-            LOrExp::LAnd(land_exp) => self.generate_land_exp(land_exp),
-            LOrExp::LOr(left, right) => {
-                // 关键：传递 AST 节点而不是提前计算的值
-                self.generate_lor_binary_op_ast(left, right)
+    fn generate_exp_at(&mut self, exp: &Expr) -> CompileResult<Value> {
+        match &exp.kind {
+            ExprKind::Number(num) => {
+                let func_data = self.function_data_mut();
+                Ok(func_data.dfg_mut().new_value().integer(*num))
             }
-        }
-    }
-
-    /// 生成land表达式的ir - 实现短路求值
-    fn generate_land_exp(&mut self, land_exp: &LAndExp) -> Value {
-        match land_exp {
-            LAndExp::Eq(eq_exp) => self.generate_eq_exp(eq_exp),
-            LAndExp::LAnd(left, right) => {
-                // 关键：传递 AST 节点而不是提前计算的值
-                self.generate_land_binary_op_ast(left, right)
+            ExprKind::Float(num) => Ok(self.generate_float_const(*num)),
+            ExprKind::Str(_) => Err(CompileError::Unsupported("string literal outside of a putf argument".to_string())),
+            ExprKind::LVal(lval) => self.generate_lval_load(lval),
+            ExprKind::Unary(UnaryOp::AddrOf, operand) => self.generate_address_of(operand),
+            ExprKind::Unary(UnaryOp::Deref, operand) => {
+                let ptr = self.generate_exp(operand)?;
+                self.generate_deref(ptr)
+            }
+            ExprKind::Unary(op, operand) => {
+                if *op == UnaryOp::Minus {
+                    self.check_constant_arith(exp);
+                }
+                let operand = self.generate_exp(operand)?;
+                self.generate_unary_op(op, operand)
+            }
+            // 逻辑运算: 短路求值, 右操作数在新的基本块中生成
+            ExprKind::Binary(op @ (ast::BinaryOp::Or | ast::BinaryOp::And), left, right) => {
+                self.generate_short_circuit(*op, left, right)
+            }
+            ExprKind::Binary(op, left, right) => {
+                if matches!(op, ast::BinaryOp::Add | ast::BinaryOp::Sub | ast::BinaryOp::Mul | ast::BinaryOp::Div | ast::BinaryOp::Mod) {
+                    self.check_constant_arith(exp);
+                }
+                let left_value = self.generate_exp(left)?;
+                let right_value = self.generate_exp(right)?;
+                let binary_op = match op {
+                    ast::BinaryOp::Eq => BinaryOp::Eq,
+                    ast::BinaryOp::Ne => BinaryOp::NotEq,
+                    ast::BinaryOp::Lt => BinaryOp::Lt,
+                    ast::BinaryOp::Gt => BinaryOp::Gt,
+                    ast::BinaryOp::Le => BinaryOp::Le,
+                    ast::BinaryOp::Ge => BinaryOp::Ge,
+                    ast::BinaryOp::Add => BinaryOp::Add,
+                    ast::BinaryOp::Sub => BinaryOp::Sub,
+                    ast::BinaryOp::Mul => BinaryOp::Mul,
+                    ast::BinaryOp::Div => BinaryOp::Div,
+                    ast::BinaryOp::Mod => BinaryOp::Mod,
+                    ast::BinaryOp::Or | ast::BinaryOp::And => unreachable!("handled by generate_short_circuit"),
+                };
+                self.generate_arith_op(binary_op, left_value, right_value)
+            }
+            // 只有表达式语句可以丢弃 void 调用的结果, 其余位置都要用到值
+            ExprKind::Call(func_name, params) => {
+                let value = self.generate_call(func_name, params)?;
+                match self.value_type(value).is_unit() {
+                    true => Err(CompileError::VoidValue(func_name.clone())),
+                    false => Ok(value),
+                }
             }
         }
     }
 
-    /// 接收 AST 节点，实现短路求值
-    fn generate_lor_binary_op_ast(&mut self, left: &LOrExp, right: &LAndExp) -> Value {
+    /// 生成 || 与 && 的ir - 实现短路求值
+    /// `a || b`: a为真时结果为1, 否则计算b; `a && b`: a为假时结果为0, 否则计算b
+    fn generate_short_circuit(&mut self, op: ast::BinaryOp, left: &Expr, right: &Expr) -> CompileResult<Value> {
+        let is_or = op == ast::BinaryOp::Or;
         self.function_irgen.bb_counter += 1;
         
         // 先创建所有需要的基本块
         let eval_rhs;
         let result_true;
         let result_false;
-        let lor_end;
+        let end_bb;
 
         {
             let bb_counter = self.function_irgen.bb_counter;
+            let end_name = if is_or { "lor_end" } else { "land_end" };
             let func_data = self.function_data_mut();
             eval_rhs = func_data.dfg_mut().new_bb().basic_block(Some(format!("%eval_rhs_{}", bb_counter)));
             result_true = func_data.dfg_mut().new_bb().basic_block(Some(format!("%result_true_{}", bb_counter)));
             result_false = func_data.dfg_mut().new_bb().basic_block(Some(format!("%result_false_{}", bb_counter)));
-            lor_end = func_data.dfg_mut().new_bb()
-                .basic_block_with_params(Some(format!("%lor_end_{}", bb_counter)), vec![Type::get_i32()]);
+            end_bb = func_data.dfg_mut().new_bb()
+                .basic_block_with_params(Some(format!("%{}_{}", end_name, bb_counter)), vec![Type::get_i32()]);
         }
         
         // 添加基本块到函数布局
@@ -71,322 +102,149 @@ impl IRGen {
             func_data.layout_mut().bbs_mut().push_key_back(eval_rhs).unwrap();
             func_data.layout_mut().bbs_mut().push_key_back(result_true).unwrap();
             func_data.layout_mut().bbs_mut().push_key_back(result_false).unwrap();
-            func_data.layout_mut().bbs_mut().push_key_back(lor_end).unwrap();
+            func_data.layout_mut().bbs_mut().push_key_back(end_bb).unwrap();
         }
         
-        let left_value = self.generate_lor_exp(left);
+        // 在当前基本块中计算左操作数(左操作数本身可能改变当前基本块)
+        let left_value = self.generate_exp(left)?;
+        let left_value = self.generate_condition(left_value);
         
-        // 检查左操作数是否为真
-        let left_cond;
-        let branch;
+        // 短路判断: || 的左操作数为真时直接得到1, && 的左操作数为假时直接得到0, 否则计算右操作数
         {
             let current_bb = self.current_bb();
             let func_data = self.function_data_mut();
             let zero = func_data.dfg_mut().new_value().integer(0);
-            left_cond = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, left_value, zero);
+            let left_cond = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, left_value, zero);
             func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(left_cond).unwrap();
             
-            // 短路判断：如果左操作数为真，直接跳到 result_true；否则跳到 eval_rhs
-            branch = func_data.dfg_mut().new_value().branch(left_cond, result_true, eval_rhs);
+            let branch = match is_or {
+                true => func_data.dfg_mut().new_value().branch(left_cond, result_true, eval_rhs),
+                false => func_data.dfg_mut().new_value().branch(left_cond, eval_rhs, result_false),
+            };
             func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(branch).unwrap();
         }
         
-        // result_true 基本块：左操作数为真，结果为 1
-        {
-            self.function_irgen.current_bb = Some(result_true);
-            let func_data = self.function_data_mut();
-            let true_value = func_data.dfg_mut().new_value().integer(1);
-            let jump_true = func_data.dfg_mut().new_value().jump_with_args(lor_end, vec![true_value]);
-            func_data.layout_mut().bb_mut(result_true).insts_mut().push_key_back(jump_true).unwrap();
-        }
-        
-        // eval_rhs 基本块：只有在左操作数为假时才计算右操作数
+        // eval_rhs 基本块：根据右操作数的值跳转
         self.function_irgen.current_bb = Some(eval_rhs);
-        let right_value = self.generate_land_exp(right);
-        
-        let right_cond;
-        let branch_rhs;
+        let right_value = self.generate_exp(right)?;
+        let right_value = self.generate_condition(right_value);
         {
             let current_bb = self.current_bb();
-            
             let func_data = self.function_data_mut();
             let zero_rhs = func_data.dfg_mut().new_value().integer(0);
-            right_cond = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, right_value, zero_rhs);
+            let right_cond = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, right_value, zero_rhs);
             func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(right_cond).unwrap();
             
-            // 根据右操作数的值跳转
-            branch_rhs = func_data.dfg_mut().new_value().branch(right_cond, result_true, result_false);
+            let branch_rhs = func_data.dfg_mut().new_value().branch(right_cond, result_true, result_false);
             func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(branch_rhs).unwrap();
         }
         
-        // result_false 基本块：右操作数也为假，结果为 0
-        {
-            self.function_irgen.current_bb = Some(result_false);
+        // result_true / result_false 基本块：分别以 1 / 0 跳转到结束块
+        for (bb, result) in [(result_true, 1), (result_false, 0)] {
             let func_data = self.function_data_mut();
-            let false_value = func_data.dfg_mut().new_value().integer(0);
-            let jump_false = func_data.dfg_mut().new_value().jump_with_args(lor_end, vec![false_value]);
-            func_data.layout_mut().bb_mut(result_false).insts_mut().push_key_back(jump_false).unwrap();
+            let value = func_data.dfg_mut().new_value().integer(result);
+            let jump = func_data.dfg_mut().new_value().jump_with_args(end_bb, vec![value]);
+            func_data.layout_mut().bb_mut(bb).insts_mut().push_key_back(jump).unwrap();
         }
         
-        // 更新当前基本块为 lor_end
-        self.function_irgen.current_bb = Some(lor_end);
+        // 更新当前基本块为结束块
+        self.function_irgen.current_bb = Some(end_bb);
         
         // 获取基本块参数作为结果（相当于 phi 节点的结果）
         let func_data = self.function_data_mut();
-        let phi_result = func_data.dfg().bb(lor_end).params()[0];
-        phi_result
+        let phi_result = func_data.dfg().bb(end_bb).params()[0];
+        Ok(phi_result)
     }
 
-    /// 接收 AST 节点，实现短路求值
-    fn generate_land_binary_op_ast(&mut self, left: &LAndExp, right: &EqExp) -> Value {
-        self.function_irgen.bb_counter += 1;
-        
-        // 先创建所有需要的基本块
-        let eval_rhs;
-        let result_true;
-        let result_false;
-        let land_end;
-
-        {
-            let bb_counter = self.function_irgen.bb_counter;
-            let func_data = self.function_data_mut();
-            eval_rhs = func_data.dfg_mut().new_bb().basic_block(Some(format!("%eval_rhs_{}", bb_counter)));
-            result_true = func_data.dfg_mut().new_bb().basic_block(Some(format!("%result_true_{}", bb_counter)));
-            result_false = func_data.dfg_mut().new_bb().basic_block(Some(format!("%result_false_{}", bb_counter)));
-            land_end = func_data.dfg_mut().new_bb()
-                .basic_block_with_params(Some(format!("%land_end_{}", bb_counter)), vec![Type::get_i32()]);
-        }
-        
-        // 添加基本块到函数布局
-        {
-            let func_data = self.function_data_mut();
-            func_data.layout_mut().bbs_mut().push_key_back(eval_rhs).unwrap();
-            func_data.layout_mut().bbs_mut().push_key_back(result_true).unwrap();
-            func_data.layout_mut().bbs_mut().push_key_back(result_false).unwrap();
-            func_data.layout_mut().bbs_mut().push_key_back(land_end).unwrap();
-        }
-        
-        // 在当前基本块中计算左操作数
-        // 如果左操作数的计算改变了当前基本块，我们需要使用新的当前基本块
-        let left_value = self.generate_land_exp(left);
-        
-        // 检查左操作数是否为假
-        let zero;
-        let left_cond;
-        let branch;
-        {
-            let current_bb = self.current_bb();
-            let func_data = self.function_data_mut();
-            zero = func_data.dfg_mut().new_value().integer(0);
-            left_cond = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, left_value, zero);
-            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(left_cond).unwrap();
-            
-            // 短路判断：如果左操作数为假，直接跳到 result_false；否则跳到 eval_rhs
-            branch = func_data.dfg_mut().new_value().branch(left_cond, eval_rhs, result_false);
-            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(branch).unwrap();
-        }
-        
-        // result_false 基本块：左操作数为假，结果为 0
-        {
-            self.function_irgen.current_bb = Some(result_false);
-            let func_data = self.function_data_mut();
-            let false_value = func_data.dfg_mut().new_value().integer(0);
-            let jump_false = func_data.dfg_mut().new_value().jump_with_args(land_end, vec![false_value]);
-            func_data.layout_mut().bb_mut(result_false).insts_mut().push_key_back(jump_false).unwrap();
-        }
-        
-        // eval_rhs 基本块：只有在左操作数为真时才计算右操作数
-        self.function_irgen.current_bb = Some(eval_rhs);
-        let right_value = self.generate_eq_exp(right);
-        
-        let right_cond;
-        let branch_rhs;
-        {
-            let current_bb = self.current_bb();
-            let func_data = self.function_data_mut();
-            let zero_rhs = func_data.dfg_mut().new_value().integer(0);
-            right_cond = func_data.dfg_mut().new_value().binary(BinaryOp::NotEq, right_value, zero_rhs);
-            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(right_cond).unwrap();
-            
-            // 根据右操作数的值跳转
-            branch_rhs = func_data.dfg_mut().new_value().branch(right_cond, result_true, result_false);
-            func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(branch_rhs).unwrap();
+    /// 函数调用
+    pub fn generate_call(&mut self, func_name: &str, params: &[Expr]) -> CompileResult<Value> {
+        if let Some(call) = VariadicCall::new(func_name, Vec::new()) {
+            return self.generate_variadic_call(call, params);
         }
+        // 查找函数句柄
+        let function_handler = match self.lookup_function(func_name) {
+            Some(func_handler) => func_handler,
+            None => return Err(CompileError::UndefinedFunction(func_name.to_string())),
+        };
+        self.called_functions.insert(function_handler);
         
-        // result_true 基本块：右操作数为真，结果为 1
-        {
-            self.function_irgen.current_bb = Some(result_true);
-            let func_data = self.function_data_mut();
-            let true_value = func_data.dfg_mut().new_value().integer(1);
-            let jump_true = func_data.dfg_mut().new_value().jump_with_args(land_end, vec![true_value]);
-            func_data.layout_mut().bb_mut(result_true).insts_mut().push_key_back(jump_true).unwrap();
+        // 生成参数列表的 IR 值
+        // 标量实参按形参类型做隐式转换(形参类型为i32说明是标量, 否则是数组指针)
+        let sig = self.float_abi.get(self.program.func(function_handler).name());
+        let param_types = match self.program.func(function_handler).ty().kind() {
+            TypeKind::Function(param_types, _) => param_types.clone(),
+            _ => panic!("Function should have function type"),
+        };
+        if params.len() != param_types.len() {
+            return Err(CompileError::WrongArgumentCount {
+                function: func_name.to_string(),
+                expected: param_types.len(),
+                found: params.len(),
+            });
         }
-        
-        // 更新当前基本块为 land_end
-        self.function_irgen.current_bb = Some(land_end);
-        
-        // 获取基本块参数作为结果
-        let func_data = self.function_data_mut();
-        let phi_result = func_data.dfg().bb(land_end).params()[0];
-        phi_result
-    }
-
-    /// 生成eq表达式的ir
-    fn generate_eq_exp(&mut self, eq_exp: &EqExp) -> Value {
-        match eq_exp {
-            EqExp::Rel(rel_exp) => self.generate_rel_exp(rel_exp),
-            EqExp::Eq(left, op, right) => {
-                let left_value = self.generate_eq_exp(left);
-                let right_value = self.generate_rel_exp(right);
-                self.generate_eq_binary_op(op, left_value, right_value)
+        let mut args = Vec::new();
+        for (i, param_exp) in params.iter().enumerate() {
+            let mut arg_value = self.generate_arg_exp(param_exp)?;
+            match param_types.get(i) {
+                Some(ty) if matches!(ty.kind(), TypeKind::Int32) => {
+                    let b_type = if sig.params.get(i).copied().unwrap_or(false) { BType::Float } else { BType::Int };
+                    arg_value = self.convert_value(arg_value, b_type)?;
+                }
+                // 指针形参可以接受空指针常量
+                Some(ty) if !self.is_pointer(arg_value) => {
+                    arg_value = self.convert_to_type(arg_value, ty, BType::Int)?;
+                }
+                _ => {}
             }
+            args.push(arg_value);
         }
-    }
-
-    fn generate_eq_binary_op(&mut self, op: &EqOp, left: Value, right: Value) -> Value {
-        let current_bb = self.current_bb();
-        let func_data = self.function_data_mut();
-        
-        let binary_op = match op {
-            EqOp::Eq => BinaryOp::Eq,
-            EqOp::Ne => BinaryOp::NotEq,
-        };
-
-        let inst = func_data.dfg_mut().new_value().binary(binary_op, left, right);
-        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(inst).unwrap();
-        inst
-    }
-
-    /// 生成rel表达式的ir
-    fn generate_rel_exp(&mut self, rel_exp: &RelExp) -> Value {
-        match rel_exp {
-            RelExp::Add(add_exp) => self.generate_add_exp(add_exp),
-            RelExp::Rel(left, op, right) => {
-                let left_value = self.generate_rel_exp(left);
-                let right_value = self.generate_add_exp(right);
-                self.generate_rel_binary_op(op, left_value, right_value)
+        // 转换后实参的类型必须与形参一致(例如数组实参的维度)
+        for (i, (&arg, ty)) in args.iter().zip(&param_types).enumerate() {
+            if self.value_type(arg) != *ty {
+                return Err(CompileError::IncompatibleTypes(format!(
+                    "argument {} of '{}' has type '{}', expected '{}'", i + 1, func_name, self.value_type(arg), ty
+                )));
             }
         }
-    }
 
-    fn generate_rel_binary_op(&mut self, op: &RelOp, left: Value, right: Value) -> Value {
+        // 生成函数调用指令
         let current_bb = self.current_bb();
         let func_data = self.function_data_mut();
         
-        let binary_op = match op {
-            RelOp::Lt => BinaryOp::Lt,
-            RelOp::Gt => BinaryOp::Gt,
-            RelOp::Le => BinaryOp::Le,
-            RelOp::Ge => BinaryOp::Ge,
-        };
-
-        let inst = func_data.dfg_mut().new_value().binary(binary_op, left, right);
-        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(inst).unwrap();
-        inst
-    }
-
-    /// 生成add表达式的ir
-    fn generate_add_exp(&mut self, add_exp: &AddExp) -> Value {
-        match add_exp {
-            AddExp::Mul(mul_exp) => self.generate_mul_exp(mul_exp),
-            AddExp::AddMul(left, op, right) => {
-                let left_value = self.generate_add_exp(left);
-                let right_value = self.generate_mul_exp(right);
-                self.generate_add_binary_op(op, left_value, right_value)
-            }
-        }
-    }
-
-    fn generate_add_binary_op(&mut self, op: &PlusSubOp, left: Value, right: Value) -> Value {
-        let current_bb = self.current_bb();
-        let func_data = self.function_data_mut();
+        let call_inst = func_data.dfg_mut().new_value().call(function_handler, args);
+        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(call_inst).unwrap();
         
-        let binary_op = match op {
-            PlusSubOp::Plus => BinaryOp::Add,
-            PlusSubOp::Minus => BinaryOp::Sub,
-        };
-
-        let inst = func_data.dfg_mut().new_value().binary(binary_op, left, right);
-        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(inst).unwrap();
-        inst
-    }
-
-    /// 生成mul表达式的ir
-    fn generate_mul_exp(&mut self, mul_exp: &MulExp) -> Value {
-        match mul_exp {
-            MulExp::Unary(unary_exp) => self.generate_unary_exp(unary_exp),
-            MulExp::MulDiv(left, op, right) => {
-                let left_value = self.generate_mul_exp(left);
-                let right_value = self.generate_unary_exp(right);
-                self.generate_mul_binary_op(op, left_value, right_value)
-            }
+        if sig.ret {
+            self.mark_float(call_inst);
         }
+        Ok(call_inst)
     }
 
-    fn generate_mul_binary_op(&mut self, op: &MulDivOp, left: Value, right: Value) -> Value {
-        let current_bb = self.current_bb();
-        let func_data = self.function_data_mut();
-        
-        let binary_op = match op {
-            MulDivOp::Mul => BinaryOp::Mul,
-            MulDivOp::Div => BinaryOp::Div,
-            MulDivOp::Mod => BinaryOp::Mod,
-        };
-
-        let inst = func_data.dfg_mut().new_value().binary(binary_op, left, right);
-        func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(inst).unwrap();
-        inst
-    }
-
-    /// 生成unary表达式(一元表达式)的ir
-    fn generate_unary_exp(&mut self, unary_exp: &UnaryExp) -> Value {
-        match unary_exp {
-            UnaryExp::Primary(primary) => self.generate_primary_exp(primary),
-            UnaryExp::Unary(op, exp) => {
-                let operand = self.generate_unary_exp(exp);
-                self.generate_unary_op(op, operand)
-            }
-            UnaryExp::FuncCall(func_name, params) => {
-                // 查找函数句柄
-                let function_handler = if let Some(&func_handler) = self.functions.get(func_name) {
-                    func_handler
-                } else {
-                    panic!("Function '{}' not found", func_name);
-                };
-                
-                // 生成参数列表的 IR 值
-                let mut args = Vec::new();
-                if let Some(func_params) = params {
-                    for param_exp in &func_params.params {
-                        let arg_value = self.generate_arg_exp(param_exp);
-                        args.push(arg_value);
-                    }
+    fn generate_unary_op(&mut self, op: &UnaryOp, operand: Value) -> CompileResult<Value> {
+        if self.is_pointer(operand) {
+            return match op {
+                UnaryOp::Plus => Ok(operand),
+                UnaryOp::Not => {
+                    let address = self.pointer_to_int(operand);
+                    self.generate_unary_op(op, address)
                 }
-                
-                // 生成函数调用指令
-                 let current_bb = self.current_bb();
-                 let func_data = self.function_data_mut();
-                 
-                 // 调试输出：打印函数名和参数类型
-                 println!("DEBUG: Calling function '{}' with {} args", func_name, args.len());
-                 for (i, &arg) in args.iter().enumerate() {
-                     let arg_type = func_data.dfg().value(arg).ty();
-                     println!("DEBUG: Arg {}: {:?}", i, arg_type);
-                 }
-                 
-                 let call_inst = func_data.dfg_mut().new_value().call(function_handler, args);
-                 func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(call_inst).unwrap();
-                
-                call_inst
-            }
+                UnaryOp::Minus => Err(CompileError::InvalidOperand("unary '-' cannot be applied to a pointer".to_string())),
+                UnaryOp::Deref | UnaryOp::AddrOf => unreachable!("handled in generate_exp"),
+            };
+        }
+        if self.is_float(operand) {
+            return match op {
+                UnaryOp::Plus => Ok(operand),
+                UnaryOp::Minus => Ok(self.call_float_intrinsic(FloatIntrinsic::Neg, vec![operand])),
+                UnaryOp::Not => Ok(self.generate_float_not(operand)),
+                UnaryOp::Deref | UnaryOp::AddrOf => unreachable!("handled in generate_exp"),
+            };
         }
-    }
 
-    fn generate_unary_op(&mut self, op: &UnaryOp, operand: Value) -> Value {
         let current_bb = self.current_bb();
         let func_data = self.function_data_mut();
         
-        match op {
+        Ok(match op {
             UnaryOp::Plus => operand,
             UnaryOp::Minus => {
                 let zero = func_data.dfg_mut().new_value().integer(0);
@@ -400,54 +258,68 @@ impl IRGen {
                 func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(eq_inst).unwrap();
                 eq_inst
             }
-        }
+            UnaryOp::Deref | UnaryOp::AddrOf => unreachable!("handled in generate_exp"),
+        })
     }
 
-    fn generate_primary_exp(&mut self, primary: &PrimaryExp) -> Value {
-        match primary {
-            PrimaryExp::Number(num) => {
-                let func_data = self.function_data_mut();
-                func_data.dfg_mut().new_value().integer(*num)
-            },
-            PrimaryExp::Paren(exp) => self.generate_exp(exp),
-            PrimaryExp::LVal(lval) => self.generate_lval_load(lval),
-        }
-    }
-    
     // 左值被调用时，返回其对应值的ptr
-    pub fn generate_lval_load(&mut self, lval: &LVal) -> Value {
+    pub fn generate_lval_load(&mut self, lval: &LVal) -> CompileResult<Value> {
         let symbol_info = self.function_irgen.scope_stack.lookup(&lval.ident).cloned();
         
-        match symbol_info {
+        // 读取结果的类型与变量(指针)的元素类型一致
+        let is_float = symbol_info.as_ref().and_then(SymbolInfo::ptr).is_some_and(|ptr| self.is_float(ptr));
+        let value = match symbol_info {
             Some(SymbolInfo::Const(value)) => {
                 if !lval.indices.is_empty() {
-                    panic!("Cannot index into scalar constant");
+                    return Err(CompileError::InvalidSubscript(format!("'{}' is a scalar constant", lval.ident)));
+                }
+                match value {
+                    ConstValue::Int(value) => {
+                        let func_data = self.function_data_mut();
+                        func_data.dfg_mut().new_value().integer(value)
+                    }
+                    ConstValue::Float(value) => self.generate_float_const(value),
                 }
-                let func_data = self.function_data_mut();
-                func_data.dfg_mut().new_value().integer(value)
             }
             Some(SymbolInfo::Var(ptr)) | Some(SymbolInfo::GlobalVar(ptr)) => {
-                if !lval.indices.is_empty() {
-                    panic!("Cannot index into scalar variable");
-                }
-                let current_bb = self.current_bb();
-                let func_data = self.function_data_mut();
-                let load_inst = func_data.dfg_mut().new_value().load(ptr);
-                func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(load_inst).unwrap();
-                load_inst
+                // 带下标时是指针变量的下标访问
+                let ptr = match lval.indices.is_empty() {
+                    true => ptr,
+                    false => self.generate_lval_address(lval)?,
+                };
+                self.generate_deref(ptr)?
             }
 
             // region 数组访问
+            // 下标不足时数组退化为指向首元素的指针
             Some(SymbolInfo::LocalConstArray(ptr, _)) |
             Some(SymbolInfo::GlobalConstArray(ptr, _)) |
             Some(SymbolInfo::LocalArray(ptr, _)) |
-            Some(SymbolInfo::GlobalArray(ptr, _)) => {
-                if lval.indices.is_empty() {
-                    panic!("Cannot load entire array '{}'", lval.ident);
+            Some(SymbolInfo::GlobalArray(ptr, _)) if lval.indices.is_empty() => {
+                let current_bb = self.current_bb();
+                let func_data = self.function_data_mut();
+                let zero = func_data.dfg_mut().new_value().integer(0);
+                let first_elem_ptr = func_data.dfg_mut().new_value().get_elem_ptr(ptr, zero);
+                func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(first_elem_ptr).unwrap();
+                first_elem_ptr
+            }
+            Some(SymbolInfo::LocalConstArray(_, dimensions)) |
+            Some(SymbolInfo::GlobalConstArray(_, dimensions)) |
+            Some(SymbolInfo::LocalArray(_, dimensions)) |
+            Some(SymbolInfo::GlobalArray(_, dimensions)) |
+            Some(SymbolInfo::ParamArray(_, dimensions)) if lval.indices.len() < dimensions.len() => {
+                match lval.indices.is_empty() {
+                    true => self.generate_lval_as_param(lval)?,
+                    false => self.generate_lval_as_arg(lval)?,
                 }
+            }
+            Some(SymbolInfo::LocalConstArray(ptr, _)) |
+            Some(SymbolInfo::GlobalConstArray(ptr, _)) |
+            Some(SymbolInfo::LocalArray(ptr, _)) |
+            Some(SymbolInfo::GlobalArray(ptr, _)) => {
                 
                 // 变量数组元素访问处理
-                let elem_ptr = self.generate_array_access_ptr(ptr, &lval.indices);
+                let elem_ptr = self.generate_array_access_ptr(ptr, &lval.indices)?;
                 let current_bb = self.current_bb();
                 let func_data = self.function_data_mut();
                 let load_inst = func_data.dfg_mut().new_value().load(elem_ptr);
@@ -457,18 +329,11 @@ impl IRGen {
 
             // 数组参数访问：使用 getptr 和 getelemptr 组合
             Some(SymbolInfo::ParamArray(param_ptr, _)) => {
-                if lval.indices.is_empty() {
-                    panic!("Cannot load entire parameter array '{}'", lval.ident);
-                }
-
-                let param_type = self.function_data_mut().dfg().value(param_ptr).ty().clone();
-                println!("Debug: visiting param_type is {:?}", param_type);
-                
                 // 先计算所有索引
                 let indexes: Vec<Value> = lval.indices
                     .iter()
-                    .map(|exp| self.generate_exp(&exp))
-                    .collect();
+                    .map(|exp| self.generate_exp(exp))
+                    .collect::<CompileResult<_>>()?;
                 
                 let current_bb = self.current_bb();
                 let func_data = self.function_data_mut();
@@ -481,8 +346,6 @@ impl IRGen {
                 let mut current_ptr = loaded_ptr;
 
                 for (i, &index) in indexes.iter().enumerate() {
-                    let param_type = func_data.dfg().value(current_ptr).ty().clone();
-                    println!("Debug: loading: i = {}, current_ptr_type = {}, index_value = {:?}", i, param_type, func_data.dfg().value(index));
                     if i == 0 {
                         // 第一层：对指针类型使用 getptr
                         // loaded_ptr 类型是 *[i32, 3], 使用 getptr 进行指针算术
@@ -502,24 +365,44 @@ impl IRGen {
                 load_inst
             }
             // endregion 数组访问
-            None => panic!("Identifier '{}' not found", lval.ident),
+            None => return Err(CompileError::UndefinedSymbol(lval.ident.clone())),
+        };
+
+        if is_float {
+            self.mark_float(value);
         }
+        Ok(value)
     }
 
-    pub fn generate_lval_store(&mut self, lval: &LVal, value: Value) {
+    pub fn generate_lval_store(&mut self, lval: &LVal, value: Value) -> CompileResult<()> {
         let symbol_info = self.function_irgen.scope_stack.lookup(&lval.ident).cloned();
 
+        // 指针变量本身, 以及经由指针变量下标的赋值
+        if let Some(SymbolInfo::Var(ptr)) | Some(SymbolInfo::GlobalVar(ptr)) = symbol_info {
+            if !lval.indices.is_empty() || self.holds_pointer(ptr)? {
+                let dest = self.generate_lval_address(lval)?;
+                return self.generate_store(value, dest);
+            }
+        }
+
+        // 按变量的元素类型做隐式转换
+        let value = match symbol_info.as_ref().and_then(SymbolInfo::ptr) {
+            Some(ptr) if self.is_float(ptr) => self.convert_value(value, BType::Float)?,
+            Some(_) => self.convert_value(value, BType::Int)?,
+            None => value,
+        };
+
         match symbol_info {
             // 常量不可变
             Some(SymbolInfo::Const(_)) |
             Some(SymbolInfo::LocalConstArray(_, _)) | Some(SymbolInfo::GlobalConstArray(_, _)) => {
-                panic!("Cannot assign to constant '{}'", lval.ident);
+                return Err(CompileError::AssignToConstant(lval.ident.clone()));
             }
 
             // 普通变量赋值
             Some(SymbolInfo::Var(ptr)) | Some(SymbolInfo::GlobalVar(ptr)) => {
                 if !lval.indices.is_empty() {
-                    panic!("Cannot index into scalar variable");
+                    return Err(CompileError::InvalidSubscript(format!("'{}' is not an array or pointer", lval.ident)));
                 }
                 let current_bb = self.current_bb();
                 let func_data = self.function_data_mut();
@@ -530,10 +413,10 @@ impl IRGen {
             // 变量数组赋值
             Some(SymbolInfo::LocalArray(ptr, _dimensions)) | Some(SymbolInfo::GlobalArray(ptr, _dimensions)) => {
                 if lval.indices.is_empty() {
-                    panic!("Cannot assign to entire array '{}'", lval.ident);
+                    return Err(CompileError::AssignToArray(lval.ident.clone()));
                 }
                 // 数组元素赋值：计算元素地址并存储值
-                let elem_ptr = self.generate_array_access_ptr(ptr, &lval.indices);
+                let elem_ptr = self.generate_array_access_ptr(ptr, &lval.indices)?;
                 let current_bb = self.current_bb();
                 let func_data = self.function_data_mut();
                 let store_inst = func_data.dfg_mut().new_value().store(value, elem_ptr);
@@ -542,17 +425,15 @@ impl IRGen {
 
             Some(SymbolInfo::ParamArray(param_ptr, _dimensions)) => {
                 if lval.indices.is_empty() {
-                    panic!("Cannot assign to entire parameter array '{}'", lval.ident);
+                    return Err(CompileError::AssignToArray(lval.ident.clone()));
                 }
                 // 和取值道理一样，先获取load指针，然后再解引用数组指针(getptr),最后使用getelemptr得到元素指针，再将要赋值的Value赋值给元素
-                let param_type = self.function_data_mut().dfg().value(param_ptr).ty().clone();
-                println!("Debug: giving val param_type is {:?}", param_type);
 
                 // 先计算所有索引
                 let indexes: Vec<Value> = lval.indices
                     .iter()
-                    .map(|exp| self.generate_exp(&exp))
-                    .collect();
+                    .map(|exp| self.generate_exp(exp))
+                    .collect::<CompileResult<_>>()?;
 
                 let current_bb = self.current_bb();
                 let func_data = self.function_data_mut();
@@ -565,8 +446,6 @@ impl IRGen {
                 let mut current_ptr = loaded_ptr;
 
                 for (i, &index) in indexes.iter().enumerate() {
-                    let param_type = func_data.dfg().value(current_ptr).ty().clone();
-                    println!("Debug: i = {}, current_ptr_type = {}", i, param_type);
                     if i == 0 {
                         // 第一层：对指针类型使用 getptr
                         // loaded_ptr 类型是 *[i32, 3], 使用 getptr 进行指针算术
@@ -585,18 +464,19 @@ impl IRGen {
                 func_data.layout_mut().bb_mut(current_bb).insts_mut().push_key_back(store_inst).unwrap();
             }
             None => {
-                panic!("Variable '{}' not found", lval.ident);
+                return Err(CompileError::UndefinedSymbol(lval.ident.clone()));
             }
         }
+        Ok(())
     }
 
-    pub fn generate_array_access_ptr(&mut self, base_ptr: Value, indices: &[Exp]) -> Value {
+    pub fn generate_array_access_ptr(&mut self, base_ptr: Value, indices: &[Expr]) -> CompileResult<Value> {
         let mut ptr = base_ptr;
 
         // 逐级处理每个索引
         for index_expr in indices {
             // 计算索引值
-            let index = self.generate_exp(index_expr);
+            let index = self.generate_exp(index_expr)?;
 
             // 使用 getelemptr 指令获取元素指针
             let current_bb = self.current_bb();
@@ -607,7 +487,7 @@ impl IRGen {
             ptr = gep_inst;
         }
 
-        ptr
+        Ok(ptr)
     }
 
 
@@ -0,0 +1,364 @@
+//! SysY 语言服务器: 通过标准输入输出以 JSON-RPC 与编辑器通信(Language Server Protocol)
+//!
+//! 支持:
+//! - 打开与保存文档时发布诊断(语法错误、未定义/重复定义的符号、IR生成的错误与警告)
+//! - 变量与函数的跳转到定义、查找引用
+//! - 悬停显示符号的类型(标量、数组维度、函数签名)
+//! - 文档符号(全局符号, 函数内的局部符号作为其子节点)
+//!
+//! 位置的列号按字节计算: 客户端支持时协商为 utf-8 编码, 否则对只含ASCII的SysY源码与 utf-16 一致
+pub mod json;
+pub mod analysis;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use crate::lab9::lsp::analysis::{Analysis, Definition};
+use crate::lab9::lsp::json::Json;
+
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+/// 打开的文档
+struct Document {
+    path: String,
+    text: String,
+    analysis: Option<Analysis>, // 文本修改后置为None, 需要时重新分析
+}
+
+impl Document {
+    fn analysis(&mut self) -> &Analysis {
+        let path = Path::new(&self.path);
+        let include_paths = path.parent().map(Path::to_path_buf).into_iter().collect();
+        self.analysis.get_or_insert_with(|| Analysis::new(&self.path, &self.text, include_paths))
+    }
+
+    /// LSP位置(行列均从0开始)在预处理后源码中的偏移
+    fn offset(&mut self, position: &Json) -> Option<usize> {
+        let line = position.get("line")?.as_usize()?;
+        let character = position.get("character")?.as_usize()?;
+        let path = self.path.clone();
+        self.analysis().line_map.offset_of(&path, line + 1, character + 1)
+    }
+}
+
+pub struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>, // uri -> 文档
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(output: W) -> Self {
+        Self { output, documents: HashMap::new(), shutdown: false }
+    }
+
+    /// 处理消息直到收到 exit 通知或输入结束, 返回此前是否收到过 shutdown 请求
+    pub fn run(mut self, mut input: impl BufRead) -> io::Result<bool> {
+        while let Some(body) = read_message(&mut input)? {
+            // 无法解析的消息回复 Parse error 后丢弃
+            let message = match body.and_then(|body| Json::parse(&body)) {
+                Ok(message) => message,
+                Err(err) => {
+                    eprintln!("sysy-lsp: invalid message: {}", err);
+                    self.send(&Json::object([
+                        ("jsonrpc", "2.0".into()),
+                        ("id", Json::Null),
+                        ("error", Json::object([("code", Json::Number(PARSE_ERROR as f64)), ("message", err.into())])),
+                    ]))?;
+                    continue;
+                }
+            };
+            let method = message.get("method").and_then(Json::as_str).unwrap_or_default();
+            if method == "exit" {
+                break;
+            }
+            let params = message.get("params").cloned().unwrap_or(Json::Null);
+            match message.get("id") {
+                Some(id) => {
+                    let response = match self.handle_request(method, &params) {
+                        Ok(result) => Json::object([("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]),
+                        Err((code, message)) => Json::object([
+                            ("jsonrpc", "2.0".into()),
+                            ("id", id.clone()),
+                            ("error", Json::object([("code", Json::Number(code as f64)), ("message", message.into())])),
+                        ]),
+                    };
+                    self.send(&response)?;
+                }
+                None => self.handle_notification(method, &params)?,
+            }
+        }
+        Ok(self.shutdown)
+    }
+
+    fn handle_request(&mut self, method: &str, params: &Json) -> Result<Json, (i32, String)> {
+        if method == "initialize" {
+            return Ok(initialize_result(params));
+        }
+        if method == "shutdown" {
+            self.shutdown = true;
+            return Ok(Json::Null);
+        }
+        if !matches!(method, "textDocument/definition" | "textDocument/references" | "textDocument/hover" | "textDocument/documentSymbol") {
+            return Err((METHOD_NOT_FOUND, format!("unsupported method '{}'", method)));
+        }
+
+        let uri = params.path(&["textDocument", "uri"]).and_then(Json::as_str)
+            .ok_or((INVALID_PARAMS, "missing textDocument.uri".to_string()))?;
+        let Some(document) = self.documents.get_mut(uri) else {
+            return Err((INVALID_PARAMS, format!("document '{}' is not open", uri)));
+        };
+        if method == "textDocument/documentSymbol" {
+            return Ok(document_symbols(document));
+        }
+
+        // 其余请求都针对光标处的符号
+        let Some(definition) = params.get("position").and_then(|position| document.offset(position))
+            .and_then(|offset| document.analysis().symbol_at(offset)) else {
+            return Ok(Json::Null);
+        };
+        let analysis = document.analysis();
+        let def = &analysis.definitions[definition];
+        Ok(match method {
+            "textDocument/definition" => match def.pos {
+                Some(pos) => location(analysis, pos, def.name.len()),
+                None => Json::Null, // 库函数没有源码
+            },
+            "textDocument/references" => {
+                let include_declaration = params.path(&["context", "includeDeclaration"]).and_then(Json::as_bool).unwrap_or(true);
+                let locations = analysis.references_to(definition, include_declaration).into_iter()
+                    .map(|pos| location(analysis, pos, def.name.len()))
+                    .collect::<Vec<_>>();
+                locations.into()
+            }
+            _ => Json::object([(
+                "contents",
+                Json::object([("kind", "markdown".into()), ("value", format!("```c\n{}\n```", def.detail).into())]),
+            )]),
+        })
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let Some(uri) = params.path(&["textDocument", "uri"]).and_then(Json::as_str) else {
+            return Ok(());
+        };
+        let uri = uri.to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.path(&["textDocument", "text"]).and_then(Json::as_str).unwrap_or_default();
+                let document = Document { path: uri_to_path(&uri), text: text.to_string(), analysis: None };
+                self.documents.insert(uri.clone(), document);
+                self.publish_diagnostics(&uri)?;
+            }
+            // 只同步全文, 诊断在保存时更新
+            "textDocument/didChange" => {
+                let text = params.get("contentChanges").and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                if let (Some(document), Some(text)) = (self.documents.get_mut(&uri), text) {
+                    document.text = text.to_string();
+                    document.analysis = None;
+                }
+            }
+            // 即使文本没变也重新分析, 包含的头文件可能改过了
+            "textDocument/didSave" => {
+                if let Some(document) = self.documents.get_mut(&uri) {
+                    if let Some(text) = params.get("text").and_then(Json::as_str) {
+                        document.text = text.to_string();
+                    }
+                    document.analysis = None;
+                }
+                self.publish_diagnostics(&uri)?;
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.send_notification("textDocument/publishDiagnostics", Json::object([
+                    ("uri", uri.into()),
+                    ("diagnostics", Json::Array(Vec::new())),
+                ]))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let Some(document) = self.documents.get_mut(uri) else {
+            return Ok(());
+        };
+        let path = document.path.clone();
+        let analysis = document.analysis();
+        let diagnostics = analysis.diagnostics.iter().map(|diagnostic| {
+            // 没有位置或位于被包含文件中的诊断放在文档开头, 消息中注明原始位置
+            let (range, message) = match diagnostic.pos.map(|pos| analysis.line_map.locate(pos)) {
+                Some(loc) if loc.file == path => (range(loc.line - 1, loc.column - 1, 1), diagnostic.message.clone()),
+                Some(loc) => (range(0, 0, 0), format!("{}: {}", loc, diagnostic.message)),
+                None => (range(0, 0, 0), diagnostic.message.clone()),
+            };
+            Json::object([
+                ("range", range),
+                ("severity", Json::Number(diagnostic.severity as i32 as f64)),
+                ("source", "sysy".into()),
+                ("message", message.into()),
+            ])
+        }).collect::<Vec<_>>();
+        self.send_notification("textDocument/publishDiagnostics", Json::object([
+            ("uri", uri.into()),
+            ("diagnostics", diagnostics.into()),
+        ]))
+    }
+
+    fn send_notification(&mut self, method: &str, params: Json) -> io::Result<()> {
+        self.send(&Json::object([("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)]))
+    }
+
+    fn send(&mut self, message: &Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+}
+
+/// 读取一条消息的内容, 输入结束时返回None
+/// 消息格式: `Content-Length: <字节数>\r\n\r\n<JSON>`, 其他头部字段忽略
+/// 头部缺少或含有不合法的 Content-Length 时返回Err(原因): 消息体的长度未知, 之后的内容
+/// 作为头部继续读取, 直到某行中出现下一条消息的 Content-Length 为止
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Result<String, String>>> {
+    const CONTENT_LENGTH: &str = "content-length:";
+    let mut length = None;
+    let mut has_header = false;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if has_header {
+                break;
+            }
+            continue; // 消息之间的空行
+        }
+        has_header = true;
+        // 丢弃的消息体不以换行结尾, 下一条消息的头部接在同一行中
+        if let Some(index) = line.to_ascii_lowercase().find(CONTENT_LENGTH) {
+            let value = line[index + CONTENT_LENGTH.len()..].trim();
+            length = Some(value.parse::<usize>().map_err(|_| format!("invalid Content-Length '{}'", value)));
+        }
+    }
+    let length = match length {
+        Some(Ok(length)) => length,
+        Some(Err(reason)) => return Ok(Some(Err(reason))),
+        None => return Ok(Some(Err("missing Content-Length header".to_string()))),
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8(body).map_err(|err| err.to_string())))
+}
+
+fn initialize_result(params: &Json) -> Json {
+    let utf8 = params.path(&["capabilities", "general", "positionEncodings"]).and_then(Json::as_array)
+        .is_some_and(|encodings| encodings.iter().any(|encoding| encoding.as_str() == Some("utf-8")));
+    let mut capabilities = vec![
+        ("textDocumentSync".to_string(), Json::object([
+            ("openClose", true.into()),
+            ("change", Json::Number(1.0)), // 全文同步
+            ("save", Json::object([("includeText", false.into())])),
+        ])),
+        ("definitionProvider".to_string(), true.into()),
+        ("referencesProvider".to_string(), true.into()),
+        ("hoverProvider".to_string(), true.into()),
+        ("documentSymbolProvider".to_string(), true.into()),
+    ];
+    if utf8 {
+        capabilities.push(("positionEncoding".to_string(), "utf-8".into()));
+    }
+    Json::object([
+        ("capabilities", Json::Object(capabilities)),
+        ("serverInfo", Json::object([("name", "sysy-lsp".into())])),
+    ])
+}
+
+/// 单行区间, 行列从0开始
+fn range(line: usize, character: usize, length: usize) -> Json {
+    let position = |character: usize| Json::object([("line", line.into()), ("character", character.into())]);
+    Json::object([("start", position(character)), ("end", position(character + length))])
+}
+
+/// 预处理后源码中 [pos, pos + length) 对应的LSP位置, 可能位于被包含的文件中
+fn location(analysis: &Analysis, pos: usize, length: usize) -> Json {
+    let loc = analysis.line_map.locate(pos);
+    Json::object([
+        ("uri", path_to_uri(&loc.file).into()),
+        ("range", range(loc.line - 1, loc.column - 1, length)),
+    ])
+}
+
+/// 文档中定义的全局符号, 函数内定义的符号作为函数的子节点
+fn document_symbols(document: &mut Document) -> Json {
+    let path = document.path.clone();
+    let analysis = document.analysis();
+    let in_document = |def: &Definition| def.pos.is_some_and(|pos| analysis.line_map.locate(pos).file == path);
+    let symbol = |def: &Definition, children: Vec<Json>| {
+        let loc = analysis.line_map.locate(def.pos.unwrap());
+        let range = range(loc.line - 1, loc.column - 1, def.name.len());
+        Json::object([
+            ("name", def.name.as_str().into()),
+            ("detail", def.detail.as_str().into()),
+            ("kind", Json::Number(def.kind as i32 as f64)),
+            ("range", range.clone()),
+            ("selectionRange", range),
+            ("children", children.into()),
+        ])
+    };
+    // 函数先于其他全局符号登记, 按源码中的位置输出
+    let mut globals: Vec<_> = analysis.definitions.iter().enumerate()
+        .filter(|(_, def)| def.container.is_none() && in_document(def))
+        .collect();
+    globals.sort_by_key(|(_, def)| def.pos);
+    let symbols = globals.into_iter()
+        .map(|(index, def)| {
+            let children = analysis.definitions.iter()
+                .filter(|child| child.container == Some(index) && in_document(child))
+                .map(|child| symbol(child, Vec::new()))
+                .collect();
+            symbol(def, children)
+        })
+        .collect::<Vec<_>>();
+    symbols.into()
+}
+
+/// file:// URI 转为本地路径, 处理百分号编码
+fn uri_to_path(uri: &str) -> String {
+    let encoded = uri.strip_prefix("file://").unwrap_or(uri).as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let hex = encoded.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (encoded[i], hex) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+fn path_to_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
@@ -0,0 +1,467 @@
+//! 语言服务器的语义分析: 解析预处理后的源码, 按作用域规则解析每个标识符, 记录符号的定义与引用并收集诊断
+//!
+//! 名字解析复用IR生成所用的 ScopeStack, 符号的类型(数组维度、常量值)取自IR生成得到的 SymbolInfo,
+//! IR生成失败时退回到源码中的维度表达式
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use lalrpop_util::ParseError;
+use crate::ast::*;
+use crate::lab9::irgen::calc::ConstValue;
+use crate::lab9::irgen::error::{CompileError, WarningOptions};
+use crate::lab9::irgen::symbol::{ScopeStack, SymbolInfo};
+use crate::lab9::irgen::IRGen;
+use crate::lab9::preprocess::{LineMap, Preprocessor};
+use crate::lab9::pretty::{format_exp, func_head};
+use crate::sysy;
+
+/// SysY 运行时库函数的签名
+const LIBRARY_FUNCTIONS: [(&str, &str); 13] = [
+    ("getint", "int getint()"),
+    ("getch", "int getch()"),
+    ("getfloat", "float getfloat()"),
+    ("getarray", "int getarray(int a[])"),
+    ("getfarray", "int getfarray(float a[])"),
+    ("putint", "void putint(int a)"),
+    ("putch", "void putch(int a)"),
+    ("putfloat", "void putfloat(float a)"),
+    ("putarray", "void putarray(int n, int a[])"),
+    ("putfarray", "void putfarray(int n, float a[])"),
+    ("putf", "void putf(char fmt[], ...)"),
+    ("starttime", "void starttime()"),
+    ("stoptime", "void stoptime()"),
+];
+
+/// 符号类别, 取值与LSP的 SymbolKind 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function = 12,
+    Variable = 13,
+    Constant = 14,
+}
+
+/// 符号的定义
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub pos: Option<usize>,       // 定义处标识符的位置, 库函数为None
+    pub detail: String,           // 符号的类型, 如 `int a[4][5]`、`int f(int a[], int n)`
+    pub container: Option<usize>, // 所在函数的定义编号, 全局符号为None
+}
+
+/// 对符号的一次引用
+#[derive(Debug, Clone, Copy)]
+pub struct Reference {
+    pub pos: usize,
+    pub definition: usize,
+}
+
+/// 诊断级别, 取值与LSP的 DiagnosticSeverity 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error = 1,
+    Warning = 2,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub pos: Option<usize>, // 预处理后源码中的位置, 预处理错误没有位置
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// 一个文档的分析结果, 位置都是预处理后源码中的偏移, 由 line_map 换算为行列号
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub line_map: LineMap,
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    /// 分析名为 name 的文档, 引号形式的 #include 在 include_paths 中查找
+    pub fn new(name: &str, source: &str, include_paths: Vec<PathBuf>) -> Self {
+        let mut analysis = Analysis::default();
+        let preprocessed = match Preprocessor::new(include_paths).preprocess_source(name, source) {
+            Ok(preprocessed) => preprocessed,
+            Err(message) => {
+                analysis.error(None, message);
+                return analysis;
+            }
+        };
+        analysis.line_map = preprocessed.line_map;
+        let unit = match sysy::CompUnitParser::new().parse(&preprocessed.text) {
+            Ok(unit) => unit,
+            Err(err) => {
+                let (pos, message) = describe_parse_error(&err);
+                analysis.error(Some(pos), message);
+                return analysis;
+            }
+        };
+
+        // IR生成的错误都以 CompileError 报告; 万一仍然panic, 不打印到服务器的stderr,
+        // 而是作为诊断报告, 其余结果只来自名字解析
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let generated = panic::catch_unwind(AssertUnwindSafe(|| IRGen::new().generate_program(vec![unit.clone()])));
+        panic::set_hook(hook);
+        let symbols = match &generated {
+            Ok(Ok(module)) => module.symbols.clone(),
+            _ => HashMap::new(),
+        };
+        let mut resolver = Resolver {
+            analysis: &mut analysis,
+            scopes: ScopeStack::new(),
+            functions: HashMap::new(),
+            symbols: &symbols,
+            function: None,
+        };
+        resolver.comp_unit(&unit);
+
+        // 名字解析已经报告过的错误定位更精确, IR生成的同一错误不再重复报告
+        match generated {
+            Ok(Ok(module)) => {
+                let options = WarningOptions::default();
+                for warning in module.warnings.iter().filter(|warning| options.is_enabled(&warning.kind)) {
+                    analysis.diagnostics.push(Diagnostic {
+                        pos: Some(warning.pos),
+                        severity: Severity::Warning,
+                        message: format!("{} [-W{}]", warning.kind, warning.kind.flag()),
+                    });
+                }
+            }
+            Ok(Err(errors)) => {
+                for err in errors {
+                    let message = err.to_string();
+                    if !analysis.diagnostics.iter().any(|diagnostic| diagnostic.message == message) {
+                        analysis.error(Some(err.pos), message);
+                    }
+                }
+            }
+            Err(payload) => {
+                let message = payload.downcast_ref::<&str>().map(|msg| msg.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string());
+                analysis.error(None, format!("internal compiler error: {}", message));
+            }
+        }
+        analysis
+    }
+
+    /// offset 处的标识符(定义或引用)对应的符号
+    pub fn symbol_at(&self, offset: usize) -> Option<usize> {
+        let covers = |name: &str, pos: usize| pos <= offset && offset <= pos + name.len();
+        self.definitions.iter()
+            .position(|def| def.pos.is_some_and(|pos| covers(&def.name, pos)))
+            .or_else(|| {
+                self.references.iter()
+                    .find(|reference| covers(&self.definitions[reference.definition].name, reference.pos))
+                    .map(|reference| reference.definition)
+            })
+    }
+
+    /// 符号的所有引用位置(按位置排序), include_declaration 时包含定义处
+    pub fn references_to(&self, definition: usize, include_declaration: bool) -> Vec<usize> {
+        let mut positions: Vec<usize> = self.references.iter()
+            .filter(|reference| reference.definition == definition)
+            .map(|reference| reference.pos)
+            .collect();
+        if include_declaration {
+            positions.extend(self.definitions[definition].pos);
+        }
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    }
+
+    fn error(&mut self, pos: Option<usize>, message: String) {
+        self.diagnostics.push(Diagnostic { pos, severity: Severity::Error, message });
+    }
+}
+
+/// 语法错误的位置与描述
+fn describe_parse_error<T: std::fmt::Display, E: std::fmt::Display>(err: &ParseError<usize, T, E>) -> (usize, String) {
+    match err {
+        ParseError::InvalidToken { location } => (*location, "invalid token".to_string()),
+        ParseError::UnrecognizedEof { location, expected } => {
+            (*location, format!("unexpected end of file, expected one of {}", expected.join(" ")))
+        }
+        ParseError::UnrecognizedToken { token: (start, token, _), expected } => {
+            (*start, format!("unexpected token '{}', expected one of {}", token, expected.join(" ")))
+        }
+        ParseError::ExtraToken { token: (start, token, _) } => (*start, format!("extra token '{}'", token)),
+        ParseError::User { error } => (0, error.to_string()),
+    }
+}
+
+/// 遍历AST, 按作用域把每个标识符解析到它的定义
+struct Resolver<'a> {
+    analysis: &'a mut Analysis,
+    scopes: ScopeStack<usize>,                       // 变量与常量名 -> 定义编号
+    functions: HashMap<String, usize>,               // 函数名 -> 定义编号
+    symbols: &'a HashMap<(usize, usize), SymbolInfo>, // IR生成得到的符号信息
+    function: Option<usize>,                         // 正在遍历的函数
+}
+
+impl Resolver<'_> {
+    fn comp_unit(&mut self, unit: &CompUnit) {
+        for (name, signature) in LIBRARY_FUNCTIONS {
+            let definition = self.add_definition(name, SymbolKind::Function, None, signature.to_string());
+            self.functions.insert(name.to_string(), definition);
+        }
+        self.declare_functions(unit);
+
+        for item in &unit.items {
+            match item {
+                CompUnitItem::GlobalDecl(GlobalDecl::Const(decl)) => self.const_decl(decl),
+                CompUnitItem::GlobalDecl(GlobalDecl::Var(decl)) => {
+                    for def in &decl.var_def_list {
+                        self.exps(&def.dimensions);
+                        if let Some(init_val) = &def.init_val {
+                            self.init_val(init_val);
+                        }
+                        let detail = format!("{}{}", storage_prefix(decl.storage), self.var_detail(decl.b_type, def.pointer, &def.ident, &def.dimensions, def.pos));
+                        self.define(&def.ident, SymbolKind::Variable, def.pos, detail);
+                    }
+                }
+                CompUnitItem::FuncDef(func_def) => self.func_def(func_def),
+                CompUnitItem::FuncDecl(func_decl) => {
+                    self.function = self.functions.get(&func_decl.id).copied();
+                    self.params(&func_decl.params, false);
+                    self.function = None;
+                }
+            }
+        }
+    }
+
+    /// 函数在整个文件中可见(IR生成先登记所有函数头):
+    /// 有定义的函数以定义为准, 否则以第一个原型为准, 其余原型都算作引用
+    fn declare_functions(&mut self, unit: &CompUnit) {
+        for item in &unit.items {
+            if let CompUnitItem::FuncDef(func_def) = item {
+                let defined = self.functions.get(&func_def.id)
+                    .is_some_and(|&definition| self.analysis.definitions[definition].pos.is_some());
+                if defined {
+                    self.analysis.error(Some(func_def.pos), CompileError::Redefinition(func_def.id.clone()).to_string());
+                    continue;
+                }
+                let detail = func_head(func_def.storage, &func_def.func_type, &func_def.id, &func_def.params);
+                let definition = self.add_definition(&func_def.id, SymbolKind::Function, Some(func_def.pos), detail);
+                self.functions.insert(func_def.id.clone(), definition);
+            }
+        }
+        for item in &unit.items {
+            if let CompUnitItem::FuncDecl(func_decl) = item {
+                match self.functions.get(&func_decl.id) {
+                    Some(&definition) if self.analysis.definitions[definition].pos.is_some() => {
+                        self.analysis.references.push(Reference { pos: func_decl.pos, definition });
+                    }
+                    _ => {
+                        let detail = func_head(func_decl.storage, &func_decl.func_type, &func_decl.id, &func_decl.params);
+                        let definition = self.add_definition(&func_decl.id, SymbolKind::Function, Some(func_decl.pos), detail);
+                        self.functions.insert(func_decl.id.clone(), definition);
+                    }
+                }
+            }
+        }
+    }
+
+    fn func_def(&mut self, func_def: &FuncDef) {
+        self.function = self.functions.get(&func_def.id).copied();
+        // 与IR生成相同: 形参在函数作用域中, 函数体再进入一层作用域
+        self.scopes.enter_scope();
+        self.params(&func_def.params, true);
+        self.block(&func_def.block);
+        self.scopes.exit_scope();
+        self.function = None;
+    }
+
+    /// 形参的维度表达式总是要解析, 只有函数定义的形参才是符号
+    fn params(&mut self, params: &Option<FuncFParams>, define: bool) {
+        for param in params.iter().flat_map(|params| &params.params) {
+            for dim in param.dimensions.iter().flatten() {
+                self.exp(dim);
+            }
+            if define {
+                let detail = self.param_detail(param);
+                self.define(&param.ident, SymbolKind::Variable, param.pos, detail);
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.enter_scope();
+        for item in &block.block_item_list {
+            match item {
+                BlockItem::Decl(Decl::Const(decl)) => self.const_decl(decl),
+                BlockItem::Decl(Decl::Var(decl)) => {
+                    for def in &decl.var_def_list {
+                        self.exps(&def.dimensions);
+                        if let Some(init_val) = &def.init_val {
+                            self.init_val(init_val);
+                        }
+                        let detail = self.var_detail(decl.b_type, def.pointer, &def.ident, &def.dimensions, def.pos);
+                        self.define(&def.ident, SymbolKind::Variable, def.pos, detail);
+                    }
+                }
+                BlockItem::Stmt(stmt) => self.stmt(stmt),
+            }
+        }
+        self.scopes.exit_scope();
+    }
+
+    fn const_decl(&mut self, decl: &ConstDecl) {
+        for def in &decl.const_def_list {
+            self.exps(&def.dimensions);
+            self.const_init_val(&def.const_init_val);
+            let detail = match self.symbols.get(&(0, def.pos)) {
+                Some(SymbolInfo::Const(value)) => format!("const {} {} = {}", b_type_name(decl.b_type), def.ident, const_value_text(*value)),
+                _ => format!("const {}", self.var_detail(decl.b_type, 0, &def.ident, &def.dimensions, def.pos)),
+            };
+            let detail = format!("{}{}", storage_prefix(decl.storage), detail);
+            self.define(&def.ident, SymbolKind::Constant, def.pos, detail);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Return(exp) | Stmt::Exp(exp) => {
+                if let Some(exp) = exp {
+                    self.exp(exp);
+                }
+            }
+            Stmt::Block(block) => self.block(block),
+            Stmt::Assign(lval, exp) => {
+                self.lval(lval);
+                self.exp(exp);
+            }
+            Stmt::DerefAssign(ptr, exp) => {
+                self.exp(ptr);
+                self.exp(exp);
+            }
+            Stmt::If(cond, then_stmt, else_stmt) => {
+                self.exp(cond);
+                self.stmt(then_stmt);
+                if let Some(else_stmt) = else_stmt {
+                    self.stmt(else_stmt);
+                }
+            }
+            Stmt::While(cond, body) => {
+                self.exp(cond);
+                self.stmt(body);
+            }
+            Stmt::Break | Stmt::Continue => {}
+        }
+    }
+
+    fn exps(&mut self, exps: &[Expr]) {
+        for exp in exps {
+            self.exp(exp);
+        }
+    }
+
+    fn exp(&mut self, exp: &Expr) {
+        match &exp.kind {
+            ExprKind::Number(_) | ExprKind::Float(_) | ExprKind::Str(_) => {}
+            ExprKind::LVal(lval) => self.lval(lval),
+            ExprKind::Unary(_, operand) => self.exp(operand),
+            ExprKind::Binary(_, left, right) => {
+                self.exp(left);
+                self.exp(right);
+            }
+            ExprKind::Call(name, args) => {
+                match self.functions.get(name) {
+                    Some(&definition) => self.analysis.references.push(Reference { pos: exp.span.start, definition }),
+                    None => self.analysis.error(Some(exp.span.start), CompileError::UndefinedFunction(name.clone()).to_string()),
+                }
+                self.exps(args);
+            }
+        }
+    }
+
+    fn lval(&mut self, lval: &LVal) {
+        match self.scopes.lookup(&lval.ident) {
+            Some(&definition) => self.analysis.references.push(Reference { pos: lval.pos, definition }),
+            None => self.analysis.error(Some(lval.pos), CompileError::UndefinedSymbol(lval.ident.clone()).to_string()),
+        }
+        self.exps(&lval.indices);
+    }
+
+    fn init_val(&mut self, init_val: &InitVal) {
+        match init_val {
+            InitVal::Exp(exp) => self.exp(exp),
+            InitVal::List(list) => list.iter().for_each(|init_val| self.init_val(init_val)),
+        }
+    }
+
+    fn const_init_val(&mut self, init_val: &ConstInitVal) {
+        match init_val {
+            ConstInitVal::Exp(exp) => self.exp(exp),
+            ConstInitVal::List(list) => list.iter().for_each(|init_val| self.const_init_val(init_val)),
+        }
+    }
+
+    /// 在当前作用域定义变量或常量, 同一作用域重复定义时报告错误(第一个定义仍然有效)
+    fn define(&mut self, name: &str, kind: SymbolKind, pos: usize, detail: String) {
+        let definition = self.add_definition(name, kind, Some(pos), detail);
+        if let Err(err) = self.scopes.define(name.to_string(), definition) {
+            self.analysis.error(Some(pos), err.to_string());
+        }
+    }
+
+    fn add_definition(&mut self, name: &str, kind: SymbolKind, pos: Option<usize>, detail: String) -> usize {
+        let container = if kind == SymbolKind::Function { None } else { self.function };
+        self.analysis.definitions.push(Definition { name: name.to_string(), kind, pos, detail, container });
+        self.analysis.definitions.len() - 1
+    }
+
+    /// 变量或数组的类型, 维度优先使用IR生成求出的值
+    fn var_detail(&self, b_type: BType, pointer: usize, ident: &str, dimensions: &[ConstExp], pos: usize) -> String {
+        let dims = match self.symbols.get(&(0, pos)) {
+            Some(SymbolInfo::LocalArray(_, dims) | SymbolInfo::LocalConstArray(_, dims) |
+                 SymbolInfo::GlobalArray(_, dims) | SymbolInfo::GlobalConstArray(_, dims)) => {
+                dims.iter().map(|dim| format!("[{}]", dim)).collect()
+            }
+            _ => dimensions.iter().map(|dim| format!("[{}]", format_exp(dim))).collect::<String>(),
+        };
+        format!("{} {}{}{}", b_type_name(b_type), "*".repeat(pointer), ident, dims)
+    }
+
+    /// 数组形参的第一维不定长, 以0记录在 ParamArray 中
+    fn param_detail(&self, param: &FuncFParam) -> String {
+        let dims: String = match self.symbols.get(&(0, param.pos)) {
+            Some(SymbolInfo::ParamArray(_, dims)) => {
+                dims.iter().map(|&dim| if dim == 0 { "[]".to_string() } else { format!("[{}]", dim) }).collect()
+            }
+            _ => param.dimensions.iter().map(|dim| match dim {
+                Some(dim) => format!("[{}]", format_exp(dim)),
+                None => "[]".to_string(),
+            }).collect(),
+        };
+        format!("{} {}{}{}", b_type_name(param.b_type), "*".repeat(param.pointer), param.ident, dims)
+    }
+}
+
+fn b_type_name(b_type: BType) -> &'static str {
+    match b_type {
+        BType::Int => "int",
+        BType::Float => "float",
+    }
+}
+
+fn storage_prefix(storage: StorageClass) -> &'static str {
+    match storage {
+        StorageClass::Default => "",
+        StorageClass::Static => "static ",
+        StorageClass::Extern => "extern ",
+    }
+}
+
+fn const_value_text(value: ConstValue) -> String {
+    match value {
+        ConstValue::Int(value) => value.to_string(),
+        ConstValue::Float(value) => format!("{:?}", value),
+    }
+}
@@ -2,3 +2,19 @@
 /// 防止单文件代码过多
 pub mod irgen;
 pub mod codegen;
+pub mod abi;
+pub mod preprocess;
+pub mod escape;
+pub mod pretty;
+pub mod cfg;
+pub mod verify;
+pub mod runtime;
+pub mod interp;
+pub mod rvsim;
+pub mod elf;
+pub mod profile;
+pub mod tailcall;
+pub mod unroll;
+pub mod fuzz;
+pub mod reduce;
+pub mod lsp;
@@ -0,0 +1,517 @@
+//! 预处理器: C 预处理器的一个子集
+//!
+//! 支持:
+//! - `#include "file"` / `#include <file>`, 按 包含者所在目录(仅引号形式) -> 包含路径 的顺序查找
+//! - 对象式与函数式 `#define`, 以及 `#undef`
+//! - `#ifdef` / `#ifndef` / `#else` / `#endif`
+//! - `__LINE__` / `__FILE__`
+//!
+//! 输出文本的每一行都记录了来源文件与行号(见 [`LineMap`]), 后续阶段的诊断据此指向原始位置
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// 嵌套包含的最大深度, 用于发现循环包含
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+/// 原始源码中的位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: usize,   // 从1开始
+    pub column: usize, // 从1开始, 按预处理后的行计算
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// 预处理输出到原始源码的行映射
+#[derive(Debug, Clone, Default)]
+pub struct LineMap {
+    files: Vec<String>,
+    lines: Vec<(usize, usize, usize)>, // (输出中的行首偏移, 文件编号, 原始行号)
+}
+
+impl LineMap {
+    /// 将预处理输出中的字节偏移映射回原始位置
+    pub fn locate(&self, offset: usize) -> SourceLocation {
+        let index = self.lines.partition_point(|&(start, _, _)| start <= offset);
+        match index.checked_sub(1).map(|i| self.lines[i]) {
+            Some((start, file, line)) => SourceLocation {
+                file: self.files[file].clone(),
+                line,
+                column: offset - start + 1,
+            },
+            None => SourceLocation {
+                file: self.files.first().cloned().unwrap_or_default(),
+                line: 1,
+                column: 1,
+            },
+        }
+    }
+
+    /// locate 的逆映射: 原始文件中第 line 行第 column 列(均从1开始)在预处理输出中的偏移
+    /// 该行不在输出中(例如被条件编译去掉)时返回None
+    pub fn offset_of(&self, file: &str, line: usize, column: usize) -> Option<usize> {
+        self.lines.iter()
+            .find(|&&(_, f, l)| l == line && self.files[f] == file)
+            .map(|&(start, _, _)| start + column - 1)
+    }
+
+    /// 未经预处理的源码的行映射, 每行对应原始文件的同一行
+    pub fn unprocessed(name: &str, source: &str) -> Self {
+        let mut line_map = Self::default();
+        let file = line_map.add_file(name.to_string());
+        let mut start = 0;
+        for (index, line) in source.split_inclusive('\n').enumerate() {
+            line_map.lines.push((start, file, index + 1));
+            start += line.len();
+        }
+        line_map
+    }
+
+    fn add_file(&mut self, name: String) -> usize {
+        self.files.push(name);
+        self.files.len() - 1
+    }
+}
+
+/// 预处理结果
+pub struct Preprocessed {
+    pub text: String,
+    pub line_map: LineMap,
+}
+
+#[derive(Debug, Clone)]
+enum Macro {
+    Object(String),                // #define N 10
+    Function(Vec<String>, String), // #define MAX(a, b) ((a) > (b) ? (a) : (b))
+}
+
+/// 条件编译的一层 #ifdef/#ifndef
+struct Conditional {
+    parent_active: bool, // 外层是否处于有效区域
+    taken: bool,         // #ifdef/#ifndef 分支的条件是否成立
+    in_else: bool,
+    line: usize,
+}
+
+impl Conditional {
+    fn active(&self) -> bool {
+        self.parent_active && (self.taken != self.in_else)
+    }
+}
+
+/// 宏展开时的记号
+enum Token<'a> {
+    Ident(&'a str),
+    Other(&'a str), // 数字、字符串/字符字面量、运算符、空白等, 原样输出
+}
+
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    output: String,
+    line_map: LineMap,
+    depth: usize,
+}
+
+impl Preprocessor {
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        Self {
+            include_paths,
+            macros: HashMap::new(),
+            output: String::new(),
+            line_map: LineMap::default(),
+            depth: 0,
+        }
+    }
+
+    /// 预处理给定文件
+    pub fn preprocess_file(mut self, path: &Path) -> Result<Preprocessed, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        self.process_source(&path.display().to_string(), path.parent(), &source)?;
+        Ok(Preprocessed { text: self.output, line_map: self.line_map })
+    }
+
+    /// 预处理内存中的源码, `name` 用于诊断信息与 __FILE__, 引号形式的 #include 只在包含路径中查找
+    pub fn preprocess_source(mut self, name: &str, source: &str) -> Result<Preprocessed, String> {
+        self.process_source(name, None, source)?;
+        Ok(Preprocessed { text: self.output, line_map: self.line_map })
+    }
+
+    fn process_source(&mut self, name: &str, dir: Option<&Path>, source: &str) -> Result<(), String> {
+        let file_id = self.line_map.add_file(name.to_string());
+        let source = strip_comments(source);
+        let mut conditionals: Vec<Conditional> = Vec::new();
+
+        for (line_number, line) in logical_lines(&source) {
+            let active = conditionals.last().is_none_or(Conditional::active);
+            let error = |message: String| format!("{}:{}: {}", name, line_number, message);
+
+            let trimmed = line.trim_start();
+            let Some(directive) = trimmed.strip_prefix('#') else {
+                if active {
+                    let expanded = self.expand(&line, name, line_number, &HashSet::new()).map_err(error)?;
+                    self.line_map.lines.push((self.output.len(), file_id, line_number));
+                    self.output.push_str(&expanded);
+                    self.output.push('\n');
+                }
+                continue;
+            };
+
+            let directive = directive.trim_start();
+            let keyword_len = directive.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(directive.len());
+            let (keyword, rest) = directive.split_at(keyword_len);
+            let rest = rest.trim();
+
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let macro_name = parse_macro_name(rest).map_err(error)?;
+                    conditionals.push(Conditional {
+                        parent_active: active,
+                        taken: self.macros.contains_key(macro_name) == (keyword == "ifdef"),
+                        in_else: false,
+                        line: line_number,
+                    });
+                }
+                "else" => match conditionals.last_mut() {
+                    Some(conditional) if !conditional.in_else => conditional.in_else = true,
+                    Some(_) => return Err(error("#else after #else".to_string())),
+                    None => return Err(error("#else without #ifdef".to_string())),
+                },
+                "endif" => {
+                    if conditionals.pop().is_none() {
+                        return Err(error("#endif without #ifdef".to_string()));
+                    }
+                }
+                // 无效区域中的其他指令一律忽略
+                _ if !active => {}
+                "define" => {
+                    let (macro_name, definition) = parse_define(rest).map_err(error)?;
+                    self.macros.insert(macro_name, definition);
+                }
+                "undef" => {
+                    let macro_name = parse_macro_name(rest).map_err(error)?;
+                    self.macros.remove(macro_name);
+                }
+                "include" => {
+                    let path = self.resolve_include(rest, dir).map_err(error)?;
+                    if self.depth >= MAX_INCLUDE_DEPTH {
+                        return Err(error(format!("#include nested too deeply (recursive include of '{}'?)", path.display())));
+                    }
+                    let source = std::fs::read_to_string(&path)
+                        .map_err(|err| error(format!("cannot read '{}': {}", path.display(), err)))?;
+                    self.depth += 1;
+                    self.process_source(&path.display().to_string(), path.parent(), &source)?;
+                    self.depth -= 1;
+                }
+                // 空指令
+                "" => {}
+                _ => return Err(error(format!("unsupported preprocessing directive '#{}'", keyword))),
+            }
+        }
+
+        match conditionals.last() {
+            Some(conditional) => Err(format!("{}:{}: unterminated conditional directive", name, conditional.line)),
+            None => Ok(()),
+        }
+    }
+
+    /// 查找 #include 的文件: 引号形式先在包含者所在目录查找, 然后依次查找包含路径
+    fn resolve_include(&self, operand: &str, dir: Option<&Path>) -> Result<PathBuf, String> {
+        let (file, quoted) = if let Some(file) = operand.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            (file, true)
+        } else if let Some(file) = operand.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            (file, false)
+        } else {
+            return Err(format!("#include expects \"FILENAME\" or <FILENAME>, found '{}'", operand));
+        };
+
+        let local_dir = if quoted { dir } else { None };
+        local_dir.into_iter()
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(file))
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("'{}' file not found", file))
+    }
+
+    /// 展开一行文本中的宏, `hidden` 为正在展开中的宏(不再重复展开, 防止无限递归)
+    fn expand(&self, text: &str, file: &str, line: usize, hidden: &HashSet<String>) -> Result<String, String> {
+        let tokens = tokenize(text);
+        let mut result = String::with_capacity(text.len());
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let name = match tokens[i] {
+                Token::Ident(name) => name,
+                Token::Other(text) => {
+                    result.push_str(text);
+                    i += 1;
+                    continue;
+                }
+            };
+            i += 1;
+
+            match name {
+                "__LINE__" => result.push_str(&line.to_string()),
+                "__FILE__" => result.push_str(&quote(file)),
+                _ => match self.macros.get(name) {
+                    Some(_) if hidden.contains(name) => result.push_str(name),
+                    None => result.push_str(name),
+                    Some(Macro::Object(body)) => {
+                        let hidden = with_hidden(hidden, name);
+                        result.push_str(&self.expand(body, file, line, &hidden)?);
+                    }
+                    Some(Macro::Function(params, body)) => {
+                        // 其后没有紧跟'('时不是宏调用
+                        let Some((args, next)) = collect_args(&tokens, i)? else {
+                            result.push_str(name);
+                            continue;
+                        };
+                        i = next;
+
+                        let args = match (params.len(), args.as_slice()) {
+                            (0, [arg]) if arg.trim().is_empty() => Vec::new(),
+                            _ => args,
+                        };
+                        if args.len() != params.len() {
+                            return Err(format!("macro '{}' requires {} arguments, but {} given", name, params.len(), args.len()));
+                        }
+
+                        // 实参先完全展开, 再替换进宏体, 最后重新扫描
+                        let args = args.iter()
+                            .map(|arg| self.expand(arg.trim(), file, line, hidden))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let substituted: String = tokenize(body).into_iter()
+                            .map(|token| match token {
+                                Token::Ident(ident) => match params.iter().position(|param| param == ident) {
+                                    Some(index) => args[index].as_str(),
+                                    None => ident,
+                                },
+                                Token::Other(text) => text,
+                            })
+                            .collect();
+                        let hidden = with_hidden(hidden, name);
+                        result.push_str(&self.expand(&substituted, file, line, &hidden)?);
+                    }
+                },
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// 源码中第一处注释或预处理指令的行号及其描述, 没有时返回None
+/// 这些内容在预处理后不复存在, 只有不含它们的源码才能不经预处理直接解析而不丢失内容
+pub fn find_preprocessor_content(source: &str) -> Option<(usize, &'static str)> {
+    let stripped = strip_comments(source);
+    let comment = source.bytes().zip(stripped.bytes())
+        .position(|(original, stripped)| original != stripped)
+        .map(|offset| (source[..offset].matches('\n').count() + 1, "comment"));
+    // 注释中的 # 不算指令, 因此在去掉注释后的源码中查找; 去掉注释不改变行号
+    let directive = stripped.lines()
+        .position(|line| line.trim_start().starts_with('#'))
+        .map(|index| (index + 1, "preprocessing directive"));
+    [comment, directive].into_iter().flatten().min()
+}
+
+// 将注释替换为空白(保留换行, 以维持行号), 字符串/字符字面量中的内容不受影响
+fn strip_comments(source: &str) -> String {
+    let bytes = source.as_bytes();
+    let mut result = String::with_capacity(source.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => {
+                let end = literal_end(source, i);
+                result.push_str(&source[i..end]);
+                i = end;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                result.push(' ');
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = source[i + 2..].find("*/").map_or(bytes.len(), |pos| i + 2 + pos + 2);
+                result.push(' ');
+                result.extend(source[i..end].chars().filter(|&c| c == '\n'));
+                i = end;
+            }
+            _ => {
+                let next = source[i..].chars().next().unwrap();
+                result.push(next);
+                i += next.len_utf8();
+            }
+        }
+    }
+
+    result
+}
+
+// 字符串/字符字面量的结束位置(不越过行尾)
+fn literal_end(text: &str, start: usize) -> usize {
+    let bytes = text.as_bytes();
+    let quote = bytes[start];
+    let mut i = start + 1;
+    while i < bytes.len() && bytes[i] != b'\n' {
+        match bytes[i] {
+            b'\\' => i += 2,
+            byte if byte == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    i.min(bytes.len())
+}
+
+// 按行切分, 以反斜杠结尾的行与下一行拼接; 返回 (起始行号, 行内容)
+fn logical_lines(source: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let (start, mut text) = current.take().unwrap_or((index + 1, String::new()));
+        match line.strip_suffix('\\') {
+            Some(line) => {
+                text.push_str(line);
+                current = Some((start, text));
+            }
+            None => {
+                text.push_str(line);
+                lines.push((start, text));
+            }
+        }
+    }
+    lines.extend(current);
+    lines
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let byte = bytes[i];
+        if byte.is_ascii_alphabetic() || byte == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(&text[start..i]));
+            continue;
+        }
+
+        if byte.is_ascii_digit() || (byte == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)) {
+            // 预处理数: 1e3 0x1p-2 中的字母不能当作标识符
+            i += 1;
+            while i < bytes.len() {
+                let is_exponent_sign = matches!(bytes[i], b'+' | b'-') && matches!(bytes[i - 1], b'e' | b'E' | b'p' | b'P');
+                if !is_exponent_sign && !bytes[i].is_ascii_alphanumeric() && !matches!(bytes[i], b'.' | b'_') {
+                    break;
+                }
+                i += 1;
+            }
+        } else if byte == b'"' || byte == b'\'' {
+            i = literal_end(text, i);
+        } else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+        }
+        tokens.push(Token::Other(&text[start..i]));
+    }
+
+    tokens
+}
+
+// 从 tokens[start] 开始收集函数式宏的实参, 返回 (实参列表, 右括号之后的位置)
+// 其后(跳过空白)不是'('时返回 None
+fn collect_args(tokens: &[Token], start: usize) -> Result<Option<(Vec<String>, usize)>, String> {
+    let mut i = start;
+    while let Some(Token::Other(text)) = tokens.get(i) {
+        if !text.trim().is_empty() {
+            break;
+        }
+        i += 1;
+    }
+    if !matches!(tokens.get(i), Some(Token::Other("("))) {
+        return Ok(None);
+    }
+    i += 1;
+
+    let mut args = vec![String::new()];
+    let mut depth = 0;
+    while let Some(token) = tokens.get(i) {
+        i += 1;
+        let text = match token {
+            Token::Ident(text) | Token::Other(text) => *text,
+        };
+        match text {
+            "(" => depth += 1,
+            ")" if depth == 0 => return Ok(Some((args, i))),
+            ")" => depth -= 1,
+            "," if depth == 0 => {
+                args.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        args.last_mut().unwrap().push_str(text);
+    }
+    Err("unterminated argument list invoking macro".to_string())
+}
+
+fn parse_macro_name(text: &str) -> Result<&str, String> {
+    let end = text.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(text.len());
+    let name = &text[..end];
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err("macro name must be an identifier".to_string());
+    }
+    if !text[end..].trim().is_empty() {
+        return Err(format!("extra tokens after macro name '{}'", name));
+    }
+    Ok(name)
+}
+
+// 解析 #define 的内容: 名字后紧跟'('时为函数式宏
+fn parse_define(text: &str) -> Result<(String, Macro), String> {
+    let end = text.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(text.len());
+    let name = &text[..end];
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err("macro name must be an identifier".to_string());
+    }
+    let rest = &text[end..];
+
+    let Some(rest) = rest.strip_prefix('(') else {
+        return Ok((name.to_string(), Macro::Object(rest.trim().to_string())));
+    };
+    let close = rest.find(')').ok_or_else(|| format!("missing ')' in parameter list of macro '{}'", name))?;
+    let params: Vec<String> = match rest[..close].trim() {
+        "" => Vec::new(),
+        params => params.split(',').map(|param| param.trim().to_string()).collect(),
+    };
+    for param in &params {
+        if parse_macro_name(param).is_err() {
+            return Err(format!("invalid parameter '{}' in macro '{}'", param, name));
+        }
+    }
+    Ok((name.to_string(), Macro::Function(params, rest[close + 1..].trim().to_string())))
+}
+
+fn with_hidden(hidden: &HashSet<String>, name: &str) -> HashSet<String> {
+    let mut hidden = hidden.clone();
+    hidden.insert(name.to_string());
+    hidden
+}
+
+// __FILE__ 展开得到的字符串字面量
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,482 @@
+//! AST的两种文本形式
+//! - `format_comp_unit`: 格式化的SysY源码, 重新交给 CompUnitParser 解析会得到相同的AST
+//! - `dump_ast`: 紧凑的树形结构, 语句与声明各占一行, 表达式按源码形式写在所在行内
+use crate::ast::*;
+
+const INDENT: &str = "    ";
+
+/// 将整个编译单元输出为格式化的SysY源码
+pub fn format_comp_unit(unit: &CompUnit) -> String {
+    let mut printer = SourcePrinter::default();
+    for (i, item) in unit.items.iter().enumerate() {
+        // 函数定义前后空一行
+        let is_func_def = matches!(item, CompUnitItem::FuncDef(_));
+        if i > 0 && (is_func_def || matches!(unit.items[i - 1], CompUnitItem::FuncDef(_))) {
+            printer.out.push('\n');
+        }
+        printer.comp_unit_item(item);
+    }
+    printer.out
+}
+
+/// 将表达式输出为SysY源码
+pub fn format_exp(exp: &Expr) -> String {
+    let mut out = String::new();
+    write_exp(&mut out, exp);
+    out
+}
+
+// region 源码输出
+
+#[derive(Default)]
+struct SourcePrinter {
+    out: String,
+    depth: usize, // 当前缩进层数
+}
+
+impl SourcePrinter {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn comp_unit_item(&mut self, item: &CompUnitItem) {
+        match item {
+            CompUnitItem::FuncDef(func_def) => {
+                let head = func_head(func_def.storage, &func_def.func_type, &func_def.id, &func_def.params);
+                self.line(&format!("{} {{", head));
+                self.block_items(&func_def.block);
+                self.line("}");
+            }
+            CompUnitItem::FuncDecl(func_decl) => {
+                let head = func_head(func_decl.storage, &func_decl.func_type, &func_decl.id, &func_decl.params);
+                self.line(&format!("{};", head));
+            }
+            CompUnitItem::GlobalDecl(GlobalDecl::Const(const_decl)) => self.line(&const_decl_text(const_decl)),
+            CompUnitItem::GlobalDecl(GlobalDecl::Var(var_decl)) => {
+                let defs: Vec<String> = var_decl.var_def_list.iter()
+                    .map(|def| var_def_text(def.pointer, &def.ident, &def.dimensions, def.init_val.as_ref()))
+                    .collect();
+                self.line(&format!("{}{} {};", storage_text(var_decl.storage), b_type_text(var_decl.b_type), defs.join(", ")));
+            }
+        }
+    }
+
+    /// 输出块内的各项(不含两侧花括号), 缩进一层
+    fn block_items(&mut self, block: &Block) {
+        self.depth += 1;
+        for item in &block.block_item_list {
+            match item {
+                BlockItem::Decl(Decl::Const(const_decl)) => self.line(&const_decl_text(const_decl)),
+                BlockItem::Decl(Decl::Var(var_decl)) => {
+                    let defs: Vec<String> = var_decl.var_def_list.iter()
+                        .map(|def| var_def_text(def.pointer, &def.ident, &def.dimensions, def.init_val.as_ref()))
+                        .collect();
+                    self.line(&format!("{} {};", b_type_text(var_decl.b_type), defs.join(", ")));
+                }
+                BlockItem::Stmt(stmt) => self.stmt(stmt),
+            }
+        }
+        self.depth -= 1;
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Return(Some(exp)) => self.line(&format!("return {};", format_exp(exp))),
+            Stmt::Return(None) => self.line("return;"),
+            Stmt::Exp(Some(exp)) => self.line(&format!("{};", format_exp(exp))),
+            Stmt::Exp(None) => self.line(";"),
+            Stmt::Block(block) => {
+                self.line("{");
+                self.block_items(block);
+                self.line("}");
+            }
+            Stmt::Assign(lval, exp) => self.line(&format!("{} = {};", lval_text(lval), format_exp(exp))),
+            Stmt::DerefAssign(target, exp) => {
+                let mut text = String::from("*");
+                write_expr(&mut text, target, UNARY_PRECEDENCE);
+                self.line(&format!("{} = {};", text, format_exp(exp)));
+            }
+            Stmt::If(..) => self.if_stmt(stmt, ""),
+            Stmt::While(cond, body) => {
+                let head = format!("while ({})", format_exp(cond));
+                if self.branch(&head, body, false) {
+                    self.line("}");
+                }
+            }
+            Stmt::Break => self.line("break;"),
+            Stmt::Continue => self.line("continue;"),
+        }
+    }
+
+    /// 输出if语句, prefix为"} else "时表示接在上一个else之后(else if 链)
+    fn if_stmt(&mut self, stmt: &Stmt, prefix: &str) {
+        let Stmt::If(cond, then_stmt, else_stmt) = stmt else { unreachable!() };
+        let head = format!("{}if ({})", prefix, format_exp(cond));
+        // then分支是没有else的if时必须加花括号, 否则后面的else会被解析为属于它
+        let needs_braces = else_stmt.is_some() && is_open(then_stmt);
+        let open = self.branch(&head, then_stmt, needs_braces);
+        match else_stmt {
+            None => {
+                if open {
+                    self.line("}");
+                }
+            }
+            Some(else_stmt) => {
+                let prefix = if open { "} else " } else { "else " };
+                if let Stmt::If(..) = else_stmt.as_ref() {
+                    self.if_stmt(else_stmt, prefix);
+                } else if self.branch(prefix.trim_end(), else_stmt, false) {
+                    self.line("}");
+                }
+            }
+        }
+    }
+
+    /// 输出 `head` 及其分支语句, 返回是否留下了一个尚未闭合的"{"
+    fn branch(&mut self, head: &str, body: &Stmt, force_braces: bool) -> bool {
+        match body {
+            Stmt::Block(block) => {
+                self.line(&format!("{} {{", head));
+                self.block_items(block);
+                true
+            }
+            _ if force_braces => {
+                self.line(&format!("{} {{", head));
+                self.depth += 1;
+                self.stmt(body);
+                self.depth -= 1;
+                // 解析器不会产生这种AST, 加上花括号后分支多一层代码块, 语义不变
+                true
+            }
+            _ => {
+                self.line(head);
+                self.depth += 1;
+                self.stmt(body);
+                self.depth -= 1;
+                false
+            }
+        }
+    }
+}
+
+/// 语句是否以没有else的if结尾(其后紧跟的else会与它匹配)
+fn is_open(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::If(_, _, None) => true,
+        Stmt::If(_, _, Some(else_stmt)) => is_open(else_stmt),
+        Stmt::While(_, body) => is_open(body),
+        _ => false,
+    }
+}
+
+fn storage_text(storage: StorageClass) -> &'static str {
+    match storage {
+        StorageClass::Default => "",
+        StorageClass::Static => "static ",
+        StorageClass::Extern => "extern ",
+    }
+}
+
+fn b_type_text(b_type: BType) -> &'static str {
+    match b_type {
+        BType::Int => "int",
+        BType::Float => "float",
+    }
+}
+
+/// 函数头, 如 `static int f(int a[], int n)`
+pub fn func_head(storage: StorageClass, func_type: &FuncType, id: &str, params: &Option<FuncFParams>) -> String {
+    let ret = match func_type {
+        FuncType::Int => "int",
+        FuncType::Float => "float",
+        FuncType::Void => "void",
+    };
+    let params: Vec<String> = params.iter().flat_map(|params| &params.params).map(|param| {
+        let mut text = format!("{} {}{}", b_type_text(param.b_type), "*".repeat(param.pointer), param.ident);
+        for dim in &param.dimensions {
+            match dim {
+                Some(dim) => text.push_str(&format!("[{}]", format_exp(dim))),
+                None => text.push_str("[]"),
+            }
+        }
+        text
+    }).collect();
+    format!("{}{} {}({})", storage_text(storage), ret, id, params.join(", "))
+}
+
+fn const_decl_text(const_decl: &ConstDecl) -> String {
+    let defs: Vec<String> = const_decl.const_def_list.iter().map(|def| {
+        let mut text = def.ident.clone();
+        for dim in &def.dimensions {
+            text.push_str(&format!("[{}]", format_exp(dim)));
+        }
+        text.push_str(" = ");
+        write_const_init_val(&mut text, &def.const_init_val);
+        text
+    }).collect();
+    format!("{}const {} {};", storage_text(const_decl.storage), b_type_text(const_decl.b_type), defs.join(", "))
+}
+
+fn var_def_text(pointer: usize, ident: &str, dimensions: &[ConstExp], init_val: Option<&InitVal>) -> String {
+    let mut text = format!("{}{}", "*".repeat(pointer), ident);
+    for dim in dimensions {
+        text.push_str(&format!("[{}]", format_exp(dim)));
+    }
+    if let Some(init_val) = init_val {
+        text.push_str(" = ");
+        write_init_val(&mut text, init_val);
+    }
+    text
+}
+
+fn write_const_init_val(out: &mut String, init_val: &ConstInitVal) {
+    match init_val {
+        ConstInitVal::Exp(exp) => write_exp(out, exp),
+        ConstInitVal::List(list) => {
+            out.push('{');
+            for (i, item) in list.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_const_init_val(out, item);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_init_val(out: &mut String, init_val: &InitVal) {
+    match init_val {
+        InitVal::Exp(exp) => write_exp(out, exp),
+        InitVal::List(list) => {
+            out.push('{');
+            for (i, item) in list.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_init_val(out, item);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn lval_text(lval: &LVal) -> String {
+    let mut out = String::new();
+    write_lval(&mut out, lval);
+    out
+}
+
+// endregion 源码输出
+
+// region 表达式输出
+// 子表达式的优先级低于所在位置要求的最低优先级时加括号;
+// 二元运算左结合, 因此右操作数要求比运算符本身高一级
+
+/// 一元运算符(包括 * 和 &)的优先级, 高于所有二元运算符
+const UNARY_PRECEDENCE: u8 = 7;
+
+fn write_exp(out: &mut String, exp: &Expr) {
+    write_expr(out, exp, 0);
+}
+
+fn write_expr(out: &mut String, exp: &Expr, min_prec: u8) {
+    match &exp.kind {
+        ExprKind::Number(num) => out.push_str(&num.to_string()),
+        ExprKind::Float(num) => out.push_str(&float_literal(*num)),
+        ExprKind::Str(bytes) => out.push_str(&string_literal(bytes)),
+        ExprKind::LVal(lval) => write_lval(out, lval),
+        ExprKind::Binary(op, lhs, rhs) => {
+            let prec = op.precedence();
+            if prec < min_prec {
+                out.push('(');
+            }
+            write_expr(out, lhs, prec);
+            out.push(' ');
+            out.push_str(op.symbol());
+            out.push(' ');
+            write_expr(out, rhs, prec + 1);
+            if prec < min_prec {
+                out.push(')');
+            }
+        }
+        ExprKind::Unary(op, operand) => {
+            let op = match op {
+                UnaryOp::Plus => '+',
+                UnaryOp::Minus => '-',
+                UnaryOp::Not => '!',
+                UnaryOp::Deref => '*',
+                UnaryOp::AddrOf => '&',
+            };
+            out.push(op);
+            // 连续的 - -、+ +、& & 不能写成 --、++、&&(操作数也可能是负数字面量)
+            let mut operand_text = String::new();
+            write_expr(&mut operand_text, operand, UNARY_PRECEDENCE);
+            if matches!(op, '-' | '+' | '&') && operand_text.starts_with(op) {
+                out.push(' ');
+            }
+            out.push_str(&operand_text);
+        }
+        ExprKind::Call(id, params) => {
+            out.push_str(id);
+            out.push('(');
+            for (i, param) in params.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_exp(out, param);
+            }
+            out.push(')');
+        }
+    }
+}
+
+fn write_lval(out: &mut String, lval: &LVal) {
+    out.push_str(&lval.ident);
+    for index in &lval.indices {
+        out.push('[');
+        write_exp(out, index);
+        out.push(']');
+    }
+}
+
+/// 浮点字面量: 最短的能精确还原的十进制形式, 总是带小数点或指数
+fn float_literal(num: f32) -> String {
+    if num.is_infinite() {
+        return "1e39".to_string(); // 超出f32范围的字面量解析为无穷大
+    }
+    let text = format!("{:?}", num);
+    if text.contains(['.', 'e']) { text } else { format!("{}.0", text) }
+}
+
+/// 字符串字面量: 可打印字符原样输出, 其余字节使用转义序列
+fn string_literal(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\t' => out.push_str("\\t"),
+            b'\r' => out.push_str("\\r"),
+            0x20..=0x7e => out.push(byte as char),
+            // 八进制转义至多三位, 不会吞掉后面的数字字符
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// endregion 表达式输出
+
+// region 树形输出
+
+/// 将整个编译单元输出为紧凑的树形结构
+pub fn dump_ast(unit: &CompUnit) -> String {
+    let mut printer = SourcePrinter::default();
+    printer.line("CompUnit");
+    printer.depth += 1;
+    for item in &unit.items {
+        match item {
+            CompUnitItem::FuncDef(func_def) => {
+                printer.line(&format!("FuncDef {}", func_head(func_def.storage, &func_def.func_type, &func_def.id, &func_def.params)));
+                printer.depth += 1;
+                printer.dump_block(&func_def.block);
+                printer.depth -= 1;
+            }
+            CompUnitItem::FuncDecl(func_decl) => {
+                printer.line(&format!("FuncDecl {}", func_head(func_decl.storage, &func_decl.func_type, &func_decl.id, &func_decl.params)));
+            }
+            CompUnitItem::GlobalDecl(GlobalDecl::Const(const_decl)) => printer.dump_const_decl(const_decl),
+            CompUnitItem::GlobalDecl(GlobalDecl::Var(var_decl)) => {
+                printer.line(&format!("VarDecl {}{}", storage_text(var_decl.storage), b_type_text(var_decl.b_type)));
+                printer.depth += 1;
+                for def in &var_decl.var_def_list {
+                    printer.line(&format!("VarDef {}", var_def_text(def.pointer, &def.ident, &def.dimensions, def.init_val.as_ref())));
+                }
+                printer.depth -= 1;
+            }
+        }
+    }
+    printer.out
+}
+
+impl SourcePrinter {
+    fn dump_block(&mut self, block: &Block) {
+        self.line("Block");
+        self.depth += 1;
+        for item in &block.block_item_list {
+            match item {
+                BlockItem::Decl(Decl::Const(const_decl)) => self.dump_const_decl(const_decl),
+                BlockItem::Decl(Decl::Var(var_decl)) => {
+                    self.line(&format!("VarDecl {}", b_type_text(var_decl.b_type)));
+                    self.depth += 1;
+                    for def in &var_decl.var_def_list {
+                        self.line(&format!("VarDef {}", var_def_text(def.pointer, &def.ident, &def.dimensions, def.init_val.as_ref())));
+                    }
+                    self.depth -= 1;
+                }
+                BlockItem::Stmt(stmt) => self.dump_stmt(stmt),
+            }
+        }
+        self.depth -= 1;
+    }
+
+    fn dump_const_decl(&mut self, const_decl: &ConstDecl) {
+        self.line(&format!("ConstDecl {}{}", storage_text(const_decl.storage), b_type_text(const_decl.b_type)));
+        self.depth += 1;
+        for def in &const_decl.const_def_list {
+            let mut text = format!("ConstDef {}", def.ident);
+            for dim in &def.dimensions {
+                text.push_str(&format!("[{}]", format_exp(dim)));
+            }
+            text.push_str(" = ");
+            write_const_init_val(&mut text, &def.const_init_val);
+            self.line(&text);
+        }
+        self.depth -= 1;
+    }
+
+    fn dump_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Return(Some(exp)) => self.line(&format!("Return {}", format_exp(exp))),
+            Stmt::Return(None) => self.line("Return"),
+            Stmt::Exp(Some(exp)) => self.line(&format!("Exp {}", format_exp(exp))),
+            Stmt::Exp(None) => self.line("Empty"),
+            Stmt::Block(block) => self.dump_block(block),
+            Stmt::Assign(lval, exp) => self.line(&format!("Assign {} = {}", lval_text(lval), format_exp(exp))),
+            Stmt::DerefAssign(target, exp) => {
+                let mut text = String::from("Assign *");
+                write_expr(&mut text, target, UNARY_PRECEDENCE);
+                self.line(&format!("{} = {}", text, format_exp(exp)));
+            }
+            Stmt::If(cond, then_stmt, else_stmt) => {
+                self.line(&format!("If {}", format_exp(cond)));
+                self.depth += 1;
+                self.dump_stmt(then_stmt);
+                self.depth -= 1;
+                if let Some(else_stmt) = else_stmt {
+                    self.line("Else");
+                    self.depth += 1;
+                    self.dump_stmt(else_stmt);
+                    self.depth -= 1;
+                }
+            }
+            Stmt::While(cond, body) => {
+                self.line(&format!("While {}", format_exp(cond)));
+                self.depth += 1;
+                self.dump_stmt(body);
+                self.depth -= 1;
+            }
+            Stmt::Break => self.line("Break"),
+            Stmt::Continue => self.line("Continue"),
+        }
+    }
+}
+
+// endregion 树形输出
@@ -0,0 +1,122 @@
+//! 基于插桩的执行计数(-profile-gen / -profile-use)
+//!
+//! -profile-gen 在每个基本块入口把全局计数数组中对应的元素加一, main 返回前调用运行时钩子
+//! `__sysy_profile_dump(n, counts)` 输出计数(真机上由 runtime/profile.c 写入 sysy.profdata, 每行一个计数).
+//! -profile-use 读入计数文件, 按相同的顺序对应回未插桩程序的基本块, 得到各块与各函数的执行次数.
+//! 两次编译的源码与选项须相同, 否则基本块的个数对不上.
+//! 目前只有基本块布局(见 lab9::codegen::layout)使用计数; 编译器还没有函数内联, 函数的执行次数暂无使用者
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, ValueKind};
+use std::collections::HashMap;
+
+/// 输出计数的运行时钩子(不含@)
+pub const DUMP_FUNCTION: &str = "__sysy_profile_dump";
+/// 计数数组的全局变量名
+const COUNTERS: &str = "@__sysy_profile_counts";
+/// 真机上运行时钩子写出的计数文件
+pub const DEFAULT_PROFILE_FILE: &str = "sysy.profdata";
+
+/// 参与计数的基本块: 依次为各个有函数体的函数中按布局顺序排列的基本块
+fn counted_blocks(program: &Program) -> Vec<(Function, BasicBlock)> {
+    program.func_layout().iter()
+        .flat_map(|&func| program.func(func).layout().bbs().keys().map(move |&bb| (func, bb)))
+        .collect()
+}
+
+/// 在每个基本块入口插入计数, 在 main 的每个 ret 之前插入对运行时钩子的调用
+pub fn instrument(program: &mut Program) {
+    let blocks = counted_blocks(program);
+    if blocks.is_empty() {
+        return;
+    }
+    let counters_type = Type::get_array(Type::get_i32(), blocks.len());
+    let init = program.new_value().zero_init(counters_type);
+    let counters = program.new_value().global_alloc(init);
+    program.set_value_name(counters, Some(COUNTERS.to_string()));
+    let dump = program.new_func(FunctionData::new_decl(
+        format!("@{}", DUMP_FUNCTION),
+        vec![Type::get_i32(), Type::get_pointer(Type::get_i32())],
+        Type::get_unit(),
+    ));
+
+    for (index, &(func, bb)) in blocks.iter().enumerate() {
+        let func_data = program.func_mut(func);
+        let dfg = func_data.dfg_mut();
+        let index = dfg.new_value().integer(index as i32);
+        let counter = dfg.new_value().get_elem_ptr(counters, index);
+        let count = dfg.new_value().load(counter);
+        let one = dfg.new_value().integer(1);
+        let incremented = dfg.new_value().binary(BinaryOp::Add, count, one);
+        let store = dfg.new_value().store(incremented, counter);
+        let insts = func_data.layout_mut().bb_mut(bb).insts_mut();
+        for inst in [store, incremented, count, counter] {
+            insts.push_key_front(inst).unwrap();
+        }
+    }
+
+    let Some(&main) = program.func_layout().iter().find(|&&func| program.func(func).name() == "@main") else {
+        return;
+    };
+    let func_data = program.func_mut(main);
+    let returns: Vec<_> = func_data.layout().bbs().iter()
+        .flat_map(|(&bb, node)| node.insts().keys().map(move |&inst| (bb, inst)))
+        .filter(|&(_, inst)| matches!(func_data.dfg().value(inst).kind(), ValueKind::Return(_)))
+        .collect();
+    for (bb, ret) in returns {
+        let dfg = func_data.dfg_mut();
+        let zero = dfg.new_value().integer(0);
+        let first = dfg.new_value().get_elem_ptr(counters, zero);
+        let count = dfg.new_value().integer(blocks.len() as i32);
+        let call = dfg.new_value().call(dump, vec![count, first]);
+        let mut cursor = func_data.layout_mut().bb_mut(bb).insts_mut().cursor_mut(ret);
+        cursor.insert_key_before(first).unwrap();
+        cursor.insert_key_before(call).unwrap();
+    }
+}
+
+/// 计数文件的内容: 每行一个计数
+pub fn format_counts(counts: &[i32]) -> String {
+    counts.iter().map(|&count| format!("{}\n", count as u32)).collect()
+}
+
+/// 各基本块与函数的执行次数
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    blocks: HashMap<BasicBlock, u64>,
+    functions: HashMap<Function, u64>,
+}
+
+impl Profile {
+    /// 读入计数文件, 对应到(未插桩的) program 的基本块上
+    pub fn parse(text: &str, program: &Program) -> Result<Self, String> {
+        let counts = text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .enumerate()
+            .map(|(line_no, line)| line.parse::<u64>().map_err(|_| format!("line {}: invalid count '{}'", line_no + 1, line)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let blocks = counted_blocks(program);
+        if counts.len() != blocks.len() {
+            return Err(format!("profile has {} counts but the program has {} basic blocks", counts.len(), blocks.len()));
+        }
+
+        let mut profile = Profile::default();
+        for (&(func, bb), &count) in blocks.iter().zip(&counts) {
+            if program.func(func).layout().entry_bb() == Some(bb) {
+                profile.functions.insert(func, count);
+            }
+            profile.blocks.insert(bb, count);
+        }
+        Ok(profile)
+    }
+
+    /// 基本块的执行次数, 不在剖析数据中的块(如之后的变换新建的块)为 None
+    pub fn block_count(&self, bb: BasicBlock) -> Option<u64> {
+        self.blocks.get(&bb).copied()
+    }
+
+    /// 函数被调用的次数(入口块的执行次数)
+    pub fn function_count(&self, func: Function) -> Option<u64> {
+        self.functions.get(&func).copied()
+    }
+}
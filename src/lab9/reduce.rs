@@ -0,0 +1,502 @@
+//! 测试用例缩小(delta debugging): 在 AST 上反复尝试删除或化简程序的一部分,
+//! 保留仍然满足判定条件(如解释执行与模拟执行不一致、编译器以某条信息 panic)的结果, 直到无法再缩小.
+//! 化简包括: 删除函数/全局声明/语句, if/while 换成分支或循环体, 内联标量常量,
+//! 删除初始化列表中的元素, 把表达式换成它的操作数或更小的字面量(数组维度也是表达式, 由此缩小)
+use crate::ast::{
+    Block, BlockItem, CompUnit, CompUnitItem, ConstDecl, ConstInitVal, Decl, Expr, ExprKind, FuncFParams, GlobalDecl,
+    InitVal, Stmt,
+};
+use crate::lab9::fuzz::{check_program, Failure, Outcome};
+use std::collections::{BTreeSet, HashSet};
+
+/// 候选程序是否仍然"有趣"的判定条件, 程序经 fuzz::check_program 的完整流水线检查
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    SameFailure(Failure), // 出现与原程序同类的错误(见 Failure::same_kind)
+    Diverged,             // Koopa IR 解释执行与 RISC-V 模拟执行的结果不一致
+    Panic(String),        // 编译器某一阶段 panic, 且信息包含给定的文本
+}
+
+impl Predicate {
+    pub fn holds(&self, unit: &CompUnit) -> bool {
+        let Outcome::Fail(failure) = check_program(unit) else {
+            return false;
+        };
+        match self {
+            Self::SameFailure(expected) => failure.same_kind(expected),
+            Self::Diverged => matches!(failure, Failure::Diverged { .. }),
+            Self::Panic(text) => matches!(&failure, Failure::Panic { message, .. } if message.contains(text.as_str())),
+        }
+    }
+}
+
+/// 一种化简: 对程序做第 site 个候选修改, 候选不足时返回 false
+type Pass = fn(&mut CompUnit, usize) -> bool;
+
+/// 先做删除量大的化简, 再逐个化简表达式
+const PASSES: [Pass; 6] = [
+    remove_items,
+    remove_block_items,
+    simplify_stmts,
+    inline_consts,
+    shrink_init_lists,
+    simplify_exprs,
+];
+
+/// 缩小程序: 依次尝试各种化简的每个候选, 满足 interesting 的候选取代当前程序, 直到一整轮没有进展
+/// 内联常量使常量定义只减不增, 其余被接受的修改都让程序严格变小(节点更少或字面量更小), 因此一定会结束
+/// 候选不能使用原程序中有声明的名字却删掉了声明: 否则对"使用未声明的标识符"这类错误, 删除声明本身就能保持错误
+pub fn reduce(mut unit: CompUnit, mut interesting: impl FnMut(&CompUnit) -> bool) -> CompUnit {
+    let undeclared = undeclared_names(&mut unit);
+    loop {
+        let mut progress = false;
+        for pass in PASSES {
+            let mut site = 0;
+            loop {
+                let mut candidate = unit.clone();
+                if !pass(&mut candidate, site) {
+                    break;
+                }
+                if undeclared_names(&mut candidate).is_subset(&undeclared) && interesting(&candidate) {
+                    unit = candidate;
+                    progress = true;
+                } else {
+                    site += 1;
+                }
+            }
+        }
+        if !progress {
+            return unit;
+        }
+    }
+}
+
+// region 删除
+
+/// 删除一个顶层项(除 main 外的函数、函数原型、全局声明), 或多个定义的全局声明中的一个定义
+fn remove_items(unit: &mut CompUnit, mut site: usize) -> bool {
+    for i in 0..unit.items.len() {
+        let defs = match &unit.items[i] {
+            CompUnitItem::FuncDef(func_def) if func_def.id == "main" => continue,
+            CompUnitItem::GlobalDecl(GlobalDecl::Const(decl)) => decl.const_def_list.len(),
+            CompUnitItem::GlobalDecl(GlobalDecl::Var(decl)) => decl.var_def_list.len(),
+            _ => 1,
+        };
+        if site == 0 {
+            unit.items.remove(i);
+            return true;
+        }
+        site -= 1;
+        if defs > 1 {
+            if site < defs {
+                match &mut unit.items[i] {
+                    CompUnitItem::GlobalDecl(GlobalDecl::Const(decl)) => drop(decl.const_def_list.remove(site)),
+                    CompUnitItem::GlobalDecl(GlobalDecl::Var(decl)) => drop(decl.var_def_list.remove(site)),
+                    _ => unreachable!(),
+                }
+                return true;
+            }
+            site -= defs;
+        }
+    }
+    false
+}
+
+/// 删除块中的一项, 或多个定义的局部声明中的一个定义
+/// 函数体末尾的 return 保留: 删除后有返回值的函数会返回未定义的值, 缩小的结果就不再可信
+fn remove_block_items(unit: &mut CompUnit, mut site: usize) -> bool {
+    visit_blocks(unit, &mut |block, is_body| {
+        let len = block.block_item_list.len();
+        for i in 0..len {
+            let defs = match &block.block_item_list[i] {
+                BlockItem::Stmt(Stmt::Return(_)) if is_body && i + 1 == len => continue,
+                BlockItem::Decl(Decl::Const(decl)) => decl.const_def_list.len(),
+                BlockItem::Decl(Decl::Var(decl)) => decl.var_def_list.len(),
+                BlockItem::Stmt(_) => 1,
+            };
+            if site == 0 {
+                block.block_item_list.remove(i);
+                block.item_positions.remove(i);
+                return true;
+            }
+            site -= 1;
+            if defs > 1 {
+                if site < defs {
+                    match &mut block.block_item_list[i] {
+                        BlockItem::Decl(Decl::Const(decl)) => drop(decl.const_def_list.remove(site)),
+                        BlockItem::Decl(Decl::Var(decl)) => drop(decl.var_def_list.remove(site)),
+                        BlockItem::Stmt(_) => unreachable!(),
+                    }
+                    return true;
+                }
+                site -= defs;
+            }
+        }
+        false
+    })
+}
+
+// endregion 删除
+
+// region 化简
+
+/// 化简语句: if 换成它的一个分支或去掉 else, while 换成循环体, 只有一条语句的块换成这条语句
+fn simplify_stmts(unit: &mut CompUnit, mut site: usize) -> bool {
+    visit_stmts(unit, &mut |stmt| {
+        let candidates = match stmt {
+            Stmt::If(_, _, None) | Stmt::While(..) => 1,
+            Stmt::If(_, _, Some(_)) => 3,
+            Stmt::Block(block) => matches!(block.block_item_list.as_slice(), [BlockItem::Stmt(_)]) as usize,
+            _ => 0,
+        };
+        if site >= candidates {
+            site -= candidates;
+            return false;
+        }
+        *stmt = match (std::mem::replace(stmt, Stmt::Exp(None)), site) {
+            (Stmt::If(_, then_stmt, _), 0) => *then_stmt,
+            (Stmt::If(_, _, Some(else_stmt)), 1) => *else_stmt,
+            (Stmt::If(cond, then_stmt, Some(_)), _) => Stmt::If(cond, then_stmt, None),
+            (Stmt::While(_, body), _) => *body,
+            (Stmt::Block(mut block), _) => match block.block_item_list.pop() {
+                Some(BlockItem::Stmt(stmt)) => stmt,
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        true
+    })
+}
+
+/// 内联标量常量: 删除第 site 个标量常量的定义, 把它的使用都换成初值表达式
+fn inline_consts(unit: &mut CompUnit, site: usize) -> bool {
+    let Some((ident, value)) = take_scalar_const(unit, site) else {
+        return false;
+    };
+    visit_exprs(unit, &mut |expr| {
+        if matches!(&expr.kind, ExprKind::LVal(lval) if lval.ident == ident && lval.indices.is_empty()) {
+            *expr = value.clone();
+        }
+        false
+    });
+    true
+}
+
+/// 取出第 site 个标量常量的定义(先全局后局部), 声明中不再有定义时删除整个声明
+fn take_scalar_const(unit: &mut CompUnit, mut site: usize) -> Option<(String, Expr)> {
+    for i in 0..unit.items.len() {
+        if let CompUnitItem::GlobalDecl(GlobalDecl::Const(decl)) = &mut unit.items[i] {
+            if let Some(taken) = take_const_def(decl, &mut site) {
+                if decl.const_def_list.is_empty() {
+                    unit.items.remove(i);
+                }
+                return Some(taken);
+            }
+        }
+    }
+
+    let mut taken = None;
+    visit_blocks(unit, &mut |block, _| {
+        for i in 0..block.block_item_list.len() {
+            if let BlockItem::Decl(Decl::Const(decl)) = &mut block.block_item_list[i] {
+                taken = take_const_def(decl, &mut site);
+                if taken.is_some() {
+                    if decl.const_def_list.is_empty() {
+                        block.block_item_list.remove(i);
+                        block.item_positions.remove(i);
+                    }
+                    return true;
+                }
+            }
+        }
+        false
+    });
+    taken
+}
+
+fn take_const_def(decl: &mut ConstDecl, site: &mut usize) -> Option<(String, Expr)> {
+    for i in 0..decl.const_def_list.len() {
+        let def = &decl.const_def_list[i];
+        let ConstInitVal::Exp(value) = &def.const_init_val else {
+            continue;
+        };
+        if !def.dimensions.is_empty() {
+            continue;
+        }
+        if *site == 0 {
+            let value = value.clone();
+            return Some((decl.const_def_list.remove(i).ident, value));
+        }
+        *site -= 1;
+    }
+    None
+}
+
+/// 删除某个初始化列表中的一个元素
+fn shrink_init_lists(unit: &mut CompUnit, mut site: usize) -> bool {
+    for item in &mut unit.items {
+        let shrunk = match item {
+            CompUnitItem::GlobalDecl(GlobalDecl::Const(decl)) => shrink_const_decl(decl, &mut site),
+            CompUnitItem::GlobalDecl(GlobalDecl::Var(decl)) => decl.var_def_list.iter_mut()
+                .any(|def| def.init_val.as_mut().is_some_and(|init| shrink_init_val(init, &mut site))),
+            _ => false,
+        };
+        if shrunk {
+            return true;
+        }
+    }
+    visit_blocks(unit, &mut |block, _| {
+        block.block_item_list.iter_mut().any(|item| match item {
+            BlockItem::Decl(Decl::Const(decl)) => shrink_const_decl(decl, &mut site),
+            BlockItem::Decl(Decl::Var(decl)) => decl.var_def_list.iter_mut()
+                .any(|def| def.init_val.as_mut().is_some_and(|init| shrink_init_val(init, &mut site))),
+            BlockItem::Stmt(_) => false,
+        })
+    })
+}
+
+fn shrink_const_decl(decl: &mut ConstDecl, site: &mut usize) -> bool {
+    decl.const_def_list.iter_mut().any(|def| shrink_const_init_val(&mut def.const_init_val, site))
+}
+
+fn shrink_init_val(init: &mut InitVal, site: &mut usize) -> bool {
+    let InitVal::List(items) = init else {
+        return false;
+    };
+    if *site < items.len() {
+        items.remove(*site);
+        return true;
+    }
+    *site -= items.len();
+    items.iter_mut().any(|item| shrink_init_val(item, site))
+}
+
+fn shrink_const_init_val(init: &mut ConstInitVal, site: &mut usize) -> bool {
+    let ConstInitVal::List(items) = init else {
+        return false;
+    };
+    if *site < items.len() {
+        items.remove(*site);
+        return true;
+    }
+    *site -= items.len();
+    items.iter_mut().any(|item| shrink_const_init_val(item, site))
+}
+
+/// 化简表达式: 换成0、换成某个操作数, 或把整数字面量换成绝对值更小的数
+fn simplify_exprs(unit: &mut CompUnit, mut site: usize) -> bool {
+    visit_exprs(unit, &mut |expr| {
+        let candidates = simpler_exprs(expr);
+        if site < candidates.len() {
+            *expr = candidates.into_iter().nth(site).unwrap();
+            return true;
+        }
+        site -= candidates.len();
+        false
+    })
+}
+
+/// 比 expr 严格更简单的候选表达式
+fn simpler_exprs(expr: &Expr) -> Vec<Expr> {
+    let number = |value: i32| Expr::new(ExprKind::Number(value), expr.span.start, expr.span.end);
+    match &expr.kind {
+        ExprKind::Number(value) => {
+            let magnitude = value.unsigned_abs();
+            let mut candidates = Vec::new();
+            if magnitude > 0 {
+                candidates.push(number(0));
+            }
+            if magnitude > 1 {
+                candidates.push(number(1));
+            }
+            if magnitude > 3 {
+                candidates.push(number(value / 2));
+            }
+            candidates
+        }
+        ExprKind::Float(value) if *value != 0.0 => vec![Expr::new(ExprKind::Float(0.0), expr.span.start, expr.span.end)],
+        ExprKind::Float(_) | ExprKind::Str(_) => Vec::new(),
+        ExprKind::LVal(_) | ExprKind::Call(..) => vec![number(0)],
+        ExprKind::Unary(_, operand) => vec![number(0), (**operand).clone()],
+        ExprKind::Binary(_, lhs, rhs) => vec![number(0), (**lhs).clone(), (**rhs).clone()],
+    }
+}
+
+// endregion 化简
+
+/// 程序中使用了却没有声明的名字(不区分作用域), 库函数也在其中
+fn undeclared_names(unit: &mut CompUnit) -> BTreeSet<String> {
+    let mut declared = HashSet::new();
+    for item in &unit.items {
+        match item {
+            CompUnitItem::FuncDef(func_def) => {
+                declared.insert(func_def.id.clone());
+                declared.extend(func_def.params.iter().flat_map(|params| params.params.iter()).map(|param| param.ident.clone()));
+            }
+            CompUnitItem::FuncDecl(func_decl) => {
+                declared.insert(func_decl.id.clone());
+            }
+            CompUnitItem::GlobalDecl(GlobalDecl::Const(decl)) => {
+                declared.extend(decl.const_def_list.iter().map(|def| def.ident.clone()));
+            }
+            CompUnitItem::GlobalDecl(GlobalDecl::Var(decl)) => {
+                declared.extend(decl.var_def_list.iter().map(|def| def.ident.clone()));
+            }
+        }
+    }
+    visit_blocks(unit, &mut |block, _| {
+        for item in &block.block_item_list {
+            match item {
+                BlockItem::Decl(Decl::Const(decl)) => declared.extend(decl.const_def_list.iter().map(|def| def.ident.clone())),
+                BlockItem::Decl(Decl::Var(decl)) => declared.extend(decl.var_def_list.iter().map(|def| def.ident.clone())),
+                BlockItem::Stmt(_) => {}
+            }
+        }
+        false
+    });
+
+    let mut used = BTreeSet::new();
+    visit_exprs(unit, &mut |expr| {
+        match &expr.kind {
+            ExprKind::LVal(lval) => used.insert(lval.ident.clone()),
+            ExprKind::Call(callee, _) => used.insert(callee.clone()),
+            _ => false,
+        };
+        false
+    });
+    visit_stmts(unit, &mut |stmt| {
+        if let Stmt::Assign(lval, _) = stmt {
+            used.insert(lval.ident.clone());
+        }
+        false
+    });
+    used.retain(|name| !declared.contains(name));
+    used
+}
+
+// region 遍历
+// 遍历函数按先序访问, 回调返回 true 时立即停止(此时回调已经修改了程序)
+
+/// 访问所有块, is_body 表示该块是函数体
+fn visit_blocks(unit: &mut CompUnit, f: &mut dyn FnMut(&mut Block, bool) -> bool) -> bool {
+    unit.items.iter_mut().any(|item| match item {
+        CompUnitItem::FuncDef(func_def) => {
+            f(&mut func_def.block, true) || visit_block_stmts(&mut func_def.block, &mut |stmt| match stmt {
+                Stmt::Block(block) => f(block, false),
+                _ => false,
+            })
+        }
+        _ => false,
+    })
+}
+
+/// 访问所有语句, 包括 if/while 中不是块的分支与循环体
+fn visit_stmts(unit: &mut CompUnit, f: &mut dyn FnMut(&mut Stmt) -> bool) -> bool {
+    unit.items.iter_mut().any(|item| match item {
+        CompUnitItem::FuncDef(func_def) => visit_block_stmts(&mut func_def.block, f),
+        _ => false,
+    })
+}
+
+fn visit_block_stmts(block: &mut Block, f: &mut dyn FnMut(&mut Stmt) -> bool) -> bool {
+    block.block_item_list.iter_mut().any(|item| match item {
+        BlockItem::Stmt(stmt) => visit_stmt(stmt, f),
+        BlockItem::Decl(_) => false,
+    })
+}
+
+fn visit_stmt(stmt: &mut Stmt, f: &mut dyn FnMut(&mut Stmt) -> bool) -> bool {
+    if f(stmt) {
+        return true;
+    }
+    match stmt {
+        Stmt::Block(block) => visit_block_stmts(block, f),
+        Stmt::If(_, then_stmt, else_stmt) => {
+            visit_stmt(then_stmt, f) || else_stmt.as_mut().is_some_and(|stmt| visit_stmt(stmt, f))
+        }
+        Stmt::While(_, body) => visit_stmt(body, f),
+        _ => false,
+    }
+}
+
+/// 访问所有表达式, 包括数组维度、形参维度与初始化列表中的表达式
+fn visit_exprs(unit: &mut CompUnit, f: &mut dyn FnMut(&mut Expr) -> bool) -> bool {
+    unit.items.iter_mut().any(|item| match item {
+        CompUnitItem::GlobalDecl(GlobalDecl::Const(decl)) => visit_const_decl_exprs(decl, f),
+        CompUnitItem::GlobalDecl(GlobalDecl::Var(decl)) => decl.var_def_list.iter_mut().any(|def| {
+            def.dimensions.iter_mut().any(|dim| visit_expr(dim, f))
+                || def.init_val.as_mut().is_some_and(|init| visit_init_exprs(init, f))
+        }),
+        CompUnitItem::FuncDecl(func_decl) => visit_param_exprs(&mut func_decl.params, f),
+        CompUnitItem::FuncDef(func_def) => {
+            visit_param_exprs(&mut func_def.params, f) || visit_block_exprs(&mut func_def.block, f)
+        }
+    })
+}
+
+fn visit_param_exprs(params: &mut Option<FuncFParams>, f: &mut dyn FnMut(&mut Expr) -> bool) -> bool {
+    params.iter_mut().flat_map(|params| params.params.iter_mut())
+        .any(|param| param.dimensions.iter_mut().flatten().any(|dim| visit_expr(dim, f)))
+}
+
+fn visit_const_decl_exprs(decl: &mut ConstDecl, f: &mut dyn FnMut(&mut Expr) -> bool) -> bool {
+    decl.const_def_list.iter_mut().any(|def| {
+        def.dimensions.iter_mut().any(|dim| visit_expr(dim, f)) || visit_const_init_exprs(&mut def.const_init_val, f)
+    })
+}
+
+fn visit_init_exprs(init: &mut InitVal, f: &mut dyn FnMut(&mut Expr) -> bool) -> bool {
+    match init {
+        InitVal::Exp(exp) => visit_expr(exp, f),
+        InitVal::List(items) => items.iter_mut().any(|item| visit_init_exprs(item, f)),
+    }
+}
+
+fn visit_const_init_exprs(init: &mut ConstInitVal, f: &mut dyn FnMut(&mut Expr) -> bool) -> bool {
+    match init {
+        ConstInitVal::Exp(exp) => visit_expr(exp, f),
+        ConstInitVal::List(items) => items.iter_mut().any(|item| visit_const_init_exprs(item, f)),
+    }
+}
+
+fn visit_block_exprs(block: &mut Block, f: &mut dyn FnMut(&mut Expr) -> bool) -> bool {
+    block.block_item_list.iter_mut().any(|item| match item {
+        BlockItem::Decl(Decl::Const(decl)) => visit_const_decl_exprs(decl, f),
+        BlockItem::Decl(Decl::Var(decl)) => decl.var_def_list.iter_mut().any(|def| {
+            def.dimensions.iter_mut().any(|dim| visit_expr(dim, f))
+                || def.init_val.as_mut().is_some_and(|init| visit_init_exprs(init, f))
+        }),
+        BlockItem::Stmt(stmt) => visit_stmt_exprs(stmt, f),
+    })
+}
+
+fn visit_stmt_exprs(stmt: &mut Stmt, f: &mut dyn FnMut(&mut Expr) -> bool) -> bool {
+    match stmt {
+        Stmt::Return(exp) | Stmt::Exp(exp) => exp.as_mut().is_some_and(|exp| visit_expr(exp, f)),
+        Stmt::Block(block) => visit_block_exprs(block, f),
+        Stmt::Assign(lval, exp) => lval.indices.iter_mut().any(|index| visit_expr(index, f)) || visit_expr(exp, f),
+        Stmt::DerefAssign(ptr, exp) => visit_expr(ptr, f) || visit_expr(exp, f),
+        Stmt::If(cond, then_stmt, else_stmt) => {
+            visit_expr(cond, f)
+                || visit_stmt_exprs(then_stmt, f)
+                || else_stmt.as_mut().is_some_and(|stmt| visit_stmt_exprs(stmt, f))
+        }
+        Stmt::While(cond, body) => visit_expr(cond, f) || visit_stmt_exprs(body, f),
+        Stmt::Break | Stmt::Continue => false,
+    }
+}
+
+fn visit_expr(expr: &mut Expr, f: &mut dyn FnMut(&mut Expr) -> bool) -> bool {
+    if f(expr) {
+        return true;
+    }
+    match &mut expr.kind {
+        ExprKind::Unary(_, operand) => visit_expr(operand, f),
+        ExprKind::Binary(_, lhs, rhs) => visit_expr(lhs, f) || visit_expr(rhs, f),
+        ExprKind::LVal(lval) => lval.indices.iter_mut().any(|index| visit_expr(index, f)),
+        ExprKind::Call(_, args) => args.iter_mut().any(|arg| visit_expr(arg, f)),
+        ExprKind::Number(_) | ExprKind::Float(_) | ExprKind::Str(_) => false,
+    }
+}
+
+// endregion 遍历
@@ -0,0 +1,424 @@
+//! SysY 运行时库(libsysy)的输入输出语义, 由 Koopa IR 解释器与 RISC-V 模拟器共用
+//!
+//! 与官方运行时一致: getint/getfloat 按 scanf 读取, putfloat 按 `%a` 输出,
+//! putarray 输出 `n: a0 a1 ...` 并换行, starttime/stoptime 不产生输出.
+//! 插桩程序(-profile-gen)输出的执行计数记录在 Execution::profile 中
+use std::fmt;
+
+/// 程序运行的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Execution {
+    pub stdout: Vec<u8>,
+    pub exit_code: i32, // main 的返回值, 与进程退出码一样只保留低8位
+    pub profile: Option<Vec<i32>>, // 插桩程序最后一次输出的执行计数
+}
+
+impl Execution {
+    /// 评测用的期望输出格式: 标准输出, 不以换行结尾时补一个换行, 最后一行为退出码
+    pub fn expected_output(&self) -> String {
+        let mut text = String::from_utf8_lossy(&self.stdout).into_owned();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&format!("{}\n", self.exit_code));
+        text
+    }
+}
+
+/// 程序运行时出错
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecError {
+    StepLimit(u64),   // 执行的指令数超过上限, 多半是死循环
+    Fault(String),    // 非法访存、栈溢出、调用未知函数等
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StepLimit(limit) => write!(f, "step limit of {} exceeded", limit),
+            Self::Fault(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+pub type ExecResult<T> = Result<T, ExecError>;
+
+/// putf 的一个转换说明需要的实参类别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PutfArgKind {
+    Int,    // %d %i %u %x %X %o %c
+    Double, // %f %e %g %a
+    Str,    // %s
+}
+
+/// putf 的实参值
+#[derive(Debug, Clone)]
+pub enum PutfArg {
+    Int(i32),
+    Double(f64),
+    Str(Vec<u8>),
+}
+
+/// 标准输入输出
+#[derive(Debug, Default)]
+pub struct Runtime {
+    input: Vec<u8>,
+    pos: usize,
+    pub output: Vec<u8>,
+    pub profile: Option<Vec<i32>>,
+}
+
+impl Runtime {
+    pub fn new(input: &[u8]) -> Self {
+        Self {
+            input: input.to_vec(),
+            pos: 0,
+            output: Vec::new(),
+            profile: None,
+        }
+    }
+
+    /// 运行时钩子 __sysy_profile_dump: 记录插桩程序的执行计数
+    pub fn profile_dump(&mut self, counts: Vec<i32>) {
+        self.profile = Some(counts);
+    }
+
+    pub fn getch(&mut self) -> i32 {
+        match self.input.get(self.pos) {
+            Some(&byte) => {
+                self.pos += 1;
+                byte as i32
+            }
+            None => -1, // EOF
+        }
+    }
+
+    /// scanf("%d"), 没有可读的整数时返回0
+    pub fn getint(&mut self) -> i32 {
+        self.skip_whitespace();
+        let start = self.pos;
+        if matches!(self.peek(), Some(b'+' | b'-')) {
+            self.pos += 1;
+        }
+        while matches!(self.peek(), Some(b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.input[start..self.pos]).unwrap_or_default();
+        // 超出范围时按补码回绕
+        text.parse::<i64>().map(|value| value as i32).unwrap_or(0)
+    }
+
+    /// scanf("%a"), 支持十进制与十六进制浮点数
+    pub fn getfloat(&mut self) -> f32 {
+        self.skip_whitespace();
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F' | b'x' | b'X' | b'p' | b'P' | b'.' | b'+' | b'-')) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.input[start..self.pos]).unwrap_or_default();
+        parse_float(text).unwrap_or(0.0)
+    }
+
+    pub fn putint(&mut self, value: i32) {
+        self.output.extend_from_slice(value.to_string().as_bytes());
+    }
+
+    pub fn putch(&mut self, value: i32) {
+        self.output.push(value as u8);
+    }
+
+    pub fn putfloat(&mut self, value: f32) {
+        self.output.extend_from_slice(hex_float(value as f64).as_bytes());
+    }
+
+    pub fn putarray(&mut self, values: &[i32]) {
+        self.output.extend_from_slice(format!("{}:", values.len()).as_bytes());
+        for value in values {
+            self.output.extend_from_slice(format!(" {}", value).as_bytes());
+        }
+        self.output.push(b'\n');
+    }
+
+    pub fn putfarray(&mut self, values: &[f32]) {
+        self.output.extend_from_slice(format!("{}:", values.len()).as_bytes());
+        for value in values {
+            self.output.extend_from_slice(format!(" {}", hex_float(*value as f64)).as_bytes());
+        }
+        self.output.push(b'\n');
+    }
+
+    /// printf 风格的格式化输出, 每遇到一个转换说明就通过 next_arg 取下一个实参
+    pub fn putf(&mut self, format: &[u8], mut next_arg: impl FnMut(PutfArgKind) -> PutfArg) {
+        let mut i = 0;
+        while i < format.len() {
+            if format[i] != b'%' {
+                self.output.push(format[i]);
+                i += 1;
+                continue;
+            }
+            i += 1;
+            let mut spec = Spec::default();
+            while let Some(&flag) = format.get(i) {
+                match flag {
+                    b'-' => spec.left = true,
+                    b'+' => spec.plus = true,
+                    b' ' => spec.space = true,
+                    b'0' => spec.zero = true,
+                    b'#' => spec.alt = true,
+                    _ => break,
+                }
+                i += 1;
+            }
+            if format.get(i) == Some(&b'*') {
+                i += 1;
+                let width = int_arg(&mut next_arg);
+                spec.left |= width < 0;
+                spec.width = width.unsigned_abs() as usize;
+            } else {
+                while let Some(digit @ b'0'..=b'9') = format.get(i) {
+                    spec.width = spec.width * 10 + (digit - b'0') as usize;
+                    i += 1;
+                }
+            }
+            if format.get(i) == Some(&b'.') {
+                i += 1;
+                let mut precision = 0;
+                if format.get(i) == Some(&b'*') {
+                    i += 1;
+                    precision = int_arg(&mut next_arg).max(0) as usize;
+                } else {
+                    while let Some(digit @ b'0'..=b'9') = format.get(i) {
+                        precision = precision * 10 + (digit - b'0') as usize;
+                        i += 1;
+                    }
+                }
+                spec.precision = Some(precision);
+            }
+            // 长度修饰符对32位实参没有影响
+            while matches!(format.get(i), Some(b'l' | b'h' | b'z' | b'j' | b't' | b'L')) {
+                i += 1;
+            }
+            let Some(&conversion) = format.get(i) else { break };
+            i += 1;
+            let text = match conversion {
+                b'%' => {
+                    self.output.push(b'%');
+                    continue;
+                }
+                b'd' | b'i' => spec.signed(int_arg(&mut next_arg) as i64, |v| v.to_string()),
+                b'u' => spec.unsigned(int_arg(&mut next_arg) as u32, |v| v.to_string(), ""),
+                b'x' => spec.unsigned(int_arg(&mut next_arg) as u32, |v| format!("{:x}", v), "0x"),
+                b'X' => spec.unsigned(int_arg(&mut next_arg) as u32, |v| format!("{:X}", v), "0X"),
+                b'o' => spec.unsigned(int_arg(&mut next_arg) as u32, |v| format!("{:o}", v), "0"),
+                b'c' => spec.pad(vec![int_arg(&mut next_arg) as u8]),
+                b's' => {
+                    let mut bytes = match next_arg(PutfArgKind::Str) {
+                        PutfArg::Str(bytes) => bytes,
+                        _ => Vec::new(),
+                    };
+                    if let Some(precision) = spec.precision {
+                        bytes.truncate(precision);
+                    }
+                    spec.pad(bytes)
+                }
+                b'f' | b'F' | b'e' | b'E' | b'g' | b'G' | b'a' | b'A' => {
+                    let value = match next_arg(PutfArgKind::Double) {
+                        PutfArg::Double(value) => value,
+                        PutfArg::Int(value) => value as f64,
+                        PutfArg::Str(_) => 0.0,
+                    };
+                    spec.float(value, conversion)
+                }
+                // 未知的转换说明原样输出
+                _ => {
+                    self.output.push(b'%');
+                    self.output.push(conversion);
+                    continue;
+                }
+            };
+            self.output.extend_from_slice(&text);
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(byte) if byte.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+}
+
+fn int_arg(next_arg: &mut impl FnMut(PutfArgKind) -> PutfArg) -> i32 {
+    match next_arg(PutfArgKind::Int) {
+        PutfArg::Int(value) => value,
+        PutfArg::Double(value) => value as i32,
+        PutfArg::Str(_) => 0,
+    }
+}
+
+/// 转换说明中的标志、宽度与精度
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alt: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    fn signed(&self, value: i64, digits: fn(u64) -> String) -> Vec<u8> {
+        let sign = if value < 0 { "-" } else if self.plus { "+" } else if self.space { " " } else { "" };
+        self.number(sign, digits(value.unsigned_abs()))
+    }
+
+    fn unsigned(&self, value: u32, digits: fn(u64) -> String, alt_prefix: &str) -> Vec<u8> {
+        let prefix = if self.alt && value != 0 { alt_prefix } else { "" };
+        self.number(prefix, digits(value as u64))
+    }
+
+    /// 整数: 精度为最少数字个数, 指定精度时忽略0标志
+    fn number(&self, prefix: &str, mut digits: String) -> Vec<u8> {
+        if let Some(precision) = self.precision {
+            if precision == 0 && digits == "0" {
+                digits.clear();
+            }
+            while digits.len() < precision {
+                digits.insert(0, '0');
+            }
+        }
+        self.pad_number(prefix, digits, self.precision.is_none())
+    }
+
+    fn float(&self, value: f64, conversion: u8) -> Vec<u8> {
+        let sign = if value.is_sign_negative() { "-" } else if self.plus { "+" } else if self.space { " " } else { "" };
+        let abs = value.abs();
+        let upper = conversion.is_ascii_uppercase();
+        if !abs.is_finite() {
+            let text = if abs.is_nan() { "nan" } else { "inf" };
+            let text = if upper { text.to_uppercase() } else { text.to_string() };
+            return self.pad_number(sign, text, false);
+        }
+        let text = match conversion.to_ascii_lowercase() {
+            b'f' => format!("{:.*}", self.precision.unwrap_or(6), abs),
+            b'e' => exp_float(abs, self.precision.unwrap_or(6)),
+            b'g' => {
+                let precision = self.precision.unwrap_or(6).max(1);
+                let exponent = if abs == 0.0 { 0 } else { exp_float(abs, precision - 1).split_once('e').unwrap().1.parse::<i32>().unwrap() };
+                let mut text = if exponent < -4 || exponent >= precision as i32 {
+                    exp_float(abs, precision - 1)
+                } else {
+                    format!("{:.*}", (precision as i32 - 1 - exponent) as usize, abs)
+                };
+                if !self.alt {
+                    // 去掉小数部分末尾的0
+                    let (mantissa, exp) = match text.find('e') {
+                        Some(pos) => (text[..pos].to_string(), text[pos..].to_string()),
+                        None => (text.clone(), String::new()),
+                    };
+                    let mantissa = match mantissa.contains('.') {
+                        true => mantissa.trim_end_matches('0').trim_end_matches('.').to_string(),
+                        false => mantissa,
+                    };
+                    text = mantissa + &exp;
+                }
+                text
+            }
+            _ => hex_float(abs),
+        };
+        let text = if upper { text.to_uppercase() } else { text };
+        self.pad_number(sign, text, true)
+    }
+
+    fn pad_number(&self, prefix: &str, body: String, zero_allowed: bool) -> Vec<u8> {
+        let len = prefix.len() + body.len();
+        if self.zero && zero_allowed && !self.left && len < self.width {
+            // 0填充在符号/前缀之后
+            let zeros = "0".repeat(self.width - len);
+            return format!("{}{}{}", prefix, zeros, body).into_bytes();
+        }
+        self.pad(format!("{}{}", prefix, body).into_bytes())
+    }
+
+    fn pad(&self, mut text: Vec<u8>) -> Vec<u8> {
+        if text.len() < self.width {
+            let padding = vec![b' '; self.width - text.len()];
+            if self.left {
+                text.extend(padding);
+            } else {
+                text.splice(0..0, padding);
+            }
+        }
+        text
+    }
+}
+
+/// C 的 %e 格式: 指数至少两位并带符号
+fn exp_float(value: f64, precision: usize) -> String {
+    let text = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    format!("{}e{}{:02}", mantissa, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+/// C 的 %a 格式(glibc): 规格化数为 0x1.<十六进制尾数>p<指数>, 尾数去掉末尾的0
+pub fn hex_float(value: f64) -> String {
+    if value.is_nan() {
+        return if value.is_sign_negative() { "-nan" } else { "nan" }.to_string();
+    }
+    let sign = if value.is_sign_negative() { "-" } else { "" };
+    if value.is_infinite() {
+        return format!("{}inf", sign);
+    }
+    if value == 0.0 {
+        return format!("{}0x0p+0", sign);
+    }
+    let bits = value.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i32;
+    let mantissa = bits & ((1u64 << 52) - 1);
+    let (lead, exponent) = match biased {
+        0 => (0, -1022), // 非规格化数
+        _ => (1, biased - 1023),
+    };
+    let digits = format!("{:013x}", mantissa);
+    let digits = digits.trim_end_matches('0');
+    let exp_sign = if exponent < 0 { '-' } else { '+' };
+    match digits.is_empty() {
+        true => format!("{}0x{}p{}{}", sign, lead, exp_sign, exponent.abs()),
+        false => format!("{}0x{}.{}p{}{}", sign, lead, digits, exp_sign, exponent.abs()),
+    }
+}
+
+/// 解析十进制或十六进制浮点数文本
+fn parse_float(text: &str) -> Option<f32> {
+    let (negative, body) = match text.strip_prefix('-') {
+        Some(body) => (true, body),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = match body.strip_prefix("0x").or_else(|| body.strip_prefix("0X")) {
+        Some(hex) => {
+            let (mantissa, exponent) = match hex.find(['p', 'P']) {
+                Some(pos) => (&hex[..pos], hex[pos + 1..].parse::<i32>().ok()?),
+                None => (hex, 0),
+            };
+            let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+            let mut value = 0f64;
+            for digit in int_part.chars() {
+                value = value * 16.0 + digit.to_digit(16)? as f64;
+            }
+            let mut scale = 1.0 / 16.0;
+            for digit in frac_part.chars() {
+                value += digit.to_digit(16)? as f64 * scale;
+                scale /= 16.0;
+            }
+            (value * 2f64.powi(exponent)) as f32
+        }
+        None => body.parse::<f32>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
@@ -0,0 +1,809 @@
+//! RISC-V 模拟器: 汇编 codegen 输出的文本并执行, 运行时库函数由模拟器直接实现
+//!
+//! 支持 RV32IMF 中 codegen 会用到的指令(及常见伪指令)和 `.data`/`.rodata` 中的 `.word`/`.zero`/`.asciz` 等伪操作.
+//! 调用运行时库后, 调用者保存的寄存器会被填入无意义的值, 以暴露跨调用依赖临时寄存器的错误
+use crate::lab9::interp::float_to_int;
+use crate::lab9::profile::DUMP_FUNCTION;
+use crate::lab9::runtime::{ExecError, ExecResult, Execution, PutfArg, PutfArgKind, Runtime};
+use std::collections::HashMap;
+
+/// 内存大小, 栈从顶端向下增长
+const MEMORY_SIZE: usize = 32 << 20;
+/// 数据段的起始地址, 其下的地址都是非法的
+const DATA_BASE: usize = 0x10000;
+/// 代码地址只用于 ra 等寄存器中的返回地址, 第i条指令的地址为 TEXT_BASE + 4i
+const TEXT_BASE: u32 = 0x8000_0000;
+/// main 返回到这个地址时结束运行
+const EXIT_ADDRESS: u32 = TEXT_BASE - 4;
+/// 调用运行时库后写入调用者保存寄存器的值
+const CLOBBER: i32 = 0x0bad_cafe;
+
+/// 汇编并运行程序, 最多执行 max_steps 条指令
+pub fn run_assembly(asm: &str, input: &[u8], max_steps: u64) -> ExecResult<Execution> {
+    let program = assemble(asm).map_err(ExecError::Fault)?;
+    let mut machine = Machine::new(program, input);
+    let exit_code = machine.run(max_steps)?;
+    Ok(Execution {
+        stdout: machine.runtime.output,
+        exit_code,
+        profile: machine.runtime.profile,
+    })
+}
+
+// region 汇编
+
+#[derive(Debug, Clone, Copy)]
+enum AluOp {
+    Add, Sub, Mul, Mulh, Mulhu, Div, Divu, Rem, Remu,
+    And, Or, Xor, Sll, Srl, Sra, Slt, Sltu,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Cond {
+    Eq, Ne, Lt, Ge, Ltu, Geu,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FloatOp {
+    Add, Sub, Mul, Div, Min, Max,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FloatCmp {
+    Eq, Lt, Le,
+}
+
+/// 跳转目标: 程序中的标签或运行时库函数
+#[derive(Debug, Clone)]
+enum Target {
+    Label(usize),
+    External(String),
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Li(usize, i32),
+    Alu(AluOp, usize, usize, usize),
+    AluImm(AluOp, usize, usize, i32),
+    Load { rd: usize, base: usize, offset: i32, width: u8, signed: bool },
+    Store { rs: usize, base: usize, offset: i32, width: u8 },
+    Branch(Cond, usize, usize, usize),
+    Jal(usize, Target),
+    Jalr(usize, usize, i32),
+    FLoad { fd: usize, base: usize, offset: i32, double: bool },
+    FStore { fs: usize, base: usize, offset: i32, double: bool },
+    FArith(FloatOp, usize, usize, usize),
+    FSqrt(usize, usize),
+    FSgnj { fd: usize, fs1: usize, fs2: usize, mode: u8 }, // 0: fsgnj 1: fsgnjn 2: fsgnjx
+    FMvWX(usize, usize),
+    FMvXW(usize, usize),
+    FCvtSW(usize, usize),
+    FCvtWS(usize, usize),
+    FCvtDS(usize, usize),
+    FCvtSD(usize, usize),
+    FCmp(FloatCmp, usize, usize, usize),
+}
+
+struct AsmProgram {
+    insts: Vec<Inst>,
+    memory: Vec<u8>,
+    data_end: usize,
+    main: usize,
+}
+
+/// 未解析的标签引用
+enum Fixup {
+    Inst(usize, String),     // 第几条指令引用的标签
+    Word(usize, String),     // .word <标签>
+}
+
+fn assemble(asm: &str) -> Result<AsmProgram, String> {
+    let mut memory = vec![0u8; MEMORY_SIZE];
+    let mut data_ptr = DATA_BASE;
+    let mut insts: Vec<Inst> = Vec::new();
+    let mut in_text = true;
+    let mut in_debug = false; // 调试信息段(-g)不装入内存
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    let mut code_labels: HashMap<String, usize> = HashMap::new();
+    let mut fixups = Vec::new();
+
+    for (line_no, raw_line) in asm.lines().enumerate() {
+        let error = |msg: &str| format!("line {}: {}: '{}'", line_no + 1, msg, raw_line.trim());
+        let mut line = strip_comment(raw_line).trim();
+        if let Some(section) = line.strip_prefix(".section") {
+            in_debug = section.trim().starts_with(".debug");
+        } else if in_debug && matches!(line, ".text" | ".data" | ".rodata" | ".bss" | ".sdata") {
+            in_debug = false;
+        }
+        if in_debug {
+            continue;
+        }
+        // 同一行中可能在标签后跟指令
+        while let Some(pos) = label_end(line) {
+            let label = line[..pos].trim().to_string();
+            if in_text {
+                code_labels.insert(label.clone(), insts.len());
+                labels.insert(label, TEXT_BASE + 4 * insts.len() as u32);
+            } else {
+                labels.insert(label, data_ptr as u32);
+            }
+            line = line[pos + 1..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let operands = split_operands(rest.trim());
+        if mnemonic.starts_with('.') {
+            match mnemonic {
+                ".text" => in_text = true,
+                ".data" | ".rodata" | ".bss" | ".sdata" => in_text = false,
+                ".section" => in_text = operands.first().is_some_and(|name| name.starts_with(".text")),
+                ".word" | ".half" | ".byte" => {
+                    let width = match mnemonic { ".word" => 4, ".half" => 2, _ => 1 };
+                    for operand in &operands {
+                        let end = data_ptr + width;
+                        if end > MEMORY_SIZE {
+                            return Err(error("data section is too large"));
+                        }
+                        match parse_imm(operand) {
+                            Some(value) => memory[data_ptr..end].copy_from_slice(&value.to_le_bytes()[..width]),
+                            None => fixups.push(Fixup::Word(data_ptr, operand.clone())),
+                        }
+                        data_ptr = end;
+                    }
+                }
+                ".zero" | ".space" => {
+                    let size = operands.first().and_then(|size| parse_imm(size)).ok_or_else(|| error("invalid size"))?;
+                    data_ptr += size as usize;
+                }
+                ".asciz" | ".string" | ".ascii" => {
+                    let bytes = parse_string_literal(rest.trim()).ok_or_else(|| error("invalid string literal"))?;
+                    memory[data_ptr..data_ptr + bytes.len()].copy_from_slice(&bytes);
+                    data_ptr += bytes.len();
+                    if mnemonic != ".ascii" {
+                        data_ptr += 1;
+                    }
+                }
+                ".align" | ".p2align" => {
+                    let align = 1 << operands.first().and_then(|n| parse_imm(n)).unwrap_or(2);
+                    data_ptr = data_ptr.div_ceil(align) * align;
+                }
+                // .global .globl .type .size 等与执行无关
+                _ => {}
+            }
+            if data_ptr > MEMORY_SIZE / 2 {
+                return Err(error("data section is too large"));
+            }
+            continue;
+        }
+        if !in_text {
+            return Err(error("instruction outside of the text section"));
+        }
+
+        let (inst, label) = parse_inst(mnemonic, &operands).map_err(|msg| error(&msg))?;
+        if let Some(label) = label {
+            fixups.push(Fixup::Inst(insts.len(), label));
+        }
+        insts.push(inst);
+    }
+
+    // 解析标签引用
+    for fixup in fixups {
+        match fixup {
+            Fixup::Inst(index, label) => {
+                let code_label = code_labels.get(&label).copied();
+                match &mut insts[index] {
+                    Inst::Jal(_, target) => {
+                        *target = match code_label {
+                            Some(pos) => Target::Label(pos),
+                            None => Target::External(label),
+                        };
+                    }
+                    Inst::Branch(_, _, _, target) => {
+                        *target = code_label.ok_or_else(|| format!("undefined label '{}'", label))?;
+                    }
+                    Inst::Li(_, value) => {
+                        *value = *labels.get(&label).ok_or_else(|| format!("undefined symbol '{}'", label))? as i32;
+                    }
+                    _ => unreachable!("only jumps and la reference labels"),
+                }
+            }
+            Fixup::Word(address, label) => {
+                let value = *labels.get(&label).ok_or_else(|| format!("undefined symbol '{}'", label))?;
+                memory[address..address + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    let main = *code_labels.get("main").ok_or("program has no main function")?;
+    Ok(AsmProgram {
+        insts,
+        memory,
+        data_end: data_ptr,
+        main,
+    })
+}
+
+pub(crate) fn strip_comment(line: &str) -> &str {
+    // 字符串中的 # 不是注释
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// 行首标签的冒号位置
+pub(crate) fn label_end(line: &str) -> Option<usize> {
+    let pos = line.find(':')?;
+    let label = &line[..pos];
+    let is_label = !label.is_empty()
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+        && !line.starts_with(".asciz");
+    is_label.then_some(pos)
+}
+
+pub(crate) fn split_operands(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.split(',').map(|operand| operand.trim().to_string()).collect()
+}
+
+pub(crate) fn parse_imm(text: &str) -> Option<i64> {
+    let (negative, body) = match text.strip_prefix('-') {
+        Some(body) => (true, body),
+        None => (false, text),
+    };
+    let value = match body.strip_prefix("0x").or_else(|| body.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None => body.parse::<i64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+pub(crate) fn parse_string_literal(text: &str) -> Option<Vec<u8>> {
+    let body = text.strip_prefix('"')?.strip_suffix('"')?.as_bytes();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < body.len() {
+        if body[i] != b'\\' {
+            bytes.push(body[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+        let escape = *body.get(i)?;
+        i += 1;
+        match escape {
+            b'n' => bytes.push(b'\n'),
+            b't' => bytes.push(b'\t'),
+            b'r' => bytes.push(b'\r'),
+            b'0'..=b'7' => {
+                let mut value = (escape - b'0') as u32;
+                for _ in 0..2 {
+                    match body.get(i) {
+                        Some(digit @ b'0'..=b'7') => {
+                            value = value * 8 + (digit - b'0') as u32;
+                            i += 1;
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            b'x' => {
+                let mut value = 0u32;
+                while let Some(digit) = body.get(i).and_then(|&c| (c as char).to_digit(16)) {
+                    value = value * 16 + digit;
+                    i += 1;
+                }
+                bytes.push(value as u8);
+            }
+            other => bytes.push(other),
+        }
+    }
+    Some(bytes)
+}
+
+fn int_reg(name: &str) -> Result<usize, String> {
+    let index = match name {
+        "zero" => 0,
+        "ra" => 1,
+        "sp" => 2,
+        "gp" => 3,
+        "tp" => 4,
+        "fp" => 8,
+        _ => {
+            let (prefix, number) = name.split_at(name.find(|c: char| c.is_ascii_digit()).ok_or(format!("invalid register '{}'", name))?);
+            let n: usize = number.parse().map_err(|_| format!("invalid register '{}'", name))?;
+            match (prefix, n) {
+                ("x", 0..=31) => n,
+                ("t", 0..=2) => 5 + n,
+                ("t", 3..=6) => 25 + n,
+                ("s", 0..=1) => 8 + n,
+                ("s", 2..=11) => 16 + n,
+                ("a", 0..=7) => 10 + n,
+                _ => return Err(format!("invalid register '{}'", name)),
+            }
+        }
+    };
+    Ok(index)
+}
+
+fn float_reg(name: &str) -> Result<usize, String> {
+    let (prefix, number) = name.split_at(name.find(|c: char| c.is_ascii_digit()).ok_or(format!("invalid register '{}'", name))?);
+    let n: usize = number.parse().map_err(|_| format!("invalid register '{}'", name))?;
+    Ok(match (prefix, n) {
+        ("f", 0..=31) => n,
+        ("ft", 0..=7) => n,
+        ("ft", 8..=11) => 20 + n,
+        ("fs", 0..=1) => 8 + n,
+        ("fs", 2..=11) => 16 + n,
+        ("fa", 0..=7) => 10 + n,
+        _ => return Err(format!("invalid register '{}'", name)),
+    })
+}
+
+/// 解析 offset(base) 形式的内存操作数
+fn mem_operand(text: &str) -> Result<(i32, usize), String> {
+    let open = text.find('(').ok_or(format!("invalid memory operand '{}'", text))?;
+    let offset = match text[..open].trim() {
+        "" => 0,
+        offset => parse_imm(offset).ok_or(format!("invalid offset '{}'", offset))? as i32,
+    };
+    let base = int_reg(text[open + 1..].trim_end_matches(')').trim())?;
+    Ok((offset, base))
+}
+
+/// 解析一条指令, 引用标签时一并返回标签名
+fn parse_inst(mnemonic: &str, ops: &[String]) -> Result<(Inst, Option<String>), String> {
+    let op = |i: usize| ops.get(i).map(String::as_str).ok_or(format!("missing operand for '{}'", mnemonic));
+    let r = |i: usize| int_reg(op(i)?);
+    let f = |i: usize| float_reg(op(i)?);
+    let imm = |i: usize| parse_imm(op(i)?).map(|v| v as i32).ok_or(format!("invalid immediate for '{}'", mnemonic));
+    let label = |i: usize| op(i).map(|l| Some(l.to_string()));
+
+    let alu = |name: &str| -> Option<AluOp> {
+        Some(match name {
+            "add" => AluOp::Add, "sub" => AluOp::Sub, "mul" => AluOp::Mul, "mulh" => AluOp::Mulh,
+            "mulhu" => AluOp::Mulhu, "div" => AluOp::Div, "divu" => AluOp::Divu, "rem" => AluOp::Rem,
+            "remu" => AluOp::Remu, "and" => AluOp::And, "or" => AluOp::Or, "xor" => AluOp::Xor,
+            "sll" => AluOp::Sll, "srl" => AluOp::Srl, "sra" => AluOp::Sra, "slt" => AluOp::Slt,
+            "sltu" => AluOp::Sltu,
+            _ => return None,
+        })
+    };
+
+    let inst = match mnemonic {
+        "nop" => (Inst::AluImm(AluOp::Add, 0, 0, 0), None),
+        "li" => (Inst::Li(r(0)?, imm(1)?), None),
+        "la" | "lla" => (Inst::Li(r(0)?, 0), label(1)?),
+        "lui" => (Inst::Li(r(0)?, imm(1)? << 12), None),
+        "mv" => (Inst::AluImm(AluOp::Add, r(0)?, r(1)?, 0), None),
+        "neg" => (Inst::Alu(AluOp::Sub, r(0)?, 0, r(1)?), None),
+        "not" => (Inst::AluImm(AluOp::Xor, r(0)?, r(1)?, -1), None),
+        "seqz" => (Inst::AluImm(AluOp::Sltu, r(0)?, r(1)?, 1), None),
+        "snez" => (Inst::Alu(AluOp::Sltu, r(0)?, 0, r(1)?), None),
+        "sltz" => (Inst::Alu(AluOp::Slt, r(0)?, r(1)?, 0), None),
+        "sgtz" => (Inst::Alu(AluOp::Slt, r(0)?, 0, r(1)?), None),
+        "sgt" => (Inst::Alu(AluOp::Slt, r(0)?, r(2)?, r(1)?), None),
+        "sgtu" => (Inst::Alu(AluOp::Sltu, r(0)?, r(2)?, r(1)?), None),
+        "addi" | "andi" | "ori" | "xori" | "slli" | "srli" | "srai" | "slti" | "sltiu" => {
+            let alu_op = alu(&mnemonic[..mnemonic.len() - 1]).or(if mnemonic == "sltiu" { Some(AluOp::Sltu) } else { None }).unwrap();
+            (Inst::AluImm(alu_op, r(0)?, r(1)?, imm(2)?), None)
+        }
+        "lw" | "lh" | "lhu" | "lb" | "lbu" => {
+            let (offset, base) = mem_operand(op(1)?)?;
+            let width = match &mnemonic[1..2] { "w" => 4, "h" => 2, _ => 1 };
+            (Inst::Load { rd: r(0)?, base, offset, width, signed: !mnemonic.ends_with('u') }, None)
+        }
+        "sw" | "sh" | "sb" => {
+            let (offset, base) = mem_operand(op(1)?)?;
+            let width = match mnemonic { "sw" => 4, "sh" => 2, _ => 1 };
+            (Inst::Store { rs: r(0)?, base, offset, width }, None)
+        }
+        "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" => {
+            let cond = match mnemonic {
+                "beq" => Cond::Eq, "bne" => Cond::Ne, "blt" => Cond::Lt,
+                "bge" => Cond::Ge, "bltu" => Cond::Ltu, _ => Cond::Geu,
+            };
+            (Inst::Branch(cond, r(0)?, r(1)?, 0), label(2)?)
+        }
+        // 交换操作数的伪指令
+        "bgt" | "ble" | "bgtu" | "bleu" => {
+            let cond = match mnemonic { "bgt" => Cond::Lt, "ble" => Cond::Ge, "bgtu" => Cond::Ltu, _ => Cond::Geu };
+            (Inst::Branch(cond, r(1)?, r(0)?, 0), label(2)?)
+        }
+        "beqz" => (Inst::Branch(Cond::Eq, r(0)?, 0, 0), label(1)?),
+        "bnez" => (Inst::Branch(Cond::Ne, r(0)?, 0, 0), label(1)?),
+        "bltz" => (Inst::Branch(Cond::Lt, r(0)?, 0, 0), label(1)?),
+        "bgez" => (Inst::Branch(Cond::Ge, r(0)?, 0, 0), label(1)?),
+        "blez" => (Inst::Branch(Cond::Ge, 0, r(0)?, 0), label(1)?),
+        "bgtz" => (Inst::Branch(Cond::Lt, 0, r(0)?, 0), label(1)?),
+        "j" => (Inst::Jal(0, Target::Label(0)), label(0)?),
+        "jal" if ops.len() == 1 => (Inst::Jal(1, Target::Label(0)), label(0)?),
+        "jal" => (Inst::Jal(r(0)?, Target::Label(0)), label(1)?),
+        "call" => (Inst::Jal(1, Target::Label(0)), label(0)?),
+        "tail" => (Inst::Jal(0, Target::Label(0)), label(0)?),
+        "ret" => (Inst::Jalr(0, 1, 0), None),
+        "jr" => (Inst::Jalr(0, r(0)?, 0), None),
+        "jalr" if ops.len() == 1 => (Inst::Jalr(1, r(0)?, 0), None),
+        "jalr" => match mem_operand(op(1)?) {
+            Ok((offset, base)) => (Inst::Jalr(r(0)?, base, offset), None),
+            Err(_) => (Inst::Jalr(r(0)?, r(1)?, imm(2).unwrap_or(0)), None),
+        },
+        "flw" | "fld" => {
+            let (offset, base) = mem_operand(op(1)?)?;
+            (Inst::FLoad { fd: f(0)?, base, offset, double: mnemonic == "fld" }, None)
+        }
+        "fsw" | "fsd" => {
+            let (offset, base) = mem_operand(op(1)?)?;
+            (Inst::FStore { fs: f(0)?, base, offset, double: mnemonic == "fsd" }, None)
+        }
+        "fadd.s" | "fsub.s" | "fmul.s" | "fdiv.s" | "fmin.s" | "fmax.s" => {
+            let float_op = match &mnemonic[1..4] {
+                "add" => FloatOp::Add, "sub" => FloatOp::Sub, "mul" => FloatOp::Mul,
+                "div" => FloatOp::Div, "min" => FloatOp::Min, _ => FloatOp::Max,
+            };
+            (Inst::FArith(float_op, f(0)?, f(1)?, f(2)?), None)
+        }
+        "fsqrt.s" => (Inst::FSqrt(f(0)?, f(1)?), None),
+        "fmv.s" => (Inst::FSgnj { fd: f(0)?, fs1: f(1)?, fs2: f(1)?, mode: 0 }, None),
+        "fneg.s" => (Inst::FSgnj { fd: f(0)?, fs1: f(1)?, fs2: f(1)?, mode: 1 }, None),
+        "fabs.s" => (Inst::FSgnj { fd: f(0)?, fs1: f(1)?, fs2: f(1)?, mode: 2 }, None),
+        "fsgnj.s" => (Inst::FSgnj { fd: f(0)?, fs1: f(1)?, fs2: f(2)?, mode: 0 }, None),
+        "fsgnjn.s" => (Inst::FSgnj { fd: f(0)?, fs1: f(1)?, fs2: f(2)?, mode: 1 }, None),
+        "fsgnjx.s" => (Inst::FSgnj { fd: f(0)?, fs1: f(1)?, fs2: f(2)?, mode: 2 }, None),
+        "fmv.w.x" => (Inst::FMvWX(f(0)?, r(1)?), None),
+        "fmv.x.w" => (Inst::FMvXW(r(0)?, f(1)?), None),
+        "fcvt.s.w" => (Inst::FCvtSW(f(0)?, r(1)?), None),
+        // 舍入模式只支持 codegen 使用的 rtz
+        "fcvt.w.s" => (Inst::FCvtWS(r(0)?, f(1)?), None),
+        "fcvt.d.s" => (Inst::FCvtDS(f(0)?, f(1)?), None),
+        "fcvt.s.d" => (Inst::FCvtSD(f(0)?, f(1)?), None),
+        "feq.s" => (Inst::FCmp(FloatCmp::Eq, r(0)?, f(1)?, f(2)?), None),
+        "flt.s" => (Inst::FCmp(FloatCmp::Lt, r(0)?, f(1)?, f(2)?), None),
+        "fle.s" => (Inst::FCmp(FloatCmp::Le, r(0)?, f(1)?, f(2)?), None),
+        "fgt.s" => (Inst::FCmp(FloatCmp::Lt, r(0)?, f(2)?, f(1)?), None),
+        "fge.s" => (Inst::FCmp(FloatCmp::Le, r(0)?, f(2)?, f(1)?), None),
+        _ => match alu(mnemonic) {
+            Some(alu_op) => (Inst::Alu(alu_op, r(0)?, r(1)?, r(2)?), None),
+            None => return Err(format!("unsupported instruction '{}'", mnemonic)),
+        },
+    };
+    Ok(inst)
+}
+
+// endregion 汇编
+
+// region 执行
+
+struct Machine {
+    program: AsmProgram,
+    regs: [i32; 32],
+    fregs: [u64; 32], // 单精度值保存在低32位
+    pc: u32,
+    runtime: Runtime,
+}
+
+impl Machine {
+    fn new(program: AsmProgram, input: &[u8]) -> Self {
+        let mut regs = [0; 32];
+        regs[1] = EXIT_ADDRESS as i32;
+        regs[2] = MEMORY_SIZE as i32;
+        let pc = TEXT_BASE + 4 * program.main as u32;
+        Self {
+            program,
+            regs,
+            fregs: [0; 32],
+            pc,
+            runtime: Runtime::new(input),
+        }
+    }
+
+    fn run(&mut self, max_steps: u64) -> ExecResult<i32> {
+        for _ in 0..max_steps {
+            if self.pc == EXIT_ADDRESS {
+                return Ok(self.regs[10] & 0xff);
+            }
+            let index = self.pc.wrapping_sub(TEXT_BASE) / 4;
+            let inst = self.program.insts.get(index as usize).cloned()
+                .ok_or_else(|| fault(&format!("jump to invalid address {:#x}", self.pc)))?;
+            self.pc = self.pc.wrapping_add(4);
+            self.execute(inst)?;
+            self.regs[0] = 0;
+        }
+        Err(ExecError::StepLimit(max_steps))
+    }
+
+    fn execute(&mut self, inst: Inst) -> ExecResult<()> {
+        match inst {
+            Inst::Li(rd, value) => self.regs[rd] = value,
+            Inst::Alu(op, rd, rs1, rs2) => self.regs[rd] = alu(op, self.regs[rs1], self.regs[rs2]),
+            Inst::AluImm(op, rd, rs1, imm) => self.regs[rd] = alu(op, self.regs[rs1], imm),
+            Inst::Load { rd, base, offset, width, signed } => {
+                let address = self.regs[base].wrapping_add(offset);
+                let bytes = self.access(address, width as usize)?;
+                let mut word = [0u8; 4];
+                word[..width as usize].copy_from_slice(bytes);
+                let value = u32::from_le_bytes(word);
+                let shift = 32 - 8 * width as u32;
+                self.regs[rd] = match signed {
+                    true => ((value << shift) as i32) >> shift,
+                    false => value as i32,
+                };
+            }
+            Inst::Store { rs, base, offset, width } => {
+                let address = self.regs[base].wrapping_add(offset);
+                let value = self.regs[rs].to_le_bytes();
+                self.access_mut(address, width as usize)?.copy_from_slice(&value[..width as usize]);
+            }
+            Inst::Branch(cond, rs1, rs2, target) => {
+                let (a, b) = (self.regs[rs1], self.regs[rs2]);
+                let taken = match cond {
+                    Cond::Eq => a == b,
+                    Cond::Ne => a != b,
+                    Cond::Lt => a < b,
+                    Cond::Ge => a >= b,
+                    Cond::Ltu => (a as u32) < (b as u32),
+                    Cond::Geu => (a as u32) >= (b as u32),
+                };
+                if taken {
+                    self.pc = TEXT_BASE + 4 * target as u32;
+                }
+            }
+            Inst::Jal(rd, Target::Label(target)) => {
+                self.regs[rd] = self.pc as i32;
+                self.pc = TEXT_BASE + 4 * target as u32;
+            }
+            Inst::Jal(rd, Target::External(name)) => {
+                self.call_external(&name)?;
+                // tail 调用运行时库后直接返回到调用者
+                if rd == 0 {
+                    self.pc = self.regs[1] as u32;
+                }
+            }
+            Inst::Jalr(rd, rs1, offset) => {
+                let target = self.regs[rs1].wrapping_add(offset) as u32;
+                self.regs[rd] = self.pc as i32;
+                self.pc = target;
+            }
+            Inst::FLoad { fd, base, offset, double } => {
+                let address = self.regs[base].wrapping_add(offset);
+                self.fregs[fd] = match double {
+                    true => u64::from_le_bytes(self.access(address, 8)?.try_into().unwrap()),
+                    false => u32::from_le_bytes(self.access(address, 4)?.try_into().unwrap()) as u64,
+                };
+            }
+            Inst::FStore { fs, base, offset, double } => {
+                let address = self.regs[base].wrapping_add(offset);
+                let value = self.fregs[fs];
+                match double {
+                    true => self.access_mut(address, 8)?.copy_from_slice(&value.to_le_bytes()),
+                    false => self.access_mut(address, 4)?.copy_from_slice(&(value as u32).to_le_bytes()),
+                }
+            }
+            Inst::FArith(op, fd, fs1, fs2) => {
+                let (a, b) = (self.single(fs1), self.single(fs2));
+                let result = match op {
+                    FloatOp::Add => a + b,
+                    FloatOp::Sub => a - b,
+                    FloatOp::Mul => a * b,
+                    FloatOp::Div => a / b,
+                    FloatOp::Min => a.min(b),
+                    FloatOp::Max => a.max(b),
+                };
+                self.set_single(fd, result);
+            }
+            Inst::FSqrt(fd, fs) => self.set_single(fd, self.single(fs).sqrt()),
+            Inst::FSgnj { fd, fs1, fs2, mode } => {
+                const SIGN: u32 = 1 << 31;
+                let (a, b) = (self.fregs[fs1] as u32, self.fregs[fs2] as u32);
+                let sign = match mode {
+                    0 => b & SIGN,
+                    1 => !b & SIGN,
+                    _ => (a ^ b) & SIGN,
+                };
+                self.fregs[fd] = ((a & !SIGN) | sign) as u64;
+            }
+            Inst::FMvWX(fd, rs) => self.fregs[fd] = self.regs[rs] as u32 as u64,
+            Inst::FMvXW(rd, fs) => self.regs[rd] = self.fregs[fs] as u32 as i32,
+            Inst::FCvtSW(fd, rs) => self.set_single(fd, self.regs[rs] as f32),
+            Inst::FCvtWS(rd, fs) => self.regs[rd] = float_to_int(self.single(fs)),
+            Inst::FCvtDS(fd, fs) => self.fregs[fd] = (self.single(fs) as f64).to_bits(),
+            Inst::FCvtSD(fd, fs) => self.set_single(fd, f64::from_bits(self.fregs[fs]) as f32),
+            Inst::FCmp(cmp, rd, fs1, fs2) => {
+                let (a, b) = (self.single(fs1), self.single(fs2));
+                self.regs[rd] = match cmp {
+                    FloatCmp::Eq => a == b,
+                    FloatCmp::Lt => a < b,
+                    FloatCmp::Le => a <= b,
+                } as i32;
+            }
+        }
+        Ok(())
+    }
+
+    /// 运行时库函数, 参数与返回值按 ilp32f 调用约定传递
+    fn call_external(&mut self, name: &str) -> ExecResult<()> {
+        let a = |machine: &Self, i: usize| machine.regs[10 + i];
+        let mut result = None;
+        match name {
+            "getint" => result = Some(self.runtime.getint()),
+            "getch" => result = Some(self.runtime.getch()),
+            "getfloat" => {
+                let value = self.runtime.getfloat();
+                self.set_single(10, value);
+            }
+            "getarray" | "getfarray" => {
+                let n = self.runtime.getint();
+                let base = a(self, 0);
+                for i in 0..n.max(0) {
+                    let value = match name == "getfarray" {
+                        true => self.runtime.getfloat().to_bits() as i32,
+                        false => self.runtime.getint(),
+                    };
+                    self.store_word(base.wrapping_add(4 * i), value)?;
+                }
+                result = Some(n);
+            }
+            "putint" => self.runtime.putint(a(self, 0)),
+            "putch" => self.runtime.putch(a(self, 0)),
+            "putfloat" => {
+                let value = self.single(10);
+                self.runtime.putfloat(value);
+            }
+            "putarray" | "putfarray" => {
+                let (n, base) = (a(self, 0), a(self, 1));
+                let values = (0..n.max(0)).map(|i| self.load_word(base.wrapping_add(4 * i))).collect::<ExecResult<Vec<_>>>()?;
+                match name == "putfarray" {
+                    true => self.runtime.putfarray(&values.iter().map(|&bits| f32::from_bits(bits as u32)).collect::<Vec<_>>()),
+                    false => self.runtime.putarray(&values),
+                }
+            }
+            "putf" => self.putf()?,
+            "starttime" | "stoptime" | "_sysy_starttime" | "_sysy_stoptime" => {}
+            DUMP_FUNCTION => {
+                let (n, base) = (a(self, 0), a(self, 1));
+                let counts = (0..n.max(0)).map(|i| self.load_word(base.wrapping_add(4 * i))).collect::<ExecResult<Vec<_>>>()?;
+                self.runtime.profile_dump(counts);
+            }
+            _ => return Err(fault(&format!("call to undefined function '{}'", name))),
+        }
+
+        // 调用者保存的寄存器在调用后的值是不确定的
+        for reg in (5..=7).chain(11..=17).chain(28..=31) {
+            self.regs[reg] = CLOBBER;
+        }
+        self.regs[10] = result.unwrap_or(CLOBBER);
+        for freg in (0..=7).chain(11..=17).chain(28..=31) {
+            self.fregs[freg] = CLOBBER as u64;
+        }
+        Ok(())
+    }
+
+    /// 变参调用: 格式串在a0, 其余实参依次占用a1-a7与栈上的位置, double占用偶数起始的两个位置
+    fn putf(&mut self) -> ExecResult<()> {
+        let format = self.read_c_string(self.regs[10])?;
+        let mut slot = 1;
+        let mut fault_result = Ok(());
+        let mut output = std::mem::take(&mut self.runtime);
+        output.putf(&format, |kind| {
+            let arg = match kind {
+                PutfArgKind::Double => {
+                    slot += slot % 2;
+                    self.vararg_word(&mut slot).and_then(|lo| self.vararg_word(&mut slot).map(|hi| {
+                        PutfArg::Double(f64::from_bits((hi as u32 as u64) << 32 | lo as u32 as u64))
+                    }))
+                }
+                PutfArgKind::Int => self.vararg_word(&mut slot).map(PutfArg::Int),
+                PutfArgKind::Str => self.vararg_word(&mut slot).and_then(|address| self.read_c_string(address).map(PutfArg::Str)),
+            };
+            arg.unwrap_or_else(|err| {
+                fault_result = Err(err);
+                PutfArg::Int(0)
+            })
+        });
+        self.runtime = output;
+        fault_result
+    }
+
+    /// 第slot个参数位置上的字, 前8个位于a0-a7, 其余位于栈上
+    fn vararg_word(&self, slot: &mut usize) -> ExecResult<i32> {
+        let value = match *slot {
+            0..=7 => Ok(self.regs[10 + *slot]),
+            _ => self.load_word(self.regs[2].wrapping_add(4 * (*slot as i32 - 8))),
+        };
+        *slot += 1;
+        value
+    }
+
+    fn single(&self, freg: usize) -> f32 {
+        f32::from_bits(self.fregs[freg] as u32)
+    }
+
+    fn set_single(&mut self, freg: usize, value: f32) {
+        self.fregs[freg] = value.to_bits() as u64;
+    }
+
+    fn check(&self, address: i32, width: usize) -> ExecResult<usize> {
+        let address = address as u32 as usize;
+        if address < DATA_BASE || address + width > MEMORY_SIZE || !address.is_multiple_of(width) {
+            return Err(fault(&format!("invalid memory access at address {:#x}", address)));
+        }
+        Ok(address)
+    }
+
+    fn access(&self, address: i32, width: usize) -> ExecResult<&[u8]> {
+        let address = self.check(address, width)?;
+        Ok(&self.program.memory[address..address + width])
+    }
+
+    fn access_mut(&mut self, address: i32, width: usize) -> ExecResult<&mut [u8]> {
+        let address = self.check(address, width)?;
+        if address >= DATA_BASE && address < self.program.data_end && (self.regs[2] as u32 as usize) < self.program.data_end {
+            return Err(fault("stack overflow"));
+        }
+        Ok(&mut self.program.memory[address..address + width])
+    }
+
+    fn load_word(&self, address: i32) -> ExecResult<i32> {
+        Ok(i32::from_le_bytes(self.access(address, 4)?.try_into().unwrap()))
+    }
+
+    fn store_word(&mut self, address: i32, value: i32) -> ExecResult<()> {
+        self.access_mut(address, 4)?.copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn read_c_string(&self, address: i32) -> ExecResult<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut address = address;
+        loop {
+            let byte = self.access(address, 1)?[0];
+            if byte == 0 {
+                return Ok(bytes);
+            }
+            bytes.push(byte);
+            address = address.wrapping_add(1);
+        }
+    }
+}
+
+fn alu(op: AluOp, a: i32, b: i32) -> i32 {
+    match op {
+        AluOp::Add => a.wrapping_add(b),
+        AluOp::Sub => a.wrapping_sub(b),
+        AluOp::Mul => a.wrapping_mul(b),
+        AluOp::Mulh => ((a as i64 * b as i64) >> 32) as i32,
+        AluOp::Mulhu => ((a as u32 as u64 * b as u32 as u64) >> 32) as i32,
+        AluOp::Div => if b == 0 { -1 } else { a.wrapping_div(b) },
+        AluOp::Divu => if b == 0 { -1 } else { (a as u32 / b as u32) as i32 },
+        AluOp::Rem => if b == 0 { a } else { a.wrapping_rem(b) },
+        AluOp::Remu => if b == 0 { a } else { (a as u32 % b as u32) as i32 },
+        AluOp::And => a & b,
+        AluOp::Or => a | b,
+        AluOp::Xor => a ^ b,
+        AluOp::Sll => a.wrapping_shl(b as u32 & 31),
+        AluOp::Srl => ((a as u32) >> (b as u32 & 31)) as i32,
+        AluOp::Sra => a >> (b as u32 & 31),
+        AluOp::Slt => (a < b) as i32,
+        AluOp::Sltu => ((a as u32) < (b as u32)) as i32,
+    }
+}
+
+fn fault(msg: &str) -> ExecError {
+    ExecError::Fault(msg.to_string())
+}
+
+// endregion 执行
@@ -0,0 +1,110 @@
+//! 尾调用: `call` 之后紧跟着 `ret` 它的结果(或 void 调用之后紧跟 `ret`)
+//!
+//! 自递归的尾调用在 IR 上改写为循环: 入口块只留下 alloc 和把形参存入栈槽的 store,
+//! 其余指令移入新的 %tail_loop 块, 尾调用改为把实参存入形参的栈槽后跳回 %tail_loop.
+//! 其他尾调用由 codegen 拆除栈帧后用 tail 跳转到被调函数(见 lab9::codegen)
+//!
+//! 局部变量的地址逃逸(见 lab9::escape)时, 被调函数可能经由指针访问调用者的栈帧, 两种改写都不进行
+//!
+//! ```text
+//! %7 = call @gcd(%3, %6)        store %3, @a_1
+//! ret %7                   =>   store %6, @b_1
+//!                               jump %tail_loop
+//! ```
+use koopa::ir::builder_traits::*;
+use koopa::ir::{Function, FunctionData, Program, Value, ValueKind};
+use crate::lab9::escape::escaping_allocs;
+
+/// 函数中的尾调用: (call, ret), 有局部变量的地址逃逸时为空
+pub fn tail_calls(func_data: &FunctionData) -> Vec<(Value, Value)> {
+    let mut result = Vec::new();
+    if !escaping_allocs(func_data).is_empty() {
+        return result;
+    }
+    for (_, bb_node) in func_data.layout().bbs() {
+        let insts: Vec<Value> = bb_node.insts().keys().copied().collect();
+        let [.., call, ret] = insts[..] else { continue };
+        let call_data = func_data.dfg().value(call);
+        let ValueKind::Return(ret_data) = func_data.dfg().value(ret).kind() else { continue };
+        let returns_result = match ret_data.value() {
+            Some(value) => value == call && call_data.used_by().len() == 1,
+            None => call_data.ty().is_unit(),
+        };
+        if returns_result && matches!(call_data.kind(), ValueKind::Call(_)) {
+            result.push((call, ret));
+        }
+    }
+    result
+}
+
+/// 把所有函数的自递归尾调用改写为循环
+pub fn eliminate_tail_recursion(program: &mut Program) {
+    let funcs: Vec<Function> = program.func_layout().to_vec();
+    for func in funcs {
+        eliminate_in_function(func, program.func_mut(func));
+    }
+}
+
+fn eliminate_in_function(func: Function, func_data: &mut FunctionData) {
+    let Some(entry) = func_data.layout().entry_bb() else { return };
+    let self_calls: Vec<(Value, Value)> = tail_calls(func_data).into_iter()
+        .filter(|&(call, _)| matches!(func_data.dfg().value(call).kind(), ValueKind::Call(c) if c.callee() == func))
+        .collect();
+    if self_calls.is_empty() {
+        return;
+    }
+    let Some(slots) = param_slots(func_data) else { return };
+
+    // 入口块中 alloc 与形参的 store 之外的指令移入循环块
+    let entry_insts: Vec<Value> = func_data.layout().bbs().node(&entry).unwrap().insts().keys().copied().collect();
+    let moved: Vec<Value> = entry_insts.into_iter()
+        .filter(|&inst| match func_data.dfg().value(inst).kind() {
+            ValueKind::Alloc(_) => false,
+            ValueKind::Store(store) => !func_data.params().contains(&store.value()),
+            _ => true,
+        })
+        .collect();
+    let tail_loop = func_data.dfg_mut().new_bb().basic_block(Some("%tail_loop".into()));
+    func_data.layout_mut().bbs_mut().cursor_mut(entry).insert_key_after(tail_loop).unwrap();
+    for inst in moved {
+        func_data.layout_mut().bb_mut(entry).insts_mut().remove(&inst);
+        func_data.layout_mut().bb_mut(tail_loop).insts_mut().push_key_back(inst).unwrap();
+    }
+    let jump = func_data.dfg_mut().new_value().jump(tail_loop);
+    func_data.layout_mut().bb_mut(entry).insts_mut().push_key_back(jump).unwrap();
+
+    for (call, ret) in self_calls {
+        let bb = func_data.layout().parent_bb(call).unwrap();
+        let ValueKind::Call(call_data) = func_data.dfg().value(call).kind() else { unreachable!() };
+        let args = call_data.args().to_vec();
+        let mut insts: Vec<Value> = args.into_iter().zip(&slots)
+            .map(|(arg, &slot)| func_data.dfg_mut().new_value().store(arg, slot))
+            .collect();
+        insts.push(func_data.dfg_mut().new_value().jump(tail_loop));
+        let bb_insts = func_data.layout_mut().bb_mut(bb).insts_mut();
+        let mut cursor = bb_insts.cursor_mut(call);
+        for inst in insts {
+            cursor.insert_key_before(inst).unwrap();
+        }
+        bb_insts.remove(&ret);
+        bb_insts.remove(&call);
+        func_data.dfg_mut().remove_value(ret);
+        func_data.dfg_mut().remove_value(call);
+    }
+}
+
+/// 各形参的栈槽: 每个形参只在入口块中被存入一个入口块的 alloc 一次(IRGen 的形式), 否则为 None
+fn param_slots(func_data: &FunctionData) -> Option<Vec<Value>> {
+    let entry = func_data.layout().entry_bb()?;
+    let entry_insts = func_data.layout().bbs().node(&entry)?.insts();
+    func_data.params().iter()
+        .map(|&param| {
+            let users = func_data.dfg().value(param).used_by();
+            let &store = users.iter().next().filter(|_| users.len() == 1)?;
+            let ValueKind::Store(store_data) = func_data.dfg().value(store).kind() else { return None };
+            let slot = store_data.dest();
+            let in_entry = entry_insts.contains_key(&store) && entry_insts.contains_key(&slot);
+            (in_entry && matches!(func_data.dfg().value(slot).kind(), ValueKind::Alloc(_))).then_some(slot)
+        })
+        .collect()
+}
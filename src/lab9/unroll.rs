@@ -0,0 +1,354 @@
+//! 循环展开: 识别 IRGen 生成的计数循环 `while (i < N) { ...; i = i + 1; ... }`
+//!
+//! 要求循环是最内层循环, 只有一条回边(没有 continue), 循环头只读出 i 与 N 并比较;
+//! i 与 N 是地址不逃逸的局部变量, 循环中 N 不被修改, i 只在每轮必经的块中被加一.
+//! 变量都在内存中, 复制循环体的基本块即可得到多轮迭代, 复制之间不再检查循环条件.
+//!
+//! - 初值与 N 都是常数且展开后足够小时完全展开, 删去原循环
+//! - 否则按 factor 展开: 新的循环头检查剩余轮数不少于 factor 时执行 factor 份循环体,
+//!   不足时转入原循环处理剩余的迭代
+//!
+//! 循环体中的 break 与 return 仍然跳出循环, 不影响正确性
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, BinaryOp, FunctionData, TypeKind, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+use crate::lab9::cfg::Cfg;
+use crate::lab9::escape::escaping_allocs;
+use crate::lab9::irgen::debug::SourceMap;
+use crate::lab9::irgen::IrModule;
+
+/// 默认的展开倍数(-unroll)
+pub const DEFAULT_UNROLL_FACTOR: usize = 4;
+/// 展开后循环体指令总数的上限
+const UNROLL_BUDGET: usize = 256;
+
+/// 循环的上界 N
+#[derive(Debug, Clone, Copy)]
+enum Bound {
+    Const(i32),
+    Var(Value), // 局部变量的 alloc
+}
+
+/// 一个可以展开的计数循环
+struct CountedLoop {
+    header: BasicBlock,
+    preheader: BasicBlock,   // 循环外唯一跳到循环头的块, 以 jump 结尾
+    entry: BasicBlock,       // 循环头条件为真时的目标
+    exit: BasicBlock,        // 循环头条件为假时的目标
+    body: Vec<BasicBlock>,   // 除循环头外的块, 按布局顺序
+    counter: Value,          // i 的 alloc
+    bound: Bound,
+    trip_count: Option<i64>, // 初值与上界都是常数时的迭代次数
+    size: usize,             // 循环体的指令数
+}
+
+/// 展开所有函数中的计数循环, factor 小于2时不展开
+pub fn unroll_loops(module: &mut IrModule, factor: usize) {
+    if factor < 2 {
+        return;
+    }
+    let funcs: Vec<_> = module.program.func_layout().to_vec();
+    for func in funcs {
+        let func_data = module.program.func_mut(func);
+        let mut done = HashSet::new(); // 已展开或不能展开的循环头
+        while let Some(counted) = next_counted_loop(func_data, &mut done) {
+            let full = counted.trip_count.filter(|&trips| trips as usize * counted.size <= UNROLL_BUDGET);
+            if let Some(trips) = full {
+                unroll_fully(func_data, module.source_map.as_mut(), &counted, trips as usize);
+            } else if counted.size * factor <= UNROLL_BUDGET && counted.trip_count.is_none_or(|trips| trips >= factor as i64) {
+                let unrolled_header = unroll_partially(func_data, module.source_map.as_mut(), &counted, factor);
+                done.insert(unrolled_header);
+            }
+            done.insert(counted.header);
+        }
+    }
+}
+
+/// 找出一个尚未处理过的最内层计数循环, 不能展开的循环头记入 done
+fn next_counted_loop(func_data: &FunctionData, done: &mut HashSet<BasicBlock>) -> Option<CountedLoop> {
+    let cfg = Cfg::new(func_data);
+    let loops = cfg.natural_loops();
+    let escaping = escaping_allocs(func_data);
+    for (header, body) in &loops {
+        let innermost = loops.iter().all(|(other, _)| other == header || !body[*other]);
+        if !innermost || done.contains(&cfg.blocks[*header]) {
+            continue;
+        }
+        match analyze(func_data, &cfg, *header, body, &escaping) {
+            Some(counted) => return Some(counted),
+            None => {
+                done.insert(cfg.blocks[*header]);
+            }
+        }
+    }
+    None
+}
+
+fn analyze(func_data: &FunctionData, cfg: &Cfg, header: usize, in_loop: &[bool], escaping: &HashSet<Value>) -> Option<CountedLoop> {
+    let dfg = func_data.dfg();
+    let insts = |bb: BasicBlock| -> Vec<Value> { func_data.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect() };
+    let local_alloc = |value: Value| {
+        dfg.values().get(&value).is_some_and(|data| matches!(data.kind(), ValueKind::Alloc(_))) && !escaping.contains(&value)
+    };
+
+    // 唯一的回边与唯一的循环外前驱
+    let (latches, outside): (Vec<usize>, Vec<usize>) = cfg.preds[header].iter().partition(|&&pred| in_loop[pred]);
+    let (&[latch], &[preheader]) = (&latches[..], &outside[..]) else { return None };
+    let ends_with_jump_to = |bb: usize, target: usize| {
+        let last = *insts(cfg.blocks[bb]).last()?;
+        match dfg.value(last).kind() {
+            ValueKind::Jump(jump) if jump.target() == cfg.blocks[target] && jump.args().is_empty() => Some(()),
+            _ => None,
+        }
+    };
+    ends_with_jump_to(latch, header)?;
+    ends_with_jump_to(preheader, header)?;
+
+    // 循环头: [%a = load @i] [%b = load @n] %c = lt %a, N; br %c, entry, exit
+    let header_insts = insts(cfg.blocks[header]);
+    let (&br, rest) = header_insts.split_last()?;
+    let (&cmp, loads) = rest.split_last()?;
+    let ValueKind::Branch(branch) = dfg.value(br).kind() else { return None };
+    let ValueKind::Binary(binary) = dfg.value(cmp).kind() else { return None };
+    if branch.cond() != cmp || binary.op() != BinaryOp::Lt || !branch.true_args().is_empty() || !branch.false_args().is_empty() {
+        return None;
+    }
+    let load_src = |value: Value| match dfg.value(value).kind() {
+        ValueKind::Load(load) if loads.contains(&value) && local_alloc(load.src()) => Some(load.src()),
+        _ => None,
+    };
+    let counter = load_src(binary.lhs())?;
+    let bound = match dfg.values().get(&binary.rhs()).map(|data| data.kind()) {
+        Some(ValueKind::Integer(int)) => Bound::Const(int.value()),
+        _ => Bound::Var(load_src(binary.rhs())?),
+    };
+    let loads_expected = if matches!(bound, Bound::Var(_)) { 2 } else { 1 };
+    let header_local = header_insts.iter().all(|&inst| dfg.value(inst).used_by().iter().all(|user| header_insts.contains(user)));
+    if loads.len() != loads_expected || !header_local {
+        return None;
+    }
+    let index = |bb: BasicBlock| cfg.blocks.iter().position(|&b| b == bb).unwrap();
+    let (entry, exit) = (branch.true_bb(), branch.false_bb());
+    if !in_loop[index(entry)] || in_loop[index(exit)] {
+        return None;
+    }
+
+    // 循环体: 指令都可以复制, 定义的值只在循环体内使用, i 只被每轮必经的块加一, N 不被修改
+    let body: Vec<BasicBlock> = (0..cfg.blocks.len()).filter(|&bb| in_loop[bb] && bb != header).map(|bb| cfg.blocks[bb]).collect();
+    let mut size = 0;
+    let mut increments = 0;
+    for &bb in &body {
+        if !dfg.bb(bb).params().is_empty() {
+            return None;
+        }
+        for inst in insts(bb) {
+            size += 1;
+            let data = dfg.value(inst);
+            let users_in_body = data.used_by().iter()
+                .all(|&user| func_data.layout().parent_bb(user).is_some_and(|parent| body.contains(&parent)));
+            if !cloneable(func_data, inst) || !users_in_body {
+                return None;
+            }
+            let ValueKind::Store(store) = data.kind() else { continue };
+            if matches!(bound, Bound::Var(n) if store.dest() == n) {
+                return None;
+            }
+            if store.dest() == counter {
+                if !is_increment(func_data, store.value(), counter, bb) || !cfg.dominates(index(bb), latch) {
+                    return None;
+                }
+                increments += 1;
+            }
+        }
+    }
+    if increments != 1 {
+        return None;
+    }
+
+    // 进入循环前 i 的初值: 前置块中最后一次存入 i 的常数
+    let trip_count = match bound {
+        Bound::Const(n) => insts(cfg.blocks[preheader]).into_iter().rev()
+            .find_map(|inst| match dfg.value(inst).kind() {
+                ValueKind::Store(store) if store.dest() == counter => Some(store.value()),
+                _ => None,
+            })
+            .and_then(|init| match dfg.values().get(&init)?.kind() {
+                ValueKind::Integer(int) => Some((n as i64 - int.value() as i64).max(0)),
+                _ => None,
+            }),
+        Bound::Var(_) => None,
+    };
+
+    Some(CountedLoop {
+        header: cfg.blocks[header],
+        preheader: cfg.blocks[preheader],
+        entry,
+        exit,
+        body,
+        counter,
+        bound,
+        trip_count,
+        size,
+    })
+}
+
+/// 循环体中可以复制的指令(跳转不带参数, 不复制数组的 alloc 以免栈帧成倍增长)
+fn cloneable(func_data: &FunctionData, inst: Value) -> bool {
+    let data = func_data.dfg().value(inst);
+    match data.kind() {
+        ValueKind::Alloc(_) => matches!(data.ty().kind(), TypeKind::Pointer(base) if !matches!(base.kind(), TypeKind::Array(..))),
+        ValueKind::Branch(branch) => branch.true_args().is_empty() && branch.false_args().is_empty(),
+        ValueKind::Jump(jump) => jump.args().is_empty(),
+        ValueKind::Load(_) | ValueKind::Store(_) | ValueKind::GetPtr(_) | ValueKind::GetElemPtr(_)
+        | ValueKind::Binary(_) | ValueKind::Call(_) | ValueKind::Return(_) => true,
+        _ => false,
+    }
+}
+
+/// value 是否为同一块中的 `add (load counter), 1`
+fn is_increment(func_data: &FunctionData, value: Value, counter: Value, bb: BasicBlock) -> bool {
+    let dfg = func_data.dfg();
+    let in_bb = |value: Value| func_data.layout().parent_bb(value) == Some(bb);
+    let is_counter = |value: Value| in_bb(value) && matches!(dfg.value(value).kind(), ValueKind::Load(load) if load.src() == counter);
+    let is_one = |value: Value| matches!(dfg.values().get(&value).map(|data| data.kind()), Some(ValueKind::Integer(int)) if int.value() == 1);
+    in_bb(value) && match dfg.value(value).kind() {
+        ValueKind::Binary(binary) if binary.op() == BinaryOp::Add => {
+            (is_counter(binary.lhs()) && is_one(binary.rhs())) || (is_one(binary.lhs()) && is_counter(binary.rhs()))
+        }
+        _ => false,
+    }
+}
+
+/// 复制一轮循环体, 回边改为跳到 next, 新块放在布局中 before 之前; 返回(复制的入口块, 布局中的第一个复制块)
+fn clone_iteration(
+    func_data: &mut FunctionData,
+    mut source_map: Option<&mut SourceMap>,
+    counted: &CountedLoop,
+    next: BasicBlock,
+    before: BasicBlock,
+) -> (BasicBlock, BasicBlock) {
+    let mut bb_map = HashMap::new();
+    for &bb in &counted.body {
+        let name = func_data.dfg().bb(bb).name().clone();
+        let copy = func_data.dfg_mut().new_bb().basic_block(name);
+        func_data.layout_mut().bbs_mut().cursor_mut(before).insert_key_before(copy).unwrap();
+        bb_map.insert(bb, copy);
+    }
+    let map_bb = |bb: BasicBlock| if bb == counted.header { next } else { bb_map.get(&bb).copied().unwrap_or(bb) };
+
+    // 按逆后序复制, 保证值的定义先于使用被复制
+    let cfg = Cfg::new(func_data);
+    let order: Vec<BasicBlock> = cfg.reverse_postorder().into_iter()
+        .map(|bb| cfg.blocks[bb])
+        .filter(|bb| counted.body.contains(bb))
+        .collect();
+    let mut value_map: HashMap<Value, Value> = HashMap::new();
+    for bb in order {
+        let insts: Vec<Value> = func_data.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect();
+        for inst in insts {
+            let data = func_data.dfg().value(inst).clone();
+            let map = |value: Value| value_map.get(&value).copied().unwrap_or(value);
+            let builder = func_data.dfg_mut().new_value();
+            let copy = match data.kind() {
+                ValueKind::Alloc(_) => match data.ty().kind() {
+                    TypeKind::Pointer(base) => builder.alloc(base.clone()),
+                    _ => unreachable!("alloc must have a pointer type"),
+                },
+                ValueKind::Load(load) => builder.load(map(load.src())),
+                ValueKind::Store(store) => builder.store(map(store.value()), map(store.dest())),
+                ValueKind::GetPtr(get_ptr) => builder.get_ptr(map(get_ptr.src()), map(get_ptr.index())),
+                ValueKind::GetElemPtr(gep) => builder.get_elem_ptr(map(gep.src()), map(gep.index())),
+                ValueKind::Binary(binary) => builder.binary(binary.op(), map(binary.lhs()), map(binary.rhs())),
+                ValueKind::Branch(branch) => builder.branch(map(branch.cond()), map_bb(branch.true_bb()), map_bb(branch.false_bb())),
+                ValueKind::Jump(jump) => builder.jump(map_bb(jump.target())),
+                ValueKind::Call(call) => builder.call(call.callee(), call.args().iter().map(|&arg| map(arg)).collect()),
+                ValueKind::Return(ret) => builder.ret(ret.value().map(map)),
+                kind => unreachable!("not cloneable: {:?}", kind),
+            };
+            if data.name().is_some() {
+                func_data.dfg_mut().set_value_name(copy, data.name().clone());
+            }
+            if let Some(source_map) = source_map.as_deref_mut() {
+                if let Some(&position) = source_map.instructions.get(&inst) {
+                    source_map.instructions.insert(copy, position);
+                }
+            }
+            func_data.layout_mut().bb_mut(bb_map[&bb]).insts_mut().push_key_back(copy).unwrap();
+            value_map.insert(inst, copy);
+        }
+    }
+    (bb_map[&counted.entry], bb_map[&counted.body[0]])
+}
+
+/// 把前置块末尾跳到循环头的 jump 改为跳到 target
+fn redirect_preheader(func_data: &mut FunctionData, counted: &CountedLoop, target: BasicBlock) {
+    let jump = *func_data.layout().bbs().node(&counted.preheader).unwrap().insts().back_key().unwrap();
+    func_data.dfg_mut().replace_value_with(jump).jump(target);
+}
+
+/// 完全展开: 依次执行 trips 份循环体后到达出口, 删去原循环
+fn unroll_fully(func_data: &mut FunctionData, mut source_map: Option<&mut SourceMap>, counted: &CountedLoop, trips: usize) {
+    let (mut next, mut before) = (counted.exit, counted.header);
+    for _ in 0..trips {
+        (next, before) = clone_iteration(func_data, source_map.as_deref_mut(), counted, next, before);
+    }
+    redirect_preheader(func_data, counted, next);
+
+    // 原循环的块不再可达: 先从布局中移除, 再按使用关系从后往前删除其中的值
+    let mut removed = Vec::new();
+    for &bb in std::iter::once(&counted.header).chain(&counted.body) {
+        let insts: Vec<Value> = func_data.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect();
+        for &inst in &insts {
+            func_data.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+        }
+        func_data.layout_mut().bbs_mut().remove(&bb);
+        removed.extend(insts);
+    }
+    while !removed.is_empty() {
+        let (unused, used): (Vec<Value>, Vec<Value>) = removed.into_iter()
+            .partition(|&inst| func_data.dfg().value(inst).used_by().is_empty());
+        for inst in unused {
+            func_data.dfg_mut().remove_value(inst);
+        }
+        removed = used;
+    }
+    for &bb in std::iter::once(&counted.header).chain(&counted.body) {
+        func_data.dfg_mut().remove_bb(bb);
+    }
+}
+
+/// 按 factor 展开: 新的循环头在剩余轮数(N - i)不少于 factor 时执行 factor 份循环体, 否则转入原循环. 返回新的循环头
+fn unroll_partially(func_data: &mut FunctionData, mut source_map: Option<&mut SourceMap>, counted: &CountedLoop, factor: usize) -> BasicBlock {
+    let name = func_data.dfg().bb(counted.header).name().as_ref().map(|name| format!("{}_unrolled", name));
+    let unrolled_header = func_data.dfg_mut().new_bb().basic_block(name);
+    func_data.layout_mut().bbs_mut().cursor_mut(counted.header).insert_key_before(unrolled_header).unwrap();
+
+    let (mut next, mut before) = (unrolled_header, counted.header);
+    for _ in 0..factor {
+        (next, before) = clone_iteration(func_data, source_map.as_deref_mut(), counted, next, before);
+    }
+
+    // i < N 且 N - i >= factor; 差值溢出为负数时交给原循环, 结果仍然正确
+    let dfg = func_data.dfg_mut();
+    let counter = dfg.new_value().load(counted.counter);
+    let (bound, bound_load) = match counted.bound {
+        Bound::Const(n) => (dfg.new_value().integer(n), None),
+        Bound::Var(alloc) => {
+            let load = dfg.new_value().load(alloc);
+            (load, Some(load))
+        }
+    };
+    let in_range = dfg.new_value().binary(BinaryOp::Lt, counter, bound);
+    let remaining = dfg.new_value().binary(BinaryOp::Sub, bound, counter);
+    let factor = dfg.new_value().integer(factor as i32);
+    let enough = dfg.new_value().binary(BinaryOp::Ge, remaining, factor);
+    let cond = dfg.new_value().binary(BinaryOp::And, in_range, enough);
+    let branch = dfg.new_value().branch(cond, next, counted.header);
+    let insts = func_data.layout_mut().bb_mut(unrolled_header).insts_mut();
+    for inst in [Some(counter), bound_load, Some(in_range), Some(remaining), Some(enough), Some(cond), Some(branch)].into_iter().flatten() {
+        insts.push_key_back(inst).unwrap();
+    }
+
+    redirect_preheader(func_data, counted, unrolled_header);
+    unrolled_header
+}
@@ -0,0 +1,333 @@
+//! Koopa IR 校验: 在IR生成之后、每个优化遍之后运行, 尽早发现畸形的IR
+//!
+//! 检查内容:
+//! - 每个基本块以且仅以一条终结指令(ret/jump/br)结尾
+//! - 跳转目标在函数布局中, 实参个数与类型和目标块的形参一致
+//! - 指令操作数的类型(store/load/getptr/getelemptr/二元运算/br条件/call/ret)
+//! - SSA支配关系: 操作数属于本函数且其定义支配使用处
+//!
+//! 报告中的函数/块/值名与 `-koopa` 输出一致
+use crate::lab9::cfg::Cfg;
+use koopa::back::NameManager;
+use koopa::ir::{BasicBlock, FunctionData, Program, Type, TypeKind, Value, ValueKind};
+use std::collections::HashMap;
+use std::fmt;
+
+/// 一处校验错误
+#[derive(Debug, Clone)]
+pub struct VerifyError {
+    pub function: String,
+    pub block: Option<String>,
+    pub value: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "in function {}", self.function)?;
+        if let Some(block) = &self.block {
+            write!(f, ", block {}", block)?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, ", value {}", value)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// 校验整个程序, 返回发现的所有错误
+pub fn verify_program(program: &Program) -> Result<(), Vec<VerifyError>> {
+    let mut names = NameManager::new();
+    // 与Koopa文本输出一致, 先为全局变量命名
+    for inst in program.inst_layout() {
+        names.value_name(&program.borrow_value(*inst));
+    }
+
+    let mut errors = Vec::new();
+    for &func in program.func_layout() {
+        let func_data = program.func(func);
+        if func_data.layout().entry_bb().is_none() {
+            continue; // 函数声明
+        }
+        names.enter_func_scope();
+        let mut verifier = FunctionVerifier::new(program, func_data, &mut names);
+        verifier.verify();
+        errors.append(&mut verifier.errors);
+        names.exit_func_scope();
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+struct FunctionVerifier<'a> {
+    program: &'a Program,
+    func_data: &'a FunctionData,
+    function: String,
+    bb_names: HashMap<BasicBlock, String>,
+    value_names: HashMap<Value, String>,
+    cfg: Cfg,
+    bb_index: HashMap<BasicBlock, usize>,
+    // 每条指令所在的块以及在块中的位置, 用于检查支配关系
+    inst_positions: HashMap<Value, (usize, usize)>,
+    errors: Vec<VerifyError>,
+}
+
+impl<'a> FunctionVerifier<'a> {
+    fn new(program: &'a Program, func_data: &'a FunctionData, names: &mut NameManager) -> Self {
+        let function = names.func_name(func_data).to_string();
+        let dfg = func_data.dfg();
+
+        // 按Koopa文本输出的顺序命名: 形参, 然后逐块的块名、块参数、指令
+        let mut bb_names = HashMap::new();
+        let mut value_names = HashMap::new();
+        for &param in func_data.params() {
+            value_names.insert(param, names.value_name(dfg.value(param)).to_string());
+        }
+        let mut inst_positions = HashMap::new();
+        for (i, (&bb, node)) in func_data.layout().bbs().iter().enumerate() {
+            let bb_data = dfg.bb(bb);
+            bb_names.insert(bb, names.bb_name(bb_data).to_string());
+            for &param in bb_data.params() {
+                value_names.insert(param, names.value_name(dfg.value(param)).to_string());
+            }
+            for (j, &inst) in node.insts().keys().enumerate() {
+                let data = dfg.value(inst);
+                if !data.ty().is_unit() {
+                    value_names.insert(inst, names.value_name(data).to_string());
+                }
+                inst_positions.insert(inst, (i, j));
+            }
+        }
+
+        let cfg = Cfg::new(func_data);
+        let bb_index = cfg.blocks.iter().enumerate().map(|(i, &bb)| (bb, i)).collect();
+        Self {
+            program,
+            func_data,
+            function,
+            bb_names,
+            value_names,
+            cfg,
+            bb_index,
+            inst_positions,
+            errors: Vec::new(),
+        }
+    }
+
+    fn verify(&mut self) {
+        let layout = self.func_data.layout();
+        for (i, (&bb, node)) in layout.bbs().iter().enumerate() {
+            let insts: Vec<Value> = node.insts().keys().copied().collect();
+            if insts.is_empty() {
+                self.error(bb, None, "basic block is empty".to_string());
+                continue;
+            }
+            for (j, &inst) in insts.iter().enumerate() {
+                let is_last = j + 1 == insts.len();
+                let is_terminator = matches!(self.func_data.dfg().value(inst).kind(),
+                    ValueKind::Return(_) | ValueKind::Jump(_) | ValueKind::Branch(_));
+                if is_terminator && !is_last {
+                    self.error(bb, Some(inst), "terminator is followed by other instructions".to_string());
+                } else if !is_terminator && is_last {
+                    self.error(bb, Some(inst), "basic block does not end with a terminator".to_string());
+                }
+                self.verify_operands(bb, i, j, inst);
+                self.verify_inst(bb, inst);
+            }
+        }
+    }
+
+    /// 操作数必须是常量、全局变量或本函数中支配使用处的值
+    fn verify_operands(&mut self, bb: BasicBlock, bb_pos: usize, inst_pos: usize, inst: Value) {
+        let dfg = self.func_data.dfg();
+        let operands: Vec<Value> = dfg.value(inst).kind().value_uses().collect();
+        for operand in operands {
+            if operand.is_global() {
+                continue;
+            }
+            let Some(data) = dfg.values().get(&operand) else {
+                self.error(bb, Some(inst), "uses a value from another function".to_string());
+                continue;
+            };
+            match data.kind() {
+                ValueKind::FuncArgRef(_) => {
+                    if !self.func_data.params().contains(&operand) {
+                        self.error(bb, Some(inst), "uses a parameter of another function".to_string());
+                    }
+                }
+                ValueKind::BlockArgRef(_) => {
+                    let owner = self.cfg.blocks.iter().position(|&b| dfg.bb(b).params().contains(&operand));
+                    match owner {
+                        Some(owner) if self.reachable(bb_pos) && !self.cfg.dominates(owner, bb_pos) => {
+                            self.error(bb, Some(inst), "uses a block argument that does not dominate it".to_string());
+                        }
+                        Some(_) => {}
+                        None => self.error(bb, Some(inst), "uses an argument of a block that is not in the layout".to_string()),
+                    }
+                }
+                _ if data.kind().is_const() => {}
+                _ => match self.inst_positions.get(&operand) {
+                    None => self.error(bb, Some(inst), "uses an instruction that is not in any basic block".to_string()),
+                    Some(&(def_bb, def_pos)) => {
+                        let dominates = if def_bb == bb_pos {
+                            def_pos < inst_pos
+                        } else {
+                            !self.reachable(bb_pos) || self.cfg.dominates(def_bb, bb_pos)
+                        };
+                        if !dominates {
+                            let name = self.value_name(operand);
+                            self.error(bb, Some(inst), format!("operand {} does not dominate this use", name));
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    fn verify_inst(&mut self, bb: BasicBlock, inst: Value) {
+        let data = self.func_data.dfg().value(inst);
+        match data.kind() {
+            ValueKind::Load(load) => {
+                if pointer_base(&self.ty(load.src())).is_none() {
+                    self.error(bb, Some(inst), format!("load from non-pointer type {}", self.ty(load.src())));
+                }
+            }
+            ValueKind::Store(store) => {
+                let value_ty = self.ty(store.value());
+                match pointer_base(&self.ty(store.dest())) {
+                    Some(base) if base == value_ty => {}
+                    Some(base) => self.error(bb, Some(inst), format!("store of {} into a pointer to {}", value_ty, base)),
+                    None => self.error(bb, Some(inst), format!("store to non-pointer type {}", self.ty(store.dest()))),
+                }
+            }
+            ValueKind::GetPtr(get_ptr) => {
+                if pointer_base(&self.ty(get_ptr.src())).is_none() {
+                    self.error(bb, Some(inst), format!("getptr on non-pointer type {}", self.ty(get_ptr.src())));
+                }
+                self.expect_i32(bb, inst, get_ptr.index(), "getptr index");
+            }
+            ValueKind::GetElemPtr(get_elem_ptr) => {
+                let is_array = pointer_base(&self.ty(get_elem_ptr.src()))
+                    .is_some_and(|base| matches!(base.kind(), TypeKind::Array(..)));
+                if !is_array {
+                    self.error(bb, Some(inst), format!("getelemptr on non-array pointer type {}", self.ty(get_elem_ptr.src())));
+                }
+                self.expect_i32(bb, inst, get_elem_ptr.index(), "getelemptr index");
+            }
+            ValueKind::Binary(binary) => {
+                self.expect_i32(bb, inst, binary.lhs(), "binary operand");
+                self.expect_i32(bb, inst, binary.rhs(), "binary operand");
+            }
+            ValueKind::Branch(branch) => {
+                self.expect_i32(bb, inst, branch.cond(), "branch condition");
+                self.verify_target(bb, inst, branch.true_bb(), branch.true_args());
+                self.verify_target(bb, inst, branch.false_bb(), branch.false_args());
+            }
+            ValueKind::Jump(jump) => self.verify_target(bb, inst, jump.target(), jump.args()),
+            ValueKind::Call(call) => {
+                let callee = self.program.func(call.callee());
+                let TypeKind::Function(params, _) = callee.ty().kind() else {
+                    panic!("Function should have function type");
+                };
+                if params.len() != call.args().len() {
+                    self.error(bb, Some(inst), format!("call to {} passes {} arguments, expected {}", callee.name(), call.args().len(), params.len()));
+                    return;
+                }
+                for (k, (&arg, param_ty)) in call.args().iter().zip(params).enumerate() {
+                    let arg_ty = self.ty(arg);
+                    if &arg_ty != param_ty {
+                        self.error(bb, Some(inst), format!("argument {} of call to {} has type {}, expected {}", k, callee.name(), arg_ty, param_ty));
+                    }
+                }
+            }
+            ValueKind::Return(ret) => {
+                let TypeKind::Function(_, ret_ty) = self.func_data.ty().kind() else {
+                    panic!("Function should have function type");
+                };
+                match ret.value() {
+                    Some(value) if &self.ty(value) != ret_ty => {
+                        self.error(bb, Some(inst), format!("returns {}, expected {}", self.ty(value), ret_ty));
+                    }
+                    None if !ret_ty.is_unit() => self.error(bb, Some(inst), format!("returns nothing, expected {}", ret_ty)),
+                    _ => {}
+                }
+            }
+            ValueKind::Alloc(_) => {}
+            _ => self.error(bb, Some(inst), "is not a local instruction".to_string()),
+        }
+    }
+
+    /// 跳转目标在函数布局中, 实参与块形参的个数和类型一致
+    fn verify_target(&mut self, bb: BasicBlock, inst: Value, target: BasicBlock, args: &[Value]) {
+        if !self.bb_index.contains_key(&target) {
+            self.error(bb, Some(inst), "jumps to a basic block that is not in the layout".to_string());
+            return;
+        }
+        let dfg = self.func_data.dfg();
+        let params = dfg.bb(target).params();
+        let target_name = self.bb_names[&target].clone();
+        if params.len() != args.len() {
+            self.error(bb, Some(inst), format!("passes {} arguments to {}, which takes {}", args.len(), target_name, params.len()));
+            return;
+        }
+        for (&arg, &param) in args.iter().zip(params) {
+            let (arg_ty, param_ty) = (self.ty(arg), dfg.value(param).ty().clone());
+            if arg_ty != param_ty {
+                self.error(bb, Some(inst), format!("passes {} to a parameter of {} with type {}", arg_ty, target_name, param_ty));
+            }
+        }
+    }
+
+    fn expect_i32(&mut self, bb: BasicBlock, inst: Value, value: Value, what: &str) {
+        let ty = self.ty(value);
+        if !ty.is_i32() {
+            self.error(bb, Some(inst), format!("{} has type {}, expected i32", what, ty));
+        }
+    }
+
+    fn reachable(&self, bb_pos: usize) -> bool {
+        bb_pos == 0 || self.cfg.idom[bb_pos].is_some()
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        if value.is_global() {
+            self.program.borrow_value(value).ty().clone()
+        } else {
+            match self.func_data.dfg().values().get(&value) {
+                Some(data) => data.ty().clone(),
+                None => Type::get_unit(), // 其他函数的值, 已作为操作数错误报告
+            }
+        }
+    }
+
+    fn value_name(&self, value: Value) -> String {
+        self.value_names.get(&value).cloned().unwrap_or_else(|| format!("{:?}", value))
+    }
+
+    fn error(&mut self, bb: BasicBlock, inst: Option<Value>, message: String) {
+        let value = inst.map(|inst| match self.value_names.get(&inst) {
+            Some(name) => name.clone(),
+            None => {
+                // 无结果的指令(store/jump等)用其在块中的位置标识
+                let (_, pos) = self.inst_positions.get(&inst).copied().unwrap_or_default();
+                format!("#{}", pos)
+            }
+        });
+        self.errors.push(VerifyError {
+            function: self.function.clone(),
+            block: self.bb_names.get(&bb).cloned(),
+            value,
+            message,
+        });
+    }
+}
+
+fn pointer_base(ty: &Type) -> Option<Type> {
+    match ty.kind() {
+        TypeKind::Pointer(base) => Some(base.clone()),
+        _ => None,
+    }
+}
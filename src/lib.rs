@@ -1,3 +1,5 @@
+use lalrpop_util::lalrpop_mod;
+
 pub mod ast;
 pub mod lab0;
 pub mod lab1;
@@ -9,4 +11,7 @@ pub mod lab5plus;
 pub mod lab6;
 pub mod lab7;
 pub mod lab8;
-pub mod lab9;
\ No newline at end of file
+pub mod lab9;
+
+// 引用 lalrpop 生成的解析器, 放在库中以便测试等其他目标直接解析SysY源码
+lalrpop_mod!(pub sysy);
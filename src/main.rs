@@ -1,16 +1,24 @@
-use koopa::ir::{Program, Type};
-use lalrpop_util::lalrpop_mod;
-use pku_compiler::{lab9};
+use koopa::ir::Type;
+use lalrpop_util::ParseError;
+use pku_compiler::lab9;
+use pku_compiler::sysy;
+use pku_compiler::lab9::irgen::error::WarningOptions;
+use pku_compiler::lab9::fuzz::Outcome;
+use pku_compiler::lab9::reduce::Predicate;
+use pku_compiler::lab9::irgen::IrModule;
+use pku_compiler::lab9::preprocess::{self, LineMap, Preprocessor};
 use std::env::args;
-use std::fs::read_to_string;
 use std::io::Result;
-
-// 引用 lalrpop 生成的解析器
-// 因为我们刚刚创建了 sysy.lalrpop, 所以模块名是 sysy
-lalrpop_mod!(sysy);
+use std::path::{Path, PathBuf};
 
 const MODE_KOOPA: &str = "-koopa";
 const MODE_RISCV: &str = "-riscv";
+const MODE_OBJ: &str = "-obj"; // 直接输出RV32可重定位目标文件(.o)
+const MODE_AST: &str = "-ast"; // 输出AST的树形结构
+const MODE_FMT: &str = "-fmt"; // 输出格式化后的源码, 输入为单个不含注释与预处理指令的文件
+const MODE_CFG: &str = "-cfg"; // 输出各函数控制流图的DOT
+const MODE_FUZZ: &str = "-fuzz"; // 随机测试: -fuzz <起始种子> [程序个数] -o <报告目录>
+const MODE_REDUCE: &str = "-reduce"; // 缩小出错的程序: -reduce <输入> -o <输出> [-diverge | -panic <信息>]
 
 fn main() -> Result<()> {
     Type::set_ptr_size(4);
@@ -18,22 +26,319 @@ fn main() -> Result<()> {
     let mut args = args();
     args.next();
     let mode = args.next().unwrap();
-    let input = args.next().unwrap();
-    args.next();
+
+    // 输入文件可以有多个, 它们作为各自独立的翻译单元一起生成到同一个输出文件中
+    let mut inputs = Vec::new();
+    for arg in args.by_ref() {
+        if arg == "-o" {
+            break;
+        }
+        inputs.push(arg);
+    }
     let output = args.next().unwrap();
 
-    // 读取输入文件
-    let input = read_to_string(input)?;
+    // 其余选项: -I <dir> 添加头文件搜索路径, -W<name>/-Wno-<name>/-Wall/-Werror/-w 控制警告,
+    // -dom 在控制流图中叠加支配树, -from-koopa 输入为Koopa IR文本, 跳过前端只运行后端, -g 在汇编中生成调试信息,
+    // -profile-gen 插桩统计各基本块的执行次数, -profile-use <文件> 读入插桩程序输出的计数, -unroll <倍数> 循环展开的倍数(1为不展开, 不指定时只在生成汇编/目标文件时按默认倍数展开),
+    // -diverge/-panic <信息> 指定 -reduce 保留的错误(默认为输入程序本身出现的错误)
+    let mut include_paths = Vec::new();
+    let mut warning_options = WarningOptions::default();
+    let mut show_dominators = false;
+    let mut from_koopa = false;
+    let mut debug_info = false;
+    let mut profile_gen = false;
+    let mut profile_use = None;
+    let mut unroll_factor = None;
+    let mut predicate = None;
+    while let Some(arg) = args.next() {
+        if arg == "-diverge" {
+            predicate = Some(Predicate::Diverged);
+            continue;
+        }
+        if arg == "-panic" {
+            predicate = Some(Predicate::Panic(args.next().expect("-panic requires a message")));
+            continue;
+        }
+        if arg == "-dom" {
+            show_dominators = true;
+            continue;
+        }
+        if arg == "-from-koopa" {
+            from_koopa = true;
+            continue;
+        }
+        if arg == "-g" {
+            debug_info = true;
+            continue;
+        }
+        if arg == "-profile-gen" {
+            profile_gen = true;
+            continue;
+        }
+        if arg == "-profile-use" {
+            profile_use = Some(args.next().expect("-profile-use requires a file"));
+            continue;
+        }
+        if arg == "-unroll" {
+            let factor = args.next().expect("-unroll requires a factor");
+            unroll_factor = Some(factor.parse().unwrap_or_else(|_| panic!("invalid unroll factor '{}'", factor)));
+            continue;
+        }
+        if arg.starts_with("-W") || arg == "-w" {
+            if let Err(err) = warning_options.parse(&arg) {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+            continue;
+        }
+        match arg.strip_prefix("-I") {
+            Some("") => include_paths.push(PathBuf::from(args.next().expect("-I requires a directory"))),
+            Some(dir) => include_paths.push(PathBuf::from(dir)),
+            None => panic!("invalid option '{}'", arg),
+        }
+    }
+
+    if debug_info && mode == MODE_OBJ {
+        eprintln!("warning: -g is ignored when emitting an object file");
+    }
+    // 不指定 -unroll 时输出的IR与控制流图保持IR生成的原样
+    let unroll_factor = unroll_factor.unwrap_or(match mode == MODE_RISCV || mode == MODE_OBJ {
+        true => lab9::unroll::DEFAULT_UNROLL_FACTOR,
+        false => 1,
+    });
+    if mode == MODE_FUZZ {
+        return run_fuzz(&inputs, &output);
+    }
+    if mode == MODE_REDUCE {
+        return run_reduce(&inputs, include_paths, predicate, &output);
+    }
+
+    if from_koopa {
+        let mut module = match read_koopa_ir(&inputs, &mode) {
+            Ok(module) => module,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        verify_ir(&module, "reading Koopa IR");
+        run_passes(&mut module, unroll_factor, profile_gen, profile_use.as_deref());
+        return output_ir_module(module, &[], &mode, &output, show_dominators);
+    }
+    if mode == MODE_FMT {
+        return run_format(&inputs, &output);
+    }
+
+    let mut units = Vec::new();
+    let mut line_maps = Vec::new(); // 各单元的行号映射, 用于定位IR生成阶段的警告
+    for input in &inputs {
+        // 读取输入文件并预处理
+        let source = match Preprocessor::new(include_paths.clone()).preprocess_file(Path::new(input)) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+
+        // 调用 lalrpop 生成的 parser 解析输入文件
+        match sysy::CompUnitParser::new().parse(&source.text) {
+            Ok(ast) => {
+                units.push(ast);
+                line_maps.push(source.line_map);
+            }
+            Err(err) => {
+                eprintln!("{}", describe_parse_error(&err, &source.line_map));
+                std::process::exit(1);
+            }
+        }
+    }
+    // 只输出AST, 多个输入依次输出
+    if mode == MODE_AST {
+        let text: String = units.iter().map(lab9::pretty::dump_ast).collect();
+        std::fs::write(&output, text)?;
+        return Ok(());
+    }
+
+    let ir_gen = match debug_info {
+        true => lab9::irgen::IRGen::new().with_debug_info(),
+        false => lab9::irgen::IRGen::new(),
+    };
+    let mut koopa_ir_in_memory = match ir_gen.generate_program(units) {
+        Ok(module) => module,
+        Err(errors) => {
+            for err in &errors {
+                eprintln!("{}: error: {}", line_maps[err.unit].locate(err.pos), err);
+                if let Some(((unit, pos), note)) = err.note() {
+                    eprintln!("{}: note: {}", line_maps[unit].locate(pos), note);
+                }
+            }
+            std::process::exit(1);
+        }
+    };
+    let mut has_error = false;
+    for warning in std::mem::take(&mut koopa_ir_in_memory.warnings) {
+        if let Some((is_error, message)) = warning_options.diagnose(&warning.kind) {
+            eprintln!("{}: {}", line_maps[warning.unit].locate(warning.pos), message);
+            has_error |= is_error;
+        }
+    }
+    if has_error {
+        std::process::exit(1);
+    }
+
+    verify_ir(&koopa_ir_in_memory, "IR generation");
+    run_passes(&mut koopa_ir_in_memory, unroll_factor, profile_gen, profile_use.as_deref());
+    output_ir_module(koopa_ir_in_memory, &line_maps, &mode, &output, show_dominators)
+}
 
-    // 调用 lalrpop 生成的 parser 解析输入文件
-    let ast = sysy::CompUnitParser::new().parse(&input).unwrap();
-    let ir_gen = lab9::irgen::IRGen::new();
-    let koopa_ir_in_memory = ir_gen.generate_koopa_ir(ast).unwrap();
+// 格式化单个输入文件的原始源码
+// 预处理会去掉注释、#include、宏定义与条件编译的其他分支, 因此源码中有注释或预处理指令时报错, 而不是输出丢失了这些内容的源码
+fn run_format(inputs: &[String], output: &str) -> Result<()> {
+    let [input] = inputs else {
+        eprintln!("error: '{}' takes exactly one input file", MODE_FMT);
+        std::process::exit(1);
+    };
+    let source = std::fs::read_to_string(input)?;
+    if let Some((line, content)) = preprocess::find_preprocessor_content(&source) {
+        eprintln!("{}:{}: error: cannot format a file containing a {}, it would be lost", input, line, content);
+        std::process::exit(1);
+    }
+    match sysy::CompUnitParser::new().parse(&source) {
+        Ok(unit) => std::fs::write(output, lab9::pretty::format_comp_unit(&unit)),
+        Err(err) => {
+            eprintln!("{}", describe_parse_error(&err, &LineMap::unprocessed(input, &source)));
+            std::process::exit(1);
+        }
+    }
+}
+
+// 随机测试, 出错的程序及缩小后的程序写入报告目录, 有出错的程序时以1退出
+fn run_fuzz(inputs: &[String], output: &str) -> Result<()> {
+    let parse_number = |text: &str| text.parse::<u64>().unwrap_or_else(|_| {
+        eprintln!("error: invalid number '{}'", text);
+        std::process::exit(1);
+    });
+    let first_seed = inputs.first().map_or(0, |seed| parse_number(seed));
+    let count = inputs.get(1).map_or(100, |count| parse_number(count));
 
+    let (mut passed, mut skipped) = (0, 0);
+    let reports = lab9::fuzz::fuzz(first_seed, count, |seed, outcome| match outcome {
+        Outcome::Pass => passed += 1,
+        Outcome::Skip(reason) => {
+            skipped += 1;
+            eprintln!("seed {}: skipped: {}", seed, reason);
+        }
+        Outcome::Fail(failure) => eprintln!("seed {}: {}", seed, failure.to_string().lines().next().unwrap_or_default()),
+    });
+
+    std::fs::create_dir_all(output)?;
+    for report in &reports {
+        let header: String = report.failure.to_string().lines().map(|line| format!("// {}\n", line)).collect();
+        let dir = Path::new(output);
+        std::fs::write(dir.join(format!("seed-{}.sy", report.seed)), format!("{}{}", header, report.source))?;
+        std::fs::write(dir.join(format!("seed-{}.min.sy", report.seed)), format!("{}{}", header, report.minimized))?;
+    }
+    eprintln!("fuzz: {} programs, {} passed, {} skipped, {} failed", count, passed, skipped, reports.len());
+    if !reports.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// 缩小程序, 保留满足判定条件的最小版本; 输入程序本身不满足条件时以1退出
+fn run_reduce(inputs: &[String], include_paths: Vec<PathBuf>, predicate: Option<Predicate>, output: &str) -> Result<()> {
+    let [input] = inputs else {
+        eprintln!("error: -reduce takes exactly one input file");
+        std::process::exit(1);
+    };
+    let source = match Preprocessor::new(include_paths).preprocess_file(Path::new(input)) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let unit = match sysy::CompUnitParser::new().parse(&source.text) {
+        Ok(unit) => unit,
+        Err(err) => {
+            eprintln!("{}", describe_parse_error(&err, &source.line_map));
+            std::process::exit(1);
+        }
+    };
+
+    let predicate = match predicate {
+        Some(predicate) => predicate,
+        None => match lab9::fuzz::check_program(&unit) {
+            Outcome::Fail(failure) => Predicate::SameFailure(failure),
+            _ => {
+                eprintln!("error: {}: the program compiles and runs correctly, nothing to reduce", input);
+                std::process::exit(1);
+            }
+        },
+    };
+    if !predicate.holds(&unit) {
+        eprintln!("error: {}: the program does not show the requested failure", input);
+        std::process::exit(1);
+    }
+
+    let original = lab9::pretty::format_comp_unit(&unit);
+    let reduced = lab9::pretty::format_comp_unit(&lab9::reduce::reduce(unit, |candidate| predicate.holds(candidate)));
+    std::fs::write(output, &reduced)?;
+    eprintln!("reduce: {} lines -> {} lines", original.lines().count(), reduced.lines().count());
+    Ok(())
+}
+
+// 依次运行IR上的各个遍, 每个遍之后都校验IR
+fn run_passes(module: &mut IrModule, unroll_factor: usize, profile_gen: bool, profile_use: Option<&str>) {
+    // 自递归的尾调用改写为循环, 避免深递归耗尽栈
+    lab9::tailcall::eliminate_tail_recursion(&mut module.program);
+    verify_ir(module, "tail recursion elimination");
+    lab9::unroll::unroll_loops(module, unroll_factor);
+    verify_ir(module, "loop unrolling");
+    apply_profile_options(module, profile_gen, profile_use);
+    verify_ir(module, "profile instrumentation");
+}
+
+// -profile-gen 插入计数, -profile-use 读入计数文件, 与IR对不上时报错退出
+fn apply_profile_options(module: &mut IrModule, profile_gen: bool, profile_use: Option<&str>) {
+    if let Some(file) = profile_use {
+        let profile = std::fs::read_to_string(file)
+            .map_err(|err| err.to_string())
+            .and_then(|text| lab9::profile::Profile::parse(&text, &module.program));
+        match profile {
+            Ok(profile) => module.profile = Some(profile),
+            Err(err) => {
+                eprintln!("error: {}: {}", file, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    if profile_gen {
+        lab9::profile::instrument(&mut module.program);
+    }
+}
+
+// 校验IR, 有错误时报告出错的阶段与函数/块/值并退出, 避免畸形IR在codegen中才以panic的形式暴露
+fn verify_ir(module: &IrModule, stage: &str) {
+    if let Err(errors) = lab9::verify::verify_program(&module.program) {
+        for err in &errors {
+            eprintln!("error: invalid IR after {} {}", stage, err);
+        }
+        std::process::exit(1);
+    }
+}
+
+// 按输出模式输出IR生成(或从文本读入)的结果
+fn output_ir_module(koopa_ir_in_memory: IrModule, line_maps: &[LineMap], mode: &str, output: &str, show_dominators: bool) -> Result<()> {
     if mode == MODE_KOOPA {
-        output_koopa_ir(koopa_ir_in_memory, &output)?;
+        output_koopa_ir(koopa_ir_in_memory, output)?;
     } else if mode == MODE_RISCV {
-        output_riscv_assembly(koopa_ir_in_memory, &output)?;
+        output_riscv_assembly(koopa_ir_in_memory, line_maps, output)?;
+    } else if mode == MODE_OBJ {
+        output_riscv_object(koopa_ir_in_memory, output)?;
+    } else if mode == MODE_CFG {
+        std::fs::write(output, lab9::cfg::generate_dot(&koopa_ir_in_memory.program, show_dominators))?;
     } else {
         panic!("invalid mode");
     }
@@ -41,18 +346,58 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+// 读取Koopa IR文本, 语法/类型错误由koopa的前端直接输出到stderr
+fn read_koopa_ir(inputs: &[String], mode: &str) -> std::result::Result<IrModule, String> {
+    if mode == MODE_AST || mode == MODE_FMT {
+        return Err(format!("'{}' requires SysY input", mode));
+    }
+    let [input] = inputs else {
+        return Err("-from-koopa takes exactly one input file".to_string());
+    };
+    let driver = koopa::front::Driver::from_path(input.as_str()).map_err(|err| format!("{}: {}", input, err))?;
+    let program = driver.generate_program().map_err(|_| format!("{}: invalid Koopa IR", input))?;
+    Ok(IrModule::from_program(program))
+}
+
+// 将语法错误的位置映射回预处理前的源码
+fn describe_parse_error<T: std::fmt::Display, E: std::fmt::Display>(err: &ParseError<usize, T, E>, line_map: &LineMap) -> String {
+    match err {
+        ParseError::InvalidToken { location } => {
+            format!("{}: error: invalid token", line_map.locate(*location))
+        }
+        ParseError::UnrecognizedEof { location, expected } => {
+            format!("{}: error: unexpected end of file, expected one of {}", line_map.locate(*location), expected.join(" "))
+        }
+        ParseError::UnrecognizedToken { token: (start, token, _), expected } => {
+            format!("{}: error: unexpected token '{}', expected one of {}", line_map.locate(*start), token, expected.join(" "))
+        }
+        ParseError::ExtraToken { token: (start, token, _) } => {
+            format!("{}: error: extra token '{}'", line_map.locate(*start), token)
+        }
+        ParseError::User { error } => format!("error: {}", error),
+    }
+}
+
 // 输出koopa ir文本到指定文件
-fn output_koopa_ir(koopa_ir_in_memory: Program, output_file: &str) -> Result<()> {
-    let mut generator = koopa::back::KoopaGenerator::new(Vec::new());
-    generator.generate_on(&koopa_ir_in_memory)?;
-    let koopa_ir_text = std::str::from_utf8(&generator.writer()).unwrap().to_string();
-    std::fs::write(output_file, koopa_ir_text)?;
+fn output_koopa_ir(koopa_ir_in_memory: IrModule, output_file: &str) -> Result<()> {
+    std::fs::write(output_file, koopa_ir_in_memory.to_koopa_text())?;
     Ok(())
 }
 
-// 输出risc-v汇编到指定文件
-fn output_riscv_assembly(koopa_ir_in_memory: Program, output_file: &str) -> Result<()> {
-    let riscv_assembly_text = lab9::codegen::generate_riscv_assembly(koopa_ir_in_memory);
+// 输出risc-v汇编到指定文件, 用 -g 生成的IR带有源码映射时同时输出调试信息
+fn output_riscv_assembly(koopa_ir_in_memory: IrModule, line_maps: &[LineMap], output_file: &str) -> Result<()> {
+    let riscv_assembly_text = lab9::codegen::generate_riscv_assembly_with_debug_info(koopa_ir_in_memory, line_maps);
     std::fs::write(output_file, riscv_assembly_text)?;
     Ok(())
 }
+
+// 输出risc-v目标文件到指定文件, 超出范围的跳转等无法编码的情况报错退出
+fn output_riscv_object(koopa_ir_in_memory: IrModule, output_file: &str) -> Result<()> {
+    match lab9::codegen::generate_riscv_object(koopa_ir_in_memory) {
+        Ok(object) => std::fs::write(output_file, object),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
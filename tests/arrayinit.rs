@@ -0,0 +1,79 @@
+//! 局部数组初始化: 零元素多时循环清零且只存非零元素, 局部常量数组从只读的全局镜像复制
+mod common;
+use common::{compile, function_data, MAX_STEPS};
+use koopa::ir::{Program, ValueKind};
+use pku_compiler::lab9::codegen::generate_riscv_assembly;
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::irgen::IrModule;
+use pku_compiler::lab9::rvsim::run_assembly;
+
+/// 函数中 store 指令的条数
+fn stores(program: &Program, func: &str) -> usize {
+    let func = function_data(program, func);
+    func.layout().bbs().nodes()
+        .flat_map(|bb_node| bb_node.insts().keys())
+        .filter(|&&inst| matches!(func.dfg().value(inst).kind(), ValueKind::Store(_)))
+        .count()
+}
+
+/// 解释执行与模拟执行的输出相同, 返回汇编
+fn check(module: IrModule, expected: &[u8]) -> String {
+    assert_eq!(run_program(&module.program, b"", MAX_STEPS).unwrap().stdout, expected);
+    let asm = generate_riscv_assembly(module);
+    assert_eq!(run_assembly(&asm, b"", MAX_STEPS).unwrap().stdout, expected);
+    asm
+}
+
+#[test]
+fn zero_runs_are_filled_by_loop() {
+    let source = "\
+int f(int c) {
+    int r = 0;
+    if (c) {
+        while (r < 3) r = r + 1;
+        int b[40] = {1, r};
+        r = r + b[1] + b[39];
+    }
+    return r;
+}
+int main() {
+    int a[1000] = {1};
+    int m[4][30] = {{1, 2}, {}, {3}, {4, 5, 6}};
+    int small[3] = {0, 7};
+    int s = 0, i = 0;
+    while (i < 1000) {
+        s = s + a[i];
+        i = i + 1;
+    }
+    putint(s + m[3][2] + m[2][0] + m[1][29] + small[1] + small[2] + f(1) + f(0));
+    return 0;
+}
+";
+    let module = compile(source);
+    // a: 计数器初值、清零循环中两条、一个非零元素; m: 同上加六个非零元素; small: 逐个存入; 以及 s 和 i 的初值与循环中的赋值
+    assert_eq!(stores(&module.program, "@main"), (3 + 1) + (3 + 6) + 3 + 2 + 2);
+    check(module, b"23");
+}
+
+#[test]
+fn const_arrays_copy_from_rodata_image() {
+    let source = "\
+int main() {
+    const int c[3][2] = {{7}, {8, 9}};
+    const float f[2] = {1.5, 2};
+    int i = 0;
+    while (i < 2) {
+        const int d[50] = {3, 1, 2};
+        putint(c[i][1] + d[2] + d[49]);
+        i = i + 1;
+    }
+    putfloat(f[0] + f[1]);
+    return 0;
+}
+";
+    let asm = check(compile(source), b"2110x1.cp+1");
+    let rodata = &asm[asm.find(".section .rodata").unwrap()..];
+    assert!(rodata.contains("__sysy_const_0:\n  .word 7\n  .word 0\n  .word 8\n  .word 9\n"), "{}", asm);
+    assert!(rodata.contains("__sysy_const_1:\n  .word 1069547520\n  .word 1073741824\n"), "{}", asm);
+    assert!(!asm.contains(".global __sysy_const"), "{}", asm);
+}
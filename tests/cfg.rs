@@ -0,0 +1,84 @@
+//! 控制流图导出: 每个有函数体的函数一个DOT图, 节点标签为Koopa IR中的块, 标出回边, 可叠加支配树
+mod common;
+use common::{compile, function_data};
+use pku_compiler::lab9::cfg::{generate_dot, Cfg};
+
+const SOURCE: &str = "\
+int sum(int n) {
+    int s = 0;
+    while (n > 0) {
+        s = s + n;
+        n = n - 1;
+    }
+    return s;
+}
+int main() {
+    putint(sum(3));
+    return 0;
+}
+";
+
+#[test]
+fn one_graph_per_defined_function() {
+    let dot = generate_dot(&compile(SOURCE).program, false);
+    // 库函数的声明没有图
+    assert_eq!(dot.matches("digraph").count(), 2, "{}", dot);
+    assert!(dot.starts_with("digraph \"@sum\" {\n"), "{}", dot);
+    assert!(dot.contains("digraph \"@main\" {\n"), "{}", dot);
+
+    // 节点标签是Koopa IR文本中的块, 块名与指令逐行左对齐
+    assert!(dot.contains("  bb1 [label=\"%loop_header_1:\\l  %0 = load @n_1\\l  %1 = gt %0, 0\\l  br %1, %loop_body_1, %loop_end_1\\l\"];\n"), "{}", dot);
+    assert!(dot.contains("  bb0 [label=\"%entry:\\l  %8 = call @sum(3)\\l  call @putint(%8)\\l  ret 0\\l\"];\n"), "{}", dot);
+
+    // 条件分支的两条边标出真假, 只有回边标红
+    assert!(dot.contains("  bb0 -> bb1;\n  bb1 -> bb2 [label=\"T\"];\n  bb1 -> bb3 [label=\"F\"];\n  bb2 -> bb1 [color=red, penwidth=2];\n"), "{}", dot);
+    assert_eq!(dot.matches("color=red").count(), 1, "{}", dot);
+    assert!(!dot.contains("style=dashed"), "{}", dot);
+}
+
+#[test]
+fn dominator_tree_overlay() {
+    let dot = generate_dot(&compile(SOURCE).program, true);
+    let sum = &dot[..dot.find("digraph \"@main\"").unwrap()];
+    for (idom, bb) in [(0, 1), (1, 2), (1, 3)] {
+        assert!(sum.contains(&format!("  bb{} -> bb{} [style=dashed, color=gray, constraint=false];\n", idom, bb)), "{}", dot);
+    }
+    assert_eq!(dot.matches("style=dashed").count(), 3, "{}", dot);
+}
+
+#[test]
+fn dominators_and_loops() {
+    let source = "\
+int f(int n) {
+    int s = 0;
+    while (n) {
+        if (n % 2) {
+            s = s + 1;
+            continue;
+        }
+        n = n / 2;
+    }
+    return s;
+}
+int main() {
+    return f(5);
+}
+";
+    let module = compile(source);
+    let cfg = Cfg::new(function_data(&module.program, "@f"));
+
+    // 入口支配所有可达块, 入口没有直接支配者
+    assert_eq!(cfg.idom[0], None);
+    let reachable = cfg.reverse_postorder();
+    assert_eq!(reachable[0], 0);
+    assert!(reachable.iter().all(|&bb| cfg.dominates(0, bb)));
+
+    // continue 与循环体末尾两条回边指向同一循环头, 合并为一个循环
+    let loops = cfg.natural_loops();
+    assert_eq!(loops.len(), 1);
+    let (header, body) = &loops[0];
+    let back_edges = (0..cfg.blocks.len()).filter(|&bb| cfg.succs[bb].contains(header) && body[bb] && bb != *header).count();
+    assert_eq!(back_edges, 2);
+    assert!(!body[0]);
+    assert!(reachable.iter().filter(|&&bb| body[bb]).all(|&bb| cfg.dominates(*header, bb)));
+}
@@ -0,0 +1,43 @@
+//! 各测试共用的编译与查找函数
+#![allow(dead_code)] // 每个测试只用到其中一部分
+use koopa::ir::{Function, FunctionData, Program, Type};
+use pku_compiler::ast::CompUnit;
+use pku_compiler::lab9::irgen::{IRGen, IrModule};
+use pku_compiler::lab9::preprocess::{Preprocessed, Preprocessor};
+use pku_compiler::lab9::verify::verify_program;
+use pku_compiler::sysy;
+
+/// 解释执行与模拟执行的最大步数
+pub const MAX_STEPS: u64 = 10_000_000;
+
+/// 预处理并解析单个源文件(文件名为 main.sy)
+pub fn parse(source: &str) -> (Preprocessed, CompUnit) {
+    parse_file("main.sy", source)
+}
+
+/// 预处理并解析一个翻译单元, name 为诊断信息中的文件名
+pub fn parse_file(name: &str, source: &str) -> (Preprocessed, CompUnit) {
+    Type::set_ptr_size(4);
+    let source = Preprocessor::new(Vec::new()).preprocess_source(name, source).unwrap();
+    let unit = sysy::CompUnitParser::new().parse(&source.text).unwrap();
+    (source, unit)
+}
+
+/// 编译单个源文件到(通过检查的)Koopa IR, 不运行任何优化
+pub fn compile(source: &str) -> IrModule {
+    let (_, unit) = parse(source);
+    let module = IRGen::new().generate_program(vec![unit])
+        .unwrap_or_else(|errors| panic!("cannot compile: {:?}", errors));
+    verify_program(&module.program).unwrap();
+    module
+}
+
+/// 按名字(含@)查找函数
+pub fn function(program: &Program, name: &str) -> Function {
+    *program.func_layout().iter().find(|&&func| program.func(func).name() == name)
+        .unwrap_or_else(|| panic!("no function {}", name))
+}
+
+pub fn function_data<'a>(program: &'a Program, name: &str) -> &'a FunctionData {
+    program.func(function(program, name))
+}
@@ -0,0 +1,107 @@
+//! 常量表达式中的常量数组元素: 按常量下标在编译时取值, 可用作数组维度与全局初始值, 下标越界是编译错误
+mod common;
+use common::{compile, parse, MAX_STEPS};
+use koopa::ir::TypeKind;
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::irgen::IRGen;
+
+/// 解释执行的输出
+fn run(source: &str) -> String {
+    let module = compile(source);
+    String::from_utf8(run_program(&module.program, b"", MAX_STEPS).unwrap().stdout).unwrap()
+}
+
+/// 全局变量 name(含@)的类型
+fn global_type(source: &str, name: &str) -> String {
+    let program = compile(source).program;
+    let global = *program.inst_layout().iter()
+        .find(|&&value| program.borrow_value(value).name().as_deref() == Some(name))
+        .unwrap_or_else(|| panic!("no global {}", name));
+    let ty = program.borrow_value(global).ty().clone();
+    match ty.kind() {
+        TypeKind::Pointer(base) => base.to_string(),
+        _ => unreachable!("global allocations are pointers"),
+    }
+}
+
+#[test]
+fn elements_as_array_dimensions() {
+    let source = "\
+const int a[3] = {2, 5, 7};
+int b[a[1]];
+int c[a[0] + a[2]][a[0]];
+int main() {
+    const int l[2] = {3, a[2]};
+    int d[l[1] - l[0]];
+    d[3] = 9;
+    putint(d[3]);
+    return 0;
+}
+";
+    assert_eq!(global_type(source, "@b"), "[i32, 5]");
+    assert_eq!(global_type(source, "@c"), "[[i32, 2], 9]");
+    assert_eq!(run(source), "9");
+}
+
+#[test]
+fn elements_in_global_initializers() {
+    let source = "\
+const int a[4] = {1, 2, 3};
+int g[4] = {a[0], a[1] + a[2], a[3], a[2] * 10};
+const float f[2] = {1.5, a[1]};
+float h = f[0] * f[1];
+int main() {
+    int i = 0;
+    while (i < 4) {
+        putint(g[i]);
+        putch(32);
+        i = i + 1;
+    }
+    putfloat(h);
+    return 0;
+}
+";
+    // 元素全是常量, 初始值是聚合常量而不是运行时的store
+    let module = compile(source);
+    let text = module.to_koopa_text();
+    assert!(text.contains("global @g = alloc [i32, 4], {1, 5, 0, 30}"), "{}", text);
+    assert_eq!(run(source), "1 5 0 30 0x1.8p+1");
+}
+
+#[test]
+fn nested_const_arrays() {
+    let source = "\
+const int m[2][3] = {{1, 2, 3}, {4}};
+const int n[m[0][2]] = {m[0][1], m[1][0], m[1][2]};
+const int k = n[1] * 10 + m[1][1];
+int main() {
+    const int o[2][2] = {{n[0], k}, {m[0][0]}};
+    int e[o[0][1]];
+    putint(o[0][0] + o[1][0] + o[1][1]);
+    putch(32);
+    putint(k);
+    e[39] = 1;
+    return 0;
+}
+";
+    assert_eq!(run(source), "3 40");
+}
+
+#[test]
+fn out_of_bounds_constant_index_is_an_error() {
+    for (source, message) in [
+        ("const int a[2] = {1, 2};\nint b[a[2]];\nint main() { return 0; }\n", "2:7: index 2 is out of bounds for array 'a' with dimension 2"),
+        ("const int a[2][2] = {1, 2, 3, 4};\nint main() {\n    const int b = a[1][-1];\n    return b;\n}\n", "3:19: index -1 is out of bounds for array 'a' with dimension 2"),
+        ("const int a[2] = {1, 2};\nint g = a[1] + a[a[1]];\nint main() { return 0; }\n", "2:16: index 2 is out of bounds for array 'a' with dimension 2"),
+    ] {
+        let (preprocessed, unit) = parse(source);
+        let errors = IRGen::new().generate_program(vec![unit]).err().unwrap_or_else(|| panic!("compiled: {}", source));
+        let messages: Vec<String> = errors.iter()
+            .map(|err| {
+                let location = preprocessed.line_map.locate(err.pos);
+                format!("{}:{}: {}", location.line, location.column, err)
+            })
+            .collect();
+        assert_eq!(messages, [message], "{}", source);
+    }
+}
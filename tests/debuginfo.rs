@@ -0,0 +1,84 @@
+//! 调试信息(-g): 检查 .loc 的行号、DWARF 中的变量, 以及去掉调试指令后与不带 -g 的汇编一致
+mod common;
+use common::parse;
+use pku_compiler::lab9::codegen::{generate_riscv_assembly, generate_riscv_assembly_with_debug_info};
+use pku_compiler::lab9::irgen::IRGen;
+use pku_compiler::lab9::rvsim::run_assembly;
+
+const SOURCE: &str = "\
+float scale(float x, int a[][2]) {
+    float y = x * 2.0;
+    return y + a[1][0];
+}
+
+int main() {
+    int arr[2][2] = {{1, 2}, {3, 4}};
+    int i = 0;
+    while (i < 2) {
+        arr[i][1] = arr[i][0] + 1;
+        i = i + 1;
+    }
+    putint(arr[1][1]);
+    return scale(1.5, arr);
+}
+";
+
+/// 返回 (带 -g 的汇编, 不带 -g 的汇编)
+fn compile() -> (String, String) {
+    let (source, unit) = parse(SOURCE);
+    let module = IRGen::new().with_debug_info().generate_program(vec![unit]).unwrap();
+    let debug = generate_riscv_assembly_with_debug_info(module, std::slice::from_ref(&source.line_map));
+    let plain = generate_riscv_assembly(common::compile(SOURCE));
+    (debug, plain)
+}
+
+/// 基本块编号是全局分配的, 两次生成的标号不同, 按出现顺序重新编号
+fn renumber_labels(asm: &str) -> String {
+    let mut labels: Vec<String> = Vec::new();
+    let words = asm.split_inclusive(|c: char| !c.is_alphanumeric()).map(|word| {
+        let (name, rest) = word.split_at(word.trim_end_matches(|c: char| !c.is_alphanumeric()).len());
+        if !name.starts_with("LBB") {
+            return word.to_string();
+        }
+        let index = labels.iter().position(|label| label == name).unwrap_or_else(|| {
+            labels.push(name.to_string());
+            labels.len() - 1
+        });
+        format!("LBB{}{}", index, rest)
+    });
+    words.collect()
+}
+
+#[test]
+fn emits_line_table_and_variables() {
+    let (asm, _) = compile();
+    assert!(asm.contains(".file 1 \"main.sy\""), "{}", asm);
+
+    // main 中依次出现的行号: 循环体之后回到条件所在的行
+    let main = &asm[asm.find("\nmain:").unwrap()..asm.find(".Ltext_end:").unwrap()];
+    let lines: Vec<usize> = main.lines()
+        .filter_map(|line| line.trim().strip_prefix(".loc 1 "))
+        .map(|loc| loc.split_whitespace().next().unwrap().parse().unwrap())
+        .collect();
+    assert_eq!(lines, [6, 7, 8, 9, 10, 11, 9, 13, 14], "{}", main);
+
+    for name in ["scale", "x", "a", "y", "main", "arr", "i"] {
+        assert!(asm.contains(&format!(".asciz \"{}\"", name)), "missing {}", name);
+    }
+}
+
+#[test]
+fn debug_info_does_not_change_code() {
+    let (debug, plain) = compile();
+    let code: Vec<&str> = debug[..debug.find(".Ltext_end:").unwrap()].lines()
+        .filter(|line| {
+            let line = line.trim();
+            !line.starts_with(".loc ") && !line.starts_with(".file ") && !line.starts_with(".Lfunc_end") && line != ".Ltext_begin:"
+        })
+        .collect();
+    assert_eq!(renumber_labels(code.join("\n").trim_end()), renumber_labels(plain.trim_end()));
+
+    let execution = run_assembly(&debug, b"", 1_000_000).unwrap();
+    assert_eq!(execution.stdout, b"4");
+    assert_eq!(execution.exit_code, 6);
+}
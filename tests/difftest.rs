@@ -0,0 +1,109 @@
+//! 差分测试: 对 tests/corpus 中的每个 SysY 程序,
+//! 比较 Koopa IR 解释执行、lab9 codegen 生成的 RISC-V 模拟执行与期望输出(.out, 格式同评测: 标准输出 + 退出码),
+//! 属于 lab8 子集的程序还要求 lab8 生成的 IR 与汇编得到相同的结果.
+//! 程序的标准输入取自同名的 .in 文件(可选)
+use koopa::ir::{Program, Type};
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::irgen::IRGen;
+use pku_compiler::lab9::preprocess::Preprocessor;
+use pku_compiler::lab9::runtime::{ExecResult, Execution};
+use pku_compiler::lab9::rvsim::run_assembly;
+use pku_compiler::lab9::verify::verify_program;
+use pku_compiler::{lab8, lab9, sysy};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAX_STEPS: u64 = 50_000_000;
+
+#[test]
+fn corpus() {
+    Type::set_ptr_size(4);
+    let mut programs: Vec<PathBuf> = fs::read_dir(corpus_dir())
+        .expect("tests/corpus should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sy"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "tests/corpus is empty");
+
+    let mut failures = Vec::new();
+    let mut lab8_programs = 0;
+    for path in &programs {
+        match check_program(path) {
+            Ok(in_lab8_subset) => lab8_programs += in_lab8_subset as usize,
+            Err(msg) => failures.push(format!("{}: {}", path.display(), msg)),
+        }
+    }
+    assert!(failures.is_empty(), "{} of {} programs failed:\n{}", failures.len(), programs.len(), failures.join("\n"));
+    assert!(lab8_programs > 0, "no program in the corpus is covered by lab8");
+}
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("corpus")
+}
+
+/// 检查一个程序, 返回它是否属于 lab8 子集
+fn check_program(path: &Path) -> Result<bool, String> {
+    let expected = fs::read_to_string(path.with_extension("out")).map_err(|err| format!("missing .out file: {}", err))?;
+    let input = fs::read(path.with_extension("in")).unwrap_or_default();
+
+    let source = Preprocessor::new(Vec::new())
+        .preprocess_file(path)
+        .map_err(|err| format!("preprocess: {}", err))?;
+    let parse = || sysy::CompUnitParser::new().parse(&source.text).map_err(|err| format!("parse: {}", err));
+
+    // lab9: IR 解释执行与期望输出一致, 汇编模拟执行与解释执行一致
+    let module = IRGen::new().generate_program(vec![parse()?]).map_err(|errors| {
+        let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        format!("lab9 irgen: {}", messages.join("; "))
+    })?;
+    let interpreted = interpret("lab9", &module.program, &input)?;
+    compare("lab9 koopa", &expected, &interpreted)?;
+    let asm = lab9::codegen::generate_riscv_assembly(module);
+    if let Some(line) = asm.lines().find(|line| uses_double(line)) {
+        return Err(format!("lab9 riscv: '{}' is not an RV32IMF instruction", line.trim()));
+    }
+    let simulated = simulate("lab9", &asm, &input)?;
+    compare("lab9 riscv", &expected, &simulated)?;
+
+    // lab8: 不在子集内的程序直接跳过
+    let Ok(program) = lab8::convert::generate_koopa_ir(&parse()?) else {
+        return Ok(false);
+    };
+    let interpreted = interpret("lab8", &program, &input)?;
+    compare("lab8 koopa", &expected, &interpreted)?;
+    let simulated = simulate("lab8", &lab8::codegen::generate_riscv_assembly(program), &input)?;
+    compare("lab8 riscv", &expected, &simulated)?;
+    Ok(true)
+}
+
+/// 汇编行是否为D扩展指令(目标为RV32IMF)
+fn uses_double(line: &str) -> bool {
+    let mnemonic = line.split_whitespace().next().unwrap_or_default();
+    matches!(mnemonic, "fld" | "fsd") || mnemonic.starts_with('f') && mnemonic.split('.').skip(1).any(|part| part == "d")
+}
+
+fn interpret(stage: &str, program: &Program, input: &[u8]) -> Result<Execution, String> {
+    if let Err(errors) = verify_program(program) {
+        let messages: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        return Err(format!("{} produced invalid IR: {}", stage, messages.join("; ")));
+    }
+    describe(stage, "koopa", run_program(program, input, MAX_STEPS))
+}
+
+fn simulate(stage: &str, asm: &str, input: &[u8]) -> Result<Execution, String> {
+    describe(stage, "riscv", run_assembly(asm, input, MAX_STEPS))
+}
+
+fn describe(stage: &str, engine: &str, result: ExecResult<Execution>) -> Result<Execution, String> {
+    result.map_err(|err| format!("{} {}: {}", stage, engine, err))
+}
+
+fn compare(what: &str, expected: &str, actual: &Execution) -> Result<(), String> {
+    let actual = actual.expected_output();
+    if actual != expected {
+        return Err(format!("{} output differs\n--- expected\n{}--- actual\n{}", what, expected, actual));
+    }
+    Ok(())
+}
+
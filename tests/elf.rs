@@ -0,0 +1,187 @@
+//! 目标文件输出: 解析生成的 ELF, 检查节、符号、重定位与指令编码(期望的编码取自 llvm-mc)
+mod common;
+use common::compile;
+use pku_compiler::lab9::codegen::generate_riscv_object;
+use pku_compiler::lab9::elf::assemble_object;
+
+const R_RISCV_CALL_PLT: u32 = 19;
+const R_RISCV_PCREL_HI20: u32 = 23;
+const R_RISCV_PCREL_LO12_I: u32 = 24;
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn c_string(bytes: &[u8], offset: usize) -> String {
+    let end = bytes[offset..].iter().position(|&b| b == 0).unwrap();
+    String::from_utf8(bytes[offset..offset + end].to_vec()).unwrap()
+}
+
+struct Section<'a> {
+    name: String,
+    link: usize,
+    data: &'a [u8],
+}
+
+struct Symbol {
+    name: String,
+    value: u32,
+    info: u8,
+    section: u16,
+}
+
+/// 最小的 ELF32 读取器
+struct Object<'a> {
+    sections: Vec<Section<'a>>,
+}
+
+impl<'a> Object<'a> {
+    fn parse(elf: &'a [u8]) -> Self {
+        assert_eq!(&elf[..6], b"\x7fELF\x01\x01");
+        assert_eq!(u16_at(elf, 16), 1, "ET_REL");
+        assert_eq!(u16_at(elf, 18), 243, "EM_RISCV");
+        let (shoff, shnum, shstrndx) = (u32_at(elf, 32) as usize, u16_at(elf, 48) as usize, u16_at(elf, 50) as usize);
+        let header = |index: usize| {
+            let base = shoff + index * 40;
+            let (offset, size) = (u32_at(elf, base + 16) as usize, u32_at(elf, base + 20) as usize);
+            (u32_at(elf, base) as usize, u32_at(elf, base + 24) as usize, &elf[offset..offset + size])
+        };
+        let shstrtab = header(shstrndx).2;
+        let sections = (0..shnum)
+            .map(|index| {
+                let (name, link, data) = header(index);
+                Section { name: c_string(shstrtab, name), link, data }
+            })
+            .collect();
+        Object { sections }
+    }
+
+    fn section(&self, name: &str) -> &Section<'a> {
+        self.sections.iter().find(|section| section.name == name).unwrap_or_else(|| panic!("no section {}", name))
+    }
+
+    fn section_index(&self, name: &str) -> u16 {
+        self.sections.iter().position(|section| section.name == name).unwrap() as u16
+    }
+
+    fn symbols(&self) -> Vec<Symbol> {
+        let symtab = self.section(".symtab");
+        let strtab = self.sections[symtab.link].data;
+        symtab.data.chunks(16)
+            .map(|entry| Symbol {
+                name: c_string(strtab, u32_at(entry, 0) as usize),
+                value: u32_at(entry, 4),
+                info: entry[12],
+                section: u16_at(entry, 14),
+            })
+            .collect()
+    }
+
+    /// (偏移, 类型, 符号名)
+    fn relocations(&self, name: &str) -> Vec<(u32, u32, String)> {
+        let symbols = self.symbols();
+        self.section(name).data.chunks(12)
+            .map(|entry| {
+                let info = u32_at(entry, 4);
+                (u32_at(entry, 0), info & 0xff, symbols[(info >> 8) as usize].name.clone())
+            })
+            .collect()
+    }
+
+    fn words(&self, name: &str) -> Vec<u32> {
+        self.section(name).data.chunks(4).map(|word| u32_at(word, 0)).collect()
+    }
+}
+
+const SOURCE: &str = "\
+int counter = 3;
+int table[4] = {1, 2};
+
+int bump(int n) {
+    counter = counter + n;
+    return counter;
+}
+
+int main() {
+    putint(bump(table[1]));
+    return 0;
+}
+";
+
+#[test]
+fn emits_symbols_and_relocations() {
+    let elf = generate_riscv_object(compile(SOURCE)).unwrap();
+    let object = Object::parse(&elf);
+
+    assert_eq!(object.words(".data"), [3, 1, 2, 0, 0]);
+
+    // 局部符号(基本块标签等)不进入符号表, 全局符号在局部符号之后
+    let symbols = object.symbols();
+    let find = |name: &str| symbols.iter().find(|symbol| symbol.name == name).unwrap_or_else(|| panic!("no symbol {}", name));
+    assert!(symbols.iter().all(|symbol| !symbol.name.starts_with("LBB")));
+    assert!(symbols.iter().skip_while(|symbol| symbol.info >> 4 == 0).all(|symbol| symbol.info >> 4 == 1));
+    let text = object.section_index(".text");
+    assert_eq!((find("bump").info, find("bump").section, find("bump").value), (0x12, text, 0));
+    assert_eq!((find("main").info, find("main").section), (0x12, text));
+    assert_eq!((find("counter").info, find("counter").section, find("counter").value), (0x11, object.section_index(".data"), 0));
+    assert_eq!(find("table").value, 4);
+    assert_eq!((find("putint").info, find("putint").section), (0x10, 0));
+
+    let relocations = object.relocations(".rela.text");
+    let calls: Vec<&str> = relocations.iter()
+        .filter(|(_, kind, _)| *kind == R_RISCV_CALL_PLT)
+        .map(|(_, _, symbol)| symbol.as_str())
+        .collect();
+    assert_eq!(calls, ["bump", "putint"]);
+    // la 的低12位引用 auipc 处的辅助标签
+    for (index, (offset, kind, symbol)) in relocations.iter().enumerate() {
+        if *kind == R_RISCV_PCREL_HI20 {
+            assert!(symbol == "counter" || symbol == "table", "{}", symbol);
+            let (lo_offset, lo_kind, label) = &relocations[index + 1];
+            assert_eq!((*lo_offset, *lo_kind), (offset + 4, R_RISCV_PCREL_LO12_I));
+            assert_eq!(find(label).value, *offset);
+        }
+    }
+}
+
+#[test]
+fn encodes_instructions() {
+    let asm = "\
+.text
+.global f
+f:
+  li    t6, 1073741824
+  li    t0, -5000
+  fcvt.w.s t0, ft0, rtz
+  fcvt.d.s ft0, ft0
+  fsd   ft0, 8(sp)
+  flw   fa0, -4(s0)
+  seqz  t2, t2
+  snez  t2, t2
+  fneg.s ft0, ft0
+  ret
+";
+    let elf = assemble_object(asm).unwrap();
+    assert_eq!(Object::parse(&elf).words(".text"), [
+        0x40000fb7, 0xfffff2b7, 0xc7828293, 0xc00012d3, 0x42000053, 0x00013427, 0xffc42507, 0x0013b393, 0x007033b3,
+        0x20001053, 0x00008067,
+    ]);
+}
+
+#[test]
+fn relaxes_out_of_range_branches() {
+    let body = "  addi  t0, t0, 1\n".repeat(1100);
+    let asm = format!(".text\nf:\nLBB1:\n  bnez  t0, LBB2\n{}LBB2:\n  beqz  t0, LBB1\n  ret\n", body);
+    let elf = assemble_object(&asm).unwrap();
+    let words = Object::parse(&elf).words(".text");
+    // bnez 超出范围: 反向的 beq 跳过紧随的 j
+    assert_eq!(&words[..2], [0x00028463, 0x1340106f]);
+    assert_eq!(&words[1102..], [0x00029463, 0xec5fe06f, 0x00008067]);
+
+    let far = format!(".text\nf:\n  j     LBB1\n{}LBB1:\n  ret\n", "  nop\n".repeat(300_000));
+    assert!(assemble_object(&far).unwrap_err().contains("out of range"));
+}
@@ -0,0 +1,213 @@
+//! 语义错误: IR生成以 CompileError 报告, 不会 panic, 也不会留给IR检查
+mod common;
+use common::{parse, parse_file, MAX_STEPS};
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::irgen::IRGen;
+use pku_compiler::sysy;
+
+/// IR生成报告的错误信息
+fn errors(source: &str) -> Vec<String> {
+    let (_, unit) = parse(source);
+    match IRGen::new().generate_program(vec![unit]) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors.iter().map(|err| err.to_string()).collect(),
+    }
+}
+
+/// 多个翻译单元一起生成时报告的错误及附注, 形如 "文件:行:列: error: 信息"
+fn program_errors(files: &[(&str, &str)]) -> Vec<String> {
+    let (sources, units): (Vec<_>, Vec<_>) = files.iter().map(|(name, source)| parse_file(name, source)).unzip();
+    let Err(errors) = IRGen::new().generate_program(units) else {
+        return Vec::new();
+    };
+    let mut messages = Vec::new();
+    for err in &errors {
+        messages.push(format!("{}: error: {}", sources[err.unit].line_map.locate(err.pos), err));
+        if let Some(((unit, pos), note)) = err.note() {
+            messages.push(format!("{}: note: {}", sources[unit].line_map.locate(pos), note));
+        }
+    }
+    messages
+}
+
+#[test]
+fn pointer_mismatch_names_both_types() {
+    let source = "\
+void f(int a[][3]) {
+    float *s = a;
+}
+int main() {
+    int *p = 0;
+    float *q = p;
+    return 0;
+}
+";
+    assert_eq!(errors(source), [
+        "incompatible types: cannot convert 'int (*)[3]' to 'float *'",
+        "incompatible types: cannot convert 'int *' to 'float *'",
+    ]);
+}
+
+#[test]
+fn void_call_values_are_rejected() {
+    let cases = [
+        "void f() {} int main() { int x = f(); return x; }",
+        "void f() {} int main() { return f(); }",
+        "void f() {} int main() { if (f()) return 1; return 0; }",
+        "void f() {} int main() { return f() + 1; }",
+        "void f() {} int main() { putint(f()); return 0; }",
+        "void f() {} int main() { while (!f()) {} return 0; }",
+    ];
+    for source in cases {
+        assert_eq!(errors(source), ["void value returned by 'f' is used"], "{}", source);
+    }
+    // 表达式语句可以丢弃 void 调用
+    assert_eq!(errors("void f() {} int main() { f(); putf(\"%d\", 1); return 0; }"), Vec::<String>::new());
+}
+
+#[test]
+fn return_must_match_function_type() {
+    assert_eq!(errors("void g() { return 1; } int main() { g(); return 0; }"), ["void function 'g' should not return a value"]);
+    assert_eq!(errors("int main() { return; }"), ["non-void function 'main' should return a value"]);
+    assert_eq!(errors("float h() { if (1) return; return 1.0; } int main() { return 0; }"), ["non-void function 'h' should return a value"]);
+}
+
+#[test]
+fn excess_initializers_are_rejected() {
+    let cases = [
+        "int main() { int a[2] = {1, 2, 3}; return a[0]; }",
+        "int main() { int a[2][2] = {{1, 2, 3}, {4}}; return a[0][0]; }",
+        "int main() { const int a[2] = {1, 2, 3}; return a[0]; }",
+        "int g[2] = {1, 2, 3}; int main() { return 0; }",
+    ];
+    for source in cases {
+        assert_eq!(errors(source), ["invalid initializer: excess elements in array initializer"], "{}", source);
+    }
+}
+
+#[test]
+fn float_array_sizes_are_rejected() {
+    let source = "\
+const float N = 2.5;
+int g[N];
+void f() {
+    int a[2][1.5];
+}
+int main() {
+    int b[3 + 0.5 * 2];
+    return 0;
+}
+";
+    assert_eq!(errors(source), [
+        "size of array 'g' has non-integer type",
+        "size of array 'a' has non-integer type",
+        "size of array 'b' has non-integer type",
+    ]);
+}
+
+#[test]
+fn bad_float_literals_are_parse_errors() {
+    for literal in ["0x1p99999999999", "0x1p200", "1e50", "3.5e99999999999"] {
+        let source = format!("int main() {{ float f = {}; return 0; }}", literal);
+        let err = sysy::CompUnitParser::new().parse(&source).unwrap_err();
+        assert_eq!(err.to_string(), "floating constant out of range", "{}", literal);
+    }
+    // 下溢为 0 不算错误
+    let source = "int main() { float f = 0x1p-99999999999 + 1e-99999; return 0; }";
+    assert!(sysy::CompUnitParser::new().parse(source).is_ok());
+}
+
+#[test]
+fn declarations_must_match_across_units() {
+    let main = "int f(int n);\nint main() {\n    putint(f(2));\n    return 0;\n}\n";
+    assert_eq!(program_errors(&[("main.sy", main), ("f.sy", "float f(int n) {\n    return n * 1.5;\n}\n")]), [
+        "f.sy:1:7: error: conflicting types for 'f'",
+        "main.sy:1:5: note: previous declaration of 'f' is here",
+    ]);
+    // 形参个数或类型不同同样冲突, 一致的原型可以重复出现
+    // 调用按定义检查
+    assert_eq!(program_errors(&[("main.sy", main), ("f.sy", "int f(int n, int m) {\n    return n;\n}\n")]), [
+        "f.sy:1:5: error: conflicting types for 'f'",
+        "main.sy:1:5: note: previous declaration of 'f' is here",
+        "main.sy:3:12: error: function 'f' expects 2 arguments, but 1 were given",
+    ]);
+    assert_eq!(program_errors(&[("main.sy", main), ("f.sy", "int f(float n) {\n    return n;\n}\n")]).len(), 2);
+    assert!(program_errors(&[("main.sy", main), ("f.sy", "int f(int x);\nint f(int n) {\n    return n;\n}\n")]).is_empty());
+
+    // extern变量的类型(标量/数组/指针, 各维长度)须与定义一致
+    let uses = "extern int a;\nint main() {\n    return a;\n}\n";
+    for (definition, column) in [("int a[3];\n", 5), ("int *a;\n", 6), ("float a;\n", 7)] {
+        assert_eq!(program_errors(&[("main.sy", uses), ("a.sy", definition)]), [
+            "main.sy:1:12: error: conflicting types for 'a'".to_string(),
+            format!("a.sy:1:{}: note: previous declaration of 'a' is here", column),
+        ]);
+    }
+    assert!(program_errors(&[("main.sy", "extern int a[2][3];\nint main() {\n    return a[1][2];\n}\n"), ("a.sy", "int a[2][3];\n")]).is_empty());
+    assert_eq!(program_errors(&[("main.sy", "extern int a[2][4];\nint main() {\n    return 0;\n}\n"), ("a.sy", "int a[2][3];\n")]).len(), 2);
+}
+
+#[test]
+fn static_names_are_local_to_their_unit() {
+    let first = "static int h(int x) {\n    return x + 1;\n}\nstatic int v = 10;\nint g() {\n    return h(v);\n}\n";
+    let second = "\
+static float h(float x) {
+    return x * 2;
+}
+static int v = 20;
+int g();
+int main() {
+    putint(g());
+    putint(h(v));
+    return 0;
+}
+";
+    let (_, first) = parse_file("first.sy", first);
+    let (_, second) = parse_file("second.sy", second);
+    let module = IRGen::new().generate_program(vec![first, second]).unwrap();
+    assert_eq!(run_program(&module.program, b"", MAX_STEPS).unwrap().stdout, b"1140");
+}
+
+#[test]
+fn errors_are_reported_per_statement_at_the_offending_expression() {
+    let source = "\
+int main() {
+    int a = 1;
+    int b = a + c;
+    a = a + *a;
+    int arr[2] = {1, 2, 3};
+    putint(arr[0] + b);
+    while (a) {
+        a = a - undefined;
+        break;
+    }
+    return a + f();
+}
+";
+    // 出错的语句跳过后继续生成, 声明出错的 arr 与 b 之后的使用不再报告
+    assert_eq!(program_errors(&[("main.sy", source)]), [
+        "main.sy:3:17: error: use of undeclared identifier 'c'",
+        "main.sy:4:13: error: invalid operand: indirection requires a pointer operand",
+        "main.sy:5:5: error: invalid initializer: excess elements in array initializer",
+        "main.sy:8:17: error: use of undeclared identifier 'undefined'",
+        "main.sy:11:16: error: call to undeclared function 'f'",
+    ]);
+}
+
+#[test]
+fn addresses_in_global_initializers_are_unsupported() {
+    let source = "\
+int g, arr[2];
+int *gp = &g;
+int x = 1 + &arr[1];
+int main() {
+    const int c = 1 + &g;
+    return c;
+}
+";
+    // 错误定位到取地址的 &
+    assert_eq!(program_errors(&[("main.sy", source)]), [
+        "main.sy:2:11: error: taking an address in a global initializer is not supported",
+        "main.sy:3:13: error: taking an address in a global initializer is not supported",
+        "main.sy:5:23: error: pointer expression is not a constant expression",
+    ]);
+}
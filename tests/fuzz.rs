@@ -0,0 +1,20 @@
+//! 随机测试: 少量固定种子生成的程序都应通过完整流水线, 且解释执行与模拟执行结果一致
+use pku_compiler::lab9::fuzz::{self, gen, Outcome};
+use pku_compiler::lab9::pretty::format_comp_unit;
+
+#[test]
+fn generated_programs_agree() {
+    let reports = fuzz::fuzz(0, 8, |_, _| {});
+    let failures: Vec<String> = reports.iter()
+        .map(|report| format!("seed {}: {}\n{}", report.seed, report.failure, report.minimized))
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn generation_is_deterministic() {
+    let first = format_comp_unit(&gen::generate_program(42));
+    let second = format_comp_unit(&gen::generate_program(42));
+    assert_eq!(first, second);
+    assert_eq!(fuzz::check_program(&gen::generate_program(42)), Outcome::Pass);
+}
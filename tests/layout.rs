@@ -0,0 +1,63 @@
+//! 基本块布局: 顺序执行的跳转被省去, 比较与分支合并, 有剖析数据时热的分支紧随其后
+mod common;
+use common::{compile, MAX_STEPS};
+use pku_compiler::lab9::codegen::generate_riscv_assembly;
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::profile::{format_counts, instrument, Profile};
+use pku_compiler::lab9::rvsim::run_assembly;
+
+const SOURCE: &str = "\
+int main() {
+    int i = 0;
+    while (i < 100) {
+        if (i % 10 == 0) {
+            putch(33);
+        } else {
+            putch(46);
+        }
+        i = i + 1;
+    }
+    return 0;
+}
+";
+
+/// 汇编中两条指令第一次出现的先后
+fn appears_before(asm: &str, first: &str, second: &str) -> bool {
+    asm.find(first).unwrap() < asm.find(second).unwrap()
+}
+
+#[test]
+fn falls_through_and_fuses_compares() {
+    let expected = run_program(&compile(SOURCE).program, b"", MAX_STEPS).unwrap();
+    let asm = generate_riscv_assembly(compile(SOURCE));
+    assert_eq!(run_assembly(&asm, b"", MAX_STEPS).unwrap().stdout, expected.stdout);
+
+    // 不跳到紧随其后的标签
+    let lines: Vec<&str> = asm.lines().collect();
+    for pair in lines.windows(2) {
+        if let Some(target) = pair[0].trim().strip_prefix("j ") {
+            assert_ne!(format!("{}:", target.trim()), pair[1], "{}", asm);
+        }
+    }
+    // 循环条件与 if 条件都合并为比较跳转, 循环头反转条件跳到循环出口, 循环体紧随其后
+    assert!(asm.contains("bge   t0, t1, "), "{}", asm);
+    assert!(asm.contains("bne   t0, x0, "), "{}", asm);
+    assert!(!asm.contains("slt") && !asm.contains("bnez"), "{}", asm);
+    assert!(appears_before(&asm, "li    a0, 33", "li    a0, 46"));
+}
+
+#[test]
+fn places_hot_successor_next() {
+    let mut instrumented = compile(SOURCE);
+    instrument(&mut instrumented.program);
+    let counts = run_program(&instrumented.program, b"", MAX_STEPS).unwrap().profile.unwrap();
+
+    let mut module = compile(SOURCE);
+    module.profile = Some(Profile::parse(&format_counts(&counts), &module.program).unwrap());
+    let expected = run_program(&module.program, b"", MAX_STEPS).unwrap();
+    let asm = generate_riscv_assembly(module);
+    assert_eq!(run_assembly(&asm, b"", MAX_STEPS).unwrap().stdout, expected.stdout);
+    // else 分支执行了90次, 排在 then 分支之前
+    assert!(appears_before(&asm, "li    a0, 46", "li    a0, 33"), "{}", asm);
+    assert!(asm.contains("beq   t0, x0, "), "{}", asm);
+}
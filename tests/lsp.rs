@@ -0,0 +1,193 @@
+//! 语言服务器: 按协议发送一组请求, 检查诊断、跳转、引用、悬停与文档符号的结果
+use koopa::ir::Type;
+use pku_compiler::lab9::lsp::analysis::{Analysis, Severity};
+use pku_compiler::lab9::lsp::json::Json;
+use pku_compiler::lab9::lsp::Server;
+
+const URI: &str = "file:///tmp/sysy-lsp-test/main.sy";
+
+const SOURCE: &str = "\
+const int N = 2 + 2;
+int a[N][5];
+
+int sum(int v[], int n) {
+    int i = 0, s = 0;
+    while (i < n) {
+        s = s + v[i];
+        i = i + 1;
+    }
+    return s;
+}
+
+int main() {
+    int x = sum(a[1], N);
+    putint(x + y);
+    return sum(a[0], 5);
+}
+";
+
+fn message(id: Option<usize>, method: &str, params: Json) -> String {
+    let mut entries = vec![("jsonrpc".to_string(), Json::from("2.0")), ("method".to_string(), method.into())];
+    if let Some(id) = id {
+        entries.push(("id".to_string(), id.into()));
+    }
+    entries.push(("params".to_string(), params));
+    let body = Json::Object(entries).to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn position(method: &str, id: usize, line: usize, character: usize) -> String {
+    message(Some(id), method, Json::object([
+        ("textDocument", Json::object([("uri", URI.into())])),
+        ("position", Json::object([("line", line.into()), ("character", character.into())])),
+        ("context", Json::object([("includeDeclaration", true.into())])),
+    ]))
+}
+
+/// 运行服务器, 返回它发出的所有消息
+fn run_session(requests: &[String]) -> (bool, Vec<Json>) {
+    Type::set_ptr_size(4);
+    let mut output = Vec::new();
+    let shutdown = Server::new(&mut output).run(requests.concat().as_bytes()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let messages = output.split("Content-Length: ").skip(1)
+        .map(|message| Json::parse(message.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect();
+    (shutdown, messages)
+}
+
+fn response(messages: &[Json], id: usize) -> &Json {
+    messages.iter()
+        .find(|message| message.get("id").and_then(Json::as_usize) == Some(id))
+        .and_then(|message| message.get("result"))
+        .unwrap_or_else(|| panic!("no response to request {}", id))
+}
+
+/// 位置的 (行, 列)
+fn start(location: &Json) -> (usize, usize) {
+    let start = location.path(&["range", "start"]).unwrap();
+    (start.get("line").unwrap().as_usize().unwrap(), start.get("character").unwrap().as_usize().unwrap())
+}
+
+#[test]
+fn answers_requests_on_open_document() {
+    let requests = [
+        message(Some(1), "initialize", Json::object([("capabilities", Json::object([]))])),
+        message(None, "initialized", Json::object([])),
+        message(None, "textDocument/didOpen", Json::object([("textDocument", Json::object([
+            ("uri", URI.into()),
+            ("languageId", "sysy".into()),
+            ("version", 1usize.into()),
+            ("text", SOURCE.into()),
+        ]))])),
+        position("textDocument/definition", 2, 5, 15),  // while (i < n) 中的 n
+        position("textDocument/references", 3, 3, 5),   // sum 的定义
+        position("textDocument/hover", 4, 13, 17),      // sum(a[1], N) 中的 a
+        position("textDocument/hover", 5, 13, 13),      // sum 的调用
+        message(Some(6), "textDocument/documentSymbol", Json::object([("textDocument", Json::object([("uri", URI.into())]))])),
+        message(Some(7), "textDocument/foldingRange", Json::object([])),
+        message(Some(8), "shutdown", Json::Null),
+        message(None, "exit", Json::Null),
+    ];
+    let (shutdown, messages) = run_session(&requests);
+    assert!(shutdown);
+
+    let capabilities = response(&messages, 1).get("capabilities").unwrap();
+    assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+
+    // 打开文档时发布诊断: 只有未定义的 y
+    let diagnostics = messages.iter()
+        .find(|message| message.get("method").and_then(Json::as_str) == Some("textDocument/publishDiagnostics"))
+        .and_then(|message| message.path(&["params", "diagnostics"]))
+        .and_then(Json::as_array)
+        .unwrap();
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0].get("message").and_then(Json::as_str), Some("use of undeclared identifier 'y'"));
+    assert_eq!(start(&diagnostics[0]), (14, 15));
+
+    // n 的定义是形参
+    let definition = response(&messages, 2);
+    assert_eq!(definition.get("uri").and_then(Json::as_str), Some(URI));
+    assert_eq!(start(definition), (3, 21));
+
+    // sum: 定义与两次调用
+    let references: Vec<_> = response(&messages, 3).as_array().unwrap().iter().map(start).collect();
+    assert_eq!(references, [(3, 4), (13, 12), (15, 11)]);
+
+    let hover = |id| response(&messages, id).path(&["contents", "value"]).and_then(Json::as_str).unwrap().to_string();
+    assert!(hover(4).contains("int a[N][5]"), "{}", hover(4)); // 有错误时IR生成失败, 维度取自源码
+    assert!(hover(5).contains("int sum(int v[], int n)"), "{}", hover(5));
+
+    let symbols = response(&messages, 6).as_array().unwrap();
+    let names: Vec<_> = symbols.iter().map(|symbol| symbol.get("name").and_then(Json::as_str).unwrap()).collect();
+    assert_eq!(names, ["N", "a", "sum", "main"]);
+    let locals: Vec<_> = symbols[2].get("children").and_then(Json::as_array).unwrap().iter()
+        .map(|symbol| symbol.get("name").and_then(Json::as_str).unwrap())
+        .collect();
+    assert_eq!(locals, ["v", "n", "i", "s"]);
+
+    // 不支持的请求
+    let unsupported = messages.iter().find(|message| message.get("id").and_then(Json::as_usize) == Some(7)).unwrap();
+    assert!(unsupported.get("error").is_some());
+}
+
+#[test]
+fn hover_shows_evaluated_dimensions() {
+    let source = SOURCE.replace("x + y", "x");
+    let requests = [
+        message(Some(1), "initialize", Json::object([])),
+        message(None, "textDocument/didOpen", Json::object([("textDocument", Json::object([
+            ("uri", URI.into()),
+            ("text", source.into()),
+        ]))])),
+        position("textDocument/hover", 2, 13, 17),
+        position("textDocument/hover", 3, 0, 10),
+        position("textDocument/hover", 4, 3, 13),
+    ];
+    let (shutdown, messages) = run_session(&requests);
+    assert!(!shutdown);
+
+    let hover = |id| response(&messages, id).path(&["contents", "value"]).and_then(Json::as_str).unwrap().to_string();
+    assert!(hover(2).contains("int a[4][5]"), "{}", hover(2));
+    assert!(hover(3).contains("const int N = 4"), "{}", hover(3));
+    assert!(hover(4).contains("int v[]"), "{}", hover(4));
+}
+
+#[test]
+fn semantic_errors_become_diagnostics() {
+    Type::set_ptr_size(4);
+    let source = "void f() {}\nint main() {\n    int x = f();\n    return x;\n}\n";
+    let analysis = Analysis::new("main.sy", source, Vec::new());
+    let messages: Vec<&str> = analysis.diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect();
+    assert_eq!(messages, ["void value returned by 'f' is used"]);
+    assert_eq!(analysis.diagnostics[0].severity, Severity::Error);
+}
+
+#[test]
+fn bad_headers_get_parse_errors() {
+    let initialize = message(Some(1), "initialize", Json::object([("capabilities", Json::object([]))]));
+    let shutdown = message(Some(2), "shutdown", Json::Null);
+    let requests = [
+        "Content-Length: abc\r\n\r\n{\"jsonrpc\": \"2.0\", \"id\": 7, \"method\": \"shutdown\"}".to_string(),
+        initialize,
+        "Content-Type: application/vscode-jsonrpc\r\n\r\n{\"jsonrpc\": \"2.0\", \"id\": 8, \"method\": \"shutdown\"}".to_string(),
+        shutdown,
+    ];
+    // 头部不合法的消息被丢弃, 之后的消息照常处理
+    let (shutdown, messages) = run_session(&requests);
+    assert!(shutdown);
+    let errors: Vec<(String, String)> = messages.iter()
+        .filter(|message| message.get("id") == Some(&Json::Null))
+        .map(|message| {
+            let error = message.get("error").unwrap();
+            (error.get("code").unwrap().to_string(), error.get("message").and_then(Json::as_str).unwrap().to_string())
+        })
+        .collect();
+    assert_eq!(errors, [
+        ("-32700".to_string(), "invalid Content-Length 'abc'".to_string()),
+        ("-32700".to_string(), "missing Content-Length header".to_string()),
+    ]);
+    assert!(response(&messages, 1).get("capabilities").is_some());
+    assert_eq!(response(&messages, 2), &Json::Null);
+    assert!(messages.iter().all(|message| !matches!(message.get("id").and_then(Json::as_usize), Some(7 | 8))));
+}
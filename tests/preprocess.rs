@@ -0,0 +1,128 @@
+//! 预处理器: 包含文件的查找顺序与循环包含, 宏展开, 条件编译, 以及输出位置到原始文件的映射
+use std::fs;
+use std::path::PathBuf;
+use pku_compiler::lab9::preprocess::{find_preprocessor_content, LineMap, Preprocessor};
+
+/// 在临时目录中写入若干文件, 返回该目录
+fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pku-preprocess-{}-{}", std::process::id(), test));
+    for (name, content) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+fn preprocess(source: &str) -> Result<String, String> {
+    Preprocessor::new(Vec::new()).preprocess_source("main.sy", source).map(|result| result.text)
+}
+
+#[test]
+fn includes_search_own_directory_then_include_paths() {
+    let dir = write_files("resolve", &[
+        ("src/main.sy", "#include \"a.h\"\n#include <a.h>\n#include \"lib/b.h\"\nint main() { return A + B; }\n"),
+        ("src/a.h", "#define A 1\nint local;\n"),
+        ("inc/a.h", "int system;\n"),
+        ("src/lib/b.h", "#include \"c.h\"\n"),
+        ("src/lib/c.h", "#define B 2\n"),
+    ]);
+    let result = Preprocessor::new(vec![dir.join("inc")]).preprocess_file(&dir.join("src/main.sy")).unwrap();
+    // 引号形式先找包含者所在目录, 尖括号形式只找包含路径, 嵌套包含相对于被包含文件的目录
+    assert_eq!(result.text, "int local;\nint system;\nint main() { return 1 + 2; }\n");
+
+    // 输出中的位置映射回原始文件
+    let location = result.line_map.locate(result.text.find("system").unwrap());
+    assert_eq!((location.file, location.line, location.column), (dir.join("inc/a.h").display().to_string(), 1, 5));
+    let location = result.line_map.locate(result.text.find("main").unwrap());
+    assert_eq!((location.file, location.line), (dir.join("src/main.sy").display().to_string(), 4));
+
+    assert_eq!(preprocess("\n#include <missing.h>\n").unwrap_err(), "main.sy:2: 'missing.h' file not found");
+}
+
+#[test]
+fn include_cycles_are_reported() {
+    let dir = write_files("cycle", &[
+        ("main.sy", "#include \"a.h\"\nint main() { return 0; }\n"),
+        ("a.h", "#include \"b.h\"\n"),
+        ("b.h", "#include \"a.h\"\n"),
+    ]);
+    let err = Preprocessor::new(Vec::new()).preprocess_file(&dir.join("main.sy")).map(|result| result.text).unwrap_err();
+    assert!(err.contains("#include nested too deeply"), "{}", err);
+
+    // 有包含保护时重复包含不是错误
+    let dir = write_files("guard", &[
+        ("main.sy", "#include \"a.h\"\n#include \"a.h\"\nint main() { return N; }\n"),
+        ("a.h", "#ifndef A_H\n#define A_H\n#include \"a.h\"\nconst int N = 3;\n#endif\n"),
+    ]);
+    let result = Preprocessor::new(Vec::new()).preprocess_file(&dir.join("main.sy")).unwrap();
+    assert_eq!(result.text, "const int N = 3;\nint main() { return N; }\n");
+}
+
+#[test]
+fn macros_expand() {
+    let source = "\
+#define N 10
+#define SQ(x) ((x) * (x))
+#define MAX(a, b) ((a) > (b) ? (a) : (b))
+#define SELF SELF + 1
+#define TWICE(f, x) f(f(x))
+#define LONG 1 + \\
+    2
+int a[N] = {SQ(N + 1), MAX(SQ(2), N), SELF, TWICE(SQ, 3), LONG};
+int SQ = SQ;
+#undef N
+int N;
+int line = __LINE__; char *file = __FILE__; char *s = \"N\";
+";
+    assert_eq!(preprocess(source).unwrap(), "\
+int a[10] = {((10 + 1) * (10 + 1)), ((((2) * (2))) > (10) ? (((2) * (2))) : (10)), SELF + 1, ((((3) * (3))) * (((3) * (3)))), 1 +     2};
+int SQ = SQ;
+int N;
+int line = 12; char *file = \"main.sy\"; char *s = \"N\";
+");
+    assert_eq!(preprocess("#define F(a, b) a\nF(1)\n").unwrap_err(), "main.sy:2: macro 'F' requires 2 arguments, but 1 given");
+}
+
+#[test]
+fn conditionals_select_branches() {
+    let source = "\
+#define DEBUG
+#ifdef DEBUG
+int debug;
+#ifndef NDEBUG
+int checks;
+#else
+int no_checks;
+#endif
+#else
+#define UNSEEN
+int release;
+#endif
+#ifdef UNSEEN
+int unseen;
+#endif
+";
+    assert_eq!(preprocess(source).unwrap(), "int debug;\nint checks;\n");
+
+    // 无效区域中的其他指令被忽略, 但不支持的指令在有效区域中报错
+    assert_eq!(preprocess("#ifdef X\n#error no\n#endif\nint x;\n").unwrap(), "int x;\n");
+    assert_eq!(preprocess("#if 1\nint x;\n#endif\n").unwrap_err(), "main.sy:1: unsupported preprocessing directive '#if'");
+    assert_eq!(preprocess("#ifdef X\n").unwrap_err(), "main.sy:1: unterminated conditional directive");
+    assert_eq!(preprocess("#endif\n").unwrap_err(), "main.sy:1: #endif without #ifdef");
+    assert_eq!(preprocess("#ifdef X\n#else\n#else\n#endif\n").unwrap_err(), "main.sy:3: #else after #else");
+}
+
+#[test]
+fn comments_and_directives_are_found_in_raw_source() {
+    assert_eq!(find_preprocessor_content("int main() {\n    return 0;\n}\n"), None);
+    assert_eq!(find_preprocessor_content("int x;\nint y; // y\n#define N 1\n"), Some((2, "comment")));
+    assert_eq!(find_preprocessor_content("int x;\n  #include \"a.h\"\n/* a */\n"), Some((2, "preprocessing directive")));
+    // 字符串中的注释符号与注释中的 # 都不算
+    assert_eq!(find_preprocessor_content("char *s = \"// #x\";\n"), None);
+    assert_eq!(find_preprocessor_content("/*\n#define N 1\n*/ int x;\n"), Some((1, "comment")));
+
+    // 不经预处理时行号即原始行号
+    let line_map = LineMap::unprocessed("a.sy", "int x;\nint y;\n");
+    assert_eq!(line_map.locate(11).to_string(), "a.sy:2:5");
+}
@@ -0,0 +1,20 @@
+//! 格式化输出: 嵌套的一元运算符之间留空格, 输出重新解析后含义不变
+use pku_compiler::ast::{Expr, ExprKind, UnaryOp};
+use pku_compiler::lab9::pretty::{format_comp_unit, format_exp};
+use pku_compiler::sysy;
+
+#[test]
+fn nested_unary_signs_stay_separate() {
+    let source = "int main() { int a = 1; return -(-a) + +(+a) - -(-1); }";
+    let unit = sysy::CompUnitParser::new().parse(source).unwrap();
+    let text = format_comp_unit(&unit);
+    assert!(text.contains("- -a + + +a - - -1"), "{}", text);
+    // 重新解析后再格式化, 结果不变
+    let reparsed = sysy::CompUnitParser::new().parse(&text).unwrap();
+    assert_eq!(format_comp_unit(&reparsed), text);
+
+    // 缩小测试用例时可能出现负数字面量
+    let negative = Expr::new(ExprKind::Number(-1), 0, 0);
+    let exp = Expr::new(ExprKind::Unary(UnaryOp::Minus, Box::new(negative)), 0, 0);
+    assert_eq!(format_exp(&exp), "- -1");
+}
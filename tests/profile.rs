@@ -0,0 +1,83 @@
+//! 执行计数: 插桩后的程序经 Koopa IR 解释与 RISC-V 模拟得到相同的计数, 读回计数后对应到未插桩程序的基本块
+mod common;
+use common::{compile, function, function_data, MAX_STEPS};
+use koopa::ir::Program;
+use pku_compiler::lab9::codegen::generate_riscv_assembly;
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::profile::{format_counts, instrument, Profile};
+use pku_compiler::lab9::rvsim::run_assembly;
+use pku_compiler::lab9::verify::verify_program;
+
+const SOURCE: &str = "\
+int odd(int x) {
+    if (x % 2 == 1) {
+        return 1;
+    }
+    return 0;
+}
+
+int main() {
+    int i = 0, n = 0;
+    while (i < 10) {
+        n = n + odd(i);
+        i = i + 1;
+    }
+    putint(n);
+    return 0;
+}
+";
+
+/// 函数中名字以 prefix 开头的基本块的执行次数
+fn block_count(profile: &Profile, program: &Program, func: &str, prefix: &str) -> Option<u64> {
+    let func_data = function_data(program, func);
+    let bb = func_data.layout().bbs().keys()
+        .find(|&&bb| func_data.dfg().bb(bb).name().as_deref().is_some_and(|name| name.starts_with(prefix)))
+        .unwrap();
+    profile.block_count(*bb)
+}
+
+#[test]
+fn counts_blocks_and_functions() {
+    let plain = compile(SOURCE);
+    let expected = run_program(&plain.program, b"", MAX_STEPS).unwrap();
+    assert_eq!(expected.profile, None);
+
+    let mut instrumented = compile(SOURCE);
+    instrument(&mut instrumented.program);
+    verify_program(&instrumented.program).unwrap();
+    let interpreted = run_program(&instrumented.program, b"", MAX_STEPS).unwrap();
+    assert_eq!((&interpreted.stdout, interpreted.exit_code), (&expected.stdout, expected.exit_code));
+    let counts = interpreted.profile.expect("the instrumented program should dump its counts");
+    let simulated = run_assembly(&generate_riscv_assembly(instrumented), b"", MAX_STEPS).unwrap();
+    assert_eq!(simulated.stdout, expected.stdout);
+    assert_eq!(simulated.profile.as_ref(), Some(&counts));
+
+    let profile = Profile::parse(&format_counts(&counts), &plain.program).unwrap();
+    assert_eq!(profile.function_count(function(&plain.program, "@main")), Some(1));
+    assert_eq!(profile.function_count(function(&plain.program, "@odd")), Some(10));
+    assert_eq!(block_count(&profile, &plain.program, "@odd", "%then"), Some(5));
+    assert_eq!(block_count(&profile, &plain.program, "@odd", "%else"), Some(5));
+    assert_eq!(block_count(&profile, &plain.program, "@main", "%loop_body"), Some(10));
+    assert_eq!(block_count(&profile, &plain.program, "@main", "%loop_header"), Some(11));
+}
+
+#[test]
+fn rejects_mismatched_profile() {
+    let module = compile(SOURCE);
+    let err = Profile::parse("1\n2\n", &module.program).unwrap_err();
+    assert!(err.contains("basic blocks"), "{}", err);
+    assert!(Profile::parse("x\n", &module.program).is_err());
+}
+
+#[test]
+fn instrumented_ir_round_trips() {
+    let mut instrumented = compile(SOURCE);
+    instrument(&mut instrumented.program);
+    let text = instrumented.to_koopa_text();
+    let program = koopa::front::Driver::from(text.as_str()).generate_program()
+        .unwrap_or_else(|_| panic!("cannot parse:\n{}", text));
+    verify_program(&program).unwrap();
+    let expected = run_program(&instrumented.program, b"", MAX_STEPS).unwrap();
+    let reparsed = run_program(&program, b"", MAX_STEPS).unwrap();
+    assert_eq!((reparsed.stdout, reparsed.profile), (expected.stdout, expected.profile));
+}
@@ -0,0 +1,64 @@
+//! 测试用例缩小: 以"Koopa IR 解释执行的输出含某个数"为判定条件, 缩小后的程序应只保留产生它的部分
+use koopa::ir::Type;
+use pku_compiler::ast::CompUnit;
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::irgen::IRGen;
+use pku_compiler::lab9::pretty::format_comp_unit;
+use pku_compiler::lab9::reduce::reduce;
+use pku_compiler::sysy;
+
+const SOURCE: &str = r#"
+const int N = 4, M = 2 + 3;
+int g[5] = {1, 2, 3, 4, 5};
+const int ka[3][2] = {{1, 2}, {3, 4}, {5, 6}};
+
+int helper(int a[], int n) {
+    int i = 0, s = 0;
+    while (i < n) {
+        s = s + a[i];
+        i = i + 1;
+    }
+    return s;
+}
+
+int main() {
+    int x = helper(g, N);
+    if (x > 3) {
+        putint(x * M);
+    } else {
+        putint(0);
+    }
+    putch(10);
+    {
+        int y = ka[1][1] + N;
+        putint(y);
+    }
+    return 0;
+}
+"#;
+
+/// 解释执行的标准输出, 程序无法编译或执行出错时为None
+fn koopa_output(unit: &CompUnit) -> Option<String> {
+    Type::set_ptr_size(4);
+    let unit = sysy::CompUnitParser::new().parse(&format_comp_unit(unit)).ok()?;
+    let module = IRGen::new().generate_program(vec![unit]).ok()?;
+    // 删掉循环变量的自增后程序不会结束, 步数上限要小, 否则缩小很慢
+    let execution = run_program(&module.program, &[], 10_000).ok()?;
+    Some(String::from_utf8_lossy(&execution.stdout).into_owned())
+}
+
+#[test]
+fn reduces_to_the_interesting_part() {
+    let unit = sysy::CompUnitParser::new().parse(SOURCE).unwrap();
+    let interesting = |unit: &CompUnit| koopa_output(unit).is_some_and(|output| output.contains("50"));
+    assert!(interesting(&unit));
+
+    let reduced = reduce(unit, interesting);
+    let text = format_comp_unit(&reduced);
+    assert!(interesting(&reduced), "reduced program lost the property:\n{}", text);
+    // 输出 50 的 putint 与它依赖的 helper 保留, 无关的分支、块与常量都被删除或内联
+    for removed in ["if", "putch", "ka", "const", "int y"] {
+        assert!(!text.contains(removed), "'{}' should have been reduced away:\n{}", removed, text);
+    }
+    assert!(text.contains("helper"), "reduced program lost the call:\n{}", text);
+}
@@ -0,0 +1,107 @@
+//! 输出的 Koopa IR 文本能被 Koopa 的前端重新解析, 解析结果(含浮点签名)与内存中的程序行为相同
+mod common;
+use common::{compile, MAX_STEPS};
+use pku_compiler::lab9::codegen::generate_riscv_assembly;
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::irgen::IrModule;
+use pku_compiler::lab9::rvsim::run_assembly;
+use pku_compiler::lab9::verify::verify_program;
+
+/// 输出文本后重新解析, 两者解释执行与生成汇编后模拟执行的输出都相同
+fn round_trip(module: IrModule, input: &[u8]) {
+    let text = module.to_koopa_text();
+    let program = koopa::front::Driver::from(text.as_str()).generate_program()
+        .unwrap_or_else(|_| panic!("cannot parse:\n{}", text));
+    let parsed = IrModule::from_program(program);
+    verify_program(&parsed.program).unwrap();
+    let expected = run_program(&module.program, input, MAX_STEPS).unwrap().stdout;
+    assert_eq!(run_program(&parsed.program, input, MAX_STEPS).unwrap().stdout, expected);
+    assert_eq!(run_assembly(&generate_riscv_assembly(module), input, MAX_STEPS).unwrap().stdout, expected);
+    assert_eq!(run_assembly(&generate_riscv_assembly(parsed), input, MAX_STEPS).unwrap().stdout, expected);
+}
+
+#[test]
+fn float_intrinsics_are_declared_before_use() {
+    let source = "\
+float half(float x) {
+    return x / 2;
+}
+int main() {
+    float a = getfloat();
+    if (a > 1.5) {
+        putfloat(half(a) * 3);
+    }
+    putint(a);
+    return 0;
+}
+";
+    let module = compile(source);
+    let text = module.to_koopa_text();
+    assert!(text.find("decl @__sysy_fdiv").unwrap() < text.find("fun @half").unwrap(), "{}", text);
+    round_trip(module, b"2.5");
+}
+
+#[test]
+fn variadic_wrappers_are_declared_before_use() {
+    let source = "\
+void show(int n) {
+    putf(\"%d:%c\\n\", n, 65 + n);
+}
+int main() {
+    show(1);
+    putf(\"%f %d\\n\", 0.5, 7);
+    return 0;
+}
+";
+    let module = compile(source);
+    let text = module.to_koopa_text();
+    assert!(text.find("decl @__sysy_va_putf_ii").unwrap() < text.find("fun @show").unwrap(), "{}", text);
+    round_trip(module, b"");
+}
+
+#[test]
+fn pointer_casts_are_declared_before_use() {
+    let source = "\
+int find(int *a, int n, int x) {
+    int *p = 0;
+    int i = 0;
+    while (i < n) {
+        if (a[i] == x) p = &a[i];
+        i = i + 1;
+    }
+    if (p) return p - a;
+    return -1;
+}
+int main() {
+    int a[4] = {3, 1, 4, 1};
+    putint(find(a, 4, 4));
+    putint(find(a, 4, 9));
+    return 0;
+}
+";
+    let module = compile(source);
+    let text = module.to_koopa_text();
+    assert!(text.find("decl @__sysy_ptrtoint_pi").unwrap() < text.find("fun @find").unwrap(), "{}", text);
+    round_trip(module, b"");
+}
+
+#[test]
+fn float_signatures_survive_round_trip() {
+    let source = "\
+float mix(int a, float b, float c) {
+    return a * b + c;
+}
+int scale(float x) {
+    return x * 10;
+}
+int main() {
+    float r = mix(2, 1.25, getfloat());
+    putfloat(r);
+    putint(scale(r));
+    return 0;
+}
+";
+    let module = compile(source);
+    assert!(module.to_koopa_text().contains("decl @__sysy_fsig_mix_fiff()\n"));
+    round_trip(module, b"0.5");
+}
@@ -0,0 +1,112 @@
+//! 表达式的源码区间: 各类表达式节点的字节范围, 以及子表达式中的错误定位到该子表达式
+mod common;
+use common::parse;
+use pku_compiler::ast::{BlockItem, CompUnitItem, Expr, ExprKind, Span, Stmt};
+use pku_compiler::lab9::irgen::IRGen;
+use pku_compiler::sysy;
+
+const PREFIX: &str = "int main() { return ";
+
+/// 解析 return 语句中的表达式
+fn parse_exp(text: &str) -> Expr {
+    let source = format!("{}{}; }}", PREFIX, text);
+    let unit = sysy::CompUnitParser::new().parse(&source).unwrap();
+    let CompUnitItem::FuncDef(main) = &unit.items[0] else { panic!("expected a function") };
+    let BlockItem::Stmt(Stmt::Return(Some(exp))) = &main.block.block_item_list[0] else { panic!("expected a return") };
+    exp.clone()
+}
+
+/// 按先序列出各节点的区间(相对于表达式文本)与其覆盖的文本
+fn spans(text: &str) -> Vec<(usize, usize, String)> {
+    fn visit(exp: &Expr, text: &str, out: &mut Vec<(usize, usize, String)>) {
+        let Span { start, end } = exp.span;
+        let (start, end) = (start - PREFIX.len(), end - PREFIX.len());
+        out.push((start, end, text[start..end].to_string()));
+        match &exp.kind {
+            ExprKind::Unary(_, operand) => visit(operand, text, out),
+            ExprKind::Binary(_, lhs, rhs) => {
+                visit(lhs, text, out);
+                visit(rhs, text, out);
+            }
+            ExprKind::Call(_, args) => args.iter().for_each(|arg| visit(arg, text, out)),
+            ExprKind::LVal(lval) => lval.indices.iter().for_each(|index| visit(index, text, out)),
+            ExprKind::Number(_) | ExprKind::Float(_) | ExprKind::Str(_) => {}
+        }
+    }
+    let mut out = Vec::new();
+    visit(&parse_exp(text), text, &mut out);
+    out
+}
+
+fn span(start: usize, end: usize, text: &str) -> (usize, usize, String) {
+    (start, end, text.to_string())
+}
+
+#[test]
+fn nested_binary_and_unary() {
+    // 二元表达式从左操作数开始到右操作数结束, 括号计入所在的子表达式
+    assert_eq!(spans("1 + (a - b) * -c"), [
+        span(0, 16, "1 + (a - b) * -c"),
+        span(0, 1, "1"),
+        span(4, 16, "(a - b) * -c"),
+        span(4, 11, "(a - b)"),
+        span(5, 6, "a"),
+        span(9, 10, "b"),
+        span(14, 16, "-c"),
+        span(15, 16, "c"),
+    ]);
+    // 一元表达式从运算符开始
+    assert_eq!(spans("!-*p"), [
+        span(0, 4, "!-*p"),
+        span(1, 4, "-*p"),
+        span(2, 4, "*p"),
+        span(3, 4, "p"),
+    ]);
+}
+
+#[test]
+fn calls_and_indices() {
+    assert_eq!(spans("f(a[i + 1][2], g())"), [
+        span(0, 19, "f(a[i + 1][2], g())"),
+        span(2, 13, "a[i + 1][2]"),
+        span(4, 9, "i + 1"),
+        span(4, 5, "i"),
+        span(8, 9, "1"),
+        span(11, 12, "2"),
+        span(15, 18, "g()"),
+    ]);
+    assert_eq!(spans("x[f(1)] || 0x1F"), [
+        span(0, 15, "x[f(1)] || 0x1F"),
+        span(0, 7, "x[f(1)]"),
+        span(2, 6, "f(1)"),
+        span(4, 5, "1"),
+        span(11, 15, "0x1F"),
+    ]);
+}
+
+#[test]
+fn type_errors_point_at_the_subexpression() {
+    let source = "\
+int a[2];
+float f;
+int main() {
+    int x = 1;
+    x = 1 + (2 * (a % 3));
+    x = x + 2 * (f % 2);
+    return x + (1 - a * 2);
+}
+";
+    let (preprocessed, unit) = parse(source);
+    let errors = IRGen::new().generate_program(vec![unit]).err().expect("type errors");
+    let messages: Vec<String> = errors.iter()
+        .map(|err| {
+            let location = preprocessed.line_map.locate(err.pos);
+            format!("{}:{}: {}", location.line, location.column, err)
+        })
+        .collect();
+    assert_eq!(messages, [
+        "5:18: invalid operand: operator '%' cannot be applied to pointer operands",
+        "6:17: invalid operand: operator '%' cannot be applied to float operands",
+        "7:21: invalid operand: operator '*' cannot be applied to pointer operands",
+    ]);
+}
@@ -0,0 +1,101 @@
+//! 尾调用: 自递归在 IR 上改写为循环, 其他尾调用拆除栈帧后用 tail 跳转, 深递归不再耗尽栈
+mod common;
+use common::function_data;
+use koopa::ir::{Program, ValueKind};
+use pku_compiler::lab9::codegen::generate_riscv_assembly;
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::irgen::IrModule;
+use pku_compiler::lab9::rvsim::run_assembly;
+use pku_compiler::lab9::tailcall::eliminate_tail_recursion;
+use pku_compiler::lab9::verify::verify_program;
+
+/// 编译后消除尾递归
+fn compile(source: &str) -> IrModule {
+    let mut module = common::compile(source);
+    eliminate_tail_recursion(&mut module.program);
+    verify_program(&module.program).unwrap();
+    module
+}
+
+/// 函数中调用 callee 的次数
+fn calls(program: &Program, func: &str, callee: &str) -> usize {
+    let func = function_data(program, func);
+    func.dfg().values().values()
+        .filter(|data| matches!(data.kind(), ValueKind::Call(call) if program.func(call.callee()).name() == callee))
+        .count()
+}
+
+#[test]
+fn self_recursion_becomes_loop() {
+    let source = "\
+int sum(int a[], int n, int acc) {
+    if (n == 0) return acc;
+    return sum(a, n - 1, acc + a[n - 1]);
+}
+void count(int n) {
+    if (n == 0) return;
+    count(n - 1);
+}
+int fact(int n) {
+    if (n == 0) return 1;
+    return n * fact(n - 1);
+}
+int local(int n) {
+    int b[2] = {n, n};
+    if (n == 0) return 0;
+    return local(b[0] - 1);
+}
+int main() {
+    int a[5] = {1, 2, 3, 4, 5};
+    count(100000);
+    putint(sum(a, 5, 0));
+    putint(fact(5));
+    putint(local(3));
+    return 0;
+}
+";
+    let module = compile(source);
+    assert_eq!(calls(&module.program, "@sum", "@sum"), 0);
+    assert_eq!(calls(&module.program, "@count", "@count"), 0);
+    // 不在尾部的递归, 以及局部数组地址逃逸的函数保持不变
+    assert_eq!(calls(&module.program, "@fact", "@fact"), 1);
+    assert_eq!(calls(&module.program, "@local", "@local"), 1);
+
+    let interpreted = run_program(&module.program, b"", 100_000_000).unwrap();
+    assert_eq!(interpreted.stdout, b"151200");
+    let simulated = run_assembly(&generate_riscv_assembly(module), b"", 100_000_000).unwrap();
+    assert_eq!(simulated.stdout, interpreted.stdout);
+}
+
+#[test]
+fn sibling_calls_jump_with_tail() {
+    let source = "\
+int even(int n);
+int odd(int n) {
+    if (n == 0) return 0;
+    return even(n - 1);
+}
+int even(int n) {
+    if (n == 0) return 1;
+    return odd(n - 1);
+}
+int many(int a, int b, int c, int d, int e, int f, int g, int h, int i) {
+    return a + i;
+}
+int spill(int n) {
+    return many(n, n, n, n, n, n, n, n, n);
+}
+int main() {
+    putint(even(600000));
+    putint(spill(2));
+    return 0;
+}
+";
+    let asm = generate_riscv_assembly(compile(source));
+    assert!(asm.contains("  tail  even\n") && asm.contains("  tail  odd\n"), "{}", asm);
+    // 有栈上实参的调用不是 tail
+    assert!(asm.contains("  call  many\n"), "{}", asm);
+    // 每层栈帧32字节, 不用 tail 时六十万层会超出模拟器16MB的栈
+    let simulated = run_assembly(&asm, b"", 100_000_000).unwrap();
+    assert_eq!(simulated.stdout, b"14");
+}
@@ -0,0 +1,102 @@
+//! 循环展开: 常数小循环完全展开, 其余计数循环按倍数展开并保留处理剩余迭代的原循环, 结果与展开前相同
+mod common;
+use common::{compile, MAX_STEPS};
+use pku_compiler::lab9::codegen::generate_riscv_assembly;
+use pku_compiler::lab9::interp::run_program;
+use pku_compiler::lab9::rvsim::run_assembly;
+use pku_compiler::lab9::unroll::unroll_loops;
+use pku_compiler::lab9::verify::verify_program;
+
+/// 展开前后解释执行与模拟执行的输出都相同, 返回展开后的 Koopa IR 文本
+fn check_unrolled(source: &str, input: &[u8], factor: usize) -> String {
+    let expected = run_program(&compile(source).program, input, MAX_STEPS).unwrap().stdout;
+    let mut module = compile(source);
+    unroll_loops(&mut module, factor);
+    verify_program(&module.program).unwrap();
+    assert_eq!(run_program(&module.program, input, MAX_STEPS).unwrap().stdout, expected);
+    let mut text = Vec::new();
+    koopa::back::KoopaGenerator::new(&mut text).generate_on(&module.program).unwrap();
+    let asm = generate_riscv_assembly(module);
+    assert_eq!(run_assembly(&asm, input, MAX_STEPS).unwrap().stdout, expected);
+    String::from_utf8(text).unwrap()
+}
+
+#[test]
+fn fully_unrolls_small_constant_loops() {
+    let source = "\
+int main() {
+    int a[8];
+    int i = 0;
+    while (i < 8) {
+        a[i] = i * i;
+        i = i + 1;
+    }
+    putint(a[7]);
+    return 0;
+}
+";
+    let ir = check_unrolled(source, b"", 4);
+    assert!(!ir.contains("%loop_header"), "{}", ir);
+    assert_eq!(ir.matches("mul").count(), 8, "{}", ir);
+}
+
+#[test]
+fn unrolls_runtime_trip_counts_with_remainder() {
+    let source = "\
+int main() {
+    int n = getint(), i = getint();
+    int s = 0;
+    while (i < n) {
+        s = s + 1;
+        if (s == 150) {
+            break;
+        }
+        i = i + 1;
+    }
+    putint(s);
+    putch(32);
+    putint(i);
+    return 0;
+}
+";
+    for (n, start) in [(0, 0), (1, 0), (3, 0), (4, 0), (5, 0), (7, 0), (8, 0), (200, 0), (5, 9), (2147483647, 2147483640), (2147483647, -2147483648), (-2147483648, 2147483647)] {
+        let input = format!("{} {}", n, start);
+        let ir = check_unrolled(source, input.as_bytes(), 4);
+        assert!(ir.contains("%loop_header_1_unrolled"), "{}", ir);
+        // 4份循环体与原循环中各有两个 add; N - i 溢出时由原循环执行
+        assert_eq!(ir.matches("add").count(), 4 * 2 + 2, "{}", ir);
+    }
+    check_unrolled(source, b"100 0", 3);
+}
+
+#[test]
+fn keeps_loops_it_cannot_count() {
+    let source = "\
+int g;
+int main() {
+    int i = 0, n = getint();
+    while (i < n) {
+        i = i + 1;
+        if (i % 2 == 0) {
+            continue;
+        }
+        putint(i);
+    }
+    while (g < n) {
+        g = g + 1;
+    }
+    int j = 0;
+    while (j < n) {
+        n = n - 1;
+        j = j + 1;
+    }
+    putint(g + n);
+    return 0;
+}
+";
+    let ir = check_unrolled(source, b"9", 4);
+    assert!(!ir.contains("unrolled"), "{}", ir);
+    // 倍数为1时不展开
+    let ir = check_unrolled("int main() { int i = 0, s = 0; while (i < 3) { s = s + i; i = i + 1; } return s; }", b"", 1);
+    assert!(ir.contains("%loop_header"), "{}", ir);
+}
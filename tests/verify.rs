@@ -0,0 +1,111 @@
+//! IR校验: 手工构造畸形的IR, 报告中应指出出错的函数、块与值
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, Value};
+use pku_compiler::lab9::verify::verify_program;
+
+/// 在程序中新建函数 @f(i32): i32
+fn new_function(program: &mut Program) -> Function {
+    Type::set_ptr_size(4);
+    program.new_func(FunctionData::with_param_names("@f".into(), vec![(Some("@x".into()), Type::get_i32())], Type::get_i32()))
+}
+
+/// 新建基本块并加到函数布局的末尾
+fn new_block(program: &mut Program, func: Function, name: &str) -> BasicBlock {
+    let func_data = program.func_mut(func);
+    let bb = func_data.dfg_mut().new_bb().basic_block(Some(name.into()));
+    func_data.layout_mut().bbs_mut().extend([bb]);
+    bb
+}
+
+/// 将指令依次加到基本块末尾
+fn push(program: &mut Program, func: Function, bb: BasicBlock, insts: &[Value]) {
+    program.func_mut(func).layout_mut().bb_mut(bb).insts_mut().extend(insts.iter().copied());
+}
+
+fn errors(program: &Program) -> Vec<String> {
+    verify_program(program).unwrap_err().iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn block_without_terminator() {
+    let mut program = Program::new();
+    let func = new_function(&mut program);
+    let entry = new_block(&mut program, func, "%entry");
+    let exit = new_block(&mut program, func, "%exit");
+    let dfg = program.func_mut(func).dfg_mut();
+    let (one, two) = (dfg.new_value().integer(1), dfg.new_value().integer(2));
+    let sum = dfg.new_value().binary(BinaryOp::Add, one, two);
+    let ret = dfg.new_value().ret(Some(sum));
+    push(&mut program, func, entry, &[sum]);
+    push(&mut program, func, exit, &[ret]);
+
+    // %entry 没有落入 %exit 的边, %exit 不可达, 不再检查其中的支配关系
+    assert_eq!(errors(&program), ["in function @f, block %entry, value %0: basic block does not end with a terminator"]);
+}
+
+#[test]
+fn use_not_dominated_by_definition() {
+    let mut program = Program::new();
+    let func = new_function(&mut program);
+    let entry = new_block(&mut program, func, "%entry");
+    let then = new_block(&mut program, func, "%then");
+    let other = new_block(&mut program, func, "%else");
+    let end = new_block(&mut program, func, "%end");
+    let x = program.func(func).params()[0];
+    let dfg = program.func_mut(func).dfg_mut();
+    let one = dfg.new_value().integer(1);
+    let branch = dfg.new_value().branch(x, then, other);
+    let doubled = dfg.new_value().binary(BinaryOp::Add, x, x);
+    let (jump_then, jump_else) = (dfg.new_value().jump(end), dfg.new_value().jump(end));
+    // %0 只在 %then 中定义, 从 %else 到达 %end 时没有定义
+    let sum = dfg.new_value().binary(BinaryOp::Add, doubled, one);
+    let ret = dfg.new_value().ret(Some(sum));
+    push(&mut program, func, entry, &[branch]);
+    push(&mut program, func, then, &[doubled, jump_then]);
+    push(&mut program, func, other, &[jump_else]);
+    push(&mut program, func, end, &[sum, ret]);
+
+    assert_eq!(errors(&program), ["in function @f, block %end, value %1: operand %0 does not dominate this use"]);
+}
+
+#[test]
+fn wrong_argument_counts() {
+    let mut program = Program::new();
+    let callee = program.new_func(FunctionData::new_decl("@g".into(), vec![Type::get_i32(), Type::get_i32()], Type::get_i32()));
+    let func = new_function(&mut program);
+    let entry = new_block(&mut program, func, "%entry");
+    let next = new_block(&mut program, func, "%next");
+    let x = program.func(func).params()[0];
+    let dfg = program.func_mut(func).dfg_mut();
+    let call = dfg.new_value().call(callee, vec![x]);
+    let jump = dfg.new_value().jump(next);
+    let ret = dfg.new_value().ret(Some(call));
+    // 构造跳转之后再给目标块加上形参, 跳转因而少传了一个实参
+    let with_param = dfg.new_bb().basic_block_with_params(None, vec![Type::get_i32()]);
+    let param = dfg.bb(with_param).params()[0];
+    dfg.bb_mut(next).params_mut().push(param);
+    push(&mut program, func, entry, &[call, jump]);
+    push(&mut program, func, next, &[ret]);
+
+    assert_eq!(errors(&program), [
+        "in function @f, block %entry, value %0: call to @g passes 1 arguments, expected 2",
+        "in function @f, block %entry, value #1: passes 0 arguments to %next, which takes 1",
+    ]);
+}
+
+#[test]
+fn store_type_mismatch() {
+    let mut program = Program::new();
+    let func = new_function(&mut program);
+    let entry = new_block(&mut program, func, "%entry");
+    let x = program.func(func).params()[0];
+    let dfg = program.func_mut(func).dfg_mut();
+    let slot = dfg.new_value().alloc(Type::get_i32());
+    let store = dfg.new_value().store(x, slot);
+    let ret = dfg.new_value().ret(Some(x));
+    // 把 %0 改为 *i32 的槽位, 之前构造的 store 仍写入 i32
+    dfg.replace_value_with(slot).alloc(Type::get_pointer(Type::get_i32()));
+    push(&mut program, func, entry, &[slot, store, ret]);
+
+    assert_eq!(errors(&program), ["in function @f, block %entry, value #1: store of i32 into a pointer to *i32"]);
+}
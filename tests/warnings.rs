@@ -0,0 +1,202 @@
+//! 警告: 各警告何时报告、何时不报告, 以及 -W 选项对它们的控制
+mod common;
+use common::parse;
+use pku_compiler::lab9::irgen::error::WarningOptions;
+use pku_compiler::lab9::irgen::IRGen;
+
+/// 在给定选项下报告的警告, 形如 "行:列: 信息 [-W选项]"
+fn warnings(source: &str, options: &[&str]) -> Vec<String> {
+    let mut warning_options = WarningOptions::default();
+    for option in options {
+        warning_options.parse(option).unwrap();
+    }
+    let (source, unit) = parse(source);
+    let module = IRGen::new().generate_program(vec![unit]).unwrap_or_else(|_| panic!("cannot compile"));
+    module.warnings.iter()
+        .filter(|warning| warning_options.is_enabled(&warning.kind))
+        .map(|warning| {
+            let location = source.line_map.locate(warning.pos);
+            format!("{}:{}: {} [-W{}]", location.line, location.column, warning.kind, warning.kind.flag())
+        })
+        .collect()
+}
+
+#[test]
+fn unused_symbols() {
+    let source = "\
+static int helper(int a, int b) {
+    return a;
+}
+static int used(int x) {
+    return x;
+}
+int exported(int y) {
+    int unused = 1, kept = 2;
+    const int C = 3;
+    int arr[2];
+    arr[0] = kept;
+    return used(arr[0]);
+}
+int main() {
+    return 0;
+}
+";
+    assert_eq!(warnings(source, &[]), [
+        "1:12: 'helper' defined but not used [-Wunused-function]",
+        "8:9: unused variable 'unused' [-Wunused-variable]",
+        "9:15: unused variable 'C' [-Wunused-variable]",
+    ]);
+    // 未使用的形参默认不报告
+    assert_eq!(warnings(source, &["-Wunused-parameter", "-Wno-unused-variable", "-Wno-unused-function"]), [
+        "1:30: unused parameter 'b' [-Wunused-parameter]",
+        "7:18: unused parameter 'y' [-Wunused-parameter]",
+    ]);
+    assert!(warnings(source, &["-w", "-Wall"]).is_empty());
+}
+
+#[test]
+fn unreachable_code_and_missing_return() {
+    let source = "\
+int f(int n) {
+    while (n) {
+        if (n > 5) {
+            break;
+            n = 0;
+        }
+        n = n - 1;
+    }
+    if (n) return 1;
+}
+int g(int n) {
+    return n;
+    n = 2;
+}
+int forever() {
+    while (1) {}
+}
+int both(int n) {
+    if (n) return 1; else return 2;
+}
+void v() {}
+int main() {
+    v();
+    return f(1) + g(2) + forever() + both(3);
+}
+";
+    assert_eq!(warnings(source, &[]), [
+        "1:5: control reaches end of non-void function 'f' [-Wreturn-type]",
+        "5:13: statement will never be executed [-Wunreachable-code]",
+        "13:5: statement will never be executed [-Wunreachable-code]",
+    ]);
+    assert_eq!(warnings(source, &["-Wno-unreachable-code"]).len(), 1);
+}
+
+#[test]
+fn shadowing_is_opt_in() {
+    let source = "\
+int g;
+int main() {
+    int g = 1, x = 2;
+    {
+        int x = g;
+        putint(x);
+    }
+    {
+        int y = x;
+        putint(y);
+    }
+    {
+        int y = 3;
+        putint(y);
+    }
+    return 0;
+}
+";
+    assert!(warnings(source, &[]).is_empty());
+    // 同级作用域中的同名变量不算遮蔽
+    assert_eq!(warnings(source, &["-Wshadow"]), [
+        "3:9: declaration of 'g' shadows a previous declaration [-Wshadow]",
+        "5:13: declaration of 'x' shadows a previous declaration [-Wshadow]",
+    ]);
+    assert_eq!(warnings(source, &["-Wall"]), warnings(source, &["-Wshadow"]));
+}
+
+#[test]
+fn warning_options() {
+    let mut options = WarningOptions::default();
+    assert!(!options.werror);
+    options.parse("-Werror").unwrap();
+    assert!(options.werror);
+    options.parse("-Wno-error").unwrap();
+    assert!(!options.werror);
+    assert_eq!(options.parse("-Wfoo").unwrap_err(), "unknown warning option '-Wfoo'");
+    assert_eq!(options.parse("-Wno-foo").unwrap_err(), "unknown warning option '-Wno-foo'");
+}
+
+#[test]
+fn overflow_and_division_by_zero() {
+    let source = "\
+const int C = 2147483647 + 1;
+int g = 7 / 0;
+int arr[10 % 0 + 1];
+int main() {
+    int y = 5;
+    int x = y / 0;
+    int z = y % 0;
+    int w = 2147483647 + 1;
+    int m = -(-2147483647 - 1) + y / (C - C);
+    arr[0] = x + z + w + m + g;
+    return y / 2 + y % 3 + 2147483647 - y;
+}
+";
+    // 常量与非常量上下文中各报告一次, 位置为运算所在的子表达式
+    assert_eq!(warnings(source, &[]), [
+        "1:15: integer overflow in expression, result wraps to -2147483648 [-Woverflow]",
+        "2:9: division by zero [-Wdiv-by-zero]",
+        "3:9: division by zero [-Wdiv-by-zero]",
+        "6:13: division by zero [-Wdiv-by-zero]",
+        "7:13: division by zero [-Wdiv-by-zero]",
+        "8:13: integer overflow in expression, result wraps to -2147483648 [-Woverflow]",
+        "9:13: integer overflow in expression, result wraps to -2147483648 [-Woverflow]",
+        "9:34: division by zero [-Wdiv-by-zero]",
+    ]);
+    assert_eq!(warnings(source, &["-Wno-overflow"]).len(), 5);
+    assert_eq!(warnings(source, &["-Wno-div-by-zero"]).len(), 3);
+}
+
+#[test]
+fn werror_turns_warnings_into_errors() {
+    let source = "\
+int main() {
+    int y = 1;
+    return y / 0 + (2147483647 + 1);
+}
+";
+    let (preprocessed, unit) = parse(source);
+    let module = IRGen::new().generate_program(vec![unit]).unwrap_or_else(|_| panic!("cannot compile"));
+    let diagnostics = |options: &[&str]| -> Vec<(bool, String)> {
+        let mut warning_options = WarningOptions::default();
+        for option in options {
+            warning_options.parse(option).unwrap();
+        }
+        module.warnings.iter()
+            .filter_map(|warning| {
+                let location = preprocessed.line_map.locate(warning.pos);
+                warning_options.diagnose(&warning.kind)
+                    .map(|(is_error, message)| (is_error, format!("{}:{}: {}", location.line, location.column, message)))
+            })
+            .collect()
+    };
+    assert_eq!(diagnostics(&[]), [
+        (false, "3:12: warning: division by zero [-Wdiv-by-zero]".to_string()),
+        (false, "3:20: warning: integer overflow in expression, result wraps to -2147483648 [-Woverflow]".to_string()),
+    ]);
+    assert_eq!(diagnostics(&["-Werror"]), [
+        (true, "3:12: error: division by zero [-Werror=div-by-zero]".to_string()),
+        (true, "3:20: error: integer overflow in expression, result wraps to -2147483648 [-Werror=overflow]".to_string()),
+    ]);
+    // 关闭的警告不会变成错误
+    assert_eq!(diagnostics(&["-Werror", "-Wno-overflow"]).len(), 1);
+    assert!(diagnostics(&["-Werror", "-w"]).is_empty());
+    assert!(diagnostics(&["-Werror", "-Wno-error"]).iter().all(|(is_error, _)| !is_error));
+}